@@ -0,0 +1,105 @@
+//! Saturation benchmark
+//! Saturates a medium workload (a chain of symmetric encryptions, each key
+//! being sent encrypted under the previous one) and prints the mean time per run,
+//! then times the scaling families of the `bench` module at growing sizes.
+//!
+//! Compare hashers with:
+//! `cargo bench --bench saturation` and
+//! `cargo bench --bench saturation --no-default-features`,
+//! and parallel resolution with `cargo bench --bench saturation --features parallel`,
+//! giving `-- --threads <n>` to set the number of threads (all available ones
+//! otherwise)
+
+use sniffer::bench::Family;
+use sniffer::{Attributes, Sniffer, Statement};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const KEYS: usize = 40;
+const RUNS: u32 = 10;
+const SEED: u64 = 0;
+
+fn workload() -> String {
+    let mut source = String::from(
+        "att(senc(X, Y)) /\\ att(Y) => att(X).\n\
+         att(X) /\\ att(Y) => att(pair(X, Y)).\n\
+         att(pair(X, Y)) => att(X).\n\
+         att(pair(X, Y)) => att(Y).\n\
+         att(k0).\n",
+    );
+    for i in 1..KEYS {
+        source += &format!("att(senc(pair(k{i}, n{i}), k{})).\n", i - 1);
+    }
+    source
+}
+
+/// Number of threads given with `--threads <n>`, if any
+fn threads() -> Option<usize> {
+    let args: Vec<String> = std::env::args().collect();
+    let position = args.iter().position(|a| a == "--threads")?;
+    let threads = args.get(position + 1).and_then(|n| n.parse().ok());
+    Some(threads.expect("`--threads` expects a positive number"))
+}
+
+fn sniffer(threads: Option<usize>) -> Sniffer {
+    match threads {
+        Some(threads) => Sniffer::builder().threads(threads).build().unwrap(),
+        None => Sniffer::default(),
+    }
+}
+
+fn main() {
+    let source = workload();
+    let threads = threads();
+    let mut total = Duration::ZERO;
+    let mut facts = 0;
+    for _ in 0..RUNS {
+        let mut sniffer = sniffer(threads);
+        sniffer.assert(&source).unwrap();
+
+        let start = Instant::now();
+        sniffer.saturate_all();
+        total += start.elapsed();
+        facts = black_box(sniffer.iter_facts().count());
+    }
+
+    let hasher = if cfg!(feature = "fxhash") {
+        "fxhash"
+    } else {
+        "std"
+    };
+    let used = match threads {
+        Some(threads) => threads,
+        None if cfg!(feature = "parallel") => {
+            std::thread::available_parallelism().map_or(1, |n| n.get())
+        }
+        None => 1,
+    };
+    println!(
+        "saturation ({hasher}, {used} threads): {KEYS} keys, {facts} facts, {:?} per run",
+        total / RUNS
+    );
+
+    for family in Family::ALL {
+        let sizes = match family {
+            Family::TransitiveClosure => [25, 50, 100],
+            Family::SameGeneration => [10, 25, 50],
+            Family::NQueens => [4, 5, 6],
+        };
+        for n in sizes {
+            let mut sniffer = sniffer(threads);
+            sniffer.assert_statements(
+                family
+                    .rules(n, SEED)
+                    .into_iter()
+                    .map(|rule| Statement::Rule(rule, Attributes::default()))
+                    .collect(),
+            );
+            let start = Instant::now();
+            sniffer.saturate_all();
+            let elapsed = start.elapsed();
+            let facts = black_box(sniffer.iter_facts().count());
+            println!("{} (n = {n}): {facts} facts, {elapsed:?}", family.name());
+        }
+    }
+}
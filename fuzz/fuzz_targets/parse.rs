@@ -0,0 +1,17 @@
+//! Checks that no input makes the lexer, parser or linter panic.
+//! Run with `cargo fuzz run parse`
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use logos_nom_bridge::Tokens;
+use sniffer::{Parser, Sniffer};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+    let _ = sniffer::lexer::tokens(source).count();
+    let _ = Parser::parse_query(Tokens::new(source));
+    let _ = sniffer::lint::lint(source);
+    let _ = Sniffer::default().assert(source);
+});
@@ -0,0 +1,32 @@
+/* C interface of the sniffer engine, built with the `ffi` feature.
+ * See src/ffi.rs for the ownership rules of every function. */
+#ifndef PIF_H
+#define PIF_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct PifSniffer PifSniffer;
+typedef struct PifDerivation PifDerivation;
+
+/* Message of the last failure on this thread, or NULL */
+const char *pif_last_error(void);
+
+/* Loads the rules of a .pif source, NULL on errors */
+PifSniffer *pif_new_from_str(const char *source);
+/* Derivation of an atom such as "att(m).", NULL if not derivable or on errors */
+PifDerivation *pif_query(PifSniffer *sniffer, const char *atom);
+/* JSON encoding of a derivation, with the fingerprint of its run, to be released
+   with pif_string_free */
+char *pif_derivation_to_json(const PifDerivation *derivation);
+
+void pif_free(PifSniffer *sniffer);
+void pif_derivation_free(PifDerivation *derivation);
+void pif_string_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
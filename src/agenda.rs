@@ -0,0 +1,225 @@
+//! Agenda module
+//! Rules waiting to be fired by the saturation loop, in rounds: rules of the rule
+//! set are in round 0, and a rule derived while firing a rule of round `n` is in
+//! round `n + 1`. Rules of higher weight are taken first, then rules of earlier
+//! rounds, then the most recently added.
+//!
+//! Without weights, every rule of a round is thus taken before any rule of the
+//! next one: rounds are the layers of a breadth-first search over derivation
+//! depth, and the round a rule is first derived in is its smallest derivation
+//! depth. Breadth-first agendas keep this guarantee with weights, which then only
+//! order the rules of a round
+//!
+//! Weights alone would let heavy rules starve the others when they keep deriving
+//! new rules, as rules derived from a rule are at least as heavy as it is. Agendas
+//! holding weighted rules are thus fair: every `FAIRNESS_INTERVAL` rules, the
+//! oldest waiting rule is taken instead, so that a rule is taken after at most
+//! `FAIRNESS_INTERVAL` times the number of rules added before it. Every rule with
+//! a derivation is thus derived given enough steps, see `Sniffer::saturate_with_fuel`
+
+use crate::ast::InnerRule;
+use crate::hashing::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+
+/// Every this many rules, agendas holding weighted rules take the oldest rule
+pub(crate) const FAIRNESS_INTERVAL: usize = 8;
+
+/// Position of a waiting rule in the priority queue
+struct Entry {
+    /// Weight and earliness of the round, in the order they are compared in
+    priority: (u64, u64),
+    order: usize,
+}
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.priority, self.order).cmp(&(other.priority, other.order))
+    }
+}
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Entry {}
+
+struct Waiting {
+    rule: InnerRule,
+    weight: u32,
+    round: usize,
+}
+
+/// Waiting rules are indexed by the order they were added in, both by priority
+/// and by age. Rules taken through one index are skipped when met in the other
+#[derive(Default)]
+pub(crate) struct Agenda {
+    queue: BinaryHeap<Entry>,
+    ages: VecDeque<usize>,
+    waiting: HashMap<usize, Waiting>,
+    pushed: usize,
+    taken: usize,
+    weighted: bool,
+    breadth_first: bool,
+    /// Last round a checkpoint was written at, see the `checkpoint` module
+    checkpointed: usize,
+}
+impl Agenda {
+    /// An agenda taking rules round by round, whatever their weight
+    pub fn breadth_first() -> Agenda {
+        Agenda {
+            breadth_first: true,
+            ..Agenda::default()
+        }
+    }
+
+    pub fn push(&mut self, rule: InnerRule, weight: u32, round: usize) {
+        let earliness = u64::MAX - round as u64;
+        let priority = if self.breadth_first {
+            (earliness, weight as u64)
+        } else {
+            (weight as u64, earliness)
+        };
+        let order = self.pushed;
+        self.queue.push(Entry { priority, order });
+        self.ages.push_back(order);
+        self.waiting.insert(
+            order,
+            Waiting {
+                rule,
+                weight,
+                round,
+            },
+        );
+        // Breadth-first agendas take rules by age anyway
+        self.weighted |= weight > 0 && !self.breadth_first;
+        self.pushed += 1;
+    }
+
+    /// Order of the next rule to take
+    fn next(&mut self) -> Option<usize> {
+        while self
+            .queue
+            .peek()
+            .is_some_and(|e| !self.waiting.contains_key(&e.order))
+        {
+            self.queue.pop();
+        }
+        while self
+            .ages
+            .front()
+            .is_some_and(|o| !self.waiting.contains_key(o))
+        {
+            self.ages.pop_front();
+        }
+        if self.weighted && (self.taken + 1).is_multiple_of(FAIRNESS_INTERVAL) {
+            self.ages.front().copied()
+        } else {
+            self.queue.peek().map(|e| e.order)
+        }
+    }
+
+    /// Takes the next rule to fire, along with its weight and round
+    pub fn pop(&mut self) -> Option<(InnerRule, u32, usize)> {
+        let order = self.next()?;
+        self.taken += 1;
+        self.waiting
+            .remove(&order)
+            .map(|w| (w.rule, w.weight, w.round))
+    }
+
+    /// Round of the next rule to fire
+    pub fn round(&mut self) -> Option<usize> {
+        let order = self.next()?;
+        self.waiting.get(&order).map(|w| w.round)
+    }
+
+    /// Whether the next rule to fire reaches a new multiple of `every` rounds, which
+    /// is only reported once
+    pub fn reaches_checkpoint(&mut self, every: usize) -> bool {
+        match self.round() {
+            Some(round) if round >= self.checkpointed + every => {
+                self.checkpointed = round - round % every;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Agenda, FAIRNESS_INTERVAL};
+    use crate::ast::{Atom, Rule};
+    use crate::{Identifier, Parser, Sniffer};
+    use logos_nom_bridge::Tokens;
+
+    fn fact(symbol: usize) -> Rule<Identifier> {
+        Rule {
+            premises: vec![],
+            conclusion: Atom {
+                symbol: Identifier::Function(symbol),
+                parameters: vec![],
+            },
+        }
+    }
+
+    #[test]
+    fn starvation_test() {
+        // A heavier rule is added every time one is taken, yet every rule added
+        // before it is taken within the bound
+        let mut agenda = Agenda::default();
+        for light in 0..4 {
+            agenda.push(fact(light), 0, 0)
+        }
+        agenda.push(fact(4), 1, 0);
+        let mut taken = vec![];
+        for step in 0..4 * FAIRNESS_INTERVAL {
+            let (rule, weight, round) = agenda.pop().unwrap();
+            taken.push(rule.conclusion.symbol);
+            agenda.push(fact(5 + step), weight + 1, round + 1);
+        }
+        for light in 0..4 {
+            assert!(taken.contains(&Identifier::Function(light)))
+        }
+
+        // A rule generating ever heavier facts no longer starves the others
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "[weight=5] n(z, a).\n[weight=5] n(X, Y) => n(s(X), Y).\n\
+                 e(a, b). e(b, c).\ne(X, Y) => r(X, Y).\nr(X, Y) /\\ e(Y, Z) => r(X, Z).",
+            )
+            .unwrap();
+        assert!(!sniffer.saturate_with_fuel(200));
+        let reached = |source: &str| {
+            let atom = Parser::parse_query(Tokens::new(source)).unwrap();
+            sniffer.iter_facts().any(|f| f == atom)
+        };
+        assert!(reached("r(a, c)."));
+        assert!(reached("n(s(s(s(z))), a)."));
+    }
+
+    #[test]
+    fn unweighted_order_test() {
+        // Without weights, rules are still taken round by round, most recent first
+        let mut agenda = Agenda::default();
+        for symbol in 0..FAIRNESS_INTERVAL {
+            agenda.push(fact(symbol), 0, 0)
+        }
+        agenda.push(fact(FAIRNESS_INTERVAL), 0, 1);
+        let order: Vec<_> = std::iter::from_fn(|| agenda.pop())
+            .map(|(rule, _, round)| (rule.conclusion.symbol, round))
+            .collect();
+        let expected: Vec<_> = (0..FAIRNESS_INTERVAL)
+            .rev()
+            .map(|s| (Identifier::Function(s), 0))
+            .chain([(Identifier::Function(FAIRNESS_INTERVAL), 1)])
+            .collect();
+        assert_eq!(order, expected);
+    }
+}
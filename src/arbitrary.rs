@@ -0,0 +1,157 @@
+//! Arbitrary module
+//! Structured generation of AST values from raw bytes, for fuzzing the parser,
+//! the unifier and the saturation loop. The API mirrors the `arbitrary` crate
+//! (an `Unstructured` byte source consumed by an `Arbitrary` trait), with bounds
+//! on the shape of generated values.
+//!
+//! Generated names always follow the surface syntax (lowercase constants,
+//! uppercase variables), so generated rules can be printed and parsed back
+
+use crate::ast::{Atom, Rule, Term};
+
+/// A source of raw bytes driving generation. Once exhausted, it keeps yielding
+/// zeros so that generation always terminates
+pub struct Unstructured<'a> {
+    data: &'a [u8],
+}
+impl<'a> Unstructured<'a> {
+    pub fn new(data: &'a [u8]) -> Unstructured<'a> {
+        Unstructured { data }
+    }
+
+    /// Returns `true` if every byte has been consumed
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn byte(&mut self) -> u8 {
+        if let Some((first, rest)) = self.data.split_first() {
+            self.data = rest;
+            *first
+        } else {
+            0
+        }
+    }
+
+    /// Returns an integer in `0..=max`
+    pub fn int_in_range(&mut self, max: usize) -> usize {
+        if max == 0 {
+            return 0;
+        }
+        let mut value = 0usize;
+        let mut range = 1usize;
+        while range <= max {
+            value = (value << 8) | self.byte() as usize;
+            range = range.saturating_mul(256);
+        }
+        value % (max + 1)
+    }
+
+    /// Returns `true` with probability one half
+    pub fn bool(&mut self) -> bool {
+        self.byte() & 1 == 1
+    }
+}
+
+/// Bounds on the shape of generated values
+#[derive(Clone, Debug)]
+pub struct Bounds {
+    /// Maximum nesting depth of terms
+    pub max_depth: usize,
+    /// Maximum number of parameters of functions and atoms
+    pub max_arity: usize,
+    /// Maximum number of premises of rules
+    pub max_premises: usize,
+    /// Number of distinct function, predicate and variable names to pick from
+    pub symbols: usize,
+}
+impl Default for Bounds {
+    fn default() -> Self {
+        Bounds {
+            max_depth: 3,
+            max_arity: 3,
+            max_premises: 3,
+            symbols: 4,
+        }
+    }
+}
+
+/// Values that can be generated from raw bytes
+pub trait Arbitrary: Sized {
+    fn arbitrary(u: &mut Unstructured, bounds: &Bounds) -> Self;
+}
+
+fn arbitrary_term(u: &mut Unstructured, bounds: &Bounds, depth: usize) -> Term<String> {
+    let last = bounds.symbols.saturating_sub(1);
+    if depth >= bounds.max_depth || u.bool() {
+        Term::Variable {
+            symbol: format!("X{}", u.int_in_range(last)),
+        }
+    } else {
+        let arity = u.int_in_range(bounds.max_arity);
+        Term::Function {
+            symbol: format!("f{}", u.int_in_range(last)),
+            parameters: (0..arity)
+                .map(|_| arbitrary_term(u, bounds, depth + 1))
+                .collect(),
+        }
+    }
+}
+
+impl Arbitrary for Term<String> {
+    fn arbitrary(u: &mut Unstructured, bounds: &Bounds) -> Self {
+        arbitrary_term(u, bounds, 0)
+    }
+}
+
+impl Arbitrary for Atom<String> {
+    fn arbitrary(u: &mut Unstructured, bounds: &Bounds) -> Self {
+        let arity = u.int_in_range(bounds.max_arity);
+        Atom {
+            symbol: format!("p{}", u.int_in_range(bounds.symbols.saturating_sub(1))),
+            parameters: (0..arity).map(|_| arbitrary_term(u, bounds, 1)).collect(),
+        }
+    }
+}
+
+impl Arbitrary for Rule<String> {
+    fn arbitrary(u: &mut Unstructured, bounds: &Bounds) -> Self {
+        let premises = u.int_in_range(bounds.max_premises);
+        Rule {
+            premises: (0..premises).map(|_| Atom::arbitrary(u, bounds)).collect(),
+            conclusion: Atom::arbitrary(u, bounds),
+        }
+    }
+}
+
+/// Generates rules until the bytes are exhausted
+pub fn arbitrary_rules(u: &mut Unstructured, bounds: &Bounds) -> Vec<Rule<String>> {
+    let mut rules = vec![];
+    while !u.is_empty() {
+        rules.push(Rule::arbitrary(u, bounds))
+    }
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Parser;
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn round_trip_test() {
+        let data: Vec<u8> = (0..4096u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let rules = arbitrary_rules(&mut Unstructured::new(&data), &Bounds::default());
+        assert!(!rules.is_empty());
+
+        // Variables appearing once are printed as `_`, so rules only round trip up to renaming
+        let source: String = rules.iter().map(|r| format!("{r}.\n")).collect();
+        let canonical =
+            |rules: Vec<Rule<String>>| -> Vec<_> { rules.iter().map(Rule::canonical).collect() };
+        assert_eq!(
+            canonical(Parser::parse_rules(Tokens::new(&source)).unwrap()),
+            canonical(rules)
+        );
+    }
+}
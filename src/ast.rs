@@ -1,9 +1,12 @@
 //! AST module
 //! High level representation of the constructs used in `.pif` files
+use crate::hashing::HashMap;
+use crate::lexer::{write_applied_symbol, write_symbol};
+use crate::macros::Macro;
+use crate::operators::Operator;
 use crate::resolution::Selection;
 use crate::Identifier;
 use ptree::{Color, Style};
-use rustc_hash::FxHashMap;
 use std::fmt::Debug;
 use std::hash::Hash;
 
@@ -22,8 +25,8 @@ impl<T: Clone + Hash + Eq + PartialEq + Debug> Term<T> {
             Term::Function { symbol, .. } => symbol,
         }
     }
-    /// Applies a valuation of the variables to this rule
-    pub fn apply(&self, bindings: &FxHashMap<Term<T>, Term<T>>) -> Term<T> {
+    /// Applies a valuation of the variables to this term
+    pub(crate) fn substitute(&self, bindings: &HashMap<Term<T>, Term<T>>) -> Term<T> {
         match self {
             Term::Variable { .. } => {
                 if let Some(binding) = bindings.get(self) {
@@ -34,11 +37,7 @@ impl<T: Clone + Hash + Eq + PartialEq + Debug> Term<T> {
             }
             Term::Function { symbol, parameters } => Term::Function {
                 symbol: symbol.clone(),
-                parameters: parameters
-                    .iter()
-                    .cloned()
-                    .map(|t| t.apply(bindings))
-                    .collect(),
+                parameters: parameters.iter().map(|t| t.substitute(bindings)).collect(),
             },
         }
     }
@@ -47,8 +46,18 @@ impl<T: Clone + Hash + Eq + PartialEq + Debug> Term<T> {
         matches!(self, Term::Variable { .. })
     }
 
+    /// Checks if this term holds no variable
+    pub fn is_ground(&self) -> bool {
+        match self {
+            Term::Variable { .. } => false,
+            Term::Function { parameters, .. } => parameters.iter().all(Term::is_ground),
+        }
+    }
+
     pub fn contains_variable(&self, variable: &Term<T>) -> bool {
-        let Term::Variable {symbol} = variable else { panic!("Expected variable")};
+        let Term::Variable { symbol } = variable else {
+            panic!("Expected variable")
+        };
         match self {
             Term::Function { parameters, .. } => {
                 parameters.iter().any(|t| t.contains_variable(variable))
@@ -56,6 +65,73 @@ impl<T: Clone + Hash + Eq + PartialEq + Debug> Term<T> {
             Term::Variable { symbol: v } => v == symbol,
         }
     }
+
+    /// Computes a value bottom-up: variables are mapped by `variable`, and functions
+    /// by `function` from their symbol and the values of their parameters
+    pub fn fold<'a, R>(
+        &'a self,
+        mut variable: impl FnMut(&'a T) -> R,
+        mut function: impl FnMut(&'a T, Vec<R>) -> R,
+    ) -> R {
+        fn fold<'a, T, R>(
+            term: &'a Term<T>,
+            variable: &mut impl FnMut(&'a T) -> R,
+            function: &mut impl FnMut(&'a T, Vec<R>) -> R,
+        ) -> R {
+            match term {
+                Term::Variable { symbol } => variable(symbol),
+                Term::Function { symbol, parameters } => {
+                    let values = parameters
+                        .iter()
+                        .map(|p| fold(p, variable, function))
+                        .collect();
+                    function(symbol, values)
+                }
+            }
+        }
+        fold(self, &mut variable, &mut function)
+    }
+
+    /// Distinct variables of this term, in order of first occurrence
+    pub fn vars(&self) -> Vec<&T> {
+        distinct(self.fold(|v| vec![v], |_, values| values.concat()))
+    }
+
+    /// Distinct constants of this term, that is functions without parameters, in
+    /// order of first occurrence
+    pub fn constants(&self) -> Vec<&T> {
+        distinct(self.fold(
+            |_| vec![],
+            |symbol, values| {
+                if values.is_empty() {
+                    vec![symbol]
+                } else {
+                    values.concat()
+                }
+            },
+        ))
+    }
+
+    /// Number of nested levels of this term, variables and constants being at depth 1
+    pub fn depth(&self) -> usize {
+        self.fold(|_| 1, |_, depths| 1 + depths.into_iter().max().unwrap_or(0))
+    }
+
+    /// Number of symbols of this term, variables included
+    pub fn size(&self) -> usize {
+        self.fold(|_| 1, |_, sizes| 1 + sizes.into_iter().sum::<usize>())
+    }
+}
+
+/// Keeps the first occurrence of every symbol
+fn distinct<T: PartialEq>(symbols: Vec<&T>) -> Vec<&T> {
+    let mut kept: Vec<&T> = vec![];
+    for symbol in symbols {
+        if !kept.contains(&symbol) {
+            kept.push(symbol)
+        }
+    }
+    kept
 }
 /// Allows transformation of Atoms to Terms seamlessly
 impl<T> From<Atom<T>> for Term<T> {
@@ -70,11 +146,12 @@ impl<T: std::fmt::Display> std::fmt::Display for Term<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Term::Function { symbol, parameters } if parameters.is_empty() => {
-                write!(f, "{symbol}")
+                write_symbol(f, &symbol.to_string())
             }
             Term::Function { symbol, parameters } => {
                 let parameters_pp = format_vec(parameters, ", ");
-                write!(f, "{symbol}({parameters_pp})")
+                write_applied_symbol(f, &symbol.to_string())?;
+                write!(f, "({parameters_pp})")
             }
             Term::Variable { symbol: value } => {
                 write!(f, "{value}")
@@ -93,19 +170,23 @@ pub struct Atom<T> {
     pub parameters: Vec<Term<T>>,
 }
 impl<T: Clone + Hash + Eq + PartialEq + Debug> Atom<T> {
-    /// Applies a valuation of the variables to this rule
-    pub fn apply(&self, bindings: &FxHashMap<Term<T>, Term<T>>) -> Atom<T> {
+    /// Applies a valuation of the variables to this atom
+    pub(crate) fn substitute(&self, bindings: &HashMap<Term<T>, Term<T>>) -> Atom<T> {
         Atom {
             symbol: self.symbol.clone(),
             parameters: self
                 .parameters
                 .iter()
-                .cloned()
-                .map(|t| t.apply(bindings))
+                .map(|t| t.substitute(bindings))
                 .collect(),
         }
     }
 
+    /// Checks if this atom holds no variable
+    pub fn is_ground(&self) -> bool {
+        self.parameters.iter().all(Term::is_ground)
+    }
+
     pub fn contains_variable(&self, variable: &Term<T>) -> bool {
         self.parameters
             .iter()
@@ -136,11 +217,13 @@ impl<T: std::fmt::Display> std::fmt::Display for Atom<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let Atom { symbol, parameters } = self;
         if parameters.is_empty() {
-            write!(f, "{symbol}")
+            write_symbol(f, &symbol.to_string())?;
         } else {
+            write_applied_symbol(f, &symbol.to_string())?;
             let parameters_pp = format_vec(parameters, ", ");
-            write!(f, "{symbol}({parameters_pp})")
+            write!(f, "({parameters_pp})")?;
         }
+        Ok(())
     }
 }
 
@@ -153,25 +236,140 @@ pub struct Rule<T> {
 }
 impl<T: Clone + Hash + Eq + PartialEq + Debug> Rule<T> {
     /// Applies a valuation of the variables to this rule
-    pub fn apply(&self, bindings: &FxHashMap<Term<T>, Term<T>>) -> Rule<T> {
+    pub(crate) fn substitute(&self, bindings: &HashMap<Term<T>, Term<T>>) -> Rule<T> {
         Rule {
-            conclusion: self.conclusion.apply(bindings),
+            conclusion: self.conclusion.substitute(bindings),
             premises: self
                 .premises
                 .iter()
-                .cloned()
-                .map(|a| a.apply(bindings))
+                .map(|a| a.substitute(bindings))
                 .collect(),
         }
     }
 }
 
+/// Renames variables in order of appearance
+struct Canonical {
+    names: HashMap<String, String>,
+}
+impl Folder<String> for Canonical {
+    fn fold_variable(&mut self, symbol: String) -> Term<String> {
+        let count = self.names.len();
+        // Sigil variables keep their sigil, so that both conventions print back
+        let name = self.names.entry(symbol).or_insert_with_key(|symbol| {
+            if symbol.starts_with('?') {
+                format!("?x{count}")
+            } else {
+                format!("X{count}")
+            }
+        });
+        Term::Variable {
+            symbol: name.clone(),
+        }
+    }
+}
+impl Rule<String> {
+    /// Renames variables in order of appearance (`X0`, `X1`, ...), premises first,
+    /// so that rules equal up to variable renaming have the same canonical form
+    pub fn canonical(&self) -> Rule<String> {
+        Canonical {
+            names: HashMap::default(),
+        }
+        .fold_rule(self.clone())
+    }
+
+    /// Returns the rule as stored: repeated premises are dropped, the others are
+    /// sorted by predicate and shape, then variables are renamed as in
+    /// `canonical`. Premises being a conjunction, the rule keeps its meaning, and
+    /// rules equal up to variable renaming and premise order have the same normal
+    /// form, unless premises only differ by the names of their variables
+    pub fn normalized(&self) -> Rule<String> {
+        /// Names every variable `_`, hiding how premises share variables
+        struct Shape;
+        impl Folder<String> for Shape {
+            fn fold_variable(&mut self, _: String) -> Term<String> {
+                Term::Variable {
+                    symbol: String::from("_"),
+                }
+            }
+        }
+        let mut premises: Vec<Atom<String>> = Vec::with_capacity(self.premises.len());
+        for premise in &self.premises {
+            if !premises.contains(premise) {
+                premises.push(premise.clone())
+            }
+        }
+        premises.sort_by_cached_key(|p| Shape.fold_atom(p.clone()).to_string());
+        Rule {
+            premises,
+            conclusion: self.conclusion.clone(),
+        }
+        .canonical()
+    }
+}
+
 impl<T: std::fmt::Display> Rule<T> {
+    /// Returns the rule as printed, where variables appearing only once are
+    /// throwaway variables, rendered as `_`
+    pub(crate) fn displayed(&self) -> Rule<String> {
+        fn name_term<T: std::fmt::Display>(term: &Term<T>) -> Term<String> {
+            match term {
+                Term::Variable { symbol } => Term::Variable {
+                    symbol: symbol.to_string(),
+                },
+                Term::Function { symbol, parameters } => Term::Function {
+                    symbol: symbol.to_string(),
+                    parameters: parameters.iter().map(name_term).collect(),
+                },
+            }
+        }
+        fn count(term: &Term<String>, occurrences: &mut HashMap<String, usize>) {
+            match term {
+                Term::Variable { symbol } => *occurrences.entry(symbol.clone()).or_default() += 1,
+                Term::Function { parameters, .. } => {
+                    parameters.iter().for_each(|t| count(t, occurrences))
+                }
+            }
+        }
+        fn hide(term: &mut Term<String>, occurrences: &HashMap<String, usize>) {
+            match term {
+                Term::Variable { symbol } if occurrences[symbol] == 1 => {
+                    *symbol = String::from("_")
+                }
+                Term::Variable { .. } => (),
+                Term::Function { parameters, .. } => {
+                    parameters.iter_mut().for_each(|t| hide(t, occurrences))
+                }
+            }
+        }
+
+        let name_atom = |atom: &Atom<T>| Atom {
+            symbol: atom.symbol.to_string(),
+            parameters: atom.parameters.iter().map(name_term).collect(),
+        };
+        let mut rule = Rule {
+            premises: self.premises.iter().map(name_atom).collect(),
+            conclusion: name_atom(&self.conclusion),
+        };
+        let mut occurrences = HashMap::default();
+        for atom in rule.premises.iter().chain([&rule.conclusion]) {
+            atom.parameters
+                .iter()
+                .for_each(|t| count(t, &mut occurrences))
+        }
+        for atom in rule.premises.iter_mut().chain([&mut rule.conclusion]) {
+            atom.parameters
+                .iter_mut()
+                .for_each(|t| hide(t, &occurrences))
+        }
+        rule
+    }
+
     pub fn selection_empathized_string(&self, selection: Selection<String>) -> String {
         let Rule {
             premises,
             conclusion,
-        } = self;
+        } = self.displayed();
 
         let empathize_style = Style {
             foreground: Some(Color::Red),
@@ -184,7 +382,7 @@ impl<T: std::fmt::Display> Rule<T> {
                 if premises.is_empty() {
                     format!("{}", empathize_style.paint(conclusion))
                 } else {
-                    let premises_pp = format_vec(premises, " /\\ ");
+                    let premises_pp = format_vec(&premises, " /\\ ");
                     format!("{premises_pp} => {}", empathize_style.paint(conclusion))
                 }
             }
@@ -192,7 +390,7 @@ impl<T: std::fmt::Display> Rule<T> {
                 if premises.is_empty() {
                     format!("{conclusion}")
                 } else {
-                    let premises_pp = format_vec_empathize(premises, " /\\ ", i, empathize_style);
+                    let premises_pp = format_vec_empathize(&premises, " /\\ ", i, empathize_style);
                     format!("{premises_pp} => {conclusion}")
                 }
             }
@@ -204,16 +402,213 @@ impl<T: std::fmt::Display> std::fmt::Display for Rule<T> {
         let Rule {
             premises,
             conclusion,
-        } = self;
+        } = self.displayed();
         if premises.is_empty() {
             write!(f, "{conclusion}")
         } else {
-            let premises_pp = format_vec(premises, " /\\ ");
+            let premises_pp = format_vec(&premises, " /\\ ");
             write!(f, "{premises_pp} => {conclusion}")
         }
     }
 }
 
+/// Attributes of a rule, written `[name=value, ...]` before it
+#[derive(Debug, Clone, Default, Hash, Eq, PartialEq)]
+pub struct Attributes {
+    /// Priority of the rule during saturation, rules of higher weight fire first
+    pub weight: u32,
+    /// Priority of a defeasible rule over conflicting ones
+    pub priority: u32,
+    /// Cost of using the rule in a derivation, see `provenance::Tropical`
+    pub cost: Option<u32>,
+    /// Probability of the rule holding, written `<probability> :: <rule>`
+    pub probability: Option<Probability>,
+    /// Whether the rule is inlined into the rules using its conclusion, see
+    /// `transform::unfold`
+    pub inline: bool,
+}
+
+/// A probability, between 0 and 1
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Probability(f64);
+impl Probability {
+    pub fn new(p: f64) -> Option<Probability> {
+        (0.0..=1.0).contains(&p).then_some(Probability(p))
+    }
+
+    pub fn value(&self) -> f64 {
+        self.0
+    }
+}
+// Probabilities are never NaN, so they can be compared and hashed bitwise
+impl Eq for Probability {}
+impl Hash for Probability {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state)
+    }
+}
+
+/// Represents the top level statements of `.pif` files
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub enum Statement<T> {
+    Rule(Rule<T>, Attributes),
+    /// `#expect <atom>.`, asserting that the atom is derivable
+    Expect(Atom<T>),
+    /// `#expect_not <atom>.`, asserting that the atom is not derivable
+    ExpectNot(Atom<T>),
+    /// `?- <atom>.`, asking for every derivable instance of the atom
+    Goal(Atom<T>),
+    /// `op <precedence> <type> <symbol>.`, declaring an infix operator
+    Operator(Operator),
+    /// `<atom> <~ <atom> /\ ... /\ <atom>.`, a rule holding unless defeated
+    Defeasible(Defeasible<T>),
+    /// `next <atom> /\ ... /\ <atom> => <atom>.`, a rule whose premises hold at some time
+    /// step and whose conclusion holds at the next one, see the `temporal` module
+    Next(Rule<T>),
+    /// `#define <name>(<parameters>) <statement>.`, defining a macro
+    Macro(Macro),
+    /// `#horizon <natural>.`, the number of time steps to evaluate `next` rules over
+    Horizon(usize),
+    /// `output <predicate>.`, asking for the facts of the predicate to be output, see
+    /// the `output` module
+    Output(String),
+    /// `mode <predicate>(<+|->, ...).`, declaring which parameters of a predicate are
+    /// usually bound in queries
+    Mode(Mode),
+    /// `constraint not (<atom> && ... && <atom>).`, a conjunction that should never
+    /// hold, see the `consistency` module
+    Constraint(Vec<Atom<T>>),
+}
+
+/// A defeasible rule, whose conclusion holds unless a conflicting defeasible rule of
+/// higher priority concludes its negation (written `~<atom>`), or the negation of a
+/// conclusion is strictly derivable
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct Defeasible<T> {
+    pub rule: Rule<T>,
+    /// Whether the rule concludes the negation of its conclusion
+    pub negated: bool,
+    pub priority: u32,
+}
+impl<T: std::fmt::Display> std::fmt::Display for Defeasible<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Rule {
+            premises,
+            conclusion,
+        } = self.rule.displayed();
+        if self.priority > 0 {
+            write!(f, "[priority={}] ", self.priority)?;
+        }
+        if self.negated {
+            write!(f, "~")?;
+        }
+        write!(f, "{conclusion} <~ {}", format_vec(&premises, " /\\ "))
+    }
+}
+
+/// Parameters of a predicate that queries usually bind (`+`) or leave free (`-`).
+/// Facts of the predicate are indexed on their bound parameters, and premises
+/// binding them are matched first
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct Mode {
+    pub predicate: String,
+    pub bound: Vec<bool>,
+}
+impl std::fmt::Display for Mode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let modes: Vec<&str> = self
+            .bound
+            .iter()
+            .map(|bound| if *bound { "+" } else { "-" })
+            .collect();
+        write!(f, "mode {}({})", self.predicate, modes.join(", "))
+    }
+}
+
+/// Traversal of rules, atoms and terms. Every method visits the children of its
+/// node by default, so implementations only override the nodes they care about
+pub trait Visitor<T> {
+    fn visit_rule(&mut self, rule: &Rule<T>) {
+        for premise in &rule.premises {
+            self.visit_atom(premise)
+        }
+        self.visit_atom(&rule.conclusion)
+    }
+
+    fn visit_atom(&mut self, atom: &Atom<T>) {
+        self.visit_predicate(&atom.symbol);
+        for parameter in &atom.parameters {
+            self.visit_term(parameter)
+        }
+    }
+
+    fn visit_predicate(&mut self, _symbol: &T) {}
+
+    fn visit_term(&mut self, term: &Term<T>) {
+        match term {
+            Term::Variable { symbol } => self.visit_variable(symbol),
+            Term::Function { symbol, parameters } => self.visit_function(symbol, parameters),
+        }
+    }
+
+    fn visit_variable(&mut self, _symbol: &T) {}
+
+    fn visit_function(&mut self, _symbol: &T, parameters: &[Term<T>]) {
+        for parameter in parameters {
+            self.visit_term(parameter)
+        }
+    }
+}
+
+/// Rewriting of rules, atoms and terms. Every method rebuilds its node
+/// from its folded children by default, so implementations only override the
+/// nodes they rewrite
+pub trait Folder<T> {
+    fn fold_rule(&mut self, rule: Rule<T>) -> Rule<T> {
+        Rule {
+            premises: rule
+                .premises
+                .into_iter()
+                .map(|a| self.fold_atom(a))
+                .collect(),
+            conclusion: self.fold_atom(rule.conclusion),
+        }
+    }
+
+    fn fold_atom(&mut self, atom: Atom<T>) -> Atom<T> {
+        Atom {
+            symbol: self.fold_predicate(atom.symbol),
+            parameters: atom
+                .parameters
+                .into_iter()
+                .map(|t| self.fold_term(t))
+                .collect(),
+        }
+    }
+
+    fn fold_predicate(&mut self, symbol: T) -> T {
+        symbol
+    }
+
+    fn fold_term(&mut self, term: Term<T>) -> Term<T> {
+        match term {
+            Term::Variable { symbol } => self.fold_variable(symbol),
+            Term::Function { symbol, parameters } => self.fold_function(symbol, parameters),
+        }
+    }
+
+    fn fold_variable(&mut self, symbol: T) -> Term<T> {
+        Term::Variable { symbol }
+    }
+
+    fn fold_function(&mut self, symbol: T, parameters: Vec<Term<T>>) -> Term<T> {
+        Term::Function {
+            symbol,
+            parameters: parameters.into_iter().map(|t| self.fold_term(t)).collect(),
+        }
+    }
+}
+
 /// Helper function to pretty print vectors
 fn format_vec<T: std::fmt::Display>(v: &[T], sep: &str) -> String {
     v.iter()
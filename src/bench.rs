@@ -0,0 +1,260 @@
+//! Bench module
+//! Generators of standard scaling families, giving performance work on the
+//! saturation engine reproducible workloads of any size:
+//! - `transitive_closure`, paths over a random directed acyclic graph,
+//! - `same_generation`, pairs of nodes at the same depth of a random tree,
+//! - `n_queens`, placements of queens on a chessboard, row by row.
+//!
+//! Random choices are drawn from a seeded splitmix64 generator, so that a family,
+//! a size and a seed always give the same rules in the same order. Rules are plain
+//! ASTs, to be loaded with `Sniffer::from_rules` or printed as `.pif` sources
+use crate::ast::{Atom, Rule, Term};
+use std::collections::BTreeSet;
+
+/// Small deterministic pseudo-random generator (splitmix64)
+struct Rng(u64);
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns an integer in `0..max`, `max` being positive
+    fn below(&mut self, max: usize) -> usize {
+        (self.next_u64() % max as u64) as usize
+    }
+}
+
+/// Families of generated workloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    TransitiveClosure,
+    SameGeneration,
+    NQueens,
+}
+impl Family {
+    pub const ALL: [Family; 3] = [
+        Family::TransitiveClosure,
+        Family::SameGeneration,
+        Family::NQueens,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Family::TransitiveClosure => "transitive-closure",
+            Family::SameGeneration => "same-generation",
+            Family::NQueens => "n-queens",
+        }
+    }
+
+    /// Rules of the family for size `n`. Random graphs have `2n` edges, and
+    /// queens ignore the seed
+    pub fn rules(&self, n: usize, seed: u64) -> Vec<Rule<String>> {
+        match self {
+            Family::TransitiveClosure => transitive_closure(n, 2 * n, seed),
+            Family::SameGeneration => same_generation(n, seed),
+            Family::NQueens => n_queens(n),
+        }
+    }
+}
+
+fn constant(symbol: String) -> Term<String> {
+    Term::Function {
+        symbol,
+        parameters: vec![],
+    }
+}
+
+fn variable(symbol: &str) -> Term<String> {
+    Term::Variable {
+        symbol: symbol.to_string(),
+    }
+}
+
+fn atom(symbol: &str, parameters: Vec<Term<String>>) -> Atom<String> {
+    Atom {
+        symbol: symbol.to_string(),
+        parameters,
+    }
+}
+
+fn fact(conclusion: Atom<String>) -> Rule<String> {
+    Rule {
+        premises: vec![],
+        conclusion,
+    }
+}
+
+fn node(i: usize) -> Term<String> {
+    constant(format!("v{i}"))
+}
+
+/// `edge` facts of a random directed acyclic graph over nodes `v0` to `v<n-1>`,
+/// with `path` concluded by chains of edges. There are `edges` distinct edges, or
+/// all possible ones if there are fewer, each going from a node to a later one:
+/// known facts being resolved again when derived again, saturation does not
+/// terminate over cycles
+pub fn transitive_closure(n: usize, edges: usize, seed: u64) -> Vec<Rule<String>> {
+    let mut rng = Rng(seed);
+    let edges = edges.min(n * n.saturating_sub(1) / 2);
+    let mut chosen = BTreeSet::new();
+    let mut rules = vec![];
+    while chosen.len() < edges {
+        let (a, b) = (rng.below(n), rng.below(n));
+        let (from, to) = (a.min(b), a.max(b));
+        if from != to && chosen.insert((from, to)) {
+            rules.push(fact(atom("edge", vec![node(from), node(to)])))
+        }
+    }
+    let [x, y, z] = ["X", "Y", "Z"].map(variable);
+    rules.push(Rule {
+        premises: vec![atom("edge", vec![x.clone(), y.clone()])],
+        conclusion: atom("path", vec![x.clone(), y.clone()]),
+    });
+    rules.push(Rule {
+        premises: vec![
+            atom("path", vec![x.clone(), y.clone()]),
+            atom("edge", vec![y, z.clone()]),
+        ],
+        conclusion: atom("path", vec![x, z]),
+    });
+    rules
+}
+
+/// `par(child, parent)` facts of a random tree over nodes `v0` to `v<n-1>`, rooted
+/// in `v0`, with `sg` concluded for pairs of nodes at the same generation, siblings
+/// being the first ones. Premises are binary, as unary premises over a variable
+/// are never selected
+pub fn same_generation(n: usize, seed: u64) -> Vec<Rule<String>> {
+    let mut rng = Rng(seed);
+    let mut rules = vec![];
+    for child in 1..n {
+        let parent = rng.below(child);
+        rules.push(fact(atom("par", vec![node(child), node(parent)])))
+    }
+    let [x, y, a, b] = ["X", "Y", "A", "B"].map(variable);
+    rules.push(Rule {
+        premises: vec![
+            atom("par", vec![x.clone(), a.clone()]),
+            atom("par", vec![y.clone(), a.clone()]),
+        ],
+        conclusion: atom("sg", vec![x.clone(), y.clone()]),
+    });
+    rules.push(Rule {
+        premises: vec![
+            atom("par", vec![x.clone(), a.clone()]),
+            atom("sg", vec![a, b.clone()]),
+            atom("par", vec![y.clone(), b]),
+        ],
+        conclusion: atom("sg", vec![x, y]),
+    });
+    rules
+}
+
+/// Placements of `n` queens on an `n`×`n` board, one `solution(C1, ..., Cn)` per
+/// placement, `Ci` being the column `c<j>` of the queen of row `i`. Queens are
+/// placed row by row, `queens<k>` holding the placements of the first `k` rows,
+/// and `safe(A, B, d<k>)` holds when queens in columns `A` and `B`, `k` rows
+/// apart, do not attack each other
+pub fn n_queens(n: usize) -> Vec<Rule<String>> {
+    let column = |i: usize| constant(format!("c{i}"));
+    let distance = |k: usize| constant(format!("d{k}"));
+    let placed = |k: usize| match k == n {
+        true => String::from("solution"),
+        false => format!("queens{k}"),
+    };
+    let mut rules: Vec<_> = (1..=n)
+        .map(|i| fact(atom(&placed(1), vec![column(i)])))
+        .collect();
+    for a in 1..=n {
+        for b in (1..=n).filter(|b| *b != a) {
+            for k in (1..n).filter(|k| a.abs_diff(b) != *k) {
+                rules.push(fact(atom("safe", vec![column(a), column(b), distance(k)])))
+            }
+        }
+    }
+
+    let columns: Vec<_> = (1..=n).map(|i| variable(&format!("C{i}"))).collect();
+    for k in 1..n {
+        let mut premises = vec![atom(&placed(k), columns[..k].to_vec())];
+        for (i, queen) in columns[..k].iter().enumerate() {
+            premises.push(atom(
+                "safe",
+                vec![queen.clone(), columns[k].clone(), distance(k - i)],
+            ))
+        }
+        rules.push(Rule {
+            premises,
+            conclusion: atom(&placed(k + 1), columns[..=k].to_vec()),
+        })
+    }
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sniffer;
+    use std::collections::HashSet;
+
+    fn count(rules: Vec<Rule<String>>, symbol: &str) -> usize {
+        let mut sniffer = Sniffer::from_rules(rules);
+        sniffer.saturate_all();
+        sniffer.iter_facts().filter(|f| f.symbol == symbol).count()
+    }
+
+    #[test]
+    fn families_test() {
+        // Generation only depends on the seed
+        assert_eq!(transitive_closure(12, 20, 3), transitive_closure(12, 20, 3));
+        assert_ne!(transitive_closure(12, 20, 3), transitive_closure(12, 20, 4));
+        assert_eq!(
+            transitive_closure(4, 100, 0)
+                .iter()
+                .filter(|r| r.premises.is_empty())
+                .count(),
+            6
+        );
+
+        // Paths match the reachability of the graph
+        let rules = transitive_closure(12, 20, 3);
+        let mut reachable = HashSet::new();
+        for rule in rules.iter().filter(|r| r.premises.is_empty()) {
+            let [from, to] = &rule.conclusion.parameters[..] else {
+                unreachable!()
+            };
+            reachable.insert((from.clone(), to.clone()));
+        }
+        let mut closed = false;
+        while !closed {
+            let pairs: Vec<_> = reachable.iter().cloned().collect();
+            closed = true;
+            for (a, b) in &pairs {
+                for (c, d) in &pairs {
+                    closed &= b != c || !reachable.insert((a.clone(), d.clone()));
+                }
+            }
+        }
+        assert_eq!(count(rules, "path"), reachable.len());
+
+        // Every node but the root is of its own generation, and the relation is symmetric
+        let mut sniffer = Sniffer::from_rules(same_generation(10, 7));
+        sniffer.saturate_all();
+        let pairs: HashSet<_> = sniffer
+            .iter_facts()
+            .filter(|f| f.symbol == "sg")
+            .map(|f| (f.parameters[0].clone(), f.parameters[1].clone()))
+            .collect();
+        assert_eq!(pairs.iter().filter(|(x, y)| x == y).count(), 9);
+        assert!(pairs
+            .iter()
+            .all(|(x, y)| pairs.contains(&(y.clone(), x.clone()))));
+
+        for (n, solutions) in [(1, 1), (3, 0), (4, 2), (5, 10)] {
+            assert_eq!(count(Family::NQueens.rules(n, 0), "solution"), solutions);
+        }
+    }
+}
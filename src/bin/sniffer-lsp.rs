@@ -0,0 +1,4 @@
+//! Language server for `.pif` files, speaking LSP over stdio
+fn main() -> std::io::Result<()> {
+    sniffer::lsp::run()
+}
@@ -0,0 +1,183 @@
+//! Cache module
+//! Answers of `find`, kept across queries and looked up modulo variable renaming.
+//!
+//! Derivations stay valid as the rule set grows, so found answers are kept until a
+//! rule is removed. A goal that has no derivation may get one once new facts or
+//! rules are added, so its answer records the number of facts and rules of every
+//! relation the goal depends on (the relation of the goal, and those of the
+//! premises of the rules concluding a dependency), and is only reused while they
+//! are unchanged
+use crate::ast::{InnerAtom, InnerRule};
+use crate::hashing::{HashMap, HashSet};
+use crate::provenance::Provenance;
+use crate::prover::{Relation, Shape};
+use crate::{DerivationTree, SaturationFailure, Sniffer};
+
+/// A cached answer, the derivation found for the goal if any
+#[derive(Debug, Clone)]
+struct Answer {
+    derivation: Option<DerivationTree>,
+    /// Number of facts and rules of the relations the goal depends on, for goals
+    /// that have no derivation
+    support: Vec<(Relation, usize)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct QueryCache {
+    answers: HashMap<Shape, Answer>,
+    hits: usize,
+    misses: usize,
+}
+impl QueryCache {
+    pub fn clear(&mut self) {
+        self.answers.clear()
+    }
+}
+
+/// Usage of the query cache, see `Sniffer::query_cache_stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueryCacheStats {
+    /// Goals whose answer is cached
+    pub entries: usize,
+    /// Queries answered from the cache
+    pub hits: usize,
+    /// Queries that had to saturate the rule set
+    pub misses: usize,
+}
+
+impl<P: Provenance> Sniffer<P> {
+    pub fn query_cache_stats(&self) -> QueryCacheStats {
+        QueryCacheStats {
+            entries: self.queries.answers.len(),
+            hits: self.queries.hits,
+            misses: self.queries.misses,
+        }
+    }
+
+    /// Forgets every cached answer, see the `cache` module
+    pub fn clear_query_cache(&mut self) {
+        self.queries.clear()
+    }
+
+    /// Returns the cached answer of a goal, if it still holds
+    pub(crate) fn cached_answer(
+        &mut self,
+        goal: &InnerAtom,
+    ) -> Option<Result<DerivationTree, SaturationFailure>> {
+        let shape = Shape::of(goal);
+        let holds = match self.queries.answers.get(&shape) {
+            Some(Answer {
+                derivation: Some(_),
+                ..
+            }) => true,
+            Some(Answer { support, .. }) => {
+                let sizes = self.relation_sizes();
+                support
+                    .iter()
+                    .all(|(relation, size)| sizes.get(relation).copied().unwrap_or(0) == *size)
+            }
+            None => false,
+        };
+        if !holds {
+            self.queries.answers.remove(&shape);
+            self.queries.misses += 1;
+            return None;
+        }
+        self.queries.hits += 1;
+        let answer = &self.queries.answers[&shape];
+        Some(
+            answer
+                .derivation
+                .clone()
+                .ok_or(SaturationFailure::Saturated),
+        )
+    }
+
+    /// Caches the answer of a goal, unless its derivation could not be built
+    pub(crate) fn cache_answer(
+        &mut self,
+        goal: &InnerAtom,
+        answer: &Result<DerivationTree, SaturationFailure>,
+    ) {
+        let answer = match answer {
+            Ok(tree) => Answer {
+                derivation: Some(tree.clone()),
+                support: vec![],
+            },
+            Err(SaturationFailure::Saturated) => {
+                let sizes = self.relation_sizes();
+                Answer {
+                    derivation: None,
+                    support: self
+                        .dependencies(goal)
+                        .into_iter()
+                        .map(|relation| (relation, sizes.get(&relation).copied().unwrap_or(0)))
+                        .collect(),
+                }
+            }
+            Err(_) => return,
+        };
+        self.queries.answers.insert(Shape::of(goal), answer);
+    }
+
+    /// Relations a goal depends on, its own included
+    fn dependencies(&self, goal: &InnerAtom) -> HashSet<Relation> {
+        let relation = |atom: &InnerAtom| (atom.symbol, atom.parameters.len());
+        let mut dependencies = HashSet::from_iter([relation(goal)]);
+        let mut pending = vec![relation(goal)];
+        while let Some(dependency) = pending.pop() {
+            for InnerRule { premises, .. } in self
+                .rules
+                .iter()
+                .filter(|r| relation(&r.conclusion) == dependency)
+            {
+                for premise in premises {
+                    if dependencies.insert(relation(premise)) {
+                        pending.push(relation(premise))
+                    }
+                }
+            }
+        }
+        dependencies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Parser, QueryCacheStats, Sniffer};
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn query_cache_test() {
+        let query = |source: &str| Parser::parse_query(Tokens::new(source)).unwrap();
+        let stats = |entries, hits, misses| QueryCacheStats {
+            entries,
+            hits,
+            misses,
+        };
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert("att(k). att(enc(m, k)).\natt(enc(X, Y)) /\\ att(Y) => att(X).\nhonest(a).")
+            .unwrap();
+        let tree = sniffer.find(&query("att(X).")).unwrap();
+        assert_eq!(sniffer.find(&query("att(Y).")).unwrap(), tree);
+        assert!(sniffer.find(&query("att(n).")).is_err());
+        assert!(sniffer.find(&query("att(n).")).is_err());
+        assert_eq!(sniffer.query_cache_stats(), stats(2, 2, 2));
+
+        // Facts of unrelated relations leave negative answers alone, others do not
+        sniffer.assert("honest(b).").unwrap();
+        assert!(sniffer.find(&query("att(n).")).is_err());
+        assert_eq!(sniffer.query_cache_stats(), stats(2, 3, 2));
+        sniffer.assert("att(enc(n, k)).").unwrap();
+        assert!(sniffer.find(&query("att(n).")).is_ok());
+        assert_eq!(sniffer.query_cache_stats(), stats(2, 3, 3));
+
+        // Removing rules invalidates every answer
+        sniffer
+            .remove_rule(&Parser::parse_rules(Tokens::new("att(enc(n, k)).")).unwrap()[0])
+            .unwrap();
+        assert!(sniffer.find(&query("att(n).")).is_err());
+        assert_eq!(sniffer.query_cache_stats(), stats(1, 3, 4));
+    }
+}
@@ -0,0 +1,360 @@
+//! Checkpoint module
+//! Saves the rule set of a Sniffer to disk while it saturates, so that long
+//! saturations survive a crash or a deliberate stop. With a `CheckpointPolicy`,
+//! saturation writes a checkpoint every time the rules it fires reach a new
+//! multiple of `every_n_rounds` rounds (see the `agenda` module), and
+//! `Sniffer::resume` rebuilds a context from the last one written.
+//!
+//! Checkpoints are `.pif` sources, a statement per line: input rules with their
+//! weight and probability, expectations, goals, constraints and modes, followed by
+//! the rules derived so far. Rules are numbered in order, and a derived rule is written
+//! `derived <rule> <selection> <other> <selection> <weight> <rule>.`, giving the
+//! two rules it was resolved from with the atom selected in each, `p<i>` for the
+//! premise `i` and `c` for the conclusion, so that derivation trees survive a
+//! resume. Rules waiting to fire are not saved, resuming saturation derives them
+//! again from the rule set. Defeasible and temporal rules, declarations of the
+//! parser and output callbacks are not saved either.
+//!
+//! Checkpoints are written to a temporary file next to the path, then renamed to
+//! it, so that the last checkpoint stays whole if the process dies while writing
+use crate::agenda::Agenda;
+use crate::ast::{InnerRule, Mode, Rule, Statement};
+use crate::hashing::HashMap;
+use crate::provenance::{Derivations, Provenance};
+use crate::resolution::Selection;
+use crate::{DerivationInfo, Parser, ParserError, Sniffer};
+use logos_nom_bridge::Tokens;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// When and where saturation saves its progress, see the `checkpoint` module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckpointPolicy {
+    /// Rounds fired between two checkpoints, at least 1
+    pub every_n_rounds: usize,
+    pub path: PathBuf,
+}
+
+/// Checkpointing state of a Sniffer
+#[derive(Debug, Clone)]
+pub(crate) struct Checkpoints {
+    policy: CheckpointPolicy,
+    /// Error of the last checkpoint written, if it failed
+    error: Option<Arc<std::io::Error>>,
+}
+
+/// Reason why a checkpoint could not be resumed, see `Sniffer::resume`
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(std::io::Error),
+    /// A line of the checkpoint is not a valid statement
+    Parsing {
+        line: usize,
+        error: ParserError,
+    },
+    /// A derived rule refers to rules no earlier line introduced, or to atoms that
+    /// do not unify
+    Malformed {
+        line: usize,
+    },
+}
+impl std::fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "failed to read checkpoint: {e}"),
+            CheckpointError::Parsing { line, error } => write!(f, "line {line}: {error}"),
+            CheckpointError::Malformed { line } => {
+                write!(f, "line {line}: derivation of unknown or mismatched rules")
+            }
+        }
+    }
+}
+
+/// Encodes the atom selected in a rule
+fn selection(selection: &Selection<crate::Identifier>) -> String {
+    match selection {
+        Selection::Premise(_, i) => format!("p{i}"),
+        Selection::Conclusion(_) => String::from("c"),
+    }
+}
+
+impl<P: Provenance> Sniffer<P> {
+    /// Writes checkpoints while saturating, replacing the current policy. `None`
+    /// stops checkpointing
+    pub fn set_checkpoint_policy(&mut self, policy: Option<CheckpointPolicy>) {
+        self.checkpoints = policy.map(|policy| Checkpoints {
+            policy,
+            error: None,
+        })
+    }
+
+    pub fn checkpoint_policy(&self) -> Option<&CheckpointPolicy> {
+        self.checkpoints.as_ref().map(|c| &c.policy)
+    }
+
+    /// Error of the last checkpoint saturation wrote, if it failed
+    pub fn checkpoint_error(&self) -> Option<&std::io::Error> {
+        self.checkpoints.as_ref()?.error.as_deref()
+    }
+
+    /// Saves the rule set to the given path, see the `checkpoint` module
+    pub fn save_checkpoint(&self, path: &Path) -> std::io::Result<()> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, self.checkpoint())?;
+        std::fs::rename(&temporary, path)
+    }
+
+    /// Writes a checkpoint if the agenda reached a new multiple of the rounds
+    /// between checkpoints
+    pub(crate) fn checkpoint_if_due(&mut self, agenda: &mut Agenda) {
+        let Some(checkpoints) = &self.checkpoints else {
+            return;
+        };
+        if !agenda.reaches_checkpoint(checkpoints.policy.every_n_rounds.max(1)) {
+            return;
+        }
+        let error = self.save_checkpoint(&checkpoints.policy.path).err();
+        if let Some(checkpoints) = &mut self.checkpoints {
+            checkpoints.error = error.map(Arc::new)
+        }
+    }
+
+    /// Encodes the rule set as a checkpoint
+    fn checkpoint(&self) -> String {
+        let mut ids: HashMap<InnerRule, usize> = HashMap::default();
+        let mut lines = format!("# {}\n", self.fingerprint());
+        let input = |lines: &mut String, rule: &InnerRule| {
+            let weight = self.weight(rule);
+            if weight > 0 {
+                let _ = write!(lines, "[weight={weight}] ");
+            }
+            if let Some(probability) = self.probabilities.get(rule) {
+                let _ = write!(lines, "{probability} :: ");
+            }
+            let _ = writeln!(lines, "{}.", rule.to_string(&self.id_server).canonical());
+        };
+        for rule in self
+            .all_rules()
+            .filter(|r| !self.derived_from.contains_key(r))
+        {
+            input(&mut lines, &rule);
+            ids.insert(rule, ids.len());
+        }
+        for expectation in &self.expectations {
+            let directive = if expectation.derivable {
+                "#expect"
+            } else {
+                "#expect_not"
+            };
+            let _ = writeln!(lines, "{directive} {}.", expectation.atom);
+        }
+        for goal in &self.goals {
+            let _ = writeln!(lines, "?- {goal}.");
+        }
+        for atoms in &self.negative_constraints {
+            let atoms: Vec<_> = atoms.iter().map(|a| a.to_string()).collect();
+            let _ = writeln!(lines, "constraint not ({}).", atoms.join(" && "));
+        }
+        let mut modes: Vec<_> = self
+            .edb
+            .modes()
+            .iter()
+            .filter_map(|((symbol, _), bound)| {
+                Some(Mode {
+                    predicate: self.id_server.name_of(symbol)?.to_string(),
+                    bound: bound.clone(),
+                })
+            })
+            .collect();
+        modes.sort_by(|a, b| (&a.predicate, &a.bound).cmp(&(&b.predicate, &b.bound)));
+        for mode in modes {
+            let _ = writeln!(lines, "{mode}.");
+        }
+
+        // Derivations come after the rules they are resolved from, rules whose
+        // parents were not saved are kept as input rules
+        for (rule, info) in &self.derived_from {
+            if !self.contains(rule) {
+                continue;
+            }
+            match (ids.get(&info.rules.0), ids.get(&info.rules.1)) {
+                (Some(first), Some(second)) => {
+                    let _ = writeln!(
+                        lines,
+                        "derived {first} {} {second} {} {} {}.",
+                        selection(&info.selected_atoms.0),
+                        selection(&info.selected_atoms.1),
+                        self.weight(rule),
+                        rule.to_string(&self.id_server).canonical()
+                    );
+                }
+                _ => input(&mut lines, rule),
+            }
+            ids.insert(rule.clone(), ids.len());
+        }
+        lines
+    }
+}
+
+impl Sniffer {
+    /// Rebuilds a context from a checkpoint written by saturation or
+    /// `Sniffer::save_checkpoint`, see the `checkpoint` module. Checkpointing is
+    /// not resumed, see `Sniffer::set_checkpoint_policy`
+    pub fn resume<T: AsRef<Path>>(path: T) -> Result<Sniffer, CheckpointError> {
+        let source = std::fs::read_to_string(path).map_err(CheckpointError::Io)?;
+        let mut sniffer = Sniffer::with_provenance(Derivations);
+        let mut rules: Vec<InnerRule> = vec![];
+        for (line, text) in source.lines().enumerate().map(|(i, t)| (i + 1, t)) {
+            if let Some(derivation) = text.strip_prefix("derived ") {
+                let rule = sniffer
+                    .resume_derived(derivation, &rules, line)
+                    .map_err(|e| e.unwrap_or(CheckpointError::Malformed { line }))?;
+                rules.push(rule);
+                continue;
+            }
+            let statements = Parser::parse_statements(Tokens::new(text))
+                .map_err(|error| CheckpointError::Parsing { line, error })?;
+            for statement in statements {
+                match statement {
+                    Statement::Rule(rule, attributes) => {
+                        rules.push(sniffer.insert_input(rule, &attributes))
+                    }
+                    statement => sniffer.assert_statements(vec![statement]),
+                }
+            }
+        }
+        sniffer.publish();
+        Ok(sniffer)
+    }
+
+    /// Adds a derived rule of a checkpoint, given the rules of the earlier lines.
+    /// Fails with `None` if the derivation is malformed
+    fn resume_derived(
+        &mut self,
+        derivation: &str,
+        rules: &[InnerRule],
+        line: usize,
+    ) -> Result<InnerRule, Option<CheckpointError>> {
+        let [first, first_selection, second, second_selection, weight, rule] =
+            <[&str; 6]>::try_from(derivation.splitn(6, ' ').collect::<Vec<_>>())
+                .map_err(|_| None)?;
+        let parent = |id: &str| rules.get(id.parse::<usize>().ok()?).cloned();
+        let (first, second) = (parent(first).ok_or(None)?, parent(second).ok_or(None)?);
+        let weight: u32 = weight.parse().map_err(|_| None)?;
+        let select = |rule: &InnerRule, selection: &str| match selection {
+            "c" => Some(Selection::Conclusion(rule.conclusion.clone())),
+            _ => {
+                let i: usize = selection.strip_prefix('p')?.parse().ok()?;
+                Some(Selection::Premise(rule.premises.get(i)?.clone(), i))
+            }
+        };
+        let selected_atoms = (
+            select(&first, first_selection).ok_or(None)?,
+            select(&second, second_selection).ok_or(None)?,
+        );
+        let resolved = match &selected_atoms {
+            (Selection::Premise(premise, _), Selection::Conclusion(conclusion))
+            | (Selection::Conclusion(conclusion), Selection::Premise(premise, _)) => {
+                premise.unify(conclusion).is_some()
+            }
+            _ => false,
+        };
+        if !resolved {
+            return Err(None);
+        }
+
+        let mut parsed = Parser::parse_rules(Tokens::new(rule))
+            .map_err(|error| Some(CheckpointError::Parsing { line, error }))?;
+        let rule: Rule<String> = match parsed.len() {
+            1 => parsed.remove(0),
+            _ => return Err(None),
+        };
+        let derived = rule.to_inner(&mut self.id_server);
+        if !self.contains(&derived) {
+            self.derived_from.insert(
+                derived.clone(),
+                DerivationInfo {
+                    rules: (first, second),
+                    selected_atoms,
+                },
+            );
+            if weight > 0 {
+                self.weights.insert(derived.clone(), weight);
+            }
+            self.insert_rule(derived.clone());
+        }
+        Ok(derived)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CheckpointError, CheckpointPolicy};
+    use crate::{Parser, Rule, Sniffer};
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn checkpoint_test() {
+        let path =
+            std::env::temp_dir().join(format!("sniffer-checkpoint-{}.pif", std::process::id()));
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "[weight=2] edge(a, b). edge(b, c). edge(c, d). 0.5 :: edge(d, e).\n\
+                 edge(X, Y) => path(X, Y).\npath(X, Y) /\\ edge(Y, Z) => path(X, Z).\n\
+                 #expect path(a, e).\n?- path(a, X).\nmode edge(+, -).\n\
+                 constraint not (path(e, a) && edge(a, b)).",
+            )
+            .unwrap();
+        sniffer.set_checkpoint_policy(Some(CheckpointPolicy {
+            every_n_rounds: 2,
+            path: path.clone(),
+        }));
+        // Saturation stops before round 5, the last checkpoint is that of round 4
+        sniffer.saturate_rounds(5);
+        assert!(sniffer.checkpoint_error().is_none());
+        let mut resumed = Sniffer::resume(&path).unwrap();
+        let checkpoint = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(resumed.iter_rules().count() < sniffer.iter_rules().count());
+        assert!(checkpoint.lines().any(|l| l.starts_with("derived ")));
+        assert_eq!(resumed.expectations().len(), 1);
+        assert_eq!(resumed.goals().len(), 1);
+        assert_eq!(resumed.constraints().len(), 1);
+        assert_eq!(resumed.edb.modes().len(), 1);
+        assert_eq!(resumed.probabilities.len(), 1);
+        // Rules derived from a weighted rule keep its weight
+        let rule = |source: &str| -> Rule<String> {
+            Parser::parse_rules(Tokens::new(source)).unwrap().remove(0)
+        };
+        let weighted = resumed.lookup(&rule("path(a, b).")).unwrap();
+        assert_eq!(resumed.weight(&weighted), 2);
+
+        // Derivations survive a resume
+        let root = rule("path(a, c).");
+        assert_eq!(
+            resumed.derivation_tree(&root).unwrap().depth(),
+            sniffer.derivation_tree(&root).unwrap().depth()
+        );
+        resumed.saturate_all();
+        sniffer.saturate_all();
+        let facts = |sniffer: &Sniffer| {
+            let mut facts: Vec<_> = sniffer.iter_facts().map(|f| f.to_string()).collect();
+            facts.sort();
+            facts
+        };
+        assert_eq!(facts(&resumed), facts(&sniffer));
+        assert!(resumed.run_expectations().is_empty());
+        assert!(resumed.check_consistency().is_empty());
+
+        std::fs::write(&path, "edge(a, b).\nderived 0 p0 3 c 0 path(a, b).").unwrap();
+        let error = Sniffer::resume(&path).err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            error,
+            Some(CheckpointError::Malformed { line: 2 })
+        ));
+    }
+}
@@ -0,0 +1,326 @@
+//! Compression module
+//! Transparent decompression of gzip compressed sources (such as `.pif.gz` files),
+//! recognized by their magic bytes rather than their extension so that compressed
+//! streams can be piped in too. Decompression is done in crate, following
+//! RFC 1951 (DEFLATE) and RFC 1952 (gzip), concatenated members included.
+//!
+//! Zstd compressed sources are handled by the `zstd` module, other formats are
+//! read as is and fail to parse
+
+use std::io::{Error, ErrorKind, Result};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Decompresses a source if it is compressed, returning it as is otherwise
+pub(crate) fn decompress(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    if bytes.starts_with(&GZIP_MAGIC) {
+        gunzip(&bytes)
+    } else {
+        Ok(bytes)
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid gzip data: {message}"),
+    )
+}
+
+fn gunzip(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut output = vec![];
+    let mut position = 0;
+    while position < bytes.len() {
+        let member = &bytes[position..];
+        if !member.starts_with(&GZIP_MAGIC) || member.len() < 18 || member[2] != 8 {
+            return Err(invalid("bad member header"));
+        }
+        let flags = member[3];
+        let mut start = 10;
+        if flags & 4 != 0 {
+            let length = u16::from_le_bytes([member[10], member[11]]) as usize;
+            start += 2 + length;
+        }
+        for flag in [8, 16] {
+            // Zero terminated file name and comment
+            if flags & flag != 0 {
+                let end = member
+                    .get(start..)
+                    .and_then(|rest| rest.iter().position(|b| *b == 0))
+                    .ok_or_else(|| invalid("unterminated header field"))?;
+                start += end + 1;
+            }
+        }
+        if flags & 2 != 0 {
+            start += 2;
+        }
+
+        let data = member
+            .get(start..)
+            .ok_or_else(|| invalid("truncated header"))?;
+        let begin = output.len();
+        let mut bits = Bits { data, position: 0 };
+        inflate(&mut bits, &mut output)?;
+        let end = start + bits.position.div_ceil(8);
+        let trailer = member
+            .get(end..end + 8)
+            .ok_or_else(|| invalid("truncated trailer"))?;
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        if crc32(&output[begin..]) != crc || (output.len() - begin) as u32 != size {
+            return Err(invalid("checksum mismatch"));
+        }
+        position += end + 8;
+    }
+    Ok(output)
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut c = i as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+        }
+        *entry = c;
+    }
+    !data.iter().fold(!0u32, |crc, b| {
+        table[((crc ^ *b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Bits of a DEFLATE stream, read least significant first
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+impl Bits<'_> {
+    fn bits(&mut self, count: u32) -> Result<u32> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self
+                .data
+                .get(self.position / 8)
+                .ok_or_else(|| invalid("truncated stream"))?;
+            value |= ((*byte as u32 >> (self.position % 8)) & 1) << i;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.position = self.position.div_ceil(8) * 8
+    }
+}
+
+/// A canonical Huffman code, as the number of codes of each length and the
+/// symbols ordered by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16)
+            .filter(|s| lengths[*s as usize] != 0)
+            .collect();
+        symbols.sort_by_key(|s| lengths[*s as usize]);
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for count in &self.counts[1..] {
+            code |= bits.bits(1)? as i32;
+            let count = *count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("bad Huffman code"))
+    }
+}
+
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which the lengths of the code length code are given
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn inflate(bits: &mut Bits, output: &mut Vec<u8>) -> Result<()> {
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                bits.align();
+                let start = bits.position / 8;
+                let header = bits
+                    .data
+                    .get(start..start + 4)
+                    .ok_or_else(|| invalid("truncated stored block"))?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                if length != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err(invalid("bad stored block length"));
+                }
+                let stored = bits
+                    .data
+                    .get(start + 4..start + 4 + length as usize)
+                    .ok_or_else(|| invalid("truncated stored block"))?;
+                output.extend_from_slice(stored);
+                bits.position = (start + 4 + length as usize) * 8;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(bits, output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(bits)?;
+                inflate_block(bits, output, &literals, &distances)?;
+            }
+            _ => return Err(invalid("bad block type")),
+        }
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+fn dynamic_codes(bits: &mut Bits) -> Result<(Huffman, Huffman)> {
+    let literal_count = bits.bits(5)? as usize + 257;
+    let distance_count = bits.bits(5)? as usize + 1;
+    let code_length_count = bits.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for i in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[*i] = bits.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![];
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (
+                *lengths
+                    .last()
+                    .ok_or_else(|| invalid("repeat without length"))?,
+                3 + bits.bits(2)?,
+            ),
+            17 => (0, 3 + bits.bits(3)?),
+            _ => (0, 11 + bits.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() != literal_count + distance_count {
+        return Err(invalid("too many code lengths"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    bits: &mut Bits,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<()> {
+    loop {
+        match literals.decode(bits)? as usize {
+            literal @ 0..=255 => output.push(literal as u8),
+            256 => return Ok(()),
+            symbol => {
+                let symbol = symbol - 257;
+                let (Some(base), Some(extra)) =
+                    (LENGTH_BASES.get(symbol), LENGTH_EXTRA.get(symbol))
+                else {
+                    return Err(invalid("bad length code"));
+                };
+                let length = *base as usize + bits.bits(*extra as u32)? as usize;
+                let symbol = distances.decode(bits)? as usize;
+                let (Some(base), Some(extra)) =
+                    (DISTANCE_BASES.get(symbol), DISTANCE_EXTRA.get(symbol))
+                else {
+                    return Err(invalid("bad distance code"));
+                };
+                let distance = *base as usize + bits.bits(*extra as u32)? as usize;
+                if distance > output.len() {
+                    return Err(invalid("distance too far back"));
+                }
+                // Copies may overlap with the bytes they produce
+                let start = output.len() - distance;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gunzip_test() {
+        // Two gzip members, compressed with dynamic then fixed Huffman codes
+        let compressed = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x55, 0xc9, 0x3b, 0x0a,
+            0x80, 0x30, 0x10, 0x05, 0xc0, 0xde, 0x53, 0x6c, 0x99, 0x85, 0x20, 0xf9, 0xa7, 0xd2,
+            0x73, 0xc4, 0x72, 0xed, 0xec, 0x44, 0x72, 0x7f, 0x7c, 0x36, 0xc2, 0x6b, 0x67, 0x6c,
+            0x4e, 0x77, 0xdb, 0xf5, 0x38, 0x0b, 0x5e, 0xce, 0xa0, 0xba, 0x2e, 0xf6, 0x53, 0x04,
+            0x45, 0xa6, 0x04, 0x4a, 0x4c, 0x19, 0x94, 0x99, 0x0a, 0xa8, 0x30, 0x55, 0x50, 0x65,
+            0x6a, 0xa0, 0xc6, 0xd4, 0x41, 0x9d, 0x68, 0x78, 0x39, 0x54, 0x65, 0xdb, 0xe5, 0xa3,
+            0x81, 0x7a, 0x01, 0xc1, 0x8d, 0x99, 0x08, 0xb3, 0x00, 0x00, 0x00, 0x1f, 0x8b, 0x08,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x4b, 0x2c, 0x29, 0xd1, 0x48, 0xd4, 0xd4,
+            0xe3, 0x4a, 0x04, 0xd3, 0x0a, 0xb6, 0x76, 0x0a, 0x20, 0x56, 0x12, 0x50, 0x04, 0x00,
+            0xa1, 0x22, 0x62, 0x30, 0x1a, 0x00, 0x00, 0x00,
+        ];
+        let mut expected: String = (0..8)
+            .map(|i| format!("att(pair(a{i}, b{i})).\n"))
+            .collect();
+        expected.push_str("att(pair(X, Y)) => att(X).\natt(a).\natt(a) => att(b).\n");
+        assert_eq!(
+            decompress(compressed.to_vec()).unwrap(),
+            expected.as_bytes()
+        );
+
+        let mut corrupted = compressed.to_vec();
+        corrupted[40] ^= 0x10;
+        assert!(decompress(corrupted).is_err());
+        assert_eq!(decompress(b"att(a).".to_vec()).unwrap(), b"att(a).");
+    }
+}
@@ -0,0 +1,369 @@
+//! Config module
+//! Options of a Sniffer gathered in one place, set once when it is built rather
+//! than by setters called between (or during) saturations.
+//!
+//! `SnifferBuilder` sets options fluently, and `SnifferBuilder::config` checks
+//! they make sense together, yielding a `SnifferConfig` that `build` applies to a
+//! new, empty context. `Sniffer::config` reports the options a context runs with.
+//! A context may also be given a `SymbolTable` shared with other contexts.
+//! Limits only apply to derived rules: input rules are kept whatever their terms,
+//! and resolvents going past a limit are dropped, so that saturation terminates
+//! on rule sets building ever deeper terms
+use crate::ast::InnerRule;
+use crate::checkpoint::CheckpointPolicy;
+use crate::identifiers::{IdentifierServer, SymbolTable};
+use crate::parser::ParserOptions;
+use crate::provenance::{Derivations, Provenance};
+use crate::prover::Strategy;
+use crate::Sniffer;
+
+/// Bounds of a saturation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct Limits {
+    /// Derived rules holding a term nested deeper than this are dropped, the
+    /// parameters of atoms being at depth 1
+    pub max_term_depth: Option<usize>,
+    /// Threads resolving rules with the `parallel` feature, every available one if unset
+    pub threads: Option<usize>,
+}
+impl Limits {
+    /// Whether a derived rule goes past the limits
+    pub fn exceeds(&self, rule: &InnerRule) -> bool {
+        self.max_term_depth.is_some_and(|max| {
+            rule.premises
+                .iter()
+                .chain([&rule.conclusion])
+                .flat_map(|a| &a.parameters)
+                .any(|t| t.depth() > max)
+        })
+    }
+}
+
+/// Options a Sniffer is built with, checked by `SnifferBuilder::config`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SnifferConfig {
+    strategy: Strategy,
+    profiling: bool,
+    journal: bool,
+    limits: Limits,
+    parser_options: ParserOptions,
+    checkpoint: Option<CheckpointPolicy>,
+    seed: Option<u64>,
+}
+impl SnifferConfig {
+    /// Strategy used by `Sniffer::prove`
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Whether saturation counters are gathered, see `Sniffer::profile`
+    pub fn profiling(&self) -> bool {
+        self.profiling
+    }
+
+    /// Whether inferences are logged, see `Sniffer::journal`
+    pub fn journal(&self) -> bool {
+        self.journal
+    }
+
+    pub fn max_term_depth(&self) -> Option<usize> {
+        self.limits.max_term_depth
+    }
+
+    pub fn threads(&self) -> Option<usize> {
+        self.limits.threads
+    }
+
+    pub fn parser_options(&self) -> &ParserOptions {
+        &self.parser_options
+    }
+
+    /// Checkpoints written while saturating, see `Sniffer::set_checkpoint_policy`
+    pub fn checkpoint(&self) -> Option<&CheckpointPolicy> {
+        self.checkpoint.as_ref()
+    }
+
+    /// Seed of the randomized choices made for the runs, see `Sniffer::fingerprint`
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+}
+
+/// Options that cannot be used together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// Top-down proving with a depth of 0 proves nothing
+    ZeroProofDepth,
+    /// A maximum term depth of 0 drops every derived rule with parameters
+    ZeroTermDepth,
+    /// Resolving needs at least one thread
+    ZeroThreads,
+    /// Several threads were asked for without the `parallel` feature
+    ParallelismUnavailable,
+    /// Checkpoints must be at least a round apart
+    ZeroCheckpointInterval,
+}
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::ZeroProofDepth => write!(f, "top-down proofs need a depth of at least 1"),
+            ConfigError::ZeroTermDepth => write!(f, "the maximum term depth must be at least 1"),
+            ConfigError::ZeroThreads => write!(f, "at least one thread is needed"),
+            ConfigError::ParallelismUnavailable => {
+                write!(f, "several threads need the `parallel` feature")
+            }
+            ConfigError::ZeroCheckpointInterval => {
+                write!(f, "checkpoints must be at least a round apart")
+            }
+        }
+    }
+}
+
+/// Builds a Sniffer from its options, see the `config` module
+#[derive(Clone)]
+pub struct SnifferBuilder<P: Provenance = Derivations> {
+    config: SnifferConfig,
+    provenance: P,
+    symbols: Option<SymbolTable>,
+}
+impl Default for SnifferBuilder {
+    fn default() -> Self {
+        SnifferBuilder::new()
+    }
+}
+impl SnifferBuilder {
+    pub fn new() -> SnifferBuilder {
+        SnifferBuilder {
+            config: SnifferConfig::default(),
+            provenance: Derivations,
+            symbols: None,
+        }
+    }
+}
+impl<P: Provenance> SnifferBuilder<P> {
+    /// Annotates rules with another provenance, see the `provenance` module
+    pub fn provenance<Q: Provenance>(self, provenance: Q) -> SnifferBuilder<Q> {
+        SnifferBuilder {
+            config: self.config,
+            provenance,
+            symbols: self.symbols,
+        }
+    }
+
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.config.strategy = strategy;
+        self
+    }
+
+    pub fn profiling(mut self, enabled: bool) -> Self {
+        self.config.profiling = enabled;
+        self
+    }
+
+    /// Logs inferences from the start, see the `journal` module
+    pub fn journal(mut self, enabled: bool) -> Self {
+        self.config.journal = enabled;
+        self
+    }
+
+    /// Drops derived rules holding terms nested deeper than `depth`
+    pub fn max_term_depth(mut self, depth: usize) -> Self {
+        self.config.limits.max_term_depth = Some(depth);
+        self
+    }
+
+    /// Resolves rules on `threads` threads, which needs the `parallel` feature
+    /// when more than one
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.config.limits.threads = Some(threads);
+        self
+    }
+
+    pub fn parser_options(mut self, options: ParserOptions) -> Self {
+        self.config.parser_options = options;
+        self
+    }
+
+    /// Saves the rule set while saturating, see the `checkpoint` module
+    pub fn checkpoint(mut self, policy: CheckpointPolicy) -> Self {
+        self.config.checkpoint = Some(policy);
+        self
+    }
+
+    /// Seeds the randomized choices made for the runs, such as generated rule sets.
+    /// Saturation itself is deterministic, the seed is recorded in the fingerprint
+    /// of the runs so that they can be reproduced, see the `fingerprint` module
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = Some(seed);
+        self
+    }
+
+    /// Interns symbols in a table shared with other contexts, see
+    /// `IdentifierServer::with_symbols`
+    pub fn symbols(mut self, symbols: SymbolTable) -> Self {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    /// Checks the options set so far
+    pub fn config(&self) -> Result<SnifferConfig, ConfigError> {
+        let config = &self.config;
+        if config.strategy == (Strategy::TopDown { depth: 0 }) {
+            return Err(ConfigError::ZeroProofDepth);
+        }
+        if config.limits.max_term_depth == Some(0) {
+            return Err(ConfigError::ZeroTermDepth);
+        }
+        match config.limits.threads {
+            Some(0) => return Err(ConfigError::ZeroThreads),
+            Some(threads) if threads > 1 && !cfg!(feature = "parallel") => {
+                return Err(ConfigError::ParallelismUnavailable)
+            }
+            _ => (),
+        }
+        if config
+            .checkpoint
+            .as_ref()
+            .is_some_and(|c| c.every_n_rounds == 0)
+        {
+            return Err(ConfigError::ZeroCheckpointInterval);
+        }
+        Ok(config.clone())
+    }
+
+    /// Creates an empty Sniffer context with the options set so far
+    pub fn build(self) -> Result<Sniffer<P>, ConfigError> {
+        let config = self.config()?;
+        let mut sniffer = Sniffer::with_provenance(self.provenance);
+        let convention = config.parser_options.convention;
+        sniffer.id_server = match self.symbols {
+            Some(symbols) => IdentifierServer::with_symbols(symbols, convention),
+            None => IdentifierServer::with_convention(convention),
+        };
+        sniffer.parser_options = config.parser_options;
+        sniffer.set_strategy(config.strategy);
+        sniffer.set_profiling(config.profiling);
+        if config.journal {
+            sniffer.start_journal()
+        }
+        sniffer.limits = config.limits;
+        sniffer.set_checkpoint_policy(config.checkpoint);
+        sniffer.seed = config.seed;
+        Ok(sniffer)
+    }
+}
+
+impl Sniffer {
+    pub fn builder() -> SnifferBuilder {
+        SnifferBuilder::new()
+    }
+}
+impl<P: Provenance> Sniffer<P> {
+    /// Options this context currently runs with
+    pub fn config(&self) -> SnifferConfig {
+        SnifferConfig {
+            strategy: self.strategy,
+            profiling: self.is_profiling(),
+            journal: self.journal.is_some(),
+            limits: self.limits,
+            parser_options: self.parser_options.clone(),
+            checkpoint: self.checkpoint_policy().cloned(),
+            seed: self.seed,
+        }
+    }
+
+    /// Table this context interns symbols in, if shared with other contexts
+    pub fn symbol_table(&self) -> Option<&SymbolTable> {
+        self.id_server.symbols()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigError, SnifferBuilder};
+    use crate::provenance::Counting;
+    use crate::{CheckpointPolicy, Parser, Sniffer, Strategy, SymbolTable};
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn builder_test() {
+        let mut sniffer = Sniffer::builder()
+            .strategy(Strategy::BottomUp)
+            .journal(true)
+            .max_term_depth(3)
+            .build()
+            .unwrap();
+        let config = sniffer.config();
+        assert_eq!(config.strategy(), Strategy::BottomUp);
+        assert!(config.journal() && !config.profiling());
+        assert_eq!((config.max_term_depth(), config.threads()), (Some(3), None));
+
+        // Terms are built until they reach the maximum depth
+        sniffer.assert("p(a, b).\np(X, Y) => p(h(X), Y).").unwrap();
+        sniffer.saturate_all();
+        let deepest = Parser::parse_query(Tokens::new("p(h(h(a)), b).")).unwrap();
+        assert!(sniffer.prove(&deepest).is_ok());
+        assert_eq!(sniffer.iter_rules().count(), 4);
+        assert!(sniffer
+            .journal()
+            .is_some_and(|j| j.inferences().count() > 0));
+
+        let invalid = |builder: SnifferBuilder| builder.build().err();
+        assert_eq!(
+            invalid(Sniffer::builder().strategy(Strategy::TopDown { depth: 0 })),
+            Some(ConfigError::ZeroProofDepth)
+        );
+        assert_eq!(
+            invalid(Sniffer::builder().max_term_depth(0)),
+            Some(ConfigError::ZeroTermDepth)
+        );
+        assert_eq!(
+            invalid(Sniffer::builder().threads(0)),
+            Some(ConfigError::ZeroThreads)
+        );
+        assert_eq!(
+            Sniffer::builder().threads(4).config().err(),
+            (!cfg!(feature = "parallel")).then_some(ConfigError::ParallelismUnavailable)
+        );
+        let policy = CheckpointPolicy {
+            every_n_rounds: 0,
+            path: std::env::temp_dir(),
+        };
+        assert_eq!(
+            invalid(Sniffer::builder().checkpoint(policy)),
+            Some(ConfigError::ZeroCheckpointInterval)
+        );
+
+        let sniffer = Sniffer::builder()
+            .provenance(Counting)
+            .profiling(true)
+            .build()
+            .unwrap();
+        assert!(sniffer.is_profiling());
+    }
+
+    #[test]
+    fn shared_symbols_test() {
+        let symbols = SymbolTable::new();
+        let build = || Sniffer::builder().symbols(symbols.clone()).build().unwrap();
+        let (mut first, mut second) = (build(), build());
+        assert!(first.symbol_table().unwrap().ptr_eq(&symbols));
+        first
+            .assert("edge(a, b).\nedge(X, Y) => path(X, Y).")
+            .unwrap();
+        second.assert("path(c, d). edge(c, a).").unwrap();
+        // Symbols are interned once, and have the same identifier in both contexts
+        assert_eq!(symbols.len(), 6);
+        assert_eq!(first.id_server.id_of("a"), second.id_server.id_of("a"));
+        // Each context only knows the symbols it registered
+        let names: Vec<_> = second.id_server.functions().map(|(_, name)| name).collect();
+        assert_eq!(names, ["path", "c", "d", "edge", "a"]);
+        assert!(Sniffer::default().symbol_table().is_none());
+        assert!(second.id_server.import(&first.id_server).is_identity());
+
+        first.saturate_all();
+        let goal = Parser::parse_query(Tokens::new("path(a, b).")).unwrap();
+        assert!(first.prove(&goal).is_ok());
+        assert!(second.prove(&goal).is_err());
+    }
+}
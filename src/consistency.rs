@@ -0,0 +1,154 @@
+//! Consistency module
+//! Negative constraints, written `constraint not (att(sk) && honest(a)).`, state
+//! conjunctions of atoms that should never hold together. Each constraint is
+//! compiled into a goal clause, a rule concluding the reserved atom `⊥(i)` with
+//! `i` the index of the constraint, so that saturation derives `⊥(i)` exactly
+//! when the constraint is violated, along with a derivation of each of its atoms.
+//! The index of a constraint with variables is applied to them, as in
+//! `bad(X) /\ honest(X) => ⊥(0(X))`: premises like `bad(X)` being kept by
+//! resolution only when their variable occurs in the conclusion. Goal clauses may
+//! select such premises, which other rules never do, see the `planner` module.
+//!
+//! `Sniffer::check_consistency` saturates the rule set and reports every violated
+//! constraint. Goal clauses are rules of the rule set like any other, so queries
+//! and checkpoints see them, but no premise may use the reserved predicate
+use crate::ast::{Atom, Attributes, InnerRule, Rule, Term};
+use crate::derivation_tree::DerivationTree;
+use crate::hashing::HashMap;
+use crate::identifiers::Identifier;
+use crate::provenance::Provenance;
+use crate::Sniffer;
+
+/// Predicate concluded by the goal clause of a constraint
+pub(crate) const BOTTOM: &str = "⊥";
+
+/// A constraint that holds in the saturated rule set, see `Sniffer::check_consistency`
+#[derive(Debug, Clone)]
+pub struct Violation {
+    /// Atoms of the constraint, which hold together
+    pub atoms: Vec<Atom<String>>,
+    /// Derivation of the goal clause of the constraint, from derivations of its atoms
+    pub derivation: DerivationTree,
+}
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let atoms: Vec<_> = self.atoms.iter().map(|a| a.to_string()).collect();
+        write!(f, "constraint not ({})", atoms.join(" && "))
+    }
+}
+
+/// Atom the goal clause of the `index`-th constraint concludes, given its variables
+fn bottom(index: usize, variables: Vec<Term<String>>) -> Atom<String> {
+    Atom {
+        symbol: BOTTOM.to_string(),
+        parameters: vec![Term::Function {
+            symbol: index.to_string(),
+            parameters: variables,
+        }],
+    }
+}
+
+/// Variables of the atoms, in order of appearance
+fn variables(atoms: &[Atom<String>]) -> Vec<Term<String>> {
+    fn visit(term: &Term<String>, variables: &mut Vec<Term<String>>) {
+        match term {
+            Term::Variable { .. } if !variables.contains(term) => variables.push(term.clone()),
+            Term::Variable { .. } => (),
+            Term::Function { parameters, .. } => {
+                parameters.iter().for_each(|t| visit(t, variables))
+            }
+        }
+    }
+    let mut variables = vec![];
+    for atom in atoms {
+        atom.parameters
+            .iter()
+            .for_each(|t| visit(t, &mut variables))
+    }
+    variables
+}
+
+impl<P: Provenance> Sniffer<P> {
+    /// Negative constraints declared in the loaded statements
+    pub fn constraints(&self) -> &[Vec<Atom<String>>] {
+        &self.negative_constraints
+    }
+
+    /// Adds a negative constraint, returning its goal clause
+    pub(crate) fn insert_constraint(&mut self, atoms: Vec<Atom<String>>) -> InnerRule {
+        let rule = Rule {
+            premises: atoms.clone(),
+            conclusion: bottom(self.negative_constraints.len(), variables(&atoms)),
+        };
+        self.negative_constraints.push(atoms);
+        self.insert_input(rule, &Attributes::default())
+    }
+
+    /// Identifier of the predicate of goal clauses, if some constraint was declared
+    pub(crate) fn bottom(&self) -> Option<Identifier> {
+        self.id_server.id_of(BOTTOM).copied()
+    }
+
+    /// Saturates the rule set, then returns every violated constraint along with
+    /// the derivation of its goal clause, in the order constraints were declared
+    pub fn check_consistency(&mut self) -> Vec<Violation> {
+        if self.negative_constraints.is_empty() {
+            return vec![];
+        }
+        self.saturate_all();
+        let mut violations = vec![];
+        for (index, atoms) in self.negative_constraints.iter().enumerate() {
+            let goal = bottom(index, variables(atoms))
+                .to_inner(&mut self.id_server, &mut HashMap::default());
+            if let Some(fact) = self.find_fact(&goal) {
+                if let Ok(derivation) = self.inner_derivation_tree(&fact, &mut vec![]) {
+                    violations.push(Violation {
+                        atoms: atoms.clone(),
+                        derivation,
+                    })
+                }
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Parser, Sniffer, Statement};
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn consistency_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "att(k). honest(a). honest(b). att(senc(sk(a), k)).\n\
+                 att(senc(X, Y)) /\\ att(Y) => att(X).\n\
+                 constraint not (att(sk(a)) && honest(a)).\n\
+                 constraint not (att(sk(b)) /\\ honest(b)).\n\
+                 constraint not (att(sk(X)) && honest(X)).\n\
+                 bad(a). constraint not (bad(X) && honest(X)).\n\
+                 constraint not (bad(X) && honest(Y) && att(sk(Y)) && neq(X, Y)).",
+            )
+            .unwrap();
+        assert_eq!(sniffer.constraints().len(), 5);
+        let violations = sniffer.check_consistency();
+        assert_eq!(
+            violations.iter().map(|v| v.to_string()).collect::<Vec<_>>(),
+            vec![
+                "constraint not (att(sk(a)) && honest(a))",
+                "constraint not (att(sk(X)) && honest(X))",
+                "constraint not (bad(X) && honest(X))"
+            ]
+        );
+        // The goal clause is derived from a derivation of every atom of the constraint
+        assert_eq!(violations[0].derivation.subtrees().len(), 2);
+        assert!(sniffer.diagnostics().is_empty());
+
+        let statements = Parser::parse_statements(Tokens::new("constraint not (p(a)).")).unwrap();
+        assert!(matches!(&statements[..], [Statement::Constraint(atoms)] if atoms.len() == 1));
+        assert!(Parser::parse_statements(Tokens::new("constraint not ().")).is_err());
+        assert!(Sniffer::default().check_consistency().is_empty());
+    }
+}
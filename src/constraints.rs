@@ -0,0 +1,96 @@
+//! Constraints module
+//! Built-in disequality constraints: a premise `neq(s, t)` holds when `s` and `t`
+//! are distinct ground terms, so that rules about distinct values (`agent(X, host)
+//! /\ agent(Y, host) /\ neq(X, Y) => pair(X, Y)`) need no facts enumerating unequal
+//! pairs.
+//!
+//! Constraints are never selected: no rule concludes them, they are only checked
+//! against the bindings that resolving the other premises brings. Every rule
+//! derived is simplified, and input rules when asserted: a constraint between terms
+//! that cannot unify, such as distinct constants, always holds and is dropped, and a
+//! rule with a constraint between identical terms is discarded, since no binding
+//! satisfies it. Other constraints are kept until their terms are bound enough to
+//! decide them
+use crate::ast::{InnerAtom, InnerRule};
+use crate::identifiers::Identifier;
+use crate::provenance::Provenance;
+use crate::Sniffer;
+
+/// Predicate of the disequality constraint
+pub(crate) const NEQ: &str = "neq";
+
+/// Whether an atom is a disequality constraint, `neq` being the identifier of its
+/// predicate if it was registered
+pub(crate) fn is_constraint(atom: &InnerAtom, neq: Option<Identifier>) -> bool {
+    neq == Some(atom.symbol) && atom.parameters.len() == 2
+}
+
+/// Drops the constraints of a rule that always hold, returning `None` if one of
+/// them never does
+pub(crate) fn simplify(mut rule: InnerRule, neq: Option<Identifier>) -> Option<InnerRule> {
+    let mut satisfiable = true;
+    rule.premises.retain(|premise| {
+        if !is_constraint(premise, neq) {
+            return true;
+        }
+        let [s, t] = &premise.parameters[..] else {
+            unreachable!("constraints have two parameters")
+        };
+        satisfiable &= s != t;
+        s.unify(t).is_some()
+    });
+    satisfiable.then_some(rule)
+}
+
+impl<P: Provenance> Sniffer<P> {
+    /// Identifier of the disequality predicate, if some rule uses it
+    pub(crate) fn neq(&self) -> Option<Identifier> {
+        self.id_server.id_of(NEQ).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::simplify;
+    use crate::{Parser, Rule, Sniffer, Strategy};
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn neq_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "agent(a, host). agent(b, host). agent(c, host).\n\
+                 agent(X, host) /\\ agent(Y, host) /\\ neq(X, Y) => pair(X, Y).\n\
+                 neq(a, b) => differ(a, b).\nneq(a, a) => differ(a, a).",
+            )
+            .unwrap();
+        let neq = sniffer.neq();
+        let inner = |sniffer: &mut Sniffer, source: &str| {
+            let rule: Rule<String> = Parser::parse_rules(Tokens::new(source)).unwrap().remove(0);
+            rule.to_inner(&mut sniffer.id_server)
+        };
+        let undecided = inner(&mut sniffer, "neq(X, f(Y)) => p(X, Y).");
+        assert_eq!(simplify(undecided.clone(), neq), Some(undecided));
+        let never = inner(&mut sniffer, "neq(f(X), f(X)) => p(X, X).");
+        assert_eq!(simplify(never, neq), None);
+        let always = inner(&mut sniffer, "q(X) /\\ neq(X, f(X)) => p(X, X).");
+        assert_eq!(simplify(always, neq).map(|r| r.premises.len()), Some(1));
+        assert!(sniffer.dead_rules().is_empty());
+
+        let query = |source: &str| Parser::parse_query(Tokens::new(source)).unwrap();
+        for strategy in [Strategy::BottomUp, Strategy::TopDown { depth: 4 }] {
+            let mut sniffer = sniffer.clone();
+            sniffer.set_strategy(strategy);
+            assert!(sniffer.prove(&query("pair(a, c).")).is_ok());
+            assert!(sniffer.prove(&query("pair(b, b).")).is_err());
+            assert!(sniffer.prove(&query("differ(a, b).")).is_ok());
+            assert!(sniffer.prove(&query("differ(a, a).")).is_err());
+        }
+        sniffer.saturate_all();
+        assert_eq!(
+            sniffer.iter_facts().filter(|f| f.symbol == "pair").count(),
+            6
+        );
+    }
+}
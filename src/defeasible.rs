@@ -0,0 +1,165 @@
+//! Defeasible module
+//! Rules written `q(X) <~ p(X)` conclude `q(X)` unless defeated, which models
+//! exceptions directly: birds fly, penguins don't.
+//!
+//! Conflicts are resolved once the strict rules are saturated. A defeasible
+//! conclusion is rejected if its negation is strictly derivable (for negated
+//! rules, if the atom itself is). Otherwise it is compared with the defeasible
+//! rules concluding the opposite on the same atom: the highest `priority`
+//! attribute wins, and a tie leaves the atom undecided.
+//! Accepted positive conclusions are added as facts and the process repeats until
+//! nothing new is concluded. Decisions of earlier rounds are kept, so a rule
+//! enabled by a later conclusion cannot defeat an earlier one.
+//! Premises are only matched against ground facts
+
+use crate::ast::{Atom, Defeasible, InnerAtom, InnerTerm, Rule};
+use crate::hashing::{HashMap, IndexMap};
+use crate::provenance::Provenance;
+use crate::Sniffer;
+
+/// The outcome of the conflict resolution among defeasible rules
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DefeasibleOutcome {
+    /// Atoms defeasibly concluded, which are added to the facts
+    pub concluded: Vec<Atom<String>>,
+    /// Atoms whose negation was defeasibly concluded
+    pub refuted: Vec<Atom<String>>,
+    /// Atoms concluded and refuted by rules of equal priority
+    pub undecided: Vec<Atom<String>>,
+}
+
+/// Highest priorities of the rules concluding an atom and its negation
+#[derive(Default)]
+struct Conflict {
+    positive: Option<u32>,
+    negative: Option<u32>,
+}
+
+impl<P: Provenance> Sniffer<P> {
+    /// Saturates the rule set, then resolves the conflicts among defeasible rules,
+    /// adding the accepted conclusions as facts. See the `defeasible` module
+    pub fn run_defeasible(&mut self) -> DefeasibleOutcome {
+        let mut outcome = DefeasibleOutcome::default();
+        let mut decided: Vec<InnerAtom> = vec![];
+        loop {
+            self.saturate_all();
+            let facts: Vec<InnerAtom> = self
+                .all_rules()
+                .filter(|r| r.premises.is_empty() && r.conclusion.is_ground())
+                .map(|r| r.conclusion.clone())
+                .collect();
+
+            let mut conflicts: IndexMap<InnerAtom, Conflict> = IndexMap::default();
+            for Defeasible {
+                rule,
+                negated,
+                priority,
+            } in &self.defeasible
+            {
+                for bindings in matches(&rule.premises, &facts, HashMap::default()) {
+                    let conclusion = rule.conclusion.substitute(&bindings);
+                    if !conclusion.is_ground() || decided.contains(&conclusion) {
+                        continue;
+                    }
+                    let conflict = conflicts.entry(conclusion).or_default();
+                    let best = if *negated {
+                        &mut conflict.negative
+                    } else {
+                        &mut conflict.positive
+                    };
+                    *best = (*best).max(Some(*priority));
+                }
+            }
+
+            let mut new_facts = vec![];
+            for (atom, conflict) in conflicts {
+                let strict = facts.contains(&atom);
+                let printed = atom.to_string(&self.id_server);
+                match (conflict.positive, conflict.negative) {
+                    (Some(_), None) => new_facts.push(atom.clone()),
+                    (None, Some(_)) if !strict => outcome.refuted.push(printed),
+                    (Some(p), Some(n)) if p > n => new_facts.push(atom.clone()),
+                    (Some(p), Some(n)) if p < n && !strict => outcome.refuted.push(printed),
+                    (Some(_), Some(_)) if !strict => outcome.undecided.push(printed),
+                    _ => continue,
+                }
+                decided.push(atom);
+            }
+
+            // Conclusions already derived strictly only need to be recorded once
+            new_facts.retain(|atom| !facts.contains(atom));
+            if new_facts.is_empty() {
+                break;
+            }
+            for atom in new_facts {
+                outcome.concluded.push(atom.to_string(&self.id_server));
+                self.insert_rule(Rule {
+                    premises: vec![],
+                    conclusion: atom,
+                });
+            }
+        }
+        self.publish();
+        outcome
+    }
+}
+
+/// Enumerates the bindings of the variables of the premises matching them against
+/// the given facts
+fn matches(
+    premises: &[InnerAtom],
+    facts: &[InnerAtom],
+    bindings: HashMap<InnerTerm, InnerTerm>,
+) -> Vec<HashMap<InnerTerm, InnerTerm>> {
+    let Some((premise, rest)) = premises.split_first() else {
+        return vec![bindings];
+    };
+    let premise = premise.substitute(&bindings);
+    facts
+        .iter()
+        .filter_map(|fact| premise.unify(fact))
+        .flat_map(|new| {
+            let mut bindings = bindings.clone();
+            bindings.extend(new);
+            matches(rest, facts, bindings)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Sniffer;
+
+    #[test]
+    fn penguins_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "bird(tweety).\nbird(pingu).\npenguin(pingu).\nwounded(tweety).\n\
+                 flies(X) <~ bird(X).\n[priority=1] ~flies(X) <~ penguin(X).\n\
+                 ~flies(X) <~ wounded(X).",
+            )
+            .unwrap();
+        let outcome = sniffer.run_defeasible();
+        let names = |atoms: &[crate::Atom<String>]| -> Vec<String> {
+            atoms.iter().map(|a| a.to_string()).collect()
+        };
+        assert_eq!(names(&outcome.refuted), vec!["flies(pingu)"]);
+        assert_eq!(names(&outcome.undecided), vec!["flies(tweety)"]);
+        assert!(outcome.concluded.is_empty());
+
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "bird(tweety).\nbird(pingu).\npenguin(pingu).\nflies(X) <~ bird(X).\n\
+                 [priority=1] ~flies(X) <~ penguin(X).\nflies(X) <~ flies(X).",
+            )
+            .unwrap();
+        let outcome = sniffer.run_defeasible();
+        assert_eq!(names(&outcome.concluded), vec!["flies(tweety)"]);
+        assert_eq!(names(&outcome.refuted), vec!["flies(pingu)"]);
+        assert!(sniffer
+            .iter_facts()
+            .any(|f| f.to_string() == "flies(tweety)"));
+    }
+}
@@ -1,14 +1,74 @@
 use crate::ast::Rule;
+#[cfg(any(feature = "ffi", feature = "serve", feature = "wasm"))]
+use crate::fmt::FormatOptions;
+use crate::hashing::HashSet;
+use crate::identifiers::Identifier;
 use crate::resolution::Selection;
 use ptree::{Style, TreeItem};
 use std::borrow::Cow;
 use std::io::Write;
 
-#[derive(Clone)]
+/// Errors that can occur while building a derivation tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DerivationError {
+    /// The rule is not part of the rule set
+    UnknownAtom(Rule<String>),
+    /// An identifier used by a rule is not known to the identifier server
+    UnregisteredIdentifier(Identifier),
+    /// The recorded derivation of a rule is inconsistent, e.g. cyclic or pointing
+    /// to a rule that is no longer part of the rule set
+    BrokenProvenance(Rule<String>),
+}
+impl std::fmt::Display for DerivationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DerivationError::UnknownAtom(r) => write!(f, "{r} is not part of the rule set"),
+            DerivationError::UnregisteredIdentifier(id) => {
+                write!(f, "identifier {id:?} is not registered")
+            }
+            DerivationError::BrokenProvenance(r) => {
+                write!(f, "the recorded derivation of {r} is broken")
+            }
+        }
+    }
+}
+impl std::error::Error for DerivationError {}
+impl From<Identifier> for DerivationError {
+    fn from(id: Identifier) -> Self {
+        DerivationError::UnregisteredIdentifier(id)
+    }
+}
+
+/// Subproof left out of a tree by `DerivationTree::prune` or `summarize`, described
+/// so that the tree can still be checked against the full one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Elision {
+    /// Depth of the subproof left out
+    pub depth: usize,
+    /// Number of resolutions of the subproof left out
+    pub resolutions: usize,
+}
+impl Elision {
+    fn of(tree: &DerivationTree) -> Elision {
+        Elision {
+            depth: tree.depth(),
+            resolutions: tree.resolutions(),
+        }
+    }
+}
+/// Printed as `[<resolutions> resolutions elided]`
+impl std::fmt::Display for Elision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{} resolutions elided]", self.resolutions)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct DerivationTree {
     root: Rule<String>,
     subtrees: Vec<DerivationTree>,
     selection: Option<Selection<String>>,
+    elided: Option<Elision>,
 }
 impl DerivationTree {
     pub fn new(root: Rule<String>) -> DerivationTree {
@@ -16,11 +76,142 @@ impl DerivationTree {
             root,
             subtrees: vec![],
             selection: None,
+            elided: None,
         }
     }
     pub fn add_subtree(&mut self, subtree: DerivationTree) {
         self.subtrees.push(subtree)
     }
+    /// Rule derived at the root of the tree
+    pub fn root(&self) -> &Rule<String> {
+        &self.root
+    }
+    /// Derivations of the two rules the root was resolved from, if any
+    pub fn subtrees(&self) -> &[DerivationTree] {
+        &self.subtrees
+    }
+    /// Atom the root was resolved upon in its parent derivation
+    pub fn selection(&self) -> Option<&Selection<String>> {
+        self.selection.as_ref()
+    }
+    /// Number of resolutions on the longest branch of the tree, 0 for input rules.
+    /// Elided subproofs are counted in
+    pub fn depth(&self) -> usize {
+        if let Some(elision) = &self.elided {
+            return elision.depth;
+        }
+        self.subtrees
+            .iter()
+            .map(|t| t.depth() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+    /// Number of resolutions in the tree, elided subproofs included
+    pub fn resolutions(&self) -> usize {
+        if let Some(elision) = &self.elided {
+            return elision.resolutions;
+        }
+        let own = usize::from(!self.subtrees.is_empty());
+        own + self.subtrees.iter().map(|t| t.resolutions()).sum::<usize>()
+    }
+    /// Subproof of the root left out of this tree, if any
+    pub fn elided(&self) -> Option<&Elision> {
+        self.elided.as_ref()
+    }
+    /// Copy of the tree whose subproofs below `max_depth` resolutions from the root
+    /// are left out
+    pub fn prune(&self, max_depth: usize) -> DerivationTree {
+        self.elide(&mut |_, depth| depth >= max_depth, 0)
+    }
+    /// Copy of the tree leaving out the derivations of the facts of the given
+    /// predicates, below the root
+    pub fn summarize(&self, predicates_to_hide: &[&str]) -> DerivationTree {
+        let hidden: HashSet<&str> = predicates_to_hide.iter().copied().collect();
+        self.elide(
+            &mut |tree, depth| {
+                depth > 0
+                    && tree.root.premises.is_empty()
+                    && hidden.contains(tree.root.conclusion.symbol.as_str())
+            },
+            0,
+        )
+    }
+    fn elide(
+        &self,
+        elided: &mut impl FnMut(&DerivationTree, usize) -> bool,
+        depth: usize,
+    ) -> DerivationTree {
+        if !self.subtrees.is_empty() && elided(self, depth) {
+            return DerivationTree {
+                root: self.root.clone(),
+                subtrees: vec![],
+                selection: self.selection.clone(),
+                elided: Some(Elision::of(self)),
+            };
+        }
+        DerivationTree {
+            root: self.root.clone(),
+            subtrees: self
+                .subtrees
+                .iter()
+                .map(|t| t.elide(elided, depth + 1))
+                .collect(),
+            selection: self.selection.clone(),
+            elided: self.elided,
+        }
+    }
+    /// Whether this tree is `full` with some subproofs left out, matching their
+    /// elisions
+    pub fn is_pruning_of(&self, full: &DerivationTree) -> bool {
+        if self.root != full.root || self.selection != full.selection {
+            return false;
+        }
+        match &self.elided {
+            Some(elision) if full.elided.is_none() => *elision == Elision::of(full),
+            _ => {
+                self.elided == full.elided
+                    && self.subtrees.len() == full.subtrees.len()
+                    && self
+                        .subtrees
+                        .iter()
+                        .zip(&full.subtrees)
+                        .all(|(t, u)| t.is_pruning_of(u))
+            }
+        }
+    }
+    /// Encodes the tree as nested `{"rule", "selected", "depth", "elided", "subtrees"}`
+    /// objects, where `selected` is the atom the rule was resolved upon, if any,
+    /// `depth` the depth of the subtree and `elided` the `{"depth", "resolutions"}`
+    /// of its subproof if it was left out
+    #[cfg(any(feature = "ffi", feature = "serve", feature = "wasm"))]
+    pub fn to_json(&self) -> serde_json::Value {
+        self.to_json_with(&FormatOptions::default())
+    }
+    /// Encodes the tree like `to_json`, printing rules with the given options
+    #[cfg(any(feature = "ffi", feature = "serve", feature = "wasm"))]
+    pub fn to_json_with(&self, options: &FormatOptions) -> serde_json::Value {
+        let selected = self.selection.as_ref().map(|s| match s {
+            Selection::Premise(atom, _) | Selection::Conclusion(atom) => atom.to_string(),
+        });
+        let operators = crate::OperatorTable::default();
+        serde_json::json!({
+            "rule": crate::fmt::rule_to_string(&self.root, &operators, options),
+            "selected": selected,
+            "depth": self.depth(),
+            "elided": self.elided.map(|e| serde_json::json!({
+                "depth": e.depth,
+                "resolutions": e.resolutions,
+            })),
+            "subtrees": self
+                .subtrees
+                .iter()
+                .map(|t| t.to_json_with(options))
+                .collect::<Vec<_>>(),
+        })
+    }
+    pub fn set_root(&mut self, root: Rule<String>) {
+        self.root = root
+    }
     pub fn set_selection(&mut self, selection: Selection<String>) {
         self.selection = Some(selection)
     }
@@ -33,12 +224,68 @@ impl TreeItem for DerivationTree {
                 f,
                 "{}",
                 style.paint(&self.root.selection_empathized_string(selection.clone()))
-            )
+            )?
         } else {
-            write!(f, "{}", style.paint(&self.root.to_string()))
+            write!(f, "{}", style.paint(&self.root.to_string()))?
+        }
+        match &self.elided {
+            Some(elision) => write!(f, " {elision}"),
+            None => Ok(()),
         }
     }
-    fn children(&self) -> Cow<[Self::Child]> {
+    fn children(&self) -> Cow<'_, [Self::Child]> {
         Cow::from(self.subtrees.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{Parser, Sniffer, Strategy};
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn prune_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "edge(a, b). edge(b, c). edge(c, d).\n\
+                 edge(X, Y) => path(X, Y).\npath(X, Y) /\\ edge(Y, Z) => path(X, Z).",
+            )
+            .unwrap();
+        sniffer.set_strategy(Strategy::BottomUp);
+        let goal = Parser::parse_query(Tokens::new("path(a, d).")).unwrap();
+        let full = sniffer.prove(&goal).unwrap();
+        assert!(full.depth() > 2);
+
+        // Elided subproofs keep their size, so that the tree can be checked
+        let pruned = full.prune(1);
+        assert_eq!(pruned.depth(), full.depth());
+        assert_eq!(pruned.resolutions(), full.resolutions());
+        assert!(pruned.elided().is_none());
+        assert!(pruned.subtrees().iter().all(|t| t.subtrees().is_empty()));
+        assert!(pruned.subtrees().iter().any(|t| t.elided().is_some()));
+        assert!(pruned.is_pruning_of(&full) && !full.is_pruning_of(&pruned));
+        assert!(full.prune(3).prune(1).is_pruning_of(&full));
+        assert!(full.is_pruning_of(&full));
+        let root = full.prune(0);
+        assert_eq!(
+            root.elided().map(|e| e.resolutions),
+            Some(full.resolutions())
+        );
+        assert!(!root.is_pruning_of(&full.subtrees()[0]));
+
+        // Derivations of hidden facts are left out, below the root
+        let summary = full.summarize(&["path"]);
+        assert!(summary.is_pruning_of(&full));
+        assert!(summary.elided().is_none());
+        fn hides(tree: &crate::DerivationTree, root: bool) -> bool {
+            let fact = tree.root().premises.is_empty() && tree.root().conclusion.symbol == "path";
+            (root || !fact || tree.subtrees().is_empty())
+                && tree.subtrees().iter().all(|t| hides(t, false))
+        }
+        assert!(hides(&summary, true) && !hides(&full, true));
+        let printed =
+            crate::fmt::tree_to_string(&summary, &Default::default(), &Default::default());
+        assert!(printed.contains("resolutions elided]"));
+    }
+}
@@ -0,0 +1,321 @@
+//! Diagnostics module
+//! Non-fatal warnings gathered while a Sniffer loads, checks and saturates its
+//! rule set, retrieved with `Sniffer::diagnostics`.
+//!
+//! Predicates used with several arities are reported as statements are asserted,
+//! and predicates no premise, directive or defeasible rule uses once the input is
+//! loaded. Input rules with premises that no derived fact comes from are reported
+//! after each full saturation. Diagnostics found again replace the previous ones
+//! of their kind, so that they describe the current rule set and the last run
+use crate::ast::{Atom, Defeasible, InnerAtom, InnerRule};
+use crate::hashing::{HashMap, HashSet, IndexMap};
+use crate::identifiers::Identifier;
+use crate::provenance::Provenance;
+use crate::{Sniffer, SourceLocation};
+
+/// How much a diagnostic matters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Worth knowing, but often intended
+    Note,
+    /// Most likely a mistake
+    Warning,
+}
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Note => write!(f, "note"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Kinds of diagnostics, each associated with a stable code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiagnosticKind {
+    /// A predicate is concluded, but used by no premise or directive
+    UnusedPredicate,
+    /// A predicate is used with another arity than at its first use
+    SuspiciousArity,
+    /// No fact was derived from an input rule during the last saturation
+    NeverFired,
+}
+impl DiagnosticKind {
+    /// Stable code of the diagnostic
+    pub fn code(&self) -> &'static str {
+        match self {
+            DiagnosticKind::UnusedPredicate => "D001",
+            DiagnosticKind::SuspiciousArity => "D002",
+            DiagnosticKind::NeverFired => "D003",
+        }
+    }
+
+    /// Human readable name of the diagnostic
+    pub fn name(&self) -> &'static str {
+        match self {
+            DiagnosticKind::UnusedPredicate => "unused-predicate",
+            DiagnosticKind::SuspiciousArity => "suspicious-arity",
+            DiagnosticKind::NeverFired => "never-fired",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            DiagnosticKind::UnusedPredicate | DiagnosticKind::NeverFired => Severity::Note,
+            DiagnosticKind::SuspiciousArity => Severity::Warning,
+        }
+    }
+}
+
+/// A warning about the rule set, pointing to the input rule it comes from when
+/// that rule was parsed from a source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub severity: Severity,
+    pub message: String,
+    pub location: Option<SourceLocation>,
+}
+/// Printed as `<location>: <severity>[<code>] <name>: <message>`, like lints
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(location) = &self.location {
+            write!(f, "{location}: ")?;
+        }
+        write!(
+            f,
+            "{}[{}] {}: {}",
+            self.severity,
+            self.kind.code(),
+            self.kind.name(),
+            self.message
+        )
+    }
+}
+
+/// Diagnostics gathered so far, in the order they were found
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    entries: Vec<Diagnostic>,
+    /// Arity every predicate was first used with
+    arities: HashMap<Identifier, usize>,
+    /// Predicates and arities already reported as suspicious
+    reported: HashSet<(Identifier, usize)>,
+}
+impl Diagnostics {
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Diagnostics of at least the given severity
+    pub fn at_least(&self, severity: Severity) -> impl Iterator<Item = &Diagnostic> {
+        self.entries.iter().filter(move |d| d.severity >= severity)
+    }
+
+    fn push(&mut self, kind: DiagnosticKind, message: String, location: Option<SourceLocation>) {
+        self.entries.push(Diagnostic {
+            kind,
+            severity: kind.severity(),
+            message,
+            location,
+        })
+    }
+
+    /// Removes the diagnostics of a kind, before they are found again
+    fn forget(&mut self, kind: DiagnosticKind) {
+        self.entries.retain(|d| d.kind != kind)
+    }
+}
+
+impl<P: Provenance> Sniffer<P> {
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    pub fn clear_diagnostics(&mut self) {
+        self.diagnostics.entries.clear()
+    }
+
+    /// Reports the atoms of an asserted rule whose predicate was first used with
+    /// another arity
+    pub(crate) fn check_arities(&mut self, rule: &InnerRule, location: Option<&SourceLocation>) {
+        for atom in rule.premises.iter().chain([&rule.conclusion]) {
+            let arity = atom.parameters.len();
+            let first = *self.diagnostics.arities.entry(atom.symbol).or_insert(arity);
+            if first != arity && self.diagnostics.reported.insert((atom.symbol, arity)) {
+                let name = self.id_server.name_of(&atom.symbol).unwrap_or_default();
+                self.diagnostics.push(
+                    DiagnosticKind::SuspiciousArity,
+                    format!("`{name}` is used with arity {arity}, but was first used with arity {first}"),
+                    location.cloned(),
+                )
+            }
+        }
+    }
+
+    /// Reports the predicates that input rules conclude, but that no premise nor
+    /// directive uses
+    pub(crate) fn check_unused(&mut self) {
+        let relation = |atom: &InnerAtom| (atom.symbol, atom.parameters.len());
+        let named = |atom: &Atom<String>| {
+            self.id_server
+                .id_of(&atom.symbol)
+                .map(|symbol| (*symbol, atom.parameters.len()))
+        };
+        let mut used: HashSet<(Identifier, usize)> = HashSet::default();
+        for rule in self.rules.iter() {
+            used.extend(rule.premises.iter().map(relation))
+        }
+        for Defeasible { rule, .. } in &self.defeasible {
+            used.extend(rule.premises.iter().chain([&rule.conclusion]).map(relation))
+        }
+        // `next` rules use atoms with the time step as an implicit last parameter
+        for rule in &self.timeline.rules {
+            used.extend(
+                rule.premises
+                    .iter()
+                    .filter_map(|p| named(p).map(|(symbol, arity)| (symbol, arity + 1))),
+            )
+        }
+        used.extend(self.expectations.iter().filter_map(|e| named(&e.atom)));
+        used.extend(self.goals.iter().filter_map(named));
+        // Goal clauses conclude the reserved predicate, see the `consistency` module
+        used.extend(self.bottom().map(|bottom| (bottom, 1)));
+        let outputs: HashSet<String> = self.outputs().into_iter().collect();
+
+        let mut unused: IndexMap<(Identifier, usize), Option<SourceLocation>> = IndexMap::default();
+        for rule in self
+            .all_rules()
+            .filter(|r| !self.derived_from.contains_key(r))
+        {
+            let concluded = relation(&rule.conclusion);
+            if !used.contains(&concluded) && !unused.contains_key(&concluded) {
+                unused.insert(concluded, self.locations.get(&rule).cloned());
+            }
+        }
+        self.diagnostics.forget(DiagnosticKind::UnusedPredicate);
+        for ((symbol, arity), location) in unused {
+            let name = self.id_server.name_of(&symbol).unwrap_or_default();
+            if outputs.contains(&name) {
+                continue;
+            }
+            self.diagnostics.push(
+                DiagnosticKind::UnusedPredicate,
+                format!("`{name}/{arity}` is concluded but never used"),
+                location,
+            )
+        }
+    }
+
+    /// Reports the input rules with premises that no derived fact comes from
+    pub(crate) fn check_fired(&mut self) {
+        let mut stack: Vec<&InnerRule> = self
+            .derived_from
+            .keys()
+            .filter(|r| r.premises.is_empty())
+            .collect();
+        let mut fired: HashSet<&InnerRule> = stack.iter().copied().collect();
+        while let Some(rule) = stack.pop() {
+            if let Some(info) = self.derived_from.get(rule) {
+                for parent in [&info.rules.0, &info.rules.1] {
+                    if fired.insert(parent) {
+                        stack.push(parent)
+                    }
+                }
+            }
+        }
+        // Goal clauses are meant never to fire, see the `consistency` module
+        let bottom = self.bottom();
+        let never: Vec<(String, Option<SourceLocation>)> = self
+            .rules
+            .iter()
+            .filter(|r| {
+                !r.premises.is_empty()
+                    && !self.derived_from.contains_key(*r)
+                    && !fired.contains(r)
+                    && Some(r.conclusion.symbol) != bottom
+            })
+            .map(|r| {
+                (
+                    r.to_string(&self.id_server).canonical().to_string(),
+                    self.locations.get(r).cloned(),
+                )
+            })
+            .collect();
+        self.diagnostics.forget(DiagnosticKind::NeverFired);
+        for (rule, location) in never {
+            self.diagnostics.push(
+                DiagnosticKind::NeverFired,
+                format!("`{rule}` never fired during this run"),
+                location,
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DiagnosticKind, Severity};
+    use crate::Sniffer;
+
+    #[test]
+    fn diagnostics_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "edge(a, b). edge(c).\nedge(X, Y) => path(X, Y).\n\
+                 secret(X) /\\ edge(X, Y) => leak(Y).\n#expect path(a, b).",
+            )
+            .unwrap();
+        let messages = |sniffer: &Sniffer, kind: DiagnosticKind| -> Vec<String> {
+            sniffer
+                .diagnostics()
+                .iter()
+                .filter(|d| d.kind == kind)
+                .map(|d| d.message.clone())
+                .collect()
+        };
+        assert_eq!(
+            messages(&sniffer, DiagnosticKind::SuspiciousArity),
+            vec!["`edge` is used with arity 1, but was first used with arity 2"]
+        );
+        assert_eq!(
+            messages(&sniffer, DiagnosticKind::UnusedPredicate),
+            vec![
+                "`edge/1` is concluded but never used",
+                "`leak/1` is concluded but never used"
+            ]
+        );
+        assert_eq!(sniffer.diagnostics().at_least(Severity::Warning).count(), 1);
+        assert!(messages(&sniffer, DiagnosticKind::NeverFired).is_empty());
+
+        sniffer.saturate_all();
+        assert_eq!(
+            messages(&sniffer, DiagnosticKind::NeverFired),
+            vec!["`edge(X0, X1) /\\ secret(X0) => leak(X1)` never fired during this run"]
+        );
+
+        // Diagnostics are found again rather than repeated
+        sniffer.assert("secret(a).").unwrap();
+        sniffer.saturate_all();
+        assert!(messages(&sniffer, DiagnosticKind::NeverFired).is_empty());
+        assert_eq!(
+            messages(&sniffer, DiagnosticKind::UnusedPredicate),
+            vec![
+                "`edge/1` is concluded but never used",
+                "`leak/1` is concluded but never used"
+            ]
+        );
+        assert_eq!(sniffer.diagnostics().len(), 3);
+        sniffer.clear_diagnostics();
+        assert!(sniffer.diagnostics().is_empty());
+    }
+}
@@ -0,0 +1,330 @@
+//! EDB module
+//! Storage of the extensional database: the ground facts given as input, as opposed
+//! to the intensional rules and the facts derived from them, which are kept in the
+//! rule set along with their provenance.
+//!
+//! Input facts have no derivation to record, so they are stored compactly: facts of
+//! each relation (predicate and arity) are laid out in columns of interned terms,
+//! one per parameter. Matching a premise against a relation only compares the
+//! columns of its ground parameters, without rebuilding the facts it rules out.
+//! Columns declared bound by a mode (`mode path(+, -).`) are also indexed by term,
+//! so that premises binding them only go through the facts they agree with
+use crate::ast::{InnerAtom, InnerRule, InnerTerm, Term};
+use crate::hashing::{HashMap, HashSet, IndexMap};
+use crate::identifiers::Identifier;
+use crate::interning::{Node, TermId, TermStore};
+
+/// Facts of a relation, stored column by column
+#[derive(Debug, Clone, Default)]
+struct Relation {
+    columns: Vec<Vec<TermId>>,
+    len: usize,
+    /// Rows holding each term, for the columns bound by the mode of the relation
+    indexes: Vec<Option<HashMap<TermId, Vec<usize>>>>,
+}
+impl Relation {
+    fn new(arity: usize, mode: Option<&Vec<bool>>) -> Relation {
+        let mut relation = Relation {
+            columns: vec![vec![]; arity],
+            len: 0,
+            indexes: vec![None; arity],
+        };
+        if let Some(mode) = mode {
+            relation.index(mode)
+        }
+        relation
+    }
+
+    /// Indexes the columns bound by a mode
+    fn index(&mut self, mode: &[bool]) {
+        for (i, bound) in mode.iter().enumerate().take(self.columns.len()) {
+            if !bound || self.indexes[i].is_some() {
+                continue;
+            }
+            let mut index: HashMap<TermId, Vec<usize>> = HashMap::default();
+            for (row, term) in self.columns[i].iter().enumerate() {
+                index.entry(*term).or_default().push(row)
+            }
+            self.indexes[i] = Some(index)
+        }
+    }
+}
+
+/// Ground input facts, by relation
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Edb {
+    relations: IndexMap<(Identifier, usize), Relation>,
+    /// Every fact, interned as a whole, to check for duplicates
+    facts: HashSet<TermId>,
+    terms: TermStore,
+    /// Parameters of each relation declared bound in queries
+    modes: HashMap<(Identifier, usize), Vec<bool>>,
+}
+impl Edb {
+    /// Whether a rule can be stored as an input fact, that is a ground fact
+    pub fn accepts(rule: &InnerRule) -> bool {
+        rule.premises.is_empty() && rule.conclusion.is_ground()
+    }
+
+    /// Adds a ground fact, returning `false` if it was already known
+    pub fn insert(&mut self, fact: &InnerAtom) -> bool {
+        let parameters: Vec<TermId> = fact
+            .parameters
+            .iter()
+            .map(|t| self.terms.intern(t))
+            .collect();
+        let atom = self
+            .terms
+            .intern_node(Node::Function(fact.symbol, &parameters));
+        if !self.facts.insert(atom) {
+            return false;
+        }
+        let key = (fact.symbol, parameters.len());
+        let relation = self
+            .relations
+            .entry(key)
+            .or_insert_with(|| Relation::new(key.1, self.modes.get(&key)));
+        let row = relation.len;
+        for (i, parameter) in parameters.into_iter().enumerate() {
+            relation.columns[i].push(parameter);
+            if let Some(index) = &mut relation.indexes[i] {
+                index.entry(parameter).or_default().push(row)
+            }
+        }
+        relation.len += 1;
+        true
+    }
+
+    /// Declares which parameters of a relation are bound in queries, indexing
+    /// their columns
+    pub fn declare_mode(&mut self, symbol: Identifier, mode: Vec<bool>) {
+        let key = (symbol, mode.len());
+        if let Some(relation) = self.relations.get_mut(&key) {
+            relation.index(&mode)
+        }
+        self.modes.insert(key, mode);
+    }
+
+    /// Parameters of every relation with a mode that queries bind
+    pub fn modes(&self) -> &HashMap<(Identifier, usize), Vec<bool>> {
+        &self.modes
+    }
+
+    /// Removes a fact, returning `false` if it was not stored. The other facts of
+    /// its relation are kept in insertion order
+    pub fn remove(&mut self, fact: &InnerAtom) -> bool {
+        let Some(atom) = self.id(fact) else {
+            return false;
+        };
+        let Some(relation) = self
+            .relations
+            .get_mut(&(fact.symbol, fact.parameters.len()))
+        else {
+            return false;
+        };
+        let Node::Function(_, parameters) = self.terms.node(atom) else {
+            return false;
+        };
+        let Some(row) = (0..relation.len).find(|&row| {
+            relation
+                .columns
+                .iter()
+                .zip(parameters)
+                .all(|(column, p)| column[row] == *p)
+        }) else {
+            return false;
+        };
+        for (column, index) in relation.columns.iter_mut().zip(&mut relation.indexes) {
+            if let Some(index) = index {
+                let rows = index.get_mut(&column[row]).expect("indexed row");
+                rows.retain(|r| *r != row);
+                if rows.is_empty() {
+                    index.remove(&column[row]);
+                }
+                // Later rows are shifted, their order being kept
+                index
+                    .values_mut()
+                    .flatten()
+                    .filter(|r| **r > row)
+                    .for_each(|r| *r -= 1);
+            }
+            column.remove(row);
+        }
+        relation.len -= 1;
+        self.facts.remove(&atom)
+    }
+
+    pub fn contains(&self, rule: &InnerRule) -> bool {
+        rule.premises.is_empty() && self.id(&rule.conclusion).is_some()
+    }
+
+    /// Identifier of a stored fact, without interning anything
+    fn id(&self, fact: &InnerAtom) -> Option<TermId> {
+        let parameters: Option<Vec<TermId>> =
+            fact.parameters.iter().map(|t| self.lookup(t)).collect();
+        let atom = self
+            .terms
+            .find_node(Node::Function(fact.symbol, &parameters?))?;
+        self.facts.contains(&atom).then_some(atom)
+    }
+
+    /// Identifier of an interned term, without interning anything
+    fn lookup(&self, term: &InnerTerm) -> Option<TermId> {
+        match term {
+            Term::Variable { symbol } => self.terms.find_node(Node::Variable(*symbol)),
+            Term::Function { symbol, parameters } => {
+                let parameters: Option<Vec<TermId>> =
+                    parameters.iter().map(|t| self.lookup(t)).collect();
+                self.terms.find_node(Node::Function(*symbol, &parameters?))
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.facts.len()
+    }
+
+    /// Every stored fact, relation by relation
+    pub fn iter(&self) -> impl Iterator<Item = InnerRule> + '_ {
+        self.relations
+            .iter()
+            .flat_map(|((symbol, _), relation)| self.rows(*symbol, relation, 0..relation.len))
+    }
+
+    /// Number of facts of each relation, along with the number of distinct terms in
+    /// each of its columns
+    pub fn statistics(
+        &self,
+    ) -> impl Iterator<Item = ((Identifier, usize), usize, Vec<usize>)> + '_ {
+        self.relations.iter().map(|(relation, facts)| {
+            let distinct = facts
+                .columns
+                .iter()
+                .map(|column| column.iter().collect::<HashSet<_>>().len())
+                .collect();
+            (*relation, facts.len, distinct)
+        })
+    }
+
+    /// Facts that may unify with the given atom: those of its relation that agree
+    /// with it on its ground parameters
+    pub fn matching(&self, atom: &InnerAtom) -> Vec<InnerRule> {
+        let Some(relation) = self.relations.get(&(atom.symbol, atom.parameters.len())) else {
+            return vec![];
+        };
+        let mut constraints = vec![];
+        for (i, parameter) in atom.parameters.iter().enumerate() {
+            if parameter.is_ground() {
+                // A ground term that was never interned cannot match any fact
+                let Some(id) = self.lookup(parameter) else {
+                    return vec![];
+                };
+                constraints.push((i, id))
+            }
+        }
+        let satisfied = |row: &usize| {
+            constraints
+                .iter()
+                .all(|(i, id)| relation.columns[*i][*row] == *id)
+        };
+        // The rows of the fewest facts agreeing with an indexed column are enough
+        let indexed = constraints
+            .iter()
+            .filter_map(|(i, id)| {
+                let index = relation.indexes[*i].as_ref()?;
+                Some(index.get(id).map_or(&[][..], Vec::as_slice))
+            })
+            .min_by_key(|rows| rows.len());
+        match indexed {
+            Some(rows) => {
+                let mut rows: Vec<usize> = rows.iter().copied().filter(satisfied).collect();
+                rows.sort_unstable();
+                self.rows(atom.symbol, relation, rows.into_iter()).collect()
+            }
+            None => {
+                let rows = (0..relation.len).filter(satisfied);
+                self.rows(atom.symbol, relation, rows).collect()
+            }
+        }
+    }
+
+    /// Rebuilds the given rows of a relation as facts
+    fn rows<'a>(
+        &'a self,
+        symbol: Identifier,
+        relation: &'a Relation,
+        rows: impl Iterator<Item = usize> + 'a,
+    ) -> impl Iterator<Item = InnerRule> + 'a {
+        rows.map(move |row| InnerRule {
+            premises: vec![],
+            conclusion: InnerAtom {
+                symbol,
+                parameters: relation
+                    .columns
+                    .iter()
+                    .map(|column| self.terms.term(column[row]))
+                    .collect(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Edb;
+    use crate::identifiers::IdentifierServer;
+    use crate::{Parser, Rule};
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn edb_test() {
+        let mut id_server = IdentifierServer::default();
+        let mut inner = |source: &str| {
+            let rule: Rule<String> = Parser::parse_rules(Tokens::new(source)).unwrap().remove(0);
+            rule.to_inner(&mut id_server)
+        };
+        let facts = [
+            inner("edge(a, b)."),
+            inner("edge(a, c)."),
+            inner("edge(b, c)."),
+        ];
+        let premise = inner("edge(a, X).").conclusion;
+        let unknown = inner("edge(d, X).").conclusion;
+        let rule = inner("edge(X, Y) => path(X, Y).");
+
+        let mut edb = Edb::default();
+        assert!(!Edb::accepts(&rule));
+        for fact in &facts {
+            assert!(Edb::accepts(fact));
+            assert!(edb.insert(&fact.conclusion));
+        }
+        assert!(!edb.insert(&facts[0].conclusion));
+        assert_eq!(edb.len(), 3);
+        assert_eq!(edb.matching(&premise), facts[..2].to_vec());
+        assert!(edb.matching(&unknown).is_empty());
+
+        assert!(edb.remove(&facts[0].conclusion));
+        assert!(!edb.contains(&facts[0]));
+        assert!(edb.contains(&facts[2]));
+        // The remaining facts keep their insertion order
+        assert_eq!(edb.iter().collect::<Vec<_>>(), facts[1..].to_vec());
+
+        // Indexed columns give the same facts, in the same order
+        let target = inner("edge(X, c).").conclusion;
+        assert_eq!(edb.matching(&target), facts[1..].to_vec());
+        edb.declare_mode(target.symbol, vec![false, true]);
+        assert_eq!(edb.matching(&target), facts[1..].to_vec());
+        assert!(edb.remove(&facts[1].conclusion));
+        assert!(edb.insert(&facts[0].conclusion));
+        assert_eq!(edb.matching(&target), vec![facts[2].clone()]);
+        assert_eq!(
+            edb.iter().collect::<Vec<_>>(),
+            vec![facts[2].clone(), facts[0].clone()]
+        );
+        assert_eq!(
+            edb.matching(&inner("edge(a, X).").conclusion),
+            vec![facts[0].clone()]
+        );
+        assert!(edb.matching(&inner("edge(X, d).").conclusion).is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+//! FFI module
+//! Stable C ABI for embedding the engine in C and C++ programs. Declarations are
+//! in `include/pif.h`.
+//!
+//! Every object returned by the library is owned by the caller and must be released
+//! with `pif_free`, `pif_derivation_free` or `pif_string_free`. Strings are
+//! NUL-terminated UTF-8. When a function fails it returns null, and
+//! `pif_last_error` describes the failure
+use crate::{DerivationTree, Parser, RunFingerprint, SaturationFailure, Sniffer};
+use logos_nom_bridge::Tokens;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+
+/// Opaque handle on a Sniffer
+pub struct PifSniffer(Sniffer);
+/// Opaque handle on a derivation tree, along with the fingerprint of the run that
+/// found it
+pub struct PifDerivation(DerivationTree, RunFingerprint);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(message: impl ToString) {
+    let message = CString::new(message.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Reads a C string, recording an error if it is null or not UTF-8
+unsafe fn read<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() {
+        set_error("unexpected null pointer");
+        return None;
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| set_error("string is not valid UTF-8"))
+        .ok()
+}
+
+/// Returns the message of the last failure on this thread, or null. The message
+/// stays valid until the next failing call on this thread
+#[no_mangle]
+pub extern "C" fn pif_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Creates a Sniffer from the rules of a `.pif` source
+///
+/// # Safety
+/// `source` must be a valid NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn pif_new_from_str(source: *const c_char) -> *mut PifSniffer {
+    let Some(source) = read(source) else {
+        return ptr::null_mut();
+    };
+    let mut sniffer = Sniffer::default();
+    match sniffer.assert(source) {
+        Ok(_) => Box::into_raw(Box::new(PifSniffer(sniffer))),
+        Err(e) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Saturates the rule set until the atom (such as `att(m).`) is derived, returning
+/// its derivation. Returns null if the atom is not derivable, in which case
+/// `pif_last_error` is left untouched, or on errors
+///
+/// # Safety
+/// `sniffer` must come from `pif_new_from_str`, and `atom` must be a valid
+/// NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn pif_query(
+    sniffer: *mut PifSniffer,
+    atom: *const c_char,
+) -> *mut PifDerivation {
+    let (Some(sniffer), Some(atom)) = (sniffer.as_mut(), read(atom)) else {
+        set_error("unexpected null pointer or invalid string");
+        return ptr::null_mut();
+    };
+    let atom = match Parser::parse_query_with(Tokens::new(atom), sniffer.0.parser_options()) {
+        Ok(atom) => atom,
+        Err(e) => {
+            set_error(e);
+            return ptr::null_mut();
+        }
+    };
+    match sniffer.0.find(&atom) {
+        Ok(tree) => Box::into_raw(Box::new(PifDerivation(tree, sniffer.0.fingerprint()))),
+        Err(SaturationFailure::Derivation(e)) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Encodes a derivation as JSON, as nested `{"rule", "selected", "depth", "subtrees"}` objects,
+/// the outermost one with a `fingerprint` field for the run
+///
+/// # Safety
+/// `derivation` must come from `pif_query`
+#[no_mangle]
+pub unsafe extern "C" fn pif_derivation_to_json(derivation: *const PifDerivation) -> *mut c_char {
+    let Some(derivation) = derivation.as_ref() else {
+        set_error("unexpected null pointer");
+        return ptr::null_mut();
+    };
+    let mut json = derivation.0.to_json();
+    json["fingerprint"] = derivation.1.to_string().into();
+    // JSON escapes NUL characters, so this cannot fail
+    CString::new(json.to_string()).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Releases a Sniffer
+///
+/// # Safety
+/// `sniffer` must come from `pif_new_from_str` (or be null), and not be released twice
+#[no_mangle]
+pub unsafe extern "C" fn pif_free(sniffer: *mut PifSniffer) {
+    if !sniffer.is_null() {
+        drop(Box::from_raw(sniffer))
+    }
+}
+
+/// Releases a derivation
+///
+/// # Safety
+/// `derivation` must come from `pif_query` (or be null), and not be released twice
+#[no_mangle]
+pub unsafe extern "C" fn pif_derivation_free(derivation: *mut PifDerivation) {
+    if !derivation.is_null() {
+        drop(Box::from_raw(derivation))
+    }
+}
+
+/// Releases a string returned by the library
+///
+/// # Safety
+/// `string` must come from `pif_derivation_to_json` (or be null), and not be released twice
+#[no_mangle]
+pub unsafe extern "C" fn pif_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_test() {
+        let source =
+            CString::new("att(senc(X, Y)) /\\ att(Y) => att(X).\natt(senc(m, k)).\natt(k).")
+                .unwrap();
+        unsafe {
+            let sniffer = pif_new_from_str(source.as_ptr());
+            assert!(!sniffer.is_null());
+
+            let atom = CString::new("att(m).").unwrap();
+            let derivation = pif_query(sniffer, atom.as_ptr());
+            assert!(!derivation.is_null());
+            let json = pif_derivation_to_json(derivation);
+            let value: serde_json::Value =
+                serde_json::from_str(CStr::from_ptr(json).to_str().unwrap()).unwrap();
+            assert_eq!(value["rule"], "att(m)");
+            assert_eq!(value["fingerprint"], (*sniffer).0.fingerprint().to_string());
+            pif_string_free(json);
+            pif_derivation_free(derivation);
+
+            let atom = CString::new("att(n).").unwrap();
+            assert!(pif_query(sniffer, atom.as_ptr()).is_null());
+            let atom = CString::new("att(").unwrap();
+            assert!(pif_query(sniffer, atom.as_ptr()).is_null());
+            assert!(!pif_last_error().is_null());
+            pif_free(sniffer);
+
+            // Queries are parsed with the operators declared by the rules
+            let source = CString::new("op 500 yfx +.\natt(a + b).").unwrap();
+            let sniffer = pif_new_from_str(source.as_ptr());
+            let atom = CString::new("att(a + b).").unwrap();
+            let derivation = pif_query(sniffer, atom.as_ptr());
+            assert!(!derivation.is_null());
+            pif_derivation_free(derivation);
+            pif_free(sniffer);
+
+            let invalid = CString::new("att(").unwrap();
+            assert!(pif_new_from_str(invalid.as_ptr()).is_null());
+        }
+    }
+}
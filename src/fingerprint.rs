@@ -0,0 +1,238 @@
+//! Fingerprint module
+//! Everything needed to reproduce a run, gathered in a `RunFingerprint`: the
+//! version and features of the engine, the options the context runs with, the
+//! seed of randomized choices and a digest of its input. Saturation itself is
+//! deterministic, so two runs with equal fingerprints derive the same rules in the
+//! same order.
+//!
+//! Fingerprints are written as a single line of `key=value` fields, such as
+//! `sniffer/0.1.0 features=fxhash strategy=hybrid max-term-depth=- threads=-
+//! convention=capitalized seed=- input=8c3f...`, and embedded in the exports of a
+//! run: journals, checkpoints, profiles and the JSON responses of the server and
+//! playground. `RunFingerprint::parse` reads one back from a bug report, and
+//! `RunFingerprint::builder` rebuilds a context with the same options.
+//!
+//! The input digest is a 64-bit FNV-1a hash of the input rules, in the order they
+//! were given, and of the directives. Rules being hashed in canonical form, it does
+//! not depend on variable names, but it does on the order of the input, which
+//! decides the order of saturation
+use crate::config::SnifferBuilder;
+use crate::parser::{NamingConvention, ParserOptions};
+use crate::provenance::Provenance;
+use crate::prover::Strategy;
+use crate::Sniffer;
+
+/// Fingerprint of a run, see the `fingerprint` module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunFingerprint {
+    /// Version of the engine
+    pub version: String,
+    /// Enabled features changing how rules are stored or resolved
+    pub features: Vec<String>,
+    pub strategy: Strategy,
+    pub max_term_depth: Option<usize>,
+    pub threads: Option<usize>,
+    pub convention: NamingConvention,
+    /// Seed of the randomized choices made for the run, if any
+    pub seed: Option<u64>,
+    /// Digest of the input rules and directives
+    pub input: u64,
+}
+impl RunFingerprint {
+    /// Reads a fingerprint written by its `Display` implementation
+    pub fn parse(line: &str) -> Option<RunFingerprint> {
+        let mut fields = line.split_whitespace();
+        let version = fields.next()?.strip_prefix("sniffer/")?.to_string();
+        let mut fields: Vec<(&str, &str)> = fields
+            .map(|field| field.split_once('='))
+            .collect::<Option<_>>()?;
+        let mut field = |key: &str| {
+            let i = fields.iter().position(|(k, _)| *k == key)?;
+            Some(fields.remove(i).1)
+        };
+        fn optional<T: std::str::FromStr>(value: &str) -> Option<Option<T>> {
+            match value {
+                "-" => Some(None),
+                value => value.parse().ok().map(Some),
+            }
+        }
+        let features = match field("features")? {
+            "-" => vec![],
+            features => features.split(',').map(String::from).collect(),
+        };
+        let strategy = match field("strategy")? {
+            "bottom-up" => Strategy::BottomUp,
+            "hybrid" => Strategy::Hybrid,
+            strategy => Strategy::TopDown {
+                depth: strategy.strip_prefix("top-down:")?.parse().ok()?,
+            },
+        };
+        let max_term_depth = optional(field("max-term-depth")?)?;
+        let threads = optional(field("threads")?)?;
+        let convention = match field("convention")? {
+            "capitalized" => NamingConvention::Capitalized,
+            "sigil" => NamingConvention::Sigil,
+            _ => return None,
+        };
+        let seed = optional(field("seed")?)?;
+        let input = u64::from_str_radix(field("input")?, 16).ok()?;
+        fields.is_empty().then_some(RunFingerprint {
+            version,
+            features,
+            strategy,
+            max_term_depth,
+            threads,
+            convention,
+            seed,
+            input,
+        })
+    }
+
+    /// A builder with the options of the run, see `SnifferBuilder`
+    pub fn builder(&self) -> SnifferBuilder {
+        let mut builder = Sniffer::builder().strategy(self.strategy);
+        if let Some(depth) = self.max_term_depth {
+            builder = builder.max_term_depth(depth)
+        }
+        if let Some(threads) = self.threads {
+            builder = builder.threads(threads)
+        }
+        if let Some(seed) = self.seed {
+            builder = builder.seed(seed)
+        }
+        builder.parser_options(ParserOptions {
+            convention: self.convention,
+            ..ParserOptions::default()
+        })
+    }
+}
+impl std::fmt::Display for RunFingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn optional<T: std::fmt::Display>(value: Option<T>) -> String {
+            value.map_or(String::from("-"), |v| v.to_string())
+        }
+        let features = match &self.features[..] {
+            [] => String::from("-"),
+            features => features.join(","),
+        };
+        let strategy = match self.strategy {
+            Strategy::BottomUp => String::from("bottom-up"),
+            Strategy::TopDown { depth } => format!("top-down:{depth}"),
+            Strategy::Hybrid => String::from("hybrid"),
+        };
+        let convention = match self.convention {
+            NamingConvention::Capitalized => "capitalized",
+            NamingConvention::Sigil => "sigil",
+        };
+        write!(
+            f,
+            "sniffer/{} features={features} strategy={strategy} max-term-depth={} threads={} \
+             convention={convention} seed={} input={:016x}",
+            self.version,
+            optional(self.max_term_depth),
+            optional(self.threads),
+            optional(self.seed),
+            self.input
+        )
+    }
+}
+
+/// Hashes bytes with 64-bit FNV-1a, which is stable across platforms and versions
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl<P: Provenance> Sniffer<P> {
+    /// Fingerprint of the runs of this context, see the `fingerprint` module
+    pub fn fingerprint(&self) -> RunFingerprint {
+        let features = [
+            ("fxhash", cfg!(feature = "fxhash")),
+            ("parallel", cfg!(feature = "parallel")),
+        ];
+        let config = self.config();
+        RunFingerprint {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            features: features
+                .into_iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(feature, _)| feature.to_string())
+                .collect(),
+            strategy: config.strategy(),
+            max_term_depth: config.max_term_depth(),
+            threads: config.threads(),
+            convention: config.parser_options().convention,
+            seed: config.seed(),
+            input: self.input_digest(),
+        }
+    }
+
+    /// Digest of the input rules, in order, and of the directives
+    fn input_digest(&self) -> u64 {
+        let mut hash = 0xcbf29ce484222325;
+        let mut add = |line: String| {
+            hash = fnv1a(hash, line.as_bytes());
+            hash = fnv1a(hash, b"\n");
+        };
+        for rule in self
+            .all_rules()
+            .filter(|r| !self.derived_from.contains_key(r))
+        {
+            add(rule.to_string(&self.id_server).canonical().to_string())
+        }
+        for expectation in &self.expectations {
+            add(format!("{} {}", expectation.derivable, expectation.atom))
+        }
+        for goal in &self.goals {
+            add(format!("?- {goal}"))
+        }
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RunFingerprint;
+    use crate::{Sniffer, Strategy};
+
+    #[test]
+    fn fingerprint_test() {
+        let source =
+            "att(k). att(senc(m, k)).\natt(senc(X, Y)) /\\ att(Y) => att(X).\n#expect att(m).";
+        let mut sniffer = Sniffer::builder()
+            .strategy(Strategy::TopDown { depth: 8 })
+            .max_term_depth(4)
+            .seed(42)
+            .build()
+            .unwrap();
+        sniffer.assert(source).unwrap();
+        let fingerprint = sniffer.fingerprint();
+        assert_eq!(fingerprint.seed, Some(42));
+        let line = fingerprint.to_string();
+        assert!(line.contains(" strategy=top-down:8 max-term-depth=4 threads=- "));
+        assert_eq!(RunFingerprint::parse(&line), Some(fingerprint.clone()));
+        assert_eq!(RunFingerprint::parse(&format!("{line} extra=1")), None);
+
+        // A context rebuilt from the fingerprint, with the same input, has the same one
+        let mut rebuilt = fingerprint.builder().build().unwrap();
+        rebuilt.assert(source).unwrap();
+        assert_eq!(rebuilt.fingerprint(), fingerprint);
+        // Derived rules are not part of the input
+        rebuilt.saturate_all();
+        assert_eq!(rebuilt.fingerprint(), fingerprint);
+        // Variable names do not change the digest, the order of the input does
+        let renamed =
+            "att(k). att(senc(m, k)).\natt(senc(A, B)) /\\ att(B) => att(A).\n#expect att(m).";
+        let mut other = fingerprint.builder().build().unwrap();
+        other.assert(renamed).unwrap();
+        assert_eq!(other.fingerprint().input, fingerprint.input);
+        let mut reordered = fingerprint.builder().build().unwrap();
+        reordered
+            .assert(
+                "att(senc(m, k)). att(k).\natt(senc(X, Y)) /\\ att(Y) => att(X).\n#expect att(m).",
+            )
+            .unwrap();
+        assert_ne!(reordered.fingerprint().input, fingerprint.input);
+    }
+}
@@ -0,0 +1,430 @@
+//! Fmt module
+//! Pretty-printing of rule sets and derivation trees for terminals, configured by
+//! `FormatOptions`. Rules can be filtered by the predicate they conclude, deep
+//! terms elided and variables renamed, and rules wider than a given number of
+//! columns are wrapped after their conjunctions, continuation lines being indented:
+//! ```text
+//! att(senc(X, Y)) /\
+//!     att(Y) => att(X)
+//! ```
+//! With the `color` feature, predicates, functions, constants and variables are
+//! painted in distinct ANSI colors
+
+use crate::ast::{Folder, Rule, Term};
+use crate::derivation_tree::DerivationTree;
+use crate::hashing::HashMap;
+use crate::lexer::{write_applied_symbol, write_symbol};
+use crate::operators::OperatorTable;
+use crate::resolution::Selection;
+use ptree::{Color, Style};
+use std::fmt::{Display, Formatter, Result};
+
+/// How printed variables are named
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VariableNaming {
+    /// Variables keep their names, derived rules using `VAR<n>`
+    #[default]
+    Preserved,
+    /// Variables are named `X`, `Y`, `Z`, `U`, `V`, `W`, then `X1`, `Y1`... in order
+    /// of first occurrence
+    Letters,
+    /// Variables are named `X0`, `X1`... in order of first occurrence
+    Indexed,
+}
+impl VariableNaming {
+    fn name(&self, index: usize) -> String {
+        const LETTERS: [&str; 6] = ["X", "Y", "Z", "U", "V", "W"];
+        match self {
+            VariableNaming::Preserved => unreachable!("preserved variables are not renamed"),
+            VariableNaming::Letters if index < LETTERS.len() => LETTERS[index].to_string(),
+            VariableNaming::Letters => {
+                format!(
+                    "{}{}",
+                    LETTERS[index % LETTERS.len()],
+                    index / LETTERS.len()
+                )
+            }
+            VariableNaming::Indexed => format!("X{index}"),
+        }
+    }
+}
+
+/// Options of the printers, which print every rule on a single line by default
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// Paints symbols with ANSI colors
+    #[cfg(feature = "color")]
+    pub color: bool,
+    /// Rules wider than this many columns are wrapped after their conjunctions
+    pub max_width: Option<usize>,
+    /// Only rules concluding one of these predicates are printed, every rule is if empty
+    pub predicates: Vec<String>,
+    /// Terms nested deeper than this in an atom are elided as `...`, the parameters
+    /// of the atom being at depth 1
+    pub max_depth: Option<usize>,
+    pub variables: VariableNaming,
+    /// Follows the input rules of a Sniffer with the location they were given at,
+    /// as a comment
+    pub labels: bool,
+    /// Indentation of continuation lines and of the levels of derivation trees, at
+    /// least 3 columns
+    pub indent: usize,
+}
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            #[cfg(feature = "color")]
+            color: false,
+            max_width: None,
+            predicates: vec![],
+            max_depth: None,
+            variables: VariableNaming::default(),
+            labels: false,
+            indent: 4,
+        }
+    }
+}
+impl FormatOptions {
+    /// Whether a rule is printed with these options
+    pub fn shows(&self, rule: &Rule<String>) -> bool {
+        self.predicates.is_empty() || self.predicates.contains(&rule.conclusion.symbol)
+    }
+
+    fn rendering(&self) -> Rendering {
+        #[cfg(feature = "color")]
+        if self.color {
+            return Rendering {
+                max_depth: self.max_depth,
+                ..Rendering::colored()
+            };
+        }
+        self.plain()
+    }
+
+    fn plain(&self) -> Rendering {
+        Rendering {
+            max_depth: self.max_depth,
+            ..Rendering::default()
+        }
+    }
+}
+
+/// How the symbols of printed rules are written, plainly and in full by default
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Rendering {
+    predicate: Option<Style>,
+    function: Option<Style>,
+    constant: Option<Style>,
+    variable: Option<Style>,
+    pub max_depth: Option<usize>,
+}
+impl Rendering {
+    #[cfg(feature = "color")]
+    fn colored() -> Rendering {
+        let style = |color, bold| Style {
+            foreground: Some(color),
+            bold,
+            ..Style::default()
+        };
+        Rendering {
+            predicate: Some(style(Color::Blue, true)),
+            function: Some(style(Color::Cyan, false)),
+            constant: Some(style(Color::Yellow, false)),
+            variable: Some(style(Color::Green, false)),
+            max_depth: None,
+        }
+    }
+
+    /// Whether terms at the given depth are elided
+    pub fn elides(&self, depth: usize) -> bool {
+        self.max_depth.is_some_and(|max| depth > max)
+    }
+
+    /// Writes the symbol of a predicate, applied to parameters or not
+    pub fn write_predicate(&self, f: &mut Formatter<'_>, symbol: &str, applied: bool) -> Result {
+        write_styled(f, &self.predicate, Symbol { symbol, applied })
+    }
+
+    pub fn write_function(&self, f: &mut Formatter<'_>, symbol: &str) -> Result {
+        write_styled(
+            f,
+            &self.function,
+            Symbol {
+                symbol,
+                applied: true,
+            },
+        )
+    }
+
+    pub fn write_constant(&self, f: &mut Formatter<'_>, symbol: &str) -> Result {
+        write_styled(
+            f,
+            &self.constant,
+            Symbol {
+                symbol,
+                applied: false,
+            },
+        )
+    }
+
+    pub fn write_variable(&self, f: &mut Formatter<'_>, symbol: &str) -> Result {
+        match &self.variable {
+            Some(style) => write!(f, "{}", style.paint(symbol)),
+            None => f.write_str(symbol),
+        }
+    }
+}
+
+/// A symbol, quoted when it has to be, depending on whether it is applied to
+/// parameters
+struct Symbol<'a> {
+    symbol: &'a str,
+    applied: bool,
+}
+impl Display for Symbol<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if self.applied {
+            write_applied_symbol(f, self.symbol)
+        } else {
+            write_symbol(f, self.symbol)
+        }
+    }
+}
+
+fn write_styled(f: &mut Formatter<'_>, style: &Option<Style>, symbol: Symbol) -> Result {
+    match style {
+        Some(style) => write!(f, "{}", style.paint(symbol)),
+        None => symbol.fmt(f),
+    }
+}
+
+/// Renames variables in order of first occurrence
+struct Rename {
+    naming: VariableNaming,
+    names: HashMap<String, String>,
+}
+impl Folder<String> for Rename {
+    fn fold_variable(&mut self, symbol: String) -> Term<String> {
+        let next = self.names.len();
+        let naming = self.naming;
+        let name = self
+            .names
+            .entry(symbol)
+            .or_insert_with(|| naming.name(next));
+        Term::Variable {
+            symbol: name.clone(),
+        }
+    }
+}
+
+/// Prints a rule, writing applications of the given operators infix
+pub fn rule_to_string(
+    rule: &Rule<String>,
+    operators: &OperatorTable,
+    options: &FormatOptions,
+) -> String {
+    format_rule(rule, operators, options, options.max_width, None)
+}
+
+/// Prints a rule with the given options, wrapping it to `max_width`, and
+/// emphasizing its `emphasized`-th atom if any, the conclusion coming after the
+/// premises
+fn format_rule(
+    rule: &Rule<String>,
+    operators: &OperatorTable,
+    options: &FormatOptions,
+    max_width: Option<usize>,
+    emphasized: Option<usize>,
+) -> String {
+    let rule = match options.variables {
+        VariableNaming::Preserved => rule.displayed(),
+        naming => Rename {
+            naming,
+            names: HashMap::default(),
+        }
+        .fold_rule(rule.clone())
+        .displayed(),
+    };
+    let rendering = options.rendering();
+    let plain = options.plain();
+    let emphasis = Style {
+        foreground: Some(Color::Red),
+        bold: true,
+        ..Style::default()
+    };
+    // Atoms along with the separator preceding them and their width once printed
+    let atoms = rule
+        .premises
+        .iter()
+        .enumerate()
+        .map(|(i, premise)| (if i == 0 { "" } else { "/\\" }, premise))
+        .chain(std::iter::once((
+            if rule.premises.is_empty() { "" } else { "=>" },
+            &rule.conclusion,
+        )))
+        .enumerate()
+        .map(|(i, (separator, atom))| {
+            let unstyled = operators.display_atom(atom, &plain).to_string();
+            let width = unstyled.chars().count();
+            let printed = if emphasized == Some(i) {
+                emphasis.paint(unstyled).to_string()
+            } else {
+                operators.display_atom(atom, &rendering).to_string()
+            };
+            (separator, printed, width)
+        });
+
+    let mut printed = String::new();
+    let mut column = 0;
+    for (separator, atom, width) in atoms {
+        if separator.is_empty() {
+            printed.push_str(&atom);
+            column = width;
+            continue;
+        }
+        // Separators stay at the end of the line when wrapping
+        if max_width.is_some_and(|max| column + separator.len() + width + 2 > max) {
+            let indent = " ".repeat(options.indent);
+            printed.push_str(&format!(" {separator}\n{indent}{atom}"));
+            column = options.indent + width;
+        } else {
+            printed.push_str(&format!(" {separator} {atom}"));
+            column += separator.len() + width + 2;
+        }
+    }
+    printed
+}
+
+/// Prints the rules shown with the given options, one per line
+pub fn rules_to_string(
+    rules: impl IntoIterator<Item = Rule<String>>,
+    operators: &OperatorTable,
+    options: &FormatOptions,
+) -> String {
+    rules
+        .into_iter()
+        .filter(|rule| options.shows(rule))
+        .map(|rule| rule_to_string(&rule, operators, options))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Prints a derivation tree, a rule per line below its parent, the atom each rule
+/// was resolved upon being emphasized. Rules are not wrapped in trees
+pub fn tree_to_string(
+    tree: &DerivationTree,
+    operators: &OperatorTable,
+    options: &FormatOptions,
+) -> String {
+    let mut printed = String::new();
+    write_tree(&mut printed, tree, operators, options, "", "");
+    printed
+}
+
+fn write_tree(
+    printed: &mut String,
+    tree: &DerivationTree,
+    operators: &OperatorTable,
+    options: &FormatOptions,
+    prefix: &str,
+    children_prefix: &str,
+) {
+    let rule = tree.root();
+    let emphasized = tree.selection().map(|selection| match selection {
+        Selection::Premise(_, i) if *i < rule.premises.len() => *i,
+        Selection::Premise(..) | Selection::Conclusion(_) => rule.premises.len(),
+    });
+    printed.push_str(prefix);
+    printed.push_str(&format_rule(rule, operators, options, None, emphasized));
+    if let Some(elision) = tree.elided() {
+        printed.push_str(&format!(" {elision}"));
+    }
+    printed.push('\n');
+
+    let width = options.indent.max(3) - 2;
+    let subtrees = tree.subtrees();
+    for (i, subtree) in subtrees.iter().enumerate() {
+        let (branch, continuation) = if i + 1 == subtrees.len() {
+            ("└", " ")
+        } else {
+            ("├", "│")
+        };
+        let prefix = format!("{children_prefix}{branch}{} ", "─".repeat(width));
+        let children = format!("{children_prefix}{continuation}{}", " ".repeat(width + 1));
+        write_tree(printed, subtree, operators, options, &prefix, &children);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rules_to_string, tree_to_string, FormatOptions, VariableNaming};
+    use crate::{OperatorTable, Parser, Sniffer};
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn print_test() {
+        let rules = Parser::parse_rules(Tokens::new(
+            "att(senc(X, Y)) /\\ att(Y) => att(X).\natt(k).\nknows(a, b).",
+        ))
+        .unwrap();
+        let operators = OperatorTable::default();
+        let print = |options: &FormatOptions| rules_to_string(rules.clone(), &operators, options);
+
+        let mut options = FormatOptions::default();
+        assert_eq!(
+            print(&options),
+            "att(senc(X, Y)) /\\ att(Y) => att(X)\natt(k)\nknows(a, b)"
+        );
+        options.predicates = vec!["att".to_string()];
+        options.max_width = Some(24);
+        assert_eq!(
+            print(&options),
+            "att(senc(X, Y)) /\\\n    att(Y) => att(X)\natt(k)"
+        );
+
+        #[cfg(feature = "color")]
+        {
+            options.color = true;
+            let colored = print(&options);
+            assert!(colored.contains("\u{1b}["));
+            // Colors do not count in the width of the rules
+            assert_eq!(colored.lines().count(), 3);
+        }
+    }
+
+    #[test]
+    fn format_options_test() {
+        let rules = Parser::parse_rules(Tokens::new(
+            "att(senc(Msg, Key)) /\\ att(Key) => att(Msg).\natt(senc(senc(m, k), k)).",
+        ))
+        .unwrap();
+        let operators = OperatorTable::default();
+        let options = FormatOptions {
+            max_depth: Some(2),
+            variables: VariableNaming::Letters,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            rules_to_string(rules, &operators, &options),
+            "att(senc(X, Y)) /\\ att(Y) => att(X)\natt(senc(senc(..., ...), k))"
+        );
+
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert("att(senc(X, Y)) /\\ att(Y) => att(X).\natt(senc(m, k)).\natt(k).")
+            .unwrap();
+        let query = Parser::parse_query(Tokens::new("att(m).")).unwrap();
+        let tree = sniffer.find(&query).unwrap();
+        let options = FormatOptions {
+            variables: VariableNaming::Indexed,
+            indent: 3,
+            ..FormatOptions::default()
+        };
+        let printed = tree_to_string(&tree, &operators, &options);
+        let lines: Vec<_> = printed.lines().collect();
+        assert_eq!(lines[0], "att(m)");
+        assert!(lines[1].starts_with("├─ "));
+        assert!(lines.iter().any(|line| line.starts_with("│  └─ ")));
+        assert!(lines.iter().all(|line| !line.contains("VAR")));
+    }
+}
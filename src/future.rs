@@ -0,0 +1,100 @@
+//! Future module
+//! Asynchronous saturation, for embedding the engine in async services.
+//!
+//! The future runs a bounded number of saturation steps each time it is polled,
+//! then publishes the facts derived so far to the views of the Sniffer and yields.
+//! Dropping it cancels saturation, leaving the Sniffer with the facts derived so
+//! far; saturating again later starts over from the whole rule set, so nothing is lost
+use crate::agenda::Agenda;
+use crate::planner::JoinPlan;
+use crate::provenance::{Derivations, Provenance};
+use crate::{guarded, keep_guarded, Sniffer};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Default number of saturation steps run before yielding
+const STEPS_PER_POLL: usize = 64;
+
+/// Future returned by `Sniffer::saturate_async`, resolving once the rule set is saturated
+pub struct SaturationFuture<'a, P: Provenance = Derivations> {
+    sniffer: &'a mut Sniffer<P>,
+    rules_set: Agenda,
+    plan: JoinPlan,
+    steps: usize,
+}
+impl<'a, P: Provenance> SaturationFuture<'a, P> {
+    pub(crate) fn new(sniffer: &'a mut Sniffer<P>) -> SaturationFuture<'a, P> {
+        let rules_set = sniffer.agenda(false);
+        let plan = sniffer.join_plan();
+        SaturationFuture {
+            sniffer,
+            rules_set,
+            plan,
+            steps: STEPS_PER_POLL,
+        }
+    }
+
+    /// Sets the number of saturation steps run before yielding to the executor
+    pub fn yield_every(mut self, steps: usize) -> Self {
+        self.steps = steps.max(1);
+        self
+    }
+}
+impl<P: Provenance> Future for SaturationFuture<'_, P> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let done = this.sniffer.saturate_steps(
+            &mut this.rules_set,
+            &[],
+            &|r| this.plan.select(r, guarded),
+            &keep_guarded,
+            this.steps,
+            usize::MAX,
+        );
+        let instances = if done {
+            this.sniffer.advance_time()
+        } else {
+            vec![]
+        };
+        if done && instances.is_empty() {
+            Poll::Ready(())
+        } else {
+            for rule in instances {
+                let weight = this.sniffer.weight(&rule);
+                this.rules_set.push(rule, weight, 0)
+            }
+            this.sniffer.publish();
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Waker;
+
+    #[test]
+    fn yield_test() {
+        let source = "att(senc(X, Y)) /\\ att(Y) => att(X).\natt(senc(m, k)).\natt(k).";
+        let mut sniffer = Sniffer::default();
+        sniffer.assert(source).unwrap();
+
+        let mut future = sniffer.saturate_async().yield_every(1);
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut polls = 1;
+        while Pin::new(&mut future).poll(&mut cx).is_pending() {
+            polls += 1;
+        }
+        assert!(polls > 1);
+
+        let mut expected = Sniffer::default();
+        expected.assert(source).unwrap();
+        expected.saturate_all();
+        assert_eq!(sniffer.rules_to_string(), expected.rules_to_string());
+    }
+}
@@ -0,0 +1,153 @@
+//! Ground module
+//! Index separating the ground facts of the rule set from its other clauses, so
+//! that saturation does not try to resolve a rule with facts it cannot use.
+//!
+//! Most derived facts become ground after a few rounds, and they make up most of
+//! the rule set. Facts never have a selected premise, so a rule whose conclusion
+//! is selected is only resolved with clauses, and a rule whose premise is selected
+//! with clauses and the ground facts of the relation of that premise, or the one
+//! fact equal to it if the premise itself is ground. Ground facts are found by
+//! hashing their conclusion in a flat table.
+//!
+//! Rules stay in `Sniffer::rules`, which keeps their insertion order: the index
+//! holds their positions, catches up with rules added since it was last used and
+//! is rebuilt when rules are removed
+use crate::ast::{InnerAtom, InnerRule};
+use crate::hashing::{HashMap, IndexSet};
+use crate::identifiers::Identifier;
+use crate::resolution::Selection;
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GroundFacts {
+    /// Position of every ground fact, by conclusion
+    facts: HashMap<InnerAtom, usize>,
+    /// Positions of the ground facts of each relation, in increasing order
+    relations: HashMap<(Identifier, usize), Vec<usize>>,
+    /// Positions of the other rules, in increasing order
+    clauses: Vec<usize>,
+    /// Number of rules indexed so far
+    indexed: usize,
+}
+impl GroundFacts {
+    /// Indexes the rules added to the set since the last call. Positions are no
+    /// longer valid once a rule is removed, so the index is then rebuilt
+    pub fn sync(&mut self, rules: &IndexSet<InnerRule>) {
+        if self.indexed > rules.len() {
+            *self = GroundFacts::default()
+        }
+        for (i, rule) in rules.iter().enumerate().skip(self.indexed) {
+            if rule.premises.is_empty() && rule.conclusion.is_ground() {
+                let relation = (rule.conclusion.symbol, rule.conclusion.parameters.len());
+                self.relations.entry(relation).or_default().push(i);
+                self.facts.insert(rule.conclusion.clone(), i);
+            } else {
+                self.clauses.push(i)
+            }
+        }
+        self.indexed = rules.len()
+    }
+
+    /// Forgets every position, for when rules were removed
+    pub fn clear(&mut self) {
+        *self = GroundFacts::default()
+    }
+
+    /// Positions of the rules a rule with the given selection may be resolved with,
+    /// in increasing order
+    pub fn candidates(&self, selection: &Selection<Identifier>) -> Vec<usize> {
+        let Selection::Premise(premise, _) = selection else {
+            return self.clauses.clone();
+        };
+        let facts: &[usize] = if premise.is_ground() {
+            match self.facts.get(premise) {
+                Some(i) => std::slice::from_ref(i),
+                None => &[],
+            }
+        } else {
+            self.relations
+                .get(&(premise.symbol, premise.parameters.len()))
+                .map_or(&[], Vec::as_slice)
+        };
+        // Both lists are sorted, merging them keeps the order of the rule set
+        let mut candidates = Vec::with_capacity(self.clauses.len() + facts.len());
+        let (mut clauses, mut facts) = (self.clauses.iter().peekable(), facts.iter().peekable());
+        while let (Some(c), Some(f)) = (clauses.peek(), facts.peek()) {
+            if c < f {
+                candidates.push(*clauses.next().unwrap())
+            } else {
+                candidates.push(*facts.next().unwrap())
+            }
+        }
+        candidates.extend(clauses.chain(facts));
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GroundFacts;
+    use crate::resolution::Selection;
+    use crate::{Parser, Sniffer};
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn ground_facts_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "edge(a, b). edge(b, c). reach(X).\n\
+                 edge(X, Y) => path(X, Y).\npath(X, Y) /\\ edge(Y, Z) => path(X, Z).",
+            )
+            .unwrap();
+        sniffer.saturate_all();
+        let mut index = GroundFacts::default();
+        index.sync(&sniffer.rules);
+        let position = |sniffer: &Sniffer, rule: &str| {
+            sniffer
+                .rules
+                .iter()
+                .position(|r| r.to_string(&sniffer.id_server).to_string() == rule)
+                .unwrap()
+        };
+        let is_fact = |i: &usize| {
+            sniffer.rules[*i].premises.is_empty() && sniffer.rules[*i].conclusion.is_ground()
+        };
+        let fact = position(&sniffer, "path(a, c)");
+        assert_eq!(
+            index.facts.get(&sniffer.rules[fact].conclusion),
+            Some(&fact)
+        );
+
+        // A selected conclusion is only resolved with clauses
+        let selection = Selection::Conclusion(sniffer.rules[fact].conclusion.clone());
+        let clauses = index.candidates(&selection);
+        assert!(!clauses.iter().any(is_fact));
+        assert!(clauses.contains(&position(&sniffer, "reach(_)")));
+
+        // A selected premise also meets the ground facts of its relation, in order
+        let rule = sniffer
+            .rules
+            .iter()
+            .find(|r| r.premises.len() == 2)
+            .unwrap();
+        let symbol = sniffer.rules[fact].conclusion.symbol;
+        let premise = rule.premises.iter().find(|p| p.symbol == symbol).unwrap();
+        let selection = Selection::Premise(premise.clone(), 0);
+        let candidates = index.candidates(&selection);
+        assert!(candidates.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(candidates.iter().filter(|i| is_fact(i)).count(), 3);
+        let selection = Selection::Premise(sniffer.rules[fact].conclusion.clone(), 0);
+        assert_eq!(index.candidates(&selection).len(), clauses.len() + 1);
+
+        // Positions are found again once rules are removed
+        let edge = Parser::parse_rules(Tokens::new("edge(b, c).")).unwrap();
+        sniffer.remove_rule(&edge[0]).unwrap();
+        index.sync(&sniffer.rules);
+        let fact = position(&sniffer, "path(a, b)");
+        assert_eq!(
+            index.facts.get(&sniffer.rules[fact].conclusion),
+            Some(&fact)
+        );
+        assert_eq!(index.facts.len(), 1);
+    }
+}
@@ -0,0 +1,20 @@
+//! Hashing module
+//! Hash maps and sets used internally by the engine. With the `fxhash` feature
+//! (enabled by default) they use the fast, non-cryptographic hasher of
+//! `rustc-hash`, otherwise the randomly seeded hasher of `std`.
+//!
+//! Nothing depends on the iteration order of these collections, so switching
+//! hashers never changes the output of a run
+
+#[cfg(feature = "fxhash")]
+pub type BuildHasher = std::hash::BuildHasherDefault<rustc_hash::FxHasher>;
+#[cfg(not(feature = "fxhash"))]
+pub type BuildHasher = std::collections::hash_map::RandomState;
+
+pub type HashMap<K, V> = std::collections::HashMap<K, V, BuildHasher>;
+pub type HashSet<T> = std::collections::HashSet<T, BuildHasher>;
+
+/// Insertion-ordered set and map, so that iterating over the rule set (and hence
+/// saturation and printing) gives the same result for identical inputs
+pub type IndexSet<T> = indexmap::IndexSet<T, BuildHasher>;
+pub type IndexMap<K, V> = indexmap::IndexMap<K, V, BuildHasher>;
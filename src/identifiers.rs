@@ -1,6 +1,7 @@
 use crate::ast::{Atom, InnerAtom, InnerRule, InnerTerm, Rule, Term};
-use rustc_hash::FxHashMap;
-use std::collections::HashMap;
+use crate::hashing::HashMap;
+use crate::parser::NamingConvention;
+use std::sync::{Arc, RwLock};
 
 /// Inner representation for identifiers
 #[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone, Debug)]
@@ -8,23 +9,115 @@ pub enum Identifier {
     Function(usize),
     Variable(usize),
 }
-#[derive(Default, Debug)]
+
+/// Function symbols interned once for several servers, see
+/// `IdentifierServer::with_symbols`. Clones share the same table, and symbols are
+/// never removed from it, so that servers using it agree on their identifiers
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: Arc<RwLock<Symbols>>,
+}
+#[derive(Debug, Default)]
+struct Symbols {
+    names: Vec<Arc<str>>,
+    ids: HashMap<Arc<str>, usize>,
+}
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    /// Number of symbols interned so far
+    pub fn len(&self) -> usize {
+        self.symbols.read().unwrap().names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether both tables are the same, shared one
+    pub fn ptr_eq(&self, other: &SymbolTable) -> bool {
+        Arc::ptr_eq(&self.symbols, &other.symbols)
+    }
+
+    /// Number and name of a symbol, interning it if needed
+    fn intern(&self, symbol: &str) -> (usize, Arc<str>) {
+        {
+            let symbols = self.symbols.read().unwrap();
+            if let Some(&i) = symbols.ids.get(symbol) {
+                return (i, symbols.names[i].clone());
+            }
+        }
+        let mut symbols = self.symbols.write().unwrap();
+        // Another server may have interned it in between
+        if let Some(&i) = symbols.ids.get(symbol) {
+            return (i, symbols.names[i].clone());
+        }
+        let name: Arc<str> = Arc::from(symbol);
+        let i = symbols.names.len();
+        symbols.names.push(name.clone());
+        symbols.ids.insert(name.clone(), i);
+        (i, name)
+    }
+}
+
+#[derive(Default, Debug, Clone)]
 pub struct IdentifierServer {
     variables_count: usize,
-    functions_count: usize,
-    ids_map: FxHashMap<Identifier, String>,
-    names_map: FxHashMap<String, Identifier>,
+    /// Function symbols registered by this server, in registration order
+    functions: Vec<Identifier>,
+    ids_map: HashMap<Identifier, Arc<str>>,
+    names_map: HashMap<Arc<str>, Identifier>,
+    convention: NamingConvention,
+    /// Table function symbols are interned in, if shared with other servers
+    symbols: Option<SymbolTable>,
 }
 impl IdentifierServer {
+    /// Creates a server naming variables according to the given convention
+    pub fn with_convention(convention: NamingConvention) -> IdentifierServer {
+        IdentifierServer {
+            convention,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a server interning function symbols in a shared table: their names
+    /// are only allocated once for every server using it, and they get the same
+    /// identifiers in all of them
+    pub fn with_symbols(symbols: SymbolTable, convention: NamingConvention) -> IdentifierServer {
+        IdentifierServer {
+            convention,
+            symbols: Some(symbols),
+            ..Default::default()
+        }
+    }
+
+    /// Table this server interns function symbols in, if shared
+    pub fn symbols(&self) -> Option<&SymbolTable> {
+        self.symbols.as_ref()
+    }
+
+    fn variable_name(&self, id: usize) -> String {
+        match self.convention {
+            NamingConvention::Capitalized => format!("VAR{id}"),
+            NamingConvention::Sigil => format!("?var{id}"),
+        }
+    }
+
     /// Registers a new term, returning its identifier
     pub fn register_function(&mut self, symbol: &str) -> Identifier {
         if let Some(identifier) = self.names_map.get(symbol) {
             *identifier
         } else {
-            let identifier = Identifier::Function(self.functions_count);
-            self.functions_count += 1;
-            self.ids_map.insert(identifier, symbol.to_string());
-            self.names_map.insert(symbol.to_string(), identifier);
+            let (id, name) = match &self.symbols {
+                Some(symbols) => symbols.intern(symbol),
+                None => (self.functions.len(), Arc::from(symbol)),
+            };
+            let identifier = Identifier::Function(id);
+            self.functions.push(identifier);
+            self.ids_map.insert(identifier, name.clone());
+            self.names_map.insert(name, identifier);
             identifier
         }
     }
@@ -32,7 +125,7 @@ impl IdentifierServer {
     pub fn register_variable(&mut self) -> Identifier {
         let id = self.variables_count;
         let identifier = Identifier::Variable(id);
-        let symbol = String::from("VAR") + &id.to_string();
+        let symbol: Arc<str> = Arc::from(self.variable_name(id));
         self.ids_map.insert(identifier, symbol.clone());
         self.names_map.insert(symbol, identifier);
         self.variables_count += 1;
@@ -42,14 +135,125 @@ impl IdentifierServer {
     /// Returns the name associated with the given identifier
     pub fn name_of(&self, id: &Identifier) -> Option<String> {
         match id {
-            Identifier::Function(_) => self.ids_map.get(id).cloned(),
-            Identifier::Variable(i) => Some(String::from("VAR") + &i.to_string()),
+            Identifier::Function(_) => self.ids_map.get(id).map(|name| name.to_string()),
+            Identifier::Variable(i) => Some(self.variable_name(*i)),
         }
     }
 
     pub fn id_of(&self, name: &str) -> Option<&Identifier> {
         self.names_map.get(name)
     }
+
+    /// Returns the identifier of a registered function symbol
+    fn known_function(&self, name: &str) -> Option<Identifier> {
+        self.id_of(name)
+            .copied()
+            .filter(|id| matches!(id, Identifier::Function(_)))
+    }
+
+    /// Returns the name of a registered identifier, without allocating
+    pub fn resolve(&self, id: Identifier) -> Option<&str> {
+        self.ids_map.get(&id).map(|name| &**name)
+    }
+
+    /// Registered function symbols (predicates, functions and constants alike), in
+    /// registration order
+    pub fn functions(&self) -> impl Iterator<Item = (Identifier, &str)> {
+        self.functions
+            .iter()
+            .filter_map(|&id| self.resolve(id).map(|name| (id, name)))
+    }
+
+    /// Number of variables registered so far
+    pub fn variables_count(&self) -> usize {
+        self.variables_count
+    }
+
+    /// Registers the function symbols of another server, in its registration
+    /// order, returning the identifiers they map to in this one. Servers fed the
+    /// same symbols in the same order assign the same identifiers, and symbols
+    /// shared by both servers keep a single identifier
+    pub fn import(&mut self, other: &IdentifierServer) -> Remapping {
+        let functions = other
+            .functions()
+            .map(|(id, name)| (id, self.register_function(name)))
+            .collect();
+        Remapping { functions }
+    }
+}
+
+/// Identifiers of a server in terms of another's, see `IdentifierServer::import`.
+/// Variables are not mapped: remapped rules get fresh variables, so that they
+/// never share any with the rules already known
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Remapping {
+    functions: HashMap<Identifier, Identifier>,
+}
+impl Remapping {
+    /// Identifier a function symbol maps to
+    pub fn get(&self, id: Identifier) -> Option<Identifier> {
+        self.functions.get(&id).copied()
+    }
+
+    /// Whether every symbol keeps its identifier
+    pub fn is_identity(&self) -> bool {
+        self.functions.iter().all(|(from, to)| from == to)
+    }
+
+    fn term(&self, term: &InnerTerm) -> InnerTerm {
+        match term {
+            Term::Variable { symbol } => Term::Variable { symbol: *symbol },
+            Term::Function { symbol, parameters } => Term::Function {
+                symbol: self.function(*symbol),
+                parameters: parameters.iter().map(|t| self.term(t)).collect(),
+            },
+        }
+    }
+
+    fn atom(&self, atom: &InnerAtom) -> InnerAtom {
+        Atom {
+            symbol: self.function(atom.symbol),
+            parameters: atom.parameters.iter().map(|t| self.term(t)).collect(),
+        }
+    }
+
+    fn function(&self, id: Identifier) -> Identifier {
+        self.get(id).expect("identifier not registered")
+    }
+}
+
+/// How a symbol is used by a rule set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SymbolKind {
+    Predicate,
+    Function,
+    Constant,
+}
+impl std::fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SymbolKind::Predicate => "predicate",
+            SymbolKind::Function => "function",
+            SymbolKind::Constant => "constant",
+        })
+    }
+}
+
+/// An entry of the symbol table of a rule set: a symbol used with a given kind and
+/// arity. Symbols used in several ways have an entry for each
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol {
+    pub identifier: Identifier,
+    pub name: String,
+    pub kind: SymbolKind,
+    pub arity: usize,
+}
+/// Printed as `<kind> <name>/<arity> <identifier>`
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (Identifier::Function(id) | Identifier::Variable(id)) = self.identifier;
+        write!(f, "{} {}/{} #{id}", self.kind, self.name, self.arity)
+    }
 }
 
 impl Term<String> {
@@ -73,28 +277,48 @@ impl Term<String> {
                 symbol: id_server.register_function(symbol),
                 parameters: parameters
                     .iter()
-                    .cloned()
                     .map(|t| t.to_inner(id_server, bindings))
                     .collect(),
             },
         }
     }
+
+    /// Returns this ground term with the identifiers already registered, without
+    /// registering any, or `None` if it holds a variable or an unknown symbol
+    pub(crate) fn to_known_inner(&self, id_server: &IdentifierServer) -> Option<InnerTerm> {
+        match self {
+            Term::Variable { .. } => None,
+            Term::Function { symbol, parameters } => Some(Term::Function {
+                symbol: id_server.known_function(symbol)?,
+                parameters: parameters
+                    .iter()
+                    .map(|t| t.to_known_inner(id_server))
+                    .collect::<Option<_>>()?,
+            }),
+        }
+    }
 }
 impl InnerTerm {
     pub fn to_string(&self, id_server: &IdentifierServer) -> Term<String> {
-        match self {
+        self.try_to_string(id_server)
+            .expect("identifier not registered")
+    }
+
+    /// Names this term using the identifier server, failing with the first
+    /// identifier that is not registered
+    pub fn try_to_string(&self, id_server: &IdentifierServer) -> Result<Term<String>, Identifier> {
+        Ok(match self {
             Term::Variable { symbol } => Term::Variable {
-                symbol: id_server.name_of(symbol).unwrap(),
+                symbol: id_server.name_of(symbol).ok_or(*symbol)?,
             },
             Term::Function { symbol, parameters } => Term::Function {
-                symbol: id_server.name_of(symbol).unwrap(),
+                symbol: id_server.name_of(symbol).ok_or(*symbol)?,
                 parameters: parameters
                     .iter()
-                    .cloned()
-                    .map(|t| t.to_string(id_server))
-                    .collect(),
+                    .map(|t| t.try_to_string(id_server))
+                    .collect::<Result<_, _>>()?,
             },
-        }
+        })
     }
 
     pub fn make_fresh(
@@ -117,7 +341,6 @@ impl InnerTerm {
                 symbol: *symbol,
                 parameters: parameters
                     .iter()
-                    .cloned()
                     .map(|t| t.make_fresh(id_server, bindings))
                     .collect(),
             },
@@ -140,17 +363,37 @@ impl Atom<String> {
                 .collect(),
         }
     }
+
+    /// Returns this ground atom with the identifiers already registered, see
+    /// `Term::to_known_inner`
+    pub(crate) fn to_known_inner(&self, id_server: &IdentifierServer) -> Option<InnerAtom> {
+        Some(Atom {
+            symbol: id_server.known_function(&self.symbol)?,
+            parameters: self
+                .parameters
+                .iter()
+                .map(|t| t.to_known_inner(id_server))
+                .collect::<Option<_>>()?,
+        })
+    }
 }
 impl InnerAtom {
     pub fn to_string(&self, id_server: &IdentifierServer) -> Atom<String> {
-        Atom {
-            symbol: id_server.name_of(&self.symbol).unwrap(),
+        self.try_to_string(id_server)
+            .expect("identifier not registered")
+    }
+
+    /// Names this atom using the identifier server, failing with the first
+    /// identifier that is not registered
+    pub fn try_to_string(&self, id_server: &IdentifierServer) -> Result<Atom<String>, Identifier> {
+        Ok(Atom {
+            symbol: id_server.name_of(&self.symbol).ok_or(self.symbol)?,
             parameters: self
                 .parameters
                 .iter()
-                .map(|t| t.to_string(id_server))
-                .collect(),
-        }
+                .map(|t| t.try_to_string(id_server))
+                .collect::<Result<_, _>>()?,
+        })
     }
 
     pub fn make_fresh(
@@ -171,7 +414,7 @@ impl InnerAtom {
 
 impl Rule<String> {
     pub fn to_inner(&self, id_server: &mut IdentifierServer) -> InnerRule {
-        let mut bindings = HashMap::new();
+        let mut bindings = HashMap::default();
         Rule {
             conclusion: self.conclusion.to_inner(id_server, &mut bindings),
             premises: self
@@ -184,18 +427,25 @@ impl Rule<String> {
 }
 impl InnerRule {
     pub fn to_string(&self, id_server: &IdentifierServer) -> Rule<String> {
-        Rule {
-            conclusion: self.conclusion.to_string(id_server),
+        self.try_to_string(id_server)
+            .expect("identifier not registered")
+    }
+
+    /// Names this rule using the identifier server, failing with the first
+    /// identifier that is not registered
+    pub fn try_to_string(&self, id_server: &IdentifierServer) -> Result<Rule<String>, Identifier> {
+        Ok(Rule {
+            conclusion: self.conclusion.try_to_string(id_server)?,
             premises: self
                 .premises
                 .iter()
-                .map(|a| a.to_string(id_server))
-                .collect(),
-        }
+                .map(|a| a.try_to_string(id_server))
+                .collect::<Result<_, _>>()?,
+        })
     }
 
     pub fn make_fresh(&self, id_server: &mut IdentifierServer) -> InnerRule {
-        let mut bindings = HashMap::new();
+        let mut bindings = HashMap::default();
         Rule {
             conclusion: self.conclusion.make_fresh(id_server, &mut bindings),
             premises: self
@@ -205,4 +455,14 @@ impl InnerRule {
                 .collect(),
         }
     }
+
+    /// Translates a rule of the server `remapping` was imported from into a rule of
+    /// `id_server`, with fresh variables
+    pub fn remap(&self, remapping: &Remapping, id_server: &mut IdentifierServer) -> InnerRule {
+        Rule {
+            conclusion: remapping.atom(&self.conclusion),
+            premises: self.premises.iter().map(|a| remapping.atom(a)).collect(),
+        }
+        .make_fresh(id_server)
+    }
 }
@@ -0,0 +1,205 @@
+//! Interning module
+//! Interned unification: every structurally distinct term is stored once in a
+//! `TermStore` and referred to by a `TermId`, so that comparing or hashing terms
+//! costs a single integer operation whatever their size.
+//!
+//! Stores are used where terms are compared many times: the input facts of the
+//! `edb` module are kept as identifiers in a store living as long as the Sniffer,
+//! and unification works on identifiers instead of cloning and hashing whole
+//! terms. Whether a term is ground is computed once when it is interned, so that
+//! unifying two ground terms is a comparison of their identifiers.
+//!
+//! Rules of the rule set are not interned: they are `InnerRule` trees, compared and
+//! hashed structurally. The Sniffer's unification store thus only serves the
+//! steps of saturation, and is emptied between them once it grows too large
+use crate::ast::{InnerTerm, Term};
+use crate::hashing::{BuildHasher, HashMap};
+use crate::identifiers::Identifier;
+use std::hash::BuildHasher as _;
+
+/// Identifier of an interned term, only meaningful for the store that created it
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone, Debug)]
+pub struct TermId(u32);
+
+/// An interned term, whose parameters are themselves interned
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum Node<'a> {
+    Function(Identifier, &'a [TermId]),
+    Variable(Identifier),
+}
+
+/// Stored representation of a node, pointing into the parameters arena
+#[derive(Debug, Clone)]
+struct Entry {
+    symbol: Identifier,
+    variable: bool,
+    /// Whether the term holds no variable
+    ground: bool,
+    start: u32,
+    len: u32,
+    /// Next entry with the same hash
+    next: Option<TermId>,
+}
+
+/// Interned terms are allocated in two arenas: one for nodes and one for the
+/// parameter lists of every node, which are stored contiguously. Interning a term
+/// thus only allocates when an arena grows, and terms interned together stay close
+/// in memory. Only interned terms live in the arenas: rules, resolvents included,
+/// are still built term by term on the heap
+#[derive(Default, Debug, Clone)]
+pub struct TermStore {
+    entries: Vec<Entry>,
+    parameters: Vec<TermId>,
+    /// First entry of each hash, the others being chained through `Entry::next`
+    buckets: HashMap<u64, TermId>,
+    hasher: BuildHasher,
+    /// Stack of parameters being interned
+    scratch: Vec<TermId>,
+}
+impl TermStore {
+    /// Returns the identifier of the given node, adding it to the store if needed
+    pub fn intern_node(&mut self, node: Node) -> TermId {
+        if let Some(id) = self.find_node(node) {
+            return id;
+        }
+        let hash = self.hasher.hash_one(node);
+
+        let (symbol, variable, parameters) = match node {
+            Node::Function(symbol, parameters) => (symbol, false, parameters),
+            Node::Variable(symbol) => (symbol, true, &[][..]),
+        };
+        let ground = !variable && parameters.iter().all(|p| self.is_ground(*p));
+        let id = TermId(self.entries.len() as u32);
+        let start = self.parameters.len() as u32;
+        self.parameters.extend_from_slice(parameters);
+        self.entries.push(Entry {
+            symbol,
+            variable,
+            ground,
+            start,
+            len: parameters.len() as u32,
+            next: self.buckets.insert(hash, id),
+        });
+        id
+    }
+
+    /// Returns the identifier of the given node if it was already interned
+    pub fn find_node(&self, node: Node) -> Option<TermId> {
+        let hash = self.hasher.hash_one(node);
+        let mut cursor = self.buckets.get(&hash).copied();
+        while let Some(id) = cursor {
+            if self.node(id) == node {
+                return Some(id);
+            }
+            cursor = self.entries[id.0 as usize].next;
+        }
+        None
+    }
+
+    /// Interns a term along with all of its subterms
+    pub fn intern(&mut self, term: &InnerTerm) -> TermId {
+        match term {
+            Term::Variable { symbol } => self.intern_node(Node::Variable(*symbol)),
+            Term::Function { symbol, parameters } => {
+                let base = self.scratch.len();
+                for parameter in parameters {
+                    let id = self.intern(parameter);
+                    self.scratch.push(id);
+                }
+                // The scratch stack is moved out while its top is borrowed as parameters
+                let mut scratch = std::mem::take(&mut self.scratch);
+                let id = self.intern_node(Node::Function(*symbol, &scratch[base..]));
+                scratch.truncate(base);
+                self.scratch = scratch;
+                id
+            }
+        }
+    }
+
+    pub fn node(&self, id: TermId) -> Node<'_> {
+        let entry = &self.entries[id.0 as usize];
+        if entry.variable {
+            Node::Variable(entry.symbol)
+        } else {
+            let start = entry.start as usize;
+            Node::Function(
+                entry.symbol,
+                &self.parameters[start..start + entry.len as usize],
+            )
+        }
+    }
+
+    /// Whether the term associated with an identifier holds no variable
+    pub fn is_ground(&self, id: TermId) -> bool {
+        self.entries[id.0 as usize].ground
+    }
+
+    /// Rebuilds the term associated with an identifier
+    pub fn term(&self, id: TermId) -> InnerTerm {
+        match self.node(id) {
+            Node::Variable(symbol) => Term::Variable { symbol },
+            Node::Function(symbol, parameters) => Term::Function {
+                symbol,
+                parameters: parameters.iter().map(|p| self.term(*p)).collect(),
+            },
+        }
+    }
+
+    /// Number of distinct terms in the store
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Forgets every interned term, keeping the memory of the arenas to be reused.
+    /// Identifiers given before are no longer meaningful
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.parameters.clear();
+        self.buckets.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sharing_test() {
+        let x = Term::Variable {
+            symbol: Identifier::Variable(0),
+        };
+        let f = |t: InnerTerm| Term::Function {
+            symbol: Identifier::Function(0),
+            parameters: vec![t.clone(), t],
+        };
+
+        let mut store = TermStore::default();
+        let id = store.intern(&f(f(x.clone())));
+        // x, f(x, x) and f(f(x, x), f(x, x)) are the only distinct terms
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.parameters.len(), 4);
+        assert_eq!(store.intern(&f(f(x.clone()))), id);
+        assert_ne!(store.intern(&f(x.clone())), id);
+        assert_eq!(store.term(id), f(f(x.clone())));
+
+        // Groundness is computed once, when interning
+        let a = Term::Function {
+            symbol: Identifier::Function(1),
+            parameters: vec![],
+        };
+        let ground = store.intern(&f(a));
+        assert!(store.is_ground(ground));
+        assert!(!store.is_ground(id));
+
+        // A cleared store starts over
+        store.clear();
+        assert!(store.is_empty());
+        assert_eq!(store.intern(&f(x.clone())), TermId(1));
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.term(TermId(1)), f(x));
+    }
+}
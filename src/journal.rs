@@ -0,0 +1,421 @@
+//! Journal module
+//! Append-only log of the inferences made by saturation, started with
+//! `Sniffer::start_journal`.
+//!
+//! Events are numbered in order, and this number identifies the rule each event
+//! introduces: input events give the rules of the input, inference events the
+//! resolvent of two earlier rules, referred to by their number. Rules already in
+//! the rule set when the journal starts are logged first, derived ones along with
+//! the inference they were first derived by.
+//!
+//! Variables are renamed in order of appearance, premises first, so that journals
+//! of different runs can be compared: rules are written in canonical form, and the
+//! unifier of an inference names the variables of the rule whose premise is
+//! selected `X0`, `X1`, ... and those of the other rule `Y0`, `Y1`, ...
+use crate::ast::{Attributes, Folder, InnerRule, Rule, Term};
+use crate::hashing::HashMap;
+use crate::identifiers::IdentifierServer;
+use crate::provenance::{Derivations, Provenance};
+use crate::resolution::Selection;
+use crate::{DerivationInfo, RunFingerprint, Sniffer, Substitution};
+use itertools::Itertools;
+use std::time::{Duration, Instant};
+
+/// An inference, resolving the selected premise of a rule with the conclusion of
+/// another
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inference {
+    /// Event that introduced the rule whose premise is selected
+    pub rule: usize,
+    /// Index of the selected premise
+    pub premise: usize,
+    /// Event that introduced the rule whose conclusion is selected
+    pub other: usize,
+    /// Most general unifier of the premise and the conclusion
+    pub substitution: Substitution<String>,
+    pub derived: Rule<String>,
+    /// Whether the derived rule was new to the rule set
+    pub new: bool,
+    /// Time elapsed since the journal started
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JournalEvent {
+    Input(Rule<String>),
+    Inference(Inference),
+}
+
+/// Log of the inferences of a Sniffer, see the `journal` module
+#[derive(Debug, Clone)]
+pub struct DerivationJournal {
+    events: Vec<JournalEvent>,
+    /// Event that introduced each rule of the rule set
+    ids: HashMap<InnerRule, usize>,
+    started: Instant,
+    /// Fingerprint of the run, as the journal started
+    fingerprint: RunFingerprint,
+}
+impl DerivationJournal {
+    fn new(fingerprint: RunFingerprint) -> DerivationJournal {
+        DerivationJournal {
+            events: vec![],
+            ids: HashMap::default(),
+            started: Instant::now(),
+            fingerprint,
+        }
+    }
+
+    /// Fingerprint of the run the journal logs, see the `fingerprint` module
+    pub fn fingerprint(&self) -> &RunFingerprint {
+        &self.fingerprint
+    }
+
+    pub fn events(&self) -> &[JournalEvent] {
+        &self.events
+    }
+
+    /// Inferences of the journal, along with their event number
+    pub fn inferences(&self) -> impl Iterator<Item = (usize, &Inference)> {
+        self.events
+            .iter()
+            .enumerate()
+            .filter_map(|(id, event)| match event {
+                JournalEvent::Input(_) => None,
+                JournalEvent::Inference(inference) => Some((id, inference)),
+            })
+    }
+
+    /// Event number of a rule, logging it as an input if it is unknown
+    fn id(&mut self, rule: &InnerRule, id_server: &IdentifierServer) -> usize {
+        if let Some(id) = self.ids.get(rule) {
+            return *id;
+        }
+        let id = self.push(JournalEvent::Input(rule.to_string(id_server).canonical()));
+        self.ids.insert(rule.clone(), id);
+        id
+    }
+
+    fn push(&mut self, event: JournalEvent) -> usize {
+        self.events.push(event);
+        self.events.len() - 1
+    }
+
+    /// Encodes the journal as JSON lines, an object per event, after a first
+    /// `{"event":"run","fingerprint":...}` object
+    pub fn to_json_lines(&self) -> String {
+        let mut lines = format!(
+            "{{\"event\":\"run\",\"fingerprint\":{}}}\n",
+            json_string(&self.fingerprint.to_string())
+        );
+        for (id, event) in self.events.iter().enumerate() {
+            match event {
+                JournalEvent::Input(rule) => lines.push_str(&format!(
+                    "{{\"id\":{id},\"event\":\"input\",\"rule\":{}}}\n",
+                    json_string(&rule.to_string())
+                )),
+                JournalEvent::Inference(inference) => {
+                    let substitution = inference
+                        .substitution
+                        .iter()
+                        .map(|(variable, term)| {
+                            format!(
+                                "{}:{}",
+                                json_string(variable),
+                                json_string(&term.to_string())
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    lines.push_str(&format!(
+                        "{{\"id\":{id},\"event\":\"inference\",\"elapsed_us\":{},\"rule\":{},\
+                         \"premise\":{},\"other\":{},\"substitution\":{{{substitution}}},\
+                         \"derived\":{},\"new\":{}}}\n",
+                        inference.elapsed.as_micros(),
+                        inference.rule,
+                        inference.premise,
+                        inference.other,
+                        json_string(&inference.derived.to_string()),
+                        inference.new
+                    ))
+                }
+            }
+        }
+        lines
+    }
+
+    /// Replays the journal into a fresh Sniffer, adding the input rules then
+    /// checking and recording every inference that derived a new rule, in order.
+    /// Fails at the first inference whose rules are unknown or do not resolve
+    pub fn replay(&self) -> Result<Sniffer, ReplayError> {
+        let mut sniffer = Sniffer::with_provenance(Derivations);
+        let mut rules: Vec<Option<InnerRule>> = Vec::with_capacity(self.events.len());
+        for (id, event) in self.events.iter().enumerate() {
+            let rule = match event {
+                JournalEvent::Input(rule) => {
+                    Some(sniffer.insert_input(rule.clone(), &Attributes::default()))
+                }
+                JournalEvent::Inference(inference) => {
+                    let parent = |i: usize| {
+                        rules
+                            .get(i)
+                            .cloned()
+                            .flatten()
+                            .ok_or(ReplayError::UnknownRule { event: id, rule: i })
+                    };
+                    let (rule, other) = (parent(inference.rule)?, parent(inference.other)?);
+                    let premise = rule
+                        .premises
+                        .get(inference.premise)
+                        .filter(|p| p.unify(&other.conclusion).is_some())
+                        .cloned()
+                        .ok_or(ReplayError::Mismatch { event: id })?;
+                    let derived = inference.derived.to_inner(&mut sniffer.id_server);
+                    if inference.new && !sniffer.contains(&derived) {
+                        sniffer.derived_from.insert(
+                            derived.clone(),
+                            DerivationInfo {
+                                selected_atoms: (
+                                    Selection::Premise(premise, inference.premise),
+                                    Selection::Conclusion(other.conclusion.clone()),
+                                ),
+                                rules: (rule, other),
+                            },
+                        );
+                        sniffer.insert_rule(derived.clone());
+                    }
+                    Some(derived)
+                }
+            };
+            rules.push(rule)
+        }
+        sniffer.publish();
+        Ok(sniffer)
+    }
+}
+
+/// Reason why a journal could not be replayed, see `DerivationJournal::replay`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// An inference refers to a rule that no earlier event introduced
+    UnknownRule { event: usize, rule: usize },
+    /// The selected premise of an inference does not unify with the conclusion
+    Mismatch { event: usize },
+}
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayError::UnknownRule { event, rule } => {
+                write!(f, "event {event} refers to unknown rule {rule}")
+            }
+            ReplayError::Mismatch { event } => {
+                write!(f, "event {event} resolves atoms that do not unify")
+            }
+        }
+    }
+}
+
+/// Renames variables in order of appearance, with a prefix
+struct Renaming {
+    prefix: &'static str,
+    names: HashMap<String, String>,
+    count: usize,
+}
+impl Folder<String> for Renaming {
+    fn fold_variable(&mut self, symbol: String) -> Term<String> {
+        let name = self.names.entry(symbol).or_insert_with_key(|symbol| {
+            let name = if symbol.starts_with('?') {
+                format!("?{}{}", self.prefix.to_lowercase(), self.count)
+            } else {
+                format!("{}{}", self.prefix, self.count)
+            };
+            self.count += 1;
+            name
+        });
+        Term::Variable {
+            symbol: name.clone(),
+        }
+    }
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+impl<P: Provenance> Sniffer<P> {
+    /// Starts logging inferences, replacing the current journal if any. Rules of
+    /// the rule set are logged first, see the `journal` module
+    pub fn start_journal(&mut self) {
+        let mut journal = DerivationJournal::new(self.fingerprint());
+        for rule in self
+            .all_rules()
+            .filter(|r| !self.derived_from.contains_key(r))
+        {
+            journal.id(&rule, &self.id_server);
+        }
+        self.journal = Some(journal);
+        let derivations: Vec<_> = self
+            .derived_from
+            .iter()
+            .map(|(rule, info)| (rule.clone(), info.clone()))
+            .collect();
+        for (rule, info) in derivations {
+            self.journal_inference(&info, &rule, true, Duration::ZERO)
+        }
+    }
+
+    pub fn journal(&self) -> Option<&DerivationJournal> {
+        self.journal.as_ref()
+    }
+
+    /// Stops logging inferences, returning the journal
+    pub fn take_journal(&mut self) -> Option<DerivationJournal> {
+        self.journal.take()
+    }
+
+    /// Logs an input rule, if a journal was started
+    pub(crate) fn journal_input(&mut self, rule: &InnerRule) {
+        if let Some(journal) = &mut self.journal {
+            journal.id(rule, &self.id_server);
+        }
+    }
+
+    /// Logs the inference of a rule from two others, if a journal was started
+    pub(crate) fn journal_derivation(&mut self, info: &DerivationInfo, derived: &InnerRule) {
+        if let Some(journal) = &self.journal {
+            let new = !self.contains(derived) && !journal.ids.contains_key(derived);
+            let elapsed = journal.started.elapsed();
+            self.journal_inference(info, derived, new, elapsed)
+        }
+    }
+
+    fn journal_inference(
+        &mut self,
+        info: &DerivationInfo,
+        derived: &InnerRule,
+        new: bool,
+        elapsed: Duration,
+    ) {
+        let Some(journal) = &mut self.journal else {
+            return;
+        };
+        // The rule whose premise is selected comes first
+        let (rule, other, premise, index, conclusion) = match &info.selected_atoms {
+            (Selection::Premise(p, i), Selection::Conclusion(c)) => {
+                (&info.rules.0, &info.rules.1, p, *i, c)
+            }
+            (Selection::Conclusion(c), Selection::Premise(p, i)) => {
+                (&info.rules.1, &info.rules.0, p, *i, c)
+            }
+            _ => return,
+        };
+        let Some(bindings) = premise.unify(conclusion) else {
+            return;
+        };
+        let rule_id = journal.id(rule, &self.id_server);
+        let other_id = journal.id(other, &self.id_server);
+
+        let mut renaming = Renaming {
+            prefix: "X",
+            names: HashMap::default(),
+            count: 0,
+        };
+        renaming.fold_rule(rule.to_string(&self.id_server));
+        renaming.prefix = "Y";
+        renaming.count = 0;
+        renaming.fold_rule(other.to_string(&self.id_server));
+        // Bindings are sorted by variable, whatever the order the unifier was built in
+        let substitution = bindings
+            .iter()
+            .filter_map(|(variable, term)| {
+                let Term::Variable { symbol } = variable.to_string(&self.id_server) else {
+                    return None;
+                };
+                let name = renaming.names.get(&symbol)?.clone();
+                Some((name, renaming.fold_term(term.to_string(&self.id_server))))
+            })
+            .sorted_by(|(a, _), (b, _)| (a.len(), a).cmp(&(b.len(), b)))
+            .collect();
+
+        let inference = Inference {
+            rule: rule_id,
+            premise: index,
+            other: other_id,
+            substitution,
+            derived: derived.to_string(&self.id_server).canonical(),
+            new,
+            elapsed,
+        };
+        let id = journal.push(JournalEvent::Inference(inference));
+        if new {
+            journal.ids.insert(derived.clone(), id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JournalEvent;
+    use crate::{Parser, Sniffer};
+    use itertools::Itertools;
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn journal_test() {
+        let query = |source: &str| Parser::parse_query(Tokens::new(source)).unwrap();
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert("att(k). att(enc(m, k)).\natt(enc(X, Y)) /\\ att(Y) => att(X).")
+            .unwrap();
+        sniffer.start_journal();
+        let tree = sniffer.find(&query("att(m).")).unwrap();
+        let journal = sniffer.take_journal().unwrap();
+
+        let inputs = journal
+            .events()
+            .iter()
+            .filter(|e| matches!(e, JournalEvent::Input(_)))
+            .count();
+        assert_eq!(inputs, 3);
+        let (_, last) = journal.inferences().filter(|(_, i)| i.new).last().unwrap();
+        assert_eq!(last.derived.to_string(), "att(m)");
+        let lines = journal.to_json_lines();
+        assert_eq!(lines.lines().count(), journal.events().len() + 1);
+        let mut lines = lines.lines();
+        let run = format!(
+            "{{\"event\":\"run\",\"fingerprint\":\"{}\"}}",
+            sniffer.fingerprint()
+        );
+        assert_eq!(lines.next(), Some(run.as_str()));
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("{\"id\":0,\"event\":\"input\""));
+
+        // The replayed rule set holds the same rules, with the same derivations
+        let mut replayed = journal.replay().unwrap();
+        let canonical = |sniffer: &Sniffer| {
+            sniffer
+                .iter_rules()
+                .map(|r| r.canonical().to_string())
+                .sorted()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(canonical(&replayed), canonical(&sniffer));
+        let replayed_tree = replayed.find(&query("att(m).")).unwrap();
+        assert_eq!(replayed_tree.root(), tree.root());
+        assert_eq!(replayed_tree.depth(), tree.depth());
+    }
+}
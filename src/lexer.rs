@@ -1,11 +1,18 @@
 //! Lexer module
 //! `Lexeme::lexer(source)` takes a string slice as input, then creates a lazily evaluated
-//! iterator of lexemes parsed from said source
+//! iterator of lexemes parsed from said source.
+//! `tokens(source)` is the public counterpart, yielding every token (comments
+//! included) with its kind and span
 
+use crate::hashing::HashSet;
 use logos::Logos;
+use std::ops::Range;
+use std::sync::Arc;
 
-/// Valid lexemes for Pif
+/// Valid lexemes for Pif. The lexer keeps the names of the modules declared so far,
+/// to lex qualified names, see `constant`
 #[derive(Logos, Eq, PartialEq, Clone, Debug)]
+#[logos(extras = Arc<HashSet<String>>)]
 pub enum Lexeme {
     #[token("=>")]
     Implies,
@@ -13,6 +20,14 @@ pub enum Lexeme {
     OpeningParentheses,
     #[token(r")")]
     ClosingParentheses,
+    #[token("[")]
+    OpeningBracket,
+    #[token("]")]
+    ClosingBracket,
+    #[token("{")]
+    OpeningBrace,
+    #[token("}")]
+    ClosingBrace,
     #[token(",")]
     Comma,
     #[token("/\\")]
@@ -20,16 +35,313 @@ pub enum Lexeme {
     #[token(".")]
     Stop,
 
-    #[regex(r"[A-Z][a-zA-Z0-9_']*", |lex| lex.slice().to_string())]
+    #[regex(r"[\p{Lu}\p{Lt}][\p{L}\p{N}_']*", |lex| lex.slice().to_string())]
     Variable(String),
-    #[regex(r"[a-z][a-zA-Z0-9_']*", |lex| lex.slice().to_string())]
+    #[regex(r"[\p{Ll}\p{Lm}\p{Lo}][\p{L}\p{N}_']*", constant)]
+    #[regex(r"'([^'\\]|\\.)*'", |lex| unquote(lex.slice()))]
     Constant(String),
-    #[regex(r"[0-9_]+", |lex| lex.slice().parse::<usize>().unwrap())]
+    #[regex(r"\?\p{L}[\p{L}\p{N}_']*", |lex| lex.slice().to_string())]
+    SigilVariable(String),
+    #[regex(r"[~<>=+\-*/\\^@&|:!$]+", |lex| lex.slice().to_string())]
+    Operator(String),
+    #[regex(r"[0-9]+\.[0-9]+", |lex| lex.slice().to_string())]
+    Float(String),
+    #[regex(r"[0-9_]+", |lex| lex.slice().parse::<usize>().ok())]
     Integer(usize),
+    #[token("_")]
+    Wildcard,
+
+    #[token("#expect", directive)]
+    Expect,
+    #[token("#expect_not", directive)]
+    ExpectNot,
+    #[token("#define", directive)]
+    Define,
+    #[token("#horizon", directive)]
+    Horizon,
+    #[token("?-")]
+    Goal,
 
     #[error]
     #[regex(r"[ \t\n\f]+", logos::skip)]
-    #[regex(r"#[^\n]*\n", logos::skip)]
+    #[token("#", comment)]
     UnrecognizedLexeme,
 }
+
+/// Reads a bare constant, qualified by the names following it after dots when they
+/// are applied to parameters (`m.p(a)`) or when it names a module declared earlier
+/// in the source (`use m.p.`). Otherwise dots are stops, and `p.q.` is two facts
+fn constant(lex: &mut logos::Lexer<Lexeme>) -> String {
+    let is_name = |c: char| c.is_alphanumeric() || c == '_' || c == '\'';
+    let remainder = lex.remainder();
+    let mut qualified = 0;
+    while let Some(segment) = remainder[qualified..].strip_prefix('.') {
+        if !segment.starts_with(|c: char| c.is_alphabetic() && !c.is_uppercase()) {
+            break;
+        }
+        qualified += 1 + segment.find(|c| !is_name(c)).unwrap_or(segment.len());
+    }
+    if qualified > 0
+        && (remainder[qualified..].starts_with('(') || lex.extras.contains(lex.slice()))
+    {
+        lex.bump(qualified);
+    } else if lex.slice() == "module" {
+        let rest = remainder.trim_start();
+        let name = &rest[..rest.find(|c| !is_name(c)).unwrap_or(rest.len())];
+        if !name.is_empty() && rest[name.len()..].trim_start().starts_with('{') {
+            Arc::make_mut(&mut lex.extras).insert(name.to_string());
+        }
+    }
+    lex.slice().to_string()
+}
+
+/// Reads the symbol of a quoted atom, failing on unknown escape sequences
+fn unquote(quoted: &str) -> Option<String> {
+    let mut symbol = String::new();
+    let mut chars = quoted[1..quoted.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        symbol.push(match c {
+            '\\' => match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                c @ ('\\' | '\'') => c,
+                _ => return None,
+            },
+            c => c,
+        })
+    }
+    Some(symbol)
+}
+
+/// Writes a symbol so that it lexes back as a constant, quoting it when it is not
+/// a bare constant
+pub(crate) fn write_symbol(f: &mut std::fmt::Formatter<'_>, symbol: &str) -> std::fmt::Result {
+    write_symbol_before(f, symbol, "")
+}
+
+/// Writes the symbol of a function or predicate applied to parameters, see
+/// `write_symbol`. Qualified names only lex back bare when applied
+pub(crate) fn write_applied_symbol(
+    f: &mut std::fmt::Formatter<'_>,
+    symbol: &str,
+) -> std::fmt::Result {
+    write_symbol_before(f, symbol, "(")
+}
+
+/// Writes a symbol so that it lexes back as a constant when followed by the given text
+fn write_symbol_before(
+    f: &mut std::fmt::Formatter<'_>,
+    symbol: &str,
+    following: &str,
+) -> std::fmt::Result {
+    let source = format!("{symbol}{following}");
+    let mut lexer = Lexeme::lexer(&source);
+    let bare = matches!(lexer.next(), Some(Lexeme::Constant(_) | Lexeme::Integer(_)))
+        && lexer.slice() == symbol
+        && !symbol.starts_with('\'');
+    if bare {
+        return f.write_str(symbol);
+    }
+    f.write_str("'")?;
+    for c in symbol.chars() {
+        match c {
+            '\n' => f.write_str("\\n")?,
+            '\t' => f.write_str("\\t")?,
+            '\r' => f.write_str("\\r")?,
+            '\\' | '\'' => write!(f, "\\{c}")?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    f.write_str("'")
+}
+
+/// Skips a comment, up to the end of the line
+fn comment(lex: &mut logos::Lexer<Lexeme>) -> logos::Skip {
+    let remainder = lex.remainder();
+    lex.bump(remainder.find('\n').unwrap_or(remainder.len()));
+    logos::Skip
+}
+
+/// Directives are recognized only as whole words, otherwise they start a comment
+fn directive(lex: &mut logos::Lexer<Lexeme>) -> logos::Filter<()> {
+    if lex
+        .remainder()
+        .starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '\'')
+    {
+        comment(lex);
+        logos::Filter::Skip
+    } else {
+        logos::Filter::Emit(())
+    }
+}
 logos_nom_bridge::token_parser!(token: Lexeme);
+
+/// Kinds of tokens exposed by the public token stream
+#[non_exhaustive]
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Hash)]
+pub enum TokenKind {
+    Implies,
+    OpeningParentheses,
+    ClosingParentheses,
+    OpeningBracket,
+    ClosingBracket,
+    OpeningBrace,
+    ClosingBrace,
+    Comma,
+    And,
+    Stop,
+    Variable,
+    Constant,
+    Integer,
+    Float,
+    /// Symbols of infix operators, such as `~>`
+    Operator,
+    /// The anonymous variable `_`
+    Wildcard,
+    /// Directives such as `#expect` or `?-`
+    Directive,
+    Comment,
+    Unrecognized,
+}
+impl From<&Lexeme> for TokenKind {
+    fn from(lexeme: &Lexeme) -> TokenKind {
+        match lexeme {
+            Lexeme::Implies => TokenKind::Implies,
+            Lexeme::OpeningParentheses => TokenKind::OpeningParentheses,
+            Lexeme::ClosingParentheses => TokenKind::ClosingParentheses,
+            Lexeme::OpeningBracket => TokenKind::OpeningBracket,
+            Lexeme::ClosingBracket => TokenKind::ClosingBracket,
+            Lexeme::OpeningBrace => TokenKind::OpeningBrace,
+            Lexeme::ClosingBrace => TokenKind::ClosingBrace,
+            Lexeme::Comma => TokenKind::Comma,
+            Lexeme::And => TokenKind::And,
+            Lexeme::Stop => TokenKind::Stop,
+            Lexeme::Variable(_) | Lexeme::SigilVariable(_) => TokenKind::Variable,
+            Lexeme::Constant(_) => TokenKind::Constant,
+            Lexeme::Integer(_) => TokenKind::Integer,
+            Lexeme::Float(_) => TokenKind::Float,
+            Lexeme::Operator(_) => TokenKind::Operator,
+            Lexeme::Wildcard => TokenKind::Wildcard,
+            Lexeme::Expect
+            | Lexeme::ExpectNot
+            | Lexeme::Define
+            | Lexeme::Horizon
+            | Lexeme::Goal => TokenKind::Directive,
+            Lexeme::UnrecognizedLexeme => TokenKind::Unrecognized,
+        }
+    }
+}
+
+/// A token of a `.pif` source, as its kind and its byte span in said source
+#[derive(Eq, PartialEq, Clone, Debug, Hash)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Range<usize>,
+}
+
+/// Returns the tokens of the given source in order, comments included.
+/// Whitespace is the only thing not covered by the returned spans, which makes
+/// this suitable for syntax highlighting and other structural tooling
+pub fn tokens(source: &str) -> impl Iterator<Item = Token> + '_ {
+    let mut last_end = 0;
+    Lexeme::lexer(source)
+        .spanned()
+        .chain(std::iter::once((
+            Lexeme::UnrecognizedLexeme,
+            source.len()..source.len(),
+        )))
+        .flat_map(move |(lexeme, span)| {
+            // Comments are skipped by the lexer, so we find them back in the gaps between tokens
+            let gap = last_end..span.start;
+            last_end = span.end;
+            let comments = comments(source, gap);
+
+            let token = (!span.is_empty()).then(|| Token {
+                kind: TokenKind::from(&lexeme),
+                span,
+            });
+            comments.into_iter().chain(token)
+        })
+}
+
+/// Finds the comment tokens in a part of the source only made of comments and whitespace
+fn comments(source: &str, gap: Range<usize>) -> Vec<Token> {
+    let mut comments = vec![];
+    let mut offset = gap.start;
+    for line in source[gap].split_inclusive('\n') {
+        if let Some(start) = line.find('#') {
+            let end = line.trim_end_matches(['\n', '\r']).len();
+            comments.push(Token {
+                kind: TokenKind::Comment,
+                span: offset + start..offset + end,
+            });
+        }
+        offset += line.len();
+    }
+    comments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_test() {
+        let source = "# comment\natt(X) => att(h(X)). # trailing\n#expect att(a).\n#expected";
+        let kinds: Vec<_> = tokens(source).map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Comment,
+                TokenKind::Constant,
+                TokenKind::OpeningParentheses,
+                TokenKind::Variable,
+                TokenKind::ClosingParentheses,
+                TokenKind::Implies,
+                TokenKind::Constant,
+                TokenKind::OpeningParentheses,
+                TokenKind::Constant,
+                TokenKind::OpeningParentheses,
+                TokenKind::Variable,
+                TokenKind::ClosingParentheses,
+                TokenKind::ClosingParentheses,
+                TokenKind::Stop,
+                TokenKind::Comment,
+                TokenKind::Directive,
+                TokenKind::Constant,
+                TokenKind::OpeningParentheses,
+                TokenKind::Constant,
+                TokenKind::ClosingParentheses,
+                TokenKind::Stop,
+                TokenKind::Comment,
+            ]
+        );
+
+        let spans: Vec<_> = tokens(source).map(|t| &source[t.span]).collect();
+        assert_eq!(spans[0], "# comment");
+        assert_eq!(spans[1], "att");
+        assert_eq!(spans[14], "# trailing");
+        assert_eq!(spans[15], "#expect");
+        assert_eq!(spans[21], "#expected");
+    }
+    #[test]
+    fn quoted_test() {
+        let constants: Vec<_> =
+            Lexeme::lexer(r"'hello world' 'weird()chars' 'it\'s' été Ωmega").collect();
+        assert_eq!(
+            constants,
+            vec![
+                Lexeme::Constant(String::from("hello world")),
+                Lexeme::Constant(String::from("weird()chars")),
+                Lexeme::Constant(String::from("it's")),
+                Lexeme::Constant(String::from("été")),
+                Lexeme::Variable(String::from("Ωmega")),
+            ]
+        );
+        assert_eq!(
+            Lexeme::lexer(r"'\q'").collect::<Vec<_>>(),
+            vec![Lexeme::UnrecognizedLexeme]
+        );
+    }
+}
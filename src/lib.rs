@@ -1,101 +1,933 @@
+use crate::agenda::Agenda;
 use crate::ast::*;
-use crate::derivation_tree::DerivationTree;
-use crate::identifiers::{Identifier, IdentifierServer};
-pub use crate::parser::Parser;
-use crate::resolution::Selection;
+pub use crate::ast::{
+    Atom, Attributes, Defeasible, Folder, Mode, Probability, Rule, Statement, Term, Visitor,
+};
+use crate::cache::QueryCache;
+pub use crate::cache::QueryCacheStats;
+use crate::checkpoint::Checkpoints;
+pub use crate::checkpoint::{CheckpointError, CheckpointPolicy};
+use crate::config::Limits;
+pub use crate::config::{ConfigError, SnifferBuilder, SnifferConfig};
+pub use crate::consistency::Violation;
+pub use crate::defeasible::DefeasibleOutcome;
+pub use crate::derivation_tree::{DerivationError, DerivationTree, Elision};
+pub use crate::diagnostics::{Diagnostic, DiagnosticKind, Diagnostics, Severity};
+use crate::edb::Edb;
+pub use crate::fingerprint::RunFingerprint;
+pub use crate::fmt::{FormatOptions, VariableNaming};
+pub use crate::future::SaturationFuture;
+use crate::ground::GroundFacts;
+use crate::hashing::{HashMap, HashSet, IndexMap, IndexSet};
+pub use crate::identifiers::{
+    Identifier, IdentifierServer, Remapping, Symbol, SymbolKind, SymbolTable,
+};
+use crate::interning::TermStore;
+pub use crate::journal::{DerivationJournal, Inference, JournalEvent, ReplayError};
+use crate::lexer::TokenKind;
+pub use crate::macros::{Macro, MacroTable};
+pub use crate::operators::{Operator, OperatorKind, OperatorTable};
+use crate::output::Outputs;
+use crate::parser::SpannedStatement;
+pub use crate::parser::{NamingConvention, Parser, ParserError, ParserErrorKind, ParserOptions};
+use crate::profile::Profiler;
+pub use crate::profile::{PredicateProfile, Profile, RuleProfile};
+use crate::provenance::{Derivations, Provenance};
+pub use crate::prover::Strategy;
+use crate::prover::{Lemma, Shape};
+use crate::resolution::resolvents;
+pub use crate::resolution::Selection;
+pub use crate::substitution::Substitution;
+use crate::temporal::Timeline;
+pub use crate::view::{Snapshot, SnifferView};
 use itertools::Itertools;
 use logos_nom_bridge::Tokens;
-use rustc_hash::{FxHashMap, FxHashSet};
-use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+mod agenda;
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
 mod ast;
+pub mod bench;
+mod cache;
+mod checkpoint;
+#[cfg(feature = "gzip")]
+mod compression;
+mod config;
+mod consistency;
+mod constraints;
+mod defeasible;
 mod derivation_tree;
+mod diagnostics;
+mod edb;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod fingerprint;
+pub mod fmt;
+mod future;
+mod ground;
+mod hashing;
 mod identifiers;
-mod lexer;
+mod interning;
+mod journal;
+pub mod lexer;
+pub mod lint;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+mod macros;
+pub mod msc;
+mod namespaces;
+mod operators;
+pub mod output;
 mod parser;
+mod planner;
+mod profile;
+pub mod provenance;
+mod prover;
 mod resolution;
+#[cfg(feature = "sat")]
+pub mod sat;
+#[cfg(feature = "serve")]
+pub mod server;
+#[cfg(feature = "proptest")]
+pub mod strategies;
+mod substitution;
+pub mod temporal;
+pub mod testing;
+pub mod transform;
 mod unify;
 mod union_find;
+mod view;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "zstd")]
+mod zstd;
 
+/// An expectation on the derivability of an atom, declared with `#expect` or `#expect_not`
+#[derive(Debug, Clone)]
+pub struct Expectation {
+    pub atom: Atom<String>,
+    pub derivable: bool,
+}
+
+/// The answers to a goal declared with `?-`, as the derivable facts unifying with it
+#[derive(Debug, Clone)]
+pub struct GoalAnswers {
+    pub goal: Atom<String>,
+    pub answers: Vec<Atom<String>>,
+}
+
+/// An expectation that did not hold, along with the derivation of the atom if it
+/// was unexpectedly derived
+#[derive(Debug, Clone)]
+pub struct FailedExpectation {
+    pub expectation: Expectation,
+    pub derivation: Option<DerivationTree>,
+}
+
+/// A rule given by several of the files loaded by `Sniffer::load_all`
+#[derive(Debug, Clone)]
+pub struct DuplicateRule {
+    pub rule: Rule<String>,
+    /// File the rule was first loaded from
+    pub first: PathBuf,
+    /// File giving the rule again
+    pub file: PathBuf,
+    /// Whether the two occurrences have different attributes
+    pub conflicting: bool,
+}
+
+/// Location of a statement in the source it was loaded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// File the statement was loaded from, if any
+    pub file: Option<PathBuf>,
+    /// 1-based line and column of the first token of the statement
+    pub line: usize,
+    pub column: usize,
+}
+impl std::fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// An input rule that can never fire, as one of its premises uses a predicate that
+/// neither an input fact nor any rule concludes
+#[derive(Debug, Clone)]
+pub struct DeadRule {
+    pub rule: Rule<String>,
+    /// The first premise that can never be matched
+    pub premise: Atom<String>,
+    /// Where the rule was given, if it was parsed from a source
+    pub location: Option<SourceLocation>,
+}
+
+#[derive(Debug, Clone)]
 pub struct DerivationInfo {
     pub rules: (InnerRule, InnerRule),
     pub selected_atoms: (Selection<Identifier>, Selection<Identifier>),
 }
 
+/// Number of new facts derived between two snapshots published during saturation
+const PUBLISH_INTERVAL: usize = 256;
+
+/// Number of interned terms above which the term store is emptied between two
+/// saturation steps. Rules hold terms rather than their identifiers, so that the
+/// store only serves unification within a step and would otherwise grow with
+/// every fresh variable of every resolvent
+const MAX_INTERNED_TERMS: usize = 1 << 16;
+
 /// Sniffer's job is to saturate a set of rules, by deriving the current set until no
-/// new rule can be added
-#[derive(Default)]
-pub struct Sniffer {
-    pub rules: FxHashSet<InnerRule>,
-    derived_from: FxHashMap<InnerRule, DerivationInfo>,
+/// new rule can be added.
+/// Rules are kept in insertion order, so identical inputs yield identical outputs.
+/// Rules are annotated with the provenance `P`, see the `provenance` module
+pub struct Sniffer<P: Provenance = Derivations> {
+    /// Input rules, save for ground facts, along with every derived rule
+    pub rules: IndexSet<InnerRule>,
+    /// Rules of `rules` other than ground facts by normal form, see `lookup`
+    normal_forms: HashMap<Rule<String>, InnerRule>,
+    /// Ground input facts, see the `edb` module
+    edb: Edb,
+    /// Positions of the ground facts of `rules`, see the `ground` module
+    ground: GroundFacts,
+    derived_from: IndexMap<InnerRule, DerivationInfo>,
+    /// Weights of the rules, only recorded when not zero
+    weights: HashMap<InnerRule, u32>,
+    /// Probabilities of the input rules, only recorded when given
+    probabilities: HashMap<InnerRule, f64>,
+    expectations: Vec<Expectation>,
+    goals: Vec<Atom<String>>,
+    /// Negative constraints, compiled into goal clauses, see the `consistency` module
+    negative_constraints: Vec<Vec<Atom<String>>>,
+    /// Defeasible rules, applied by `run_defeasible` once the rule set is saturated
+    defeasible: Vec<Defeasible<Identifier>>,
+    timeline: Timeline,
+    /// Locations of the input rules parsed from a source
+    locations: HashMap<InnerRule, SourceLocation>,
+    /// Strategy used by `prove`, see the `prover` module
+    strategy: Strategy,
+    /// Bounds of saturation, see the `config` module
+    limits: Limits,
+    /// Seed of the randomized choices made for the runs, see the `fingerprint` module
+    seed: Option<u64>,
+    /// Goals `prove` found no proof of, see the `prover` module
+    lemmas: HashMap<Shape, Lemma>,
+    /// Answers of `find`, see the `cache` module
+    queries: QueryCache,
+    /// Log of the inferences, once started, see the `journal` module
+    journal: Option<DerivationJournal>,
+    /// Checkpoints written while saturating, see the `checkpoint` module
+    checkpoints: Option<Checkpoints>,
+    /// Saturation counters, when profiling is enabled, see the `profile` module
+    profiler: Option<Profiler>,
+    /// Output predicates and their callbacks, see the `output` module
+    outputs: Outputs,
+    /// Warnings about the rule set, see the `diagnostics` module
+    diagnostics: Diagnostics,
 
+    parser_options: ParserOptions,
     id_server: IdentifierServer,
+    terms: TermStore,
+    view: SnifferView,
+
+    provenance: P,
+    annotations: HashMap<InnerRule, P::Annotation>,
+    /// Derivations already accounted for in annotations, as both parents and the resolvent
+    derivations: HashSet<(InnerRule, InnerRule, InnerRule)>,
+}
+/// Clones are independent of the original Sniffer, views included, so that what-if
+/// scenarios can be explored on a copy
+impl<P: Provenance + Clone> Clone for Sniffer<P> {
+    fn clone(&self) -> Self {
+        let sniffer = Sniffer {
+            rules: self.rules.clone(),
+            normal_forms: self.normal_forms.clone(),
+            edb: self.edb.clone(),
+            ground: self.ground.clone(),
+            derived_from: self.derived_from.clone(),
+            weights: self.weights.clone(),
+            probabilities: self.probabilities.clone(),
+            expectations: self.expectations.clone(),
+            goals: self.goals.clone(),
+            negative_constraints: self.negative_constraints.clone(),
+            defeasible: self.defeasible.clone(),
+            timeline: self.timeline.clone(),
+            locations: self.locations.clone(),
+            strategy: self.strategy,
+            limits: self.limits,
+            seed: self.seed,
+            lemmas: self.lemmas.clone(),
+            queries: self.queries.clone(),
+            journal: self.journal.clone(),
+            checkpoints: self.checkpoints.clone(),
+            profiler: self.profiler.clone(),
+            outputs: self.outputs.clone(),
+            diagnostics: self.diagnostics.clone(),
+            parser_options: self.parser_options.clone(),
+            id_server: self.id_server.clone(),
+            terms: self.terms.clone(),
+            view: SnifferView::default(),
+            provenance: self.provenance.clone(),
+            annotations: self.annotations.clone(),
+            derivations: self.derivations.clone(),
+        };
+        sniffer.publish();
+        sniffer
+    }
+}
+impl Default for Sniffer {
+    fn default() -> Self {
+        Sniffer::with_provenance(Derivations)
+    }
 }
 impl Sniffer {
     /// Creates a Sniffer context from a `.pif` file
-    pub fn new<P: AsRef<Path>>(file: P) -> Result<Sniffer, ()> {
-        // Parses the `.pif` file
-        let mut file_contents = String::new();
-        if let Ok(mut file) = File::open(file) {
-            file.read_to_string(&mut file_contents).unwrap();
-        } else {
-            return Err(());
-        }
-        let parsed_rules =
-            Parser::parse_rules(Tokens::new(&file_contents)).expect("failed to parse file");
+    pub fn new<P: AsRef<Path>>(file: P) -> Result<Sniffer, LoadError> {
+        Sniffer::from_reader(File::open(file).map_err(LoadError::Io)?)
+    }
+
+    /// Creates a Sniffer context from `.pif` statements read from the given reader,
+    /// such as the standard input. With the `gzip` and `zstd` features, compressed
+    /// sources are decompressed on the fly
+    pub fn from_reader<R: Read>(reader: R) -> Result<Sniffer, LoadError> {
+        let source = read_source(reader).map_err(LoadError::Io)?;
 
-        // Then maps every string id to an inner identifier
         let mut sniffer = Sniffer::default();
-        for rule in parsed_rules {
-            let inner_rule = rule.to_inner(&mut sniffer.id_server);
-            sniffer.rules.insert(inner_rule);
-        }
+        sniffer.assert(&source).map_err(LoadError::Parsing)?;
         Ok(sniffer)
     }
 
-    /// Returns a derivation that results in a given rule if one exists
-    pub fn find(&mut self, atom: &Atom<String>) -> Result<DerivationTree, SaturationFailure> {
-        let inner_atom = atom.to_inner(&mut self.id_server, &mut HashMap::new());
-        let inner_rule = Rule {
-            conclusion: inner_atom.clone(),
-            premises: vec![],
-        };
+    /// Creates a Sniffer context from already parsed rules, such as the output of
+    /// `Parser::parse_rules` or rules built programmatically
+    pub fn from_rules(rules: Vec<Rule<String>>) -> Sniffer {
+        let mut sniffer = Sniffer::default();
+        sniffer.assert_statements(
+            rules
+                .into_iter()
+                .map(|rule| Statement::Rule(rule, Attributes::default()))
+                .collect(),
+        );
+        sniffer
+    }
+
+    /// Creates an empty Sniffer context, parsing and printing with the given options
+    pub fn with_parser_options(options: ParserOptions) -> Sniffer {
+        Sniffer {
+            id_server: IdentifierServer::with_convention(options.convention),
+            parser_options: options,
+            ..Default::default()
+        }
+    }
+}
+impl<P: Provenance> Sniffer<P> {
+    /// Creates an empty Sniffer context annotating rules with the given provenance
+    pub fn with_provenance(provenance: P) -> Sniffer<P> {
+        Sniffer {
+            rules: IndexSet::default(),
+            normal_forms: HashMap::default(),
+            edb: Edb::default(),
+            ground: GroundFacts::default(),
+            derived_from: IndexMap::default(),
+            weights: HashMap::default(),
+            probabilities: HashMap::default(),
+            expectations: vec![],
+            goals: vec![],
+            negative_constraints: vec![],
+            defeasible: vec![],
+            timeline: Timeline::default(),
+            locations: HashMap::default(),
+            strategy: Strategy::default(),
+            limits: Limits::default(),
+            seed: None,
+            lemmas: HashMap::default(),
+            queries: QueryCache::default(),
+            journal: None,
+            checkpoints: None,
+            profiler: None,
+            outputs: Outputs::default(),
+            diagnostics: Diagnostics::default(),
+            parser_options: ParserOptions::default(),
+            id_server: IdentifierServer::default(),
+            terms: TermStore::default(),
+            view: SnifferView::default(),
+            provenance,
+            annotations: HashMap::default(),
+            derivations: HashSet::default(),
+        }
+    }
+
+    /// Options used to parse statements asserted in this context, along with the
+    /// operators declared so far
+    pub fn parser_options(&self) -> &ParserOptions {
+        &self.parser_options
+    }
+
+    /// Parses the given statements and adds them to the current context, returning
+    /// the number of statements that were parsed
+    pub fn assert(&mut self, source: &str) -> Result<usize, ParserError> {
+        let statements =
+            Parser::parse_spanned_statements_with(Tokens::new(source), &self.parser_options)?;
+        let count = statements.len();
+        self.assert_located(locate(source, statements, None));
+        Ok(count)
+    }
+
+    /// Loads several `.pif` files into the current context, in order. Rules given
+    /// by several files (up to variable renaming) are only added once, with the
+    /// attributes they were first given, and are returned along with the files
+    /// they come from
+    pub fn load_all<I: IntoIterator<Item = PathBuf>>(
+        &mut self,
+        files: I,
+    ) -> Result<Vec<DuplicateRule>, LoadError> {
+        self.load_all_from(files.into_iter().map(|path| {
+            let file = File::open(&path);
+            (path, file)
+        }))
+    }
+
+    /// Loads several sources like `load_all`, each given by its name along with a
+    /// reader, or the error that occurred while opening it. Sources are only read
+    /// once the previous ones are loaded
+    pub fn load_all_from<R: Read, I: IntoIterator<Item = (PathBuf, std::io::Result<R>)>>(
+        &mut self,
+        sources: I,
+    ) -> Result<Vec<DuplicateRule>, LoadError> {
+        let mut origins: HashMap<Rule<String>, (usize, Attributes)> = HashMap::default();
+        let mut paths: Vec<PathBuf> = vec![];
+        let mut duplicates = vec![];
+        for (index, (path, reader)) in sources.into_iter().enumerate() {
+            let in_file = |e| LoadError::File(path.clone(), Box::new(e));
+            let source = reader
+                .and_then(read_source)
+                .map_err(|e| in_file(LoadError::Io(e)))?;
+            let statements =
+                Parser::parse_spanned_statements_with(Tokens::new(&source), &self.parser_options)
+                    .map_err(|e| in_file(LoadError::Parsing(e)))?;
 
-        // Create a selection function using the query
-        let select = move |r: &InnerRule| {
-            for (i, p) in r.premises.iter().enumerate() {
-                if p.is_symbol(inner_atom.symbol) && !p.is_smth_of_variable() {
-                    return Selection::Premise(p.clone(), i);
+            let mut kept = vec![];
+            for (statement, location) in locate(&source, statements, Some(&path)) {
+                if let Statement::Rule(rule, attributes) = &statement {
+                    match origins.get(&rule.canonical()) {
+                        Some((first, first_attributes)) if *first != index => {
+                            duplicates.push(DuplicateRule {
+                                rule: rule.clone(),
+                                first: paths[*first].clone(),
+                                file: path.clone(),
+                                conflicting: attributes != first_attributes,
+                            });
+                            continue;
+                        }
+                        Some(_) => (),
+                        None => {
+                            origins.insert(rule.canonical(), (index, attributes.clone()));
+                        }
+                    }
                 }
+                kept.push((statement, location))
             }
-            Selection::Conclusion(r.conclusion.clone())
-        };
+            self.assert_located(kept);
+            paths.push(path);
+        }
+        Ok(duplicates)
+    }
+
+    /// Adds parsed statements to the current context, such as the output of a pass
+    /// of the `transform` module
+    pub fn assert_statements(&mut self, statements: Vec<Statement<String>>) {
+        self.assert_located(statements.into_iter().map(|s| (s, None)).collect())
+    }
 
-        // Filter for not useful atoms
-        let keep = move |a: &Atom<Identifier>, c: &Atom<Identifier>| {
-            if a.symbol == inner_atom.symbol && a.is_smth_of_variable() {
-                c.contains_variable(&a.parameters[0])
-            } else {
-                true
+    /// Adds parsed statements to the current context, recording where rules come from
+    fn assert_located(&mut self, statements: Vec<(Statement<String>, Option<SourceLocation>)>) {
+        for (statement, location) in statements {
+            match statement {
+                // Maps every string id to an inner identifier
+                Statement::Rule(rule, attributes) => {
+                    let inner_rule = self.insert_input(rule, &attributes);
+                    self.check_arities(&inner_rule, location.as_ref());
+                    if let Some(location) = location {
+                        self.locations.entry(inner_rule).or_insert(location);
+                    }
+                }
+                Statement::Expect(atom) => self.expectations.push(Expectation {
+                    atom,
+                    derivable: true,
+                }),
+                Statement::ExpectNot(atom) => self.expectations.push(Expectation {
+                    atom,
+                    derivable: false,
+                }),
+                Statement::Goal(atom) => self.goals.push(atom),
+                Statement::Operator(operator) => self.parser_options.operators.declare(operator),
+                Statement::Macro(definition) => self.parser_options.macros.define(definition),
+                Statement::Next(rule) => self.timeline.rules.push(rule),
+                Statement::Horizon(horizon) => self.timeline.horizon = horizon,
+                Statement::Output(predicate) => {
+                    let symbol = self.id_server.register_function(&predicate);
+                    self.outputs.designate(symbol)
+                }
+                Statement::Mode(Mode { predicate, bound }) => {
+                    let symbol = self.id_server.register_function(&predicate);
+                    self.edb.declare_mode(symbol, bound)
+                }
+                Statement::Constraint(atoms) => {
+                    let goal_clause = self.insert_constraint(atoms);
+                    if let Some(location) = location {
+                        self.locations.entry(goal_clause).or_insert(location);
+                    }
+                }
+                Statement::Defeasible(Defeasible {
+                    rule,
+                    negated,
+                    priority,
+                }) => self.defeasible.push(Defeasible {
+                    rule: rule.to_inner(&mut self.id_server),
+                    negated,
+                    priority,
+                }),
             }
+        }
+        self.check_unused();
+        self.publish();
+    }
+
+    /// Adds an input rule to the rule set in normal form, see `Rule::normalized`,
+    /// recording its attributes. A rule already known up to normalization is not
+    /// added again, keeping the highest weight of the two
+    pub(crate) fn insert_input(
+        &mut self,
+        rule: Rule<String>,
+        attributes: &Attributes,
+    ) -> InnerRule {
+        let mut rule = rule.normalized();
+        let mut inner_rule = rule.to_inner(&mut self.id_server);
+        // Constraints that always hold are dropped, see the `constraints` module
+        if let Some(simplified) = constraints::simplify(inner_rule.clone(), self.neq()) {
+            if simplified != inner_rule {
+                rule = simplified.to_string(&self.id_server).normalized();
+                inner_rule = rule.to_inner(&mut self.id_server);
+            }
+        }
+        if !Edb::accepts(&inner_rule) {
+            if let Some(known) = self.normal_forms.get(&rule).cloned() {
+                if attributes.weight > self.weight(&known) {
+                    self.weights.insert(known.clone(), attributes.weight);
+                }
+                return known;
+            }
+        }
+        if P::TRACKED && !self.annotations.contains_key(&inner_rule) {
+            let index = self.rules.len() + self.edb.len();
+            let annotation = self.provenance.input(index, &rule, attributes);
+            self.annotations.insert(inner_rule.clone(), annotation);
+        }
+        if attributes.weight > 0 {
+            self.weights.insert(inner_rule.clone(), attributes.weight);
+        }
+        if let Some(probability) = attributes.probability {
+            self.probabilities
+                .insert(inner_rule.clone(), probability.value());
+        }
+        let added = if Edb::accepts(&inner_rule) {
+            self.edb.insert(&inner_rule.conclusion)
+        } else {
+            self.insert_rule(inner_rule.clone())
         };
+        if added {
+            self.output(&inner_rule)
+        }
+        self.journal_input(&inner_rule);
+        inner_rule
+    }
+
+    /// Adds the input rules, defeasible rules, expectations and goals of another
+    /// context to this one, returning the number of rules added. Symbols are
+    /// matched by name, see `IdentifierServer::import`, and rules already known up
+    /// to variable renaming are skipped. Rules keep their weights, probabilities
+    /// and locations. Rules derived by the other context are left out, saturating
+    /// this one derives them again
+    pub fn merge<Q: Provenance>(&mut self, other: &Sniffer<Q>) -> usize {
+        let remapping = self.id_server.import(&other.id_server);
+        // Goal clauses are numbered by constraint, so constraints are added again
+        let bottom = other.bottom();
+        let inputs: Vec<InnerRule> = other
+            .all_rules()
+            .filter(|r| !other.derived_from.contains_key(r))
+            .filter(|r| Some(r.conclusion.symbol) != bottom)
+            .collect();
+        let mut added = 0;
+        for rule in inputs {
+            let attributes = Attributes {
+                weight: other.weights.get(&rule).copied().unwrap_or_default(),
+                probability: other
+                    .probabilities
+                    .get(&rule)
+                    .and_then(|p| Probability::new(*p)),
+                ..Default::default()
+            };
+            let named = rule.to_string(&other.id_server);
+            if self.lookup(&named).is_some() {
+                continue;
+            }
+            added += 1;
+            let inner_rule = self.insert_input(named, &attributes);
+            if let Some(location) = other.locations.get(&rule) {
+                self.locations
+                    .entry(inner_rule)
+                    .or_insert_with(|| location.clone());
+            }
+        }
+        for defeasible in &other.defeasible {
+            self.defeasible.push(Defeasible {
+                rule: defeasible.rule.remap(&remapping, &mut self.id_server),
+                ..defeasible.clone()
+            })
+        }
+        for atoms in &other.negative_constraints {
+            if !self.negative_constraints.contains(atoms) {
+                added += 1;
+                self.insert_constraint(atoms.clone());
+            }
+        }
+        self.expectations.extend(other.expectations.iter().cloned());
+        self.goals.extend(other.goals.iter().cloned());
+        added
+    }
 
-        // We keep saturating our rule set until we either find our atom or the set is fully saturated
-        self.saturate(&inner_rule, select, keep);
+    /// Removes a rule of the rule set, looked up modulo variable renaming, along
+    /// with every rule derived from it. Facts that had other derivations are
+    /// derived again by saturating the rule set, the others are returned. Returns
+    /// `None` if the rule is unknown.
+    /// A derived rule may be removed as well, in which case it comes back if it is
+    /// still derivable
+    pub fn remove_rule(&mut self, rule: &Rule<String>) -> Option<Vec<Atom<String>>> {
+        let removed = self.lookup(rule)?;
 
-        if self.rules.contains(&inner_rule) {
-            Ok(self
-                .derivation_tree(&Rule {
-                    conclusion: atom.clone(),
-                    premises: vec![],
+        // Rules whose recorded derivation (or any derivation accounted for in their
+        // annotation) uses a removed rule are removed as well
+        let mut invalidated: IndexSet<InnerRule> = IndexSet::from_iter([removed.clone()]);
+        loop {
+            let dependents: Vec<InnerRule> = self
+                .derived_from
+                .iter()
+                .filter(|(_, info)| {
+                    invalidated.contains(&info.rules.0) || invalidated.contains(&info.rules.1)
                 })
-                .unwrap())
-        } else {
-            Err(SaturationFailure::Saturated)
+                .map(|(r, _)| r.clone())
+                .chain(
+                    self.derivations
+                        .iter()
+                        .filter(|(a, b, _)| invalidated.contains(a) || invalidated.contains(b))
+                        .map(|(_, _, r)| r.clone()),
+                )
+                .filter(|r| !invalidated.contains(r))
+                .collect();
+            if dependents.is_empty() {
+                break;
+            }
+            invalidated.extend(dependents);
         }
+
+        // Failure lemmas and cached answers only check the size of relations, which
+        // may be restored
+        self.lemmas.clear();
+        self.queries.clear();
+        self.edb.remove(&removed.conclusion);
+        self.ground.clear();
+        let lost: HashSet<Rule<String>> = self
+            .normal_forms
+            .extract_if(|_, r| invalidated.contains(r))
+            .map(|(normal_form, _)| normal_form)
+            .collect();
+        for r in &invalidated {
+            self.rules.shift_remove(r);
+            self.derived_from.shift_remove(r);
+            self.weights.remove(r);
+            self.annotations.remove(r);
+        }
+        // Rules left with the normal form of a removed rule are indexed in its place
+        if !lost.is_empty() {
+            for r in self.rules.iter().filter(|r| !Edb::accepts(r)) {
+                if let Ok(named) = r.try_to_string(&self.id_server) {
+                    let normal_form = named.normalized();
+                    if lost.contains(&normal_form) {
+                        self.normal_forms.entry(normal_form).or_insert(r.clone());
+                    }
+                }
+            }
+        }
+        self.probabilities.remove(&removed);
+        self.locations.remove(&removed);
+        self.derivations.retain(|(a, b, r)| {
+            !(invalidated.contains(a) || invalidated.contains(b) || invalidated.contains(r))
+        });
+
+        let facts: Vec<InnerAtom> = invalidated
+            .iter()
+            .filter(|r| r.premises.is_empty() && **r != removed)
+            .map(|r| r.conclusion.clone())
+            .collect();
+        if !facts.is_empty() {
+            self.saturate_all();
+        }
+        let retracted = facts
+            .iter()
+            .filter(|fact| self.find_fact(fact).is_none())
+            .map(|fact| fact.to_string(&self.id_server))
+            .collect();
+        self.publish();
+        Some(retracted)
+    }
+
+    /// Returns the input rules that can never fire, in order, as one of their
+    /// premises uses a predicate that no input fact or rule concludes
+    pub fn dead_rules(&self) -> Vec<DeadRule> {
+        let mut concluded: HashSet<(Identifier, usize)> = self
+            .all_rules()
+            .map(|r| (r.conclusion.symbol, r.conclusion.parameters.len()))
+            .chain(self.neq().map(|neq| (neq, 2)))
+            .collect();
+        for Defeasible { rule, negated, .. } in &self.defeasible {
+            if !negated {
+                concluded.insert((rule.conclusion.symbol, rule.conclusion.parameters.len()));
+            }
+        }
+        // `next` rules conclude atoms with the time step as an implicit last parameter
+        for rule in &self.timeline.rules {
+            if let Some(symbol) = self.id_server.id_of(&rule.conclusion.symbol) {
+                concluded.insert((*symbol, rule.conclusion.parameters.len() + 1));
+            }
+        }
+
+        self.rules
+            .iter()
+            .filter(|r| !self.derived_from.contains_key(*r))
+            .filter_map(|r| {
+                let premise = r
+                    .premises
+                    .iter()
+                    .find(|p| !concluded.contains(&(p.symbol, p.parameters.len())))?;
+                Some(DeadRule {
+                    rule: r.to_string(&self.id_server),
+                    premise: premise.to_string(&self.id_server),
+                    location: self.locations.get(r).cloned(),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns a read handle on the facts of this Sniffer, updated as it saturates
+    pub fn view(&self) -> SnifferView {
+        self.view.clone()
+    }
+
+    /// Publishes the current facts to the views of this Sniffer
+    pub(crate) fn publish(&self) {
+        self.view.publish(self.iter_facts())
+    }
+
+    /// Expectations declared in the loaded statements
+    pub fn expectations(&self) -> &[Expectation] {
+        &self.expectations
+    }
+
+    /// Saturates the rule set, then checks every declared expectation and returns
+    /// the ones that did not hold
+    pub fn run_expectations(&mut self) -> Vec<FailedExpectation> {
+        self.saturate_all();
+        let expectations = self.expectations.clone();
+        expectations
+            .into_iter()
+            .filter_map(|expectation| {
+                let goal = expectation
+                    .atom
+                    .to_inner(&mut self.id_server, &mut HashMap::default());
+                let fact = self.find_fact(&goal);
+                match (fact, expectation.derivable) {
+                    (Some(_), true) | (None, false) => None,
+                    (Some(fact), false) => Some(FailedExpectation {
+                        derivation: self.inner_derivation_tree(&fact, &mut vec![]).ok(),
+                        expectation,
+                    }),
+                    (None, true) => Some(FailedExpectation {
+                        expectation,
+                        derivation: None,
+                    }),
+                }
+            })
+            .collect()
+    }
+
+    /// Goals declared in the loaded statements
+    pub fn goals(&self) -> &[Atom<String>] {
+        &self.goals
+    }
+
+    /// Saturates the rule set, then answers every declared goal in order
+    pub fn run_goals(&mut self) -> Vec<GoalAnswers> {
+        self.saturate_all();
+        self.goals
+            .iter()
+            .map(|goal| GoalAnswers {
+                goal: goal.clone(),
+                answers: self.iter_facts().filter(|f| goal.unifies_with(f)).collect(),
+            })
+            .collect()
+    }
+
+    /// Returns a derivation that results in a given atom if one exists.
+    /// The atom may contain variables, in which case the derivation of any
+    /// matching instance is returned, see `find_all` for every instance
+    pub fn find(&mut self, atom: &Atom<String>) -> Result<DerivationTree, SaturationFailure> {
+        self.find_many(std::slice::from_ref(atom)).remove(0)
+    }
+
+    /// Returns a derivation for each of the given atoms, in order, like `find` but
+    /// saturating the rule set once for all of them: saturation goes on until every
+    /// atom has a derivation or the rule set is saturated, so answers do not depend
+    /// on the order atoms are searched in
+    pub fn find_many(
+        &mut self,
+        atoms: &[Atom<String>],
+    ) -> Vec<Result<DerivationTree, SaturationFailure>> {
+        let goals: Vec<InnerAtom> = atoms
+            .iter()
+            .map(|atom| atom.to_inner(&mut self.id_server, &mut HashMap::default()))
+            .collect();
+        let cached: Vec<_> = goals.iter().map(|goal| self.cached_answer(goal)).collect();
+        let searching: Vec<InnerAtom> = goals
+            .iter()
+            .zip(&cached)
+            .filter(|(_, answer)| answer.is_none())
+            .map(|(goal, _)| goal.clone())
+            .collect();
+
+        if !searching.is_empty() {
+            // Create a selection function using the queries, premises of the queried
+            // symbols being matched in the order given by the join plan
+            let symbols: HashSet<Identifier> = searching.iter().map(|goal| goal.symbol).collect();
+            let plan = self.join_plan();
+            let queried = symbols.clone();
+            let select = move |r: &InnerRule| {
+                plan.select(r, |p| {
+                    queried.contains(&p.symbol) && !p.is_smth_of_variable()
+                })
+            };
+
+            // Filter for not useful atoms
+            let keep = move |a: &Atom<Identifier>, c: &Atom<Identifier>| {
+                if symbols.contains(&a.symbol) && a.is_smth_of_variable() {
+                    c.contains_variable(&a.parameters[0])
+                } else {
+                    true
+                }
+            };
+
+            // We keep saturating our rule set until we either find every atom or the set is fully saturated
+            self.saturate(&searching, select, keep);
+        }
+
+        goals
+            .iter()
+            .zip(cached)
+            .map(|(goal, cached)| {
+                cached.unwrap_or_else(|| {
+                    let answer = if let Some(fact) = self.find_fact(goal) {
+                        self.inner_derivation_tree(&fact, &mut vec![])
+                            .map_err(SaturationFailure::Derivation)
+                    } else {
+                        Err(SaturationFailure::Saturated)
+                    };
+                    self.cache_answer(goal, &answer);
+                    answer
+                })
+            })
+            .collect()
+    }
+
+    /// Returns every instance of an atom that can be derived, along with its
+    /// derivation. Unlike `find`, which stops at the first instance, the rule set is
+    /// fully saturated as done by `saturate_all`
+    pub fn find_all(
+        &mut self,
+        atom: &Atom<String>,
+    ) -> Result<Vec<(Atom<String>, DerivationTree)>, DerivationError> {
+        self.saturate_all();
+        let searching = atom.to_inner(&mut self.id_server, &mut HashMap::default());
+        let facts: Vec<_> = self
+            .edb
+            .matching(&searching)
+            .into_iter()
+            .chain(self.rules.iter().cloned())
+            .filter(|r| r.is_fact_matching(&searching))
+            .collect();
+        facts
+            .into_iter()
+            .map(|fact| {
+                let tree = self.inner_derivation_tree(&fact, &mut vec![])?;
+                Ok((fact.conclusion.to_string(&self.id_server), tree))
+            })
+            .collect()
+    }
+
+    /// Computes the probability of an atom in the style of ProbLog: its derivation
+    /// holds if every input rule it is built from holds, each independently with
+    /// the probability it was given (1 by default).
+    /// Only the first derivation of a rule is recorded, so this is a lower bound of
+    /// the probability of the atom, which is exact when it has a single derivation
+    pub fn probability(&mut self, atom: &Atom<String>) -> Result<f64, SaturationFailure> {
+        self.find(atom)?;
+        let searching = atom.to_inner(&mut self.id_server, &mut HashMap::default());
+        let fact = self
+            .find_fact(&searching)
+            .ok_or(SaturationFailure::Saturated)?;
+
+        // Input rules used several times in the derivation are a single event
+        let mut visited = HashSet::default();
+        let mut stack = vec![&fact];
+        let mut probability = 1.0;
+        while let Some(rule) = stack.pop() {
+            if !visited.insert(rule) {
+                continue;
+            }
+            match self.derived_from.get(rule) {
+                Some(DerivationInfo { rules, .. }) => stack.extend([&rules.0, &rules.1]),
+                None => probability *= self.probabilities.get(rule).copied().unwrap_or(1.0),
+            }
+        }
+        Ok(probability)
+    }
+
+    /// Saturates the rule set without any query in mind, so that every derivable
+    /// fact ends up in the rule set. Premises are matched in the order given by the
+    /// join plan, see the `planner` module.
+    /// Premises like `p(X)` are never selected and are dropped when `X` does not
+    /// appear in the conclusion, as done for the queried symbol in `find`
+    pub fn saturate_all(&mut self) {
+        let plan = self.join_plan();
+        self.saturate(&[], move |r| plan.select(r, guarded), keep_guarded);
+        self.check_fired()
+    }
+
+    /// Returns a future saturating the rule set like `saturate_all`, which yields
+    /// regularly to the executor. See `SaturationFuture`
+    pub fn saturate_async(&mut self) -> SaturationFuture<'_, P> {
+        SaturationFuture::new(self)
+    }
+
+    /// Iterates over the facts (rules without premises) of the rule set, input
+    /// facts first
+    pub fn iter_facts(&self) -> impl Iterator<Item = Atom<String>> + '_ {
+        self.all_rules()
+            .filter(|r| r.premises.is_empty())
+            .map(|r| r.conclusion.to_string(&self.id_server))
     }
 
     /// We derive new rules through resolution:
@@ -122,19 +954,269 @@ impl Sniffer {
     /// return Some(DerivationTree ??) if it is finished because we have find our solution
     fn saturate(
         &mut self,
-        searching: &InnerRule,
-        select: impl Fn(&InnerRule) -> Selection<Identifier>,
-        keep: impl Fn(&Atom<Identifier>, &Atom<Identifier>) -> bool,
+        searching: &[InnerAtom],
+        select: impl Fn(&InnerRule) -> Selection<Identifier> + Sync,
+        keep: impl Fn(&Atom<Identifier>, &Atom<Identifier>) -> bool + Sync,
     ) -> Option<DerivationTree> {
-        let mut rules_set: Vec<_> = self.rules.clone().into_iter().collect();
+        let mut rules_set = self.agenda(false);
+        loop {
+            self.saturate_steps(
+                &mut rules_set,
+                searching,
+                &select,
+                &keep,
+                usize::MAX,
+                usize::MAX,
+            );
+            if !searching.is_empty() && searching.iter().all(|atom| self.find_fact(atom).is_some())
+            {
+                break;
+            }
+            // Saturation stopped at one of several searched atoms
+            if rules_set.round().is_some() {
+                continue;
+            }
+            // Time steps are saturated one after the other, see the `temporal` module
+            let instances = self.advance_time();
+            if instances.is_empty() {
+                break;
+            }
+            for rule in instances {
+                let weight = self.weight(&rule);
+                rules_set.push(rule, weight, 0)
+            }
+        }
+        None
+    }
+
+    /// Saturates the rule set for the given number of rounds, like `saturate_all`
+    /// but without advancing time. Rules of the rule set are in round 0, and each
+    /// round resolves the rules of the previous one with every rule known so far,
+    /// see the `agenda` module: rules derived in round `n` have a derivation of
+    /// depth `n` from the current rule set, and every rule with such a derivation
+    /// is derived in round `n` at the latest. Returns the number of rules added
+    /// by the last round
+    pub fn saturate_rounds(&mut self, rounds: usize) -> usize {
+        let plan = self.join_plan();
+        let select = move |r: &InnerRule| plan.select(r, guarded);
+        let mut rules_set = self.agenda(true);
+        self.saturate_steps(
+            &mut rules_set,
+            &[],
+            &select,
+            &keep_guarded,
+            usize::MAX,
+            rounds,
+        );
+        // Rules of the last round are added without being fired
+        self.insert_waiting(&mut rules_set)
+    }
+
+    /// Saturates the rule set like `saturate_all`, firing at most `fuel` rules, and
+    /// returns whether saturation is over. Rules left waiting are added without
+    /// being fired. Heavier rules are fired first, but not forever, see the
+    /// `agenda` module: every derivable fact is derived given enough fuel, even
+    /// when heavy rules keep deriving new facts
+    pub fn saturate_with_fuel(&mut self, fuel: usize) -> bool {
+        let plan = self.join_plan();
+        let select = move |r: &InnerRule| plan.select(r, guarded);
+        let mut rules_set = self.agenda(false);
+        let saturated = self.saturate_steps(
+            &mut rules_set,
+            &[],
+            &select,
+            &keep_guarded,
+            fuel,
+            usize::MAX,
+        );
+        self.insert_waiting(&mut rules_set);
+        saturated
+    }
+
+    /// Adds the rules left on an agenda to the rule set without firing them,
+    /// returning the number of rules added
+    fn insert_waiting(&mut self, rules_set: &mut Agenda) -> usize {
+        let mut added = 0;
+        while let Some((rule, weight, _)) = rules_set.pop() {
+            if weight > 0 {
+                self.weights.entry(rule.clone()).or_insert(weight);
+            }
+            if !self.edb.contains(&rule) && self.insert_rule(rule) {
+                self.output(&self.rules[self.rules.len() - 1]);
+                added += 1
+            }
+        }
+        self.publish();
+        added
+    }
+
+    /// Weight of a rule of the rule set, given by its `weight` attribute. Derived
+    /// rules take the highest weight of the two rules they are resolved from
+    pub(crate) fn weight(&self, rule: &InnerRule) -> u32 {
+        if self.weights.is_empty() {
+            0
+        } else {
+            self.weights.get(rule).copied().unwrap_or(0)
+        }
+    }
+
+    /// Returns an agenda holding every rule of the rule set, input facts first
+    pub(crate) fn agenda(&self, breadth_first: bool) -> Agenda {
+        let mut agenda = if breadth_first {
+            Agenda::breadth_first()
+        } else {
+            Agenda::default()
+        };
+        for rule in self.all_rules() {
+            let weight = self.weight(&rule);
+            agenda.push(rule, weight, 0)
+        }
+        agenda
+    }
+
+    /// Every rule of the rule set, input facts first
+    pub(crate) fn all_rules(&self) -> impl Iterator<Item = InnerRule> + '_ {
+        self.edb.iter().chain(self.rules.iter().cloned())
+    }
+
+    /// Whether a rule is in the rule set, input facts included
+    fn contains(&self, rule: &InnerRule) -> bool {
+        self.edb.contains(rule) || self.rules.contains(rule)
+    }
 
-        while let Some(rule) = rules_set.pop() {
-            for other in &self.rules {
-                if let Some(r) = rule
-                    .resolve(other, &select, &keep)
-                    .map(|r| r.make_fresh(&mut self.id_server))
+    /// Returns a fact of the rule set matching the given atom, looking up input
+    /// facts first
+    fn find_fact(&self, atom: &InnerAtom) -> Option<InnerRule> {
+        self.edb
+            .matching(atom)
+            .into_iter()
+            .find(|r| r.is_fact_matching(atom))
+            .or_else(|| {
+                // Ground atoms are only matched by the fact equal to them
+                if atom.is_ground() {
+                    let fact = Rule {
+                        premises: vec![],
+                        conclusion: atom.clone(),
+                    };
+                    return self.rules.get(&fact).cloned();
+                }
+                self.rules
+                    .iter()
+                    .find(|r| r.is_fact_matching(atom))
+                    .cloned()
+            })
+    }
+
+    /// Runs at most `steps` iterations of the saturation loop, taking rules from
+    /// `rules_set` up to round `rounds` excluded. Returns `true` once saturation is
+    /// over, either because there is no rule left to take or because one of the
+    /// searched atoms was found
+    pub(crate) fn saturate_steps(
+        &mut self,
+        rules_set: &mut Agenda,
+        searching: &[InnerAtom],
+        select: &(impl Fn(&InnerRule) -> Selection<Identifier> + Sync),
+        keep: &(impl Fn(&Atom<Identifier>, &Atom<Identifier>) -> bool + Sync),
+        steps: usize,
+        rounds: usize,
+    ) -> bool {
+        let mut new_facts = 0;
+        for _ in 0..steps {
+            if rules_set.round().is_some_and(|round| round >= rounds) {
+                self.publish();
+                return false;
+            }
+            self.checkpoint_if_due(rules_set);
+            if self.terms.len() > MAX_INTERNED_TERMS {
+                self.terms.clear()
+            }
+            let Some((rule, weight, round)) = rules_set.pop() else {
+                self.publish();
+                return true;
+            };
+            // Only the rules that may be resolved with the selected atom are tried
+            let selection = select(&rule);
+            self.ground.sync(&self.rules);
+            let candidates = self.ground.candidates(&selection);
+            let mut resolvents: Vec<(InnerRule, InnerRule)> = resolvents(
+                &self.rules,
+                &candidates,
+                &rule,
+                &mut self.terms,
+                self.limits.threads,
+                select,
+                keep,
+            )
+            .into_iter()
+            .map(|(r, other)| (r, self.rules[other].clone()))
+            .collect();
+            self.profile_attempts(&rule, select);
+            // Input facts are only those of the relation of the selected premise
+            if let Selection::Premise(premise, _) = &selection {
+                for fact in self.edb.matching(premise) {
+                    if let Some(r) = rule.resolve(&fact, &mut self.terms, select, keep) {
+                        resolvents.push((r, fact))
+                    }
+                }
+            }
+            let neq = self.neq();
+            if neq.is_some() {
+                resolvents = resolvents
+                    .into_iter()
+                    .filter_map(|(r, parent)| Some((constraints::simplify(r, neq)?, parent)))
+                    .collect();
+            }
+
+            // The same rule may be derived from several others: we only keep the first
+            // derivation, but every one of them adds up to its annotation
+            let mut derived: IndexMap<InnerRule, (InnerRule, Option<P::Annotation>)> =
+                IndexMap::default();
+            for (r, parent) in resolvents {
+                let parent = &parent;
+                if let Some(profiler) = &mut self.profiler {
+                    match selection {
+                        Selection::Premise(..) => profiler.fire(&rule),
+                        Selection::Conclusion(_) => profiler.fire(parent),
+                    }
+                }
+                // Derivations are found from both of their parents, but only count once
+                let annotation = self.annotation_product(&rule, parent).filter(|_| {
+                    !self
+                        .derivations
+                        .contains(&(parent.clone(), rule.clone(), r.clone()))
+                        && self
+                            .derivations
+                            .insert((rule.clone(), parent.clone(), r.clone()))
+                });
+                match derived.entry(r) {
+                    indexmap::map::Entry::Vacant(e) => {
+                        e.insert((parent.clone(), annotation));
+                    }
+                    indexmap::map::Entry::Occupied(mut e) => {
+                        if let (Some(sum), Some(annotation)) = (&mut e.get_mut().1, annotation) {
+                            *sum = self.provenance.plus(sum, &annotation)
+                        }
+                    }
+                }
+            }
+            for (r, (other, annotation)) in derived {
+                let other = &other;
+                let derived_weight = weight.max(self.weight(other));
+                let r = r.make_fresh(&mut self.id_server);
+                if !(r.premises.len() == 1 && r.premises[0] == r.conclusion)
+                    && r != rule
+                    && !self.limits.exceeds(&r)
                 {
-                    if !(r.premises.len() == 1 && r.premises[0] == r.conclusion) && r != rule {
+                    if self.journal.is_some() {
+                        let info = DerivationInfo {
+                            rules: (rule.clone(), other.clone()),
+                            selected_atoms: (select(&rule), select(other)),
+                        };
+                        self.journal_derivation(&info, &r)
+                    }
+                    // Rules that were already known keep their own provenance, which
+                    // keeps derivations acyclic
+                    if !self.contains(&r) {
                         let selected = (select(&rule), select(other));
                         self.derived_from
                             .entry(r.clone())
@@ -142,83 +1224,903 @@ impl Sniffer {
                                 rules: (rule.clone(), other.clone()),
                                 selected_atoms: selected,
                             });
-                        rules_set.push(r)
                     }
+                    if let Some(annotation) = annotation {
+                        match self.annotations.entry(r.clone()) {
+                            std::collections::hash_map::Entry::Vacant(e) => {
+                                e.insert(annotation);
+                            }
+                            std::collections::hash_map::Entry::Occupied(mut e) => {
+                                let sum = self.provenance.plus(e.get(), &annotation);
+                                e.insert(sum);
+                            }
+                        }
+                    }
+                    rules_set.push(r, derived_weight, round + 1)
                 }
             }
 
-            self.rules.insert(rule.clone());
-            if &rule == searching {
-                return None;
+            let found = searching.iter().any(|atom| rule.is_fact_matching(atom));
+            let is_fact = rule.premises.is_empty();
+            if weight > 0 {
+                self.weights.entry(rule.clone()).or_insert(weight);
+            }
+            if !self.edb.contains(&rule) && self.insert_rule(rule) && is_fact {
+                // Rules are inserted last
+                self.output(&self.rules[self.rules.len() - 1]);
+                new_facts += 1;
+                if new_facts % PUBLISH_INTERVAL == 0 {
+                    self.publish()
+                }
+            }
+            if found {
+                self.publish();
+                return true;
             }
         }
+        false
+    }
 
-        None
+    /// Annotation of a rule resolved from the two given rules, if annotations are tracked
+    fn annotation_product(&self, a: &InnerRule, b: &InnerRule) -> Option<P::Annotation> {
+        if !P::TRACKED {
+            return None;
+        }
+        Some(
+            self.provenance
+                .times(self.annotations.get(a)?, self.annotations.get(b)?),
+        )
     }
 
-    /// Returns the derivation tree for a given rule
-    pub fn derivation_tree(&mut self, root: &Rule<String>) -> Option<DerivationTree> {
-        fn inner(root: &InnerRule, sniffer: &Sniffer) -> Option<DerivationTree> {
-            let mut derivation_tree = DerivationTree::new(root.to_string(&sniffer.id_server));
-            if let Some(DerivationInfo {
-                rules,
-                selected_atoms,
-            }) = sniffer.derived_from.get(root)
-            {
-                if let Some(mut tree) = inner(&rules.0, sniffer) {
-                    tree.set_selection(
-                        Selection::try_from((&selected_atoms.0, &sniffer.id_server)).unwrap(),
-                    );
-                    derivation_tree.add_subtree(tree)
-                }
-                if let Some(mut tree) = inner(&rules.1, sniffer) {
-                    tree.set_selection(
-                        Selection::try_from((&selected_atoms.1, &sniffer.id_server)).unwrap(),
-                    );
-                    derivation_tree.add_subtree(tree)
-                }
+    /// Returns the annotation of a rule of the rule set, which is looked up modulo
+    /// variable renaming
+    pub fn annotation(&self, rule: &Rule<String>) -> Option<&P::Annotation> {
+        self.annotations.get(&self.lookup(rule)?)
+    }
+
+    /// Finds a rule of the rule set modulo variable renaming and premise order, see
+    /// `Rule::normalized`
+    fn lookup(&self, rule: &Rule<String>) -> Option<InnerRule> {
+        let normalized = rule.normalized();
+        if normalized.premises.is_empty() && normalized.conclusion.is_ground() {
+            // Ground facts are their own normal form, and are not indexed
+            let fact = Rule {
+                premises: vec![],
+                conclusion: normalized.conclusion.to_known_inner(&self.id_server)?,
             };
-            Some(derivation_tree)
+            return self.contains(&fact).then_some(fact);
         }
+        self.normal_forms
+            .get(&normalized)
+            .filter(|r| self.rules.contains(*r))
+            .cloned()
+    }
 
-        let inner_rule = root.to_inner(&mut self.id_server);
+    /// Adds a rule to `rules`, indexing it by normal form unless it is a ground
+    /// fact, see `lookup`. Returns `false` if it was already there
+    pub(crate) fn insert_rule(&mut self, rule: InnerRule) -> bool {
+        let indexed = !Edb::accepts(&rule);
+        if !self.rules.insert(rule) {
+            return false;
+        }
+        if indexed {
+            let rule = &self.rules[self.rules.len() - 1];
+            if let Ok(named) = rule.try_to_string(&self.id_server) {
+                self.normal_forms
+                    .entry(named.normalized())
+                    .or_insert_with(|| rule.clone());
+            }
+        }
+        true
+    }
 
-        let mut decision_tree = DerivationTree::new(root.clone());
+    /// Returns the derivation tree for an inner rule, naming it using the identifier server.
+    /// `visiting` holds the rules on the path from the root, to detect cyclic derivations
+    fn inner_derivation_tree(
+        &self,
+        root: &InnerRule,
+        visiting: &mut Vec<InnerRule>,
+    ) -> Result<DerivationTree, DerivationError> {
+        let mut derivation_tree = DerivationTree::new(root.try_to_string(&self.id_server)?);
         if let Some(DerivationInfo {
             rules,
             selected_atoms,
-        }) = self.derived_from.get(&inner_rule)
+        }) = self.derived_from.get(root)
         {
-            if let Some(mut tree) = inner(&rules.0, self) {
-                tree.set_selection(
-                    Selection::try_from((&selected_atoms.0, &self.id_server)).unwrap(),
-                );
-                decision_tree.add_subtree(tree)
-            }
-            if let Some(mut tree) = inner(&rules.1, self) {
-                tree.set_selection(
-                    Selection::try_from((&selected_atoms.1, &self.id_server)).unwrap(),
-                );
-                decision_tree.add_subtree(tree)
+            if visiting.contains(root) {
+                return Err(DerivationError::BrokenProvenance(
+                    root.try_to_string(&self.id_server)?,
+                ));
+            }
+            visiting.push(root.clone());
+            for (rule, selected) in [(&rules.0, &selected_atoms.0), (&rules.1, &selected_atoms.1)] {
+                if !self.contains(rule) {
+                    return Err(DerivationError::BrokenProvenance(
+                        root.try_to_string(&self.id_server)?,
+                    ));
+                }
+                let mut tree = self.inner_derivation_tree(rule, visiting)?;
+                tree.set_selection(Selection::try_from((selected, &self.id_server))?);
+                derivation_tree.add_subtree(tree);
+            }
+            visiting.pop();
+        };
+        Ok(derivation_tree)
+    }
+
+    /// Returns the derivation tree for a given rule of the rule set, which is
+    /// looked up modulo variable renaming
+    pub fn derivation_tree(&self, root: &Rule<String>) -> Result<DerivationTree, DerivationError> {
+        let inner_rule = self
+            .lookup(root)
+            .ok_or_else(|| DerivationError::UnknownAtom(root.clone()))?;
+
+        let mut tree = self.inner_derivation_tree(&inner_rule, &mut vec![])?;
+        tree.set_root(root.clone());
+        Ok(tree)
+    }
+
+    /// Returns the depth of the recorded derivation of a fact of the rule set: 0 for
+    /// input facts, and one more than the deepest of the two rules it was resolved
+    /// from for derived facts
+    pub fn derivation_depth(&self, atom: &Atom<String>) -> Option<usize> {
+        let fact = self.lookup(&Rule {
+            premises: vec![],
+            conclusion: atom.clone(),
+        })?;
+        Some(self.inner_derivation_depth(&fact, &mut HashMap::default()))
+    }
+
+    /// Derivation depth of an inner rule, `depths` holding the depths already known
+    fn inner_derivation_depth(
+        &self,
+        rule: &InnerRule,
+        depths: &mut HashMap<InnerRule, usize>,
+    ) -> usize {
+        if let Some(depth) = depths.get(rule) {
+            return *depth;
+        }
+        // Recorded derivations are acyclic, this only guards against broken ones
+        depths.insert(rule.clone(), 0);
+        let depth = match self.derived_from.get(rule) {
+            Some(DerivationInfo { rules, .. }) => {
+                1 + self
+                    .inner_derivation_depth(&rules.0, depths)
+                    .max(self.inner_derivation_depth(&rules.1, depths))
             }
+            None => 0,
         };
-        Some(decision_tree)
+        depths.insert(rule.clone(), depth);
+        depth
     }
 
     pub fn rules_to_string(&self) -> String {
-        self.rules
-            .iter()
-            .map(|r| r.to_string(&self.id_server).to_string())
+        self.rules_to_string_with(&FormatOptions::default())
+    }
+
+    /// Prints the rules of the rule set with the given options, see the `fmt` module
+    pub fn rules_to_string_with(&self, options: &FormatOptions) -> String {
+        self.all_rules()
+            .filter_map(|r| self.format(&r, options, ""))
+            .join("\n")
+    }
+
+    /// Prints the facts of the rule set so that they can be parsed back, input facts
+    /// first
+    pub fn axioms_to_string(&self) -> String {
+        self.axioms_to_string_with(&FormatOptions::default())
+    }
+
+    /// Prints the facts of the rule set with the given options, see `axioms_to_string`
+    pub fn axioms_to_string_with(&self, options: &FormatOptions) -> String {
+        let derived = self.rules.iter().filter(|r| r.premises.is_empty()).cloned();
+        ["# Extensional facts".to_string()]
+            .into_iter()
+            .chain(
+                self.edb
+                    .iter()
+                    .filter_map(|r| self.format(&r, options, ".")),
+            )
+            .chain(["# Intensional facts".to_string()])
+            .chain(derived.filter_map(|r| self.format(&r, options, ".")))
             .join("\n")
     }
 
+    /// Prints a rule of the rule set if the options show it, along with its location
+    /// if they label rules
+    fn format(&self, rule: &InnerRule, options: &FormatOptions, stop: &str) -> Option<String> {
+        let named = rule.to_string(&self.id_server);
+        if !options.shows(&named) {
+            return None;
+        }
+        let printed = fmt::rule_to_string(&named, &self.parser_options.operators, options);
+        Some(match self.locations.get(rule).filter(|_| options.labels) {
+            Some(location) => format!("{printed}{stop}  # {location}"),
+            None => format!("{printed}{stop}"),
+        })
+    }
+
+    /// Identifiers of the symbols and variables used by the rule set
+    pub fn identifiers(&self) -> &IdentifierServer {
+        &self.id_server
+    }
+
+    /// Returns the symbol table of the rule set, input facts included: every
+    /// predicate, function and constant with the arities it is used with, by
+    /// identifier
+    pub fn symbols(&self) -> Vec<Symbol> {
+        fn term_symbols(term: &InnerTerm, symbols: &mut HashSet<(Identifier, SymbolKind, usize)>) {
+            if let Term::Function { symbol, parameters } = term {
+                let kind = if parameters.is_empty() {
+                    SymbolKind::Constant
+                } else {
+                    SymbolKind::Function
+                };
+                symbols.insert((*symbol, kind, parameters.len()));
+                for parameter in parameters {
+                    term_symbols(parameter, symbols)
+                }
+            }
+        }
+        let mut symbols = HashSet::default();
+        for rule in self.all_rules() {
+            for atom in rule.premises.iter().chain([&rule.conclusion]) {
+                symbols.insert((atom.symbol, SymbolKind::Predicate, atom.parameters.len()));
+                for parameter in &atom.parameters {
+                    term_symbols(parameter, &mut symbols)
+                }
+            }
+        }
+        let mut symbols: Vec<_> = symbols
+            .into_iter()
+            .filter_map(|(identifier, kind, arity)| {
+                Some(Symbol {
+                    identifier,
+                    name: self.id_server.resolve(identifier)?.to_string(),
+                    kind,
+                    arity,
+                })
+            })
+            .collect();
+        symbols.sort();
+        symbols
+    }
+
+    /// Iterates over the rules of the rule set, input facts first
     pub fn iter_rules(&self) -> impl Iterator<Item = Rule<String>> + '_ {
-        self.rules.iter().map(|r| r.to_string(&self.id_server))
+        self.all_rules().map(|r| r.to_string(&self.id_server))
+    }
+}
+
+/// Premises that may be selected when saturating without a query: those that are
+/// not like `p(X)`
+pub(crate) fn guarded(p: &Atom<Identifier>) -> bool {
+    !p.is_smth_of_variable()
+}
+
+/// Keeps premises like `p(X)` only if `X` appears in the conclusion
+pub(crate) fn keep_guarded(a: &Atom<Identifier>, c: &Atom<Identifier>) -> bool {
+    !a.is_smth_of_variable() || c.contains_variable(&a.parameters[0])
+}
+
+/// Locates parsed statements in their source, at their first token
+fn locate(
+    source: &str,
+    statements: Vec<SpannedStatement>,
+    file: Option<&Path>,
+) -> Vec<(Statement<String>, Option<SourceLocation>)> {
+    let mut tokens = lexer::tokens(source)
+        .filter(|t| t.kind != TokenKind::Comment)
+        .peekable();
+    statements
+        .into_iter()
+        .map(|(statement, span)| {
+            while tokens.next_if(|t| t.span.start < span.start).is_some() {}
+            let offset = tokens.peek().map_or(span.start, |t| t.span.start);
+            let (line, column) = lint::line_column(source, offset);
+            let location = SourceLocation {
+                file: file.map(Path::to_path_buf),
+                line,
+                column,
+            };
+            (statement, Some(location))
+        })
+        .collect()
+}
+
+/// Reads a whole source, decompressing it if needed, see the `compression` and
+/// `zstd` modules
+pub fn read_source<R: Read>(mut reader: R) -> std::io::Result<String> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    #[cfg(feature = "gzip")]
+    let bytes = compression::decompress(bytes)?;
+    #[cfg(feature = "zstd")]
+    let bytes = zstd::decompress(bytes)?;
+    String::from_utf8(bytes).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Errors that can occur while loading a `.pif` file
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Parsing(ParserError),
+    /// An error in one of the files loaded by `Sniffer::load_all`
+    File(PathBuf, Box<LoadError>),
+}
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "failed to read file: {e}"),
+            LoadError::Parsing(e) => write!(f, "failed to parse file: {e}"),
+            LoadError::File(path, e) => write!(f, "{}: {e}", path.display()),
+        }
     }
 }
 
 /// Represents the result of a saturation attempt
+#[derive(Debug)]
 pub enum SaturationFailure {
-    Saturated,     // The saturation attempt did not create any new rule
-    DerivedBottom, // The saturation derived a contradiction
+    Saturated,                   // The saturation attempt did not create any new rule
+    DerivedBottom,               // The saturation derived a contradiction
+    Derivation(DerivationError), // The derivation of the result could not be built
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn goals_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert("knows(a, b).\nknows(c, b).\nknows(a, d).\n?- knows(X, b).\n?- knows(d, X).")
+            .unwrap();
+        assert_eq!(sniffer.goals().len(), 2);
+
+        let answers: Vec<Vec<String>> = sniffer
+            .run_goals()
+            .into_iter()
+            .map(|g| g.answers.iter().map(|a| a.to_string()).collect())
+            .collect();
+        assert_eq!(answers, vec![vec!["knows(a, b)", "knows(c, b)"], vec![]]);
+    }
+
+    #[test]
+    fn expectations_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "agent(a, host). agent(b, host).\n\
+                 agent(X, host) /\\ agent(Y, host) => pair(X, Y).\n\
+                 #expect_not pair(a, b).\n#expect pair(b, b).\n#expect pair(c, a).",
+            )
+            .unwrap();
+        let failures = sniffer.run_expectations();
+        assert_eq!(failures.len(), 2);
+        assert!(!failures[0].expectation.derivable);
+        let derivation = failures[0].derivation.as_ref().unwrap();
+        assert_eq!(derivation.root().to_string(), "pair(a, b)");
+        assert_eq!(derivation.subtrees().len(), 2);
+        assert_eq!(failures[1].expectation.atom.to_string(), "pair(c, a)");
+        assert!(failures[1].derivation.is_none());
+    }
+    #[test]
+    fn sigil_convention_test() {
+        let options = ParserOptions {
+            convention: NamingConvention::Sigil,
+            ..Default::default()
+        };
+        let mut sniffer = Sniffer::with_parser_options(options.clone());
+        sniffer
+            .assert("p(f(Alice)).\nq(Bob, Bob).\np(f(?x)) /\\ q(?x, ?y) => r(?y, ?y).")
+            .unwrap();
+        sniffer.saturate_all();
+
+        // Printed rules follow the convention, so they parse back to the same rules
+        let printed: String = sniffer.iter_rules().map(|r| format!("{r}.\n")).collect();
+        assert!(printed.contains("q('Alice', ?var"), "{printed}");
+        let canonical =
+            |rules: Vec<Rule<String>>| -> Vec<_> { rules.iter().map(Rule::canonical).collect() };
+        assert_eq!(
+            canonical(Parser::parse_rules_with(Tokens::new(&printed), &options).unwrap()),
+            canonical(sniffer.iter_rules().collect())
+        );
+    }
+    #[test]
+    fn weights_test() {
+        let explored = |weight: &str| {
+            let mut sniffer = Sniffer::default();
+            sniffer
+                .assert(&format!(
+                    "{weight} att(key(X)) => att(lock(X)).\n{weight} att(lock(X)) => att(goal(X)).\n\
+                     {weight} att(key(k)).\n\
+                     att(f(b)). att(f(c)). att(f(d)).\n\
+                     att(f(X)) /\\ att(f(Y)) => att(pair(X, Y))."
+                ))
+                .unwrap();
+            let goal = Parser::parse_query(Tokens::new("att(goal(k)).")).unwrap();
+            assert!(sniffer.find(&goal).is_ok());
+            sniffer.iter_rules().count()
+        };
+        // Rules of higher weight fire first, so only the goal and its premise are
+        // derived, while breadth-first saturation derives pairs on the way
+        assert_eq!(explored("[weight=5]"), 9);
+        assert!(explored("") > 9);
+    }
+    #[test]
+    fn saturate_rounds_test() {
+        let saturated = |rounds: usize| {
+            let mut sniffer = Sniffer::default();
+            sniffer
+                .assert(
+                    "[weight=5] edge(a, b). edge(b, c). edge(c, d). edge(d, e).\n\
+                     edge(X, Y) => path(X, Y).\n\
+                     path(X, Y) /\\ edge(Y, Z) => path(X, Z).",
+                )
+                .unwrap();
+            sniffer.saturate_rounds(rounds);
+            sniffer
+        };
+        let paths = |sniffer: &Sniffer| {
+            sniffer
+                .iter_rules()
+                .filter(|r| r.premises.is_empty() && r.conclusion.symbol == "path")
+                .count()
+        };
+        // Whatever the weights, a path of length n is derived in round 2n - 1, each
+        // edge after the first resolving with the path rule then with an edge
+        let counts: Vec<_> = (0..6).map(|n| paths(&saturated(n))).collect();
+        assert_eq!(counts, vec![0, 4, 4, 7, 7, 9]);
+
+        let mut sniffer = saturated(usize::MAX);
+        assert_eq!(paths(&sniffer), 10);
+        assert_eq!(sniffer.saturate_rounds(usize::MAX), 0);
+    }
+    #[test]
+    fn term_store_test() {
+        let mut sniffer = Sniffer::from_rules(bench::transitive_closure(12, 20, 3));
+        for i in 0..=MAX_INTERNED_TERMS {
+            sniffer.terms.intern(&Term::Function {
+                symbol: Identifier::Function(i),
+                parameters: vec![],
+            });
+        }
+        let mut expected = Sniffer::from_rules(bench::transitive_closure(12, 20, 3));
+        expected.saturate_all();
+        // The store is emptied on the next step, without changing what is derived
+        sniffer.saturate_all();
+        assert!(sniffer.terms.len() < MAX_INTERNED_TERMS);
+        assert_eq!(sniffer.rules_to_string(), expected.rules_to_string());
+    }
+    #[test]
+    fn probability_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "0.5 :: att(key(a)).\n0.8 :: att(key(X)) => att(secret(X)).\n\
+                 att(secret(X)) /\\ att(key(X)) => att(leak(X)).",
+            )
+            .unwrap();
+        let probability = |sniffer: &mut Sniffer, query| {
+            sniffer.probability(&Parser::parse_query(Tokens::new(query)).unwrap())
+        };
+        // `att(key(a))` is used twice but only counts once
+        let leak = probability(&mut sniffer, "att(leak(a)).").unwrap();
+        assert!((leak - 0.4).abs() < 1e-9, "{leak}");
+        assert!(probability(&mut sniffer, "att(leak(b)).").is_err());
+    }
+
+    #[test]
+    fn load_all_test() {
+        let directory = std::env::temp_dir().join(format!("sniffer-load-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let files = [
+            (
+                "a.pif",
+                "att(k).\natt(senc(X, Y)) /\\ att(Y) => att(X).\natt(k).",
+            ),
+            (
+                "b.pif",
+                "att(senc(m, k)).\natt(senc(M, K)) /\\ att(K) => att(M).\n[weight=2] att(k).",
+            ),
+        ];
+        let paths: Vec<_> = files
+            .iter()
+            .map(|(name, source)| {
+                let path = directory.join(name);
+                std::fs::write(&path, source).unwrap();
+                path
+            })
+            .collect();
+
+        let mut sniffer = Sniffer::default();
+        let duplicates = sniffer.load_all(paths.clone()).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(
+            duplicates
+                .iter()
+                .map(|d| (d.rule.to_string(), d.conflicting))
+                .collect::<Vec<_>>(),
+            vec![
+                ("att(senc(M, K)) /\\ att(K) => att(M)".to_string(), false),
+                ("att(k)".to_string(), true)
+            ]
+        );
+        assert!(duplicates
+            .iter()
+            .all(|d| d.first == paths[0] && d.file == paths[1]));
+        assert!(sniffer
+            .find(&Parser::parse_query(Tokens::new("att(m).")).unwrap())
+            .is_ok());
+    }
+
+    #[test]
+    fn normalization_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "q(X) /\\ q(X) => p(X).\nq(Y) => p(Y).\n\
+                 [weight=3] r(Z) /\\ q(Z) => s(Z).\nq(A) /\\ r(A) => s(A).",
+            )
+            .unwrap();
+        assert_eq!(
+            sniffer.rules_to_string(),
+            "q(VAR0) => p(VAR0)\nq(VAR2) /\\ r(VAR2) => s(VAR2)"
+        );
+        let rule = Parser::parse_rules(Tokens::new("r(B) /\\ q(B) => s(B).")).unwrap();
+        let inner = sniffer.lookup(&rule[0]).unwrap();
+        assert_eq!(sniffer.weight(&inner), 3);
+    }
+
+    #[test]
+    fn from_rules_test() {
+        let rules = vec![
+            Rule {
+                premises: vec![],
+                conclusion: Atom {
+                    symbol: String::from("att"),
+                    parameters: vec![Term::Function {
+                        symbol: String::from("h"),
+                        parameters: vec![Term::Function {
+                            symbol: String::from("a"),
+                            parameters: vec![],
+                        }],
+                    }],
+                },
+            },
+            Parser::parse_rules(Tokens::new("att(h(X)) => att(X).")).unwrap()[0].clone(),
+        ];
+        let mut sniffer = Sniffer::from_rules(rules);
+        assert!(sniffer
+            .find(&Parser::parse_query(Tokens::new("att(a).")).unwrap())
+            .is_ok());
+    }
+
+    #[test]
+    fn clone_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer.assert("att(h(a)).\natt(h(X)) => att(X).").unwrap();
+        let query = Parser::parse_query(Tokens::new("att(b).")).unwrap();
+
+        let mut what_if = sniffer.clone();
+        what_if.assert("att(h(b)).").unwrap();
+        let tree = what_if.find(&query).unwrap();
+        assert!(sniffer.find(&query).is_err());
+        assert!(!sniffer.view().contains(&query));
+        assert!(what_if.view().contains(&query));
+
+        let trees = HashSet::from_iter([tree.clone(), what_if.find(&query).unwrap()]);
+        assert_eq!(trees.len(), 1);
+    }
+
+    #[test]
+    fn reader_test() {
+        let source: &[u8] = b"att(pair(a, b)).\natt(pair(X, Y)) => att(X).";
+        let mut sniffer = Sniffer::from_reader(source).unwrap();
+        assert!(sniffer
+            .find(&Parser::parse_query(Tokens::new("att(a).")).unwrap())
+            .is_ok());
+        assert!(matches!(
+            Sniffer::from_reader(&b"att(a"[..]),
+            Err(LoadError::Parsing(_))
+        ));
+    }
+
+    #[test]
+    fn visitor_test() {
+        struct Variables(Vec<String>);
+        impl Visitor<String> for Variables {
+            fn visit_variable(&mut self, symbol: &String) {
+                self.0.push(symbol.clone())
+            }
+        }
+        struct Rename;
+        impl Folder<String> for Rename {
+            fn fold_variable(&mut self, symbol: String) -> Term<String> {
+                Term::Variable {
+                    symbol: symbol.to_lowercase(),
+                }
+            }
+            fn fold_predicate(&mut self, symbol: String) -> String {
+                format!("m.{symbol}")
+            }
+        }
+
+        let rule = Parser::parse_rules(Tokens::new("att(pair(X, h(Y))) => att(Y)."))
+            .unwrap()
+            .remove(0);
+        let mut variables = Variables(vec![]);
+        variables.visit_rule(&rule);
+        assert_eq!(variables.0, vec!["X", "Y", "Y"]);
+        assert_eq!(
+            Rename.fold_rule(rule).to_string(),
+            "m.att(pair(_, h(y))) => m.att(y)"
+        );
+    }
+
+    #[test]
+    fn term_metrics_test() {
+        let atom = Parser::parse_query(Tokens::new("att(pair(X, h(a, pair(Y, X, b)))).")).unwrap();
+        let term = &atom.parameters[0];
+        assert_eq!(term.vars(), vec!["X", "Y"]);
+        assert_eq!(term.constants(), vec!["a", "b"]);
+        assert_eq!(term.depth(), 4);
+        assert_eq!(term.size(), 8);
+        // Number of function applications, constants excluded
+        let applications = term.fold(
+            |_| 0,
+            |_, counts: Vec<usize>| counts.iter().sum::<usize>() + usize::from(!counts.is_empty()),
+        );
+        assert_eq!(applications, 3);
+        let x = Term::Variable {
+            symbol: "X".to_string(),
+        };
+        assert_eq!((x.depth(), x.size()), (1, 1));
+        assert_eq!(x.vars(), vec!["X"]);
+    }
+
+    #[test]
+    fn remove_rule_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "att(pair(a, b)).\natt(h(a)).\natt(c).\natt(pair(X, Y)) => att(X).\n\
+                 att(pair(X, Y)) => att(Y).\natt(h(X)) => att(X).",
+            )
+            .unwrap();
+        sniffer.saturate_all();
+        let rule = |source: &str| Parser::parse_rules(Tokens::new(source)).unwrap().remove(0);
+
+        let retracted = sniffer.remove_rule(&rule("att(pair(c, d))."));
+        assert!(retracted.is_none());
+        let retracted = sniffer.remove_rule(&rule("att(pair(a, b)).")).unwrap();
+        assert_eq!(retracted, vec![rule("att(b).").conclusion]);
+        let facts: Vec<_> = sniffer.iter_facts().map(|f| f.to_string()).collect();
+        // Input facts are left in the order they were given
+        assert_eq!(facts, vec!["att(h(a))", "att(c)", "att(a)"]);
+        assert!(sniffer.derivation_tree(&rule("att(a).")).is_ok());
+    }
+
+    #[test]
+    fn normal_forms_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "[weight=1] att(X) /\\ key(Y) => att(enc(X, Y)).\n\
+                 [weight=3] key(K) /\\ att(M) => att(enc(M, K)).\natt(h(X)).",
+            )
+            .unwrap();
+        // Rules equal up to normalization are only added once, see `insert_input`
+        assert_eq!(sniffer.iter_rules().count(), 2);
+        let rule = |source: &str| Parser::parse_rules(Tokens::new(source)).unwrap().remove(0);
+        let known = sniffer
+            .lookup(&rule("att(A) /\\ key(B) => att(enc(A, B))."))
+            .unwrap();
+        assert_eq!(sniffer.weight(&known), 3);
+        assert!(sniffer.lookup(&rule("att(h(Y)).")).is_some());
+
+        assert!(sniffer
+            .remove_rule(&rule("key(B) /\\ att(A) => att(enc(A, B))."))
+            .is_some());
+        assert!(sniffer
+            .lookup(&known.to_string(&sniffer.id_server))
+            .is_none());
+        sniffer
+            .assert("att(X) /\\ key(Y) => att(enc(X, Y)).")
+            .unwrap();
+        assert_eq!(sniffer.iter_rules().count(), 2);
+    }
+
+    #[test]
+    fn axioms_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert("att(pair(a, b)).\natt(pair(X, Y)) => att(Y).\natt(h(X)).")
+            .unwrap();
+        sniffer.saturate_all();
+        assert_eq!(
+            sniffer.axioms_to_string(),
+            "# Extensional facts\natt(pair(a, b)).\n# Intensional facts\natt(h(_)).\natt(b)."
+        );
+        let tree = sniffer
+            .find(&Parser::parse_query(Tokens::new("att(b).")).unwrap())
+            .unwrap();
+        assert_eq!(tree.subtrees().len(), 2);
+    }
+
+    #[test]
+    fn dead_rules_test() {
+        let mut sniffer = Sniffer::default();
+        let source: &[u8] = b"att(k).\n# comment\n  att(senc(M, K)) /\\ lost(K) => att(M).";
+        sniffer
+            .load_all_from([
+                (PathBuf::from("a.pif"), Ok(source)),
+                (
+                    PathBuf::from("b.pif"),
+                    Ok(b"att(K) /\\ gone(K) => att(h(K))."),
+                ),
+            ])
+            .unwrap();
+        let dead: Vec<_> = sniffer
+            .dead_rules()
+            .into_iter()
+            .map(|d| (d.premise.symbol, d.location.unwrap().to_string()))
+            .collect();
+        assert_eq!(
+            dead,
+            vec![
+                ("lost".to_string(), "a.pif:3:3".to_string()),
+                ("gone".to_string(), "b.pif:1:1".to_string())
+            ]
+        );
+
+        sniffer.assert("lost(k).\ngone(k).").unwrap();
+        assert!(sniffer.dead_rules().is_empty());
+    }
+
+    #[test]
+    fn find_all_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert("edge(a, b). edge(b, c).\nedge(X, Y) => path(X, Y).\npath(X, Y) /\\ edge(Y, Z) => path(X, Z).")
+            .unwrap();
+        let query = Parser::parse_query(Tokens::new("path(a, X).")).unwrap();
+        let mut found: Vec<_> = sniffer
+            .find_all(&query)
+            .unwrap()
+            .into_iter()
+            .map(|(fact, tree)| (fact.to_string(), tree.subtrees().len()))
+            .collect();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![("path(a, b)".to_string(), 2), ("path(a, c)".to_string(), 2)]
+        );
+
+        let query = Parser::parse_query(Tokens::new("path(c, X).")).unwrap();
+        assert!(sniffer.find_all(&query).unwrap().is_empty());
+    }
+
+    #[test]
+    fn find_many_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert("att(k). att(senc(senc(m, n), k)).\natt(senc(X, Y)) /\\ att(Y) => att(X).")
+            .unwrap();
+        let queries: Vec<_> = ["att(senc(m, n)).", "att(n).", "att(k).", "att(senc(m, n))."]
+            .into_iter()
+            .map(|query| Parser::parse_query(Tokens::new(query)).unwrap())
+            .collect();
+        let answers = sniffer.clone().find_many(&queries);
+        assert_eq!(
+            answers.iter().map(|a| a.is_ok()).collect::<Vec<_>>(),
+            vec![true, false, true, true]
+        );
+        for (query, answer) in queries.iter().zip(answers) {
+            let expected = sniffer.clone().find(query);
+            assert_eq!(answer.ok(), expected.ok());
+        }
+    }
+
+    #[test]
+    fn derivation_depth_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert("att(k). att(enc(m, k)).\natt(enc(X, Y)) /\\ att(Y) => att(X).")
+            .unwrap();
+        let query = |source: &str| Parser::parse_query(Tokens::new(source)).unwrap();
+        let tree = sniffer.find(&query("att(m).")).unwrap();
+        assert_eq!(sniffer.derivation_depth(&query("att(k).")), Some(0));
+        assert_eq!(
+            sniffer.derivation_depth(&query("att(m).")),
+            Some(tree.depth())
+        );
+        assert!(tree.depth() > 0);
+        assert_eq!(sniffer.derivation_depth(&query("att(n).")), None);
+    }
+
+    #[test]
+    fn symbols_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert("att(senc(X, Y)) /\\ att(Y) => att(X).\natt(senc(m, k)).\nsenc(k).")
+            .unwrap();
+        let symbols: Vec<_> = sniffer.symbols().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            symbols,
+            vec![
+                "predicate att/1 #0",
+                "predicate senc/1 #1",
+                "function senc/2 #1",
+                "constant m/0 #2",
+                "constant k/0 #3",
+            ]
+        );
+        let identifiers = sniffer.identifiers();
+        let att = *identifiers.id_of("att").unwrap();
+        assert_eq!(identifiers.resolve(att), Some("att"));
+        assert_eq!(
+            identifiers
+                .functions()
+                .map(|(_, name)| name)
+                .collect::<Vec<_>>(),
+            vec!["att", "senc", "m", "k"]
+        );
+    }
+
+    #[test]
+    fn merge_test() {
+        let mut first = Sniffer::default();
+        first
+            .assert("att(k). att(X) /\\ att(Y) => att(pair(X, Y)).")
+            .unwrap();
+        let mut second = Sniffer::default();
+        second
+            .assert("att(enc(m, k)).\natt(enc(X, Y)) /\\ att(Y) => att(X).")
+            .unwrap();
+
+        // Identifiers are assigned in registration order, shared symbols being
+        // reconciled by name
+        let mut replay = IdentifierServer::default();
+        assert!(replay.import(first.identifiers()).is_identity());
+        let remapping = first.clone().id_server.import(second.identifiers());
+        let att = *second.identifiers().id_of("att").unwrap();
+        assert_eq!(
+            remapping.get(att),
+            first.identifiers().id_of("att").copied()
+        );
+        assert!(!remapping.is_identity());
+
+        assert_eq!(first.merge(&second), 2);
+        assert_eq!(first.merge(&second), 0);
+        let query = Parser::parse_query(Tokens::new("att(m).")).unwrap();
+        assert!(first.find(&query).is_ok());
+
+        // Constraints are renumbered, each violation being reported under its own
+        let mut first = Sniffer::default();
+        first
+            .assert("honest(a). constraint not (bad(a) && honest(a)).")
+            .unwrap();
+        let mut second = Sniffer::default();
+        second
+            .assert("leak(a, k). constraint not (leak(a, k) && honest(a)).")
+            .unwrap();
+        let mut empty = Sniffer::default();
+        assert_eq!(empty.merge(&second), 2);
+        assert_eq!(empty.check_consistency().len(), 0);
+        assert_eq!(empty.merge(&first), 2);
+        assert_eq!(empty.merge(&first), 0);
+        assert_eq!(empty.constraints().len(), 2);
+        first.merge(&second);
+        let violations: Vec<_> = first
+            .check_consistency()
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(violations, vec!["constraint not (leak(a, k) && honest(a))"]);
+        let violations = empty.check_consistency();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].atoms, second.constraints()[0]);
+    }
 }
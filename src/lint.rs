@@ -0,0 +1,332 @@
+//! Lint module
+//! Static checks over `.pif` sources, flagging constructs that are valid but most
+//! likely mistakes. Every lint has a stable code and points to a span of the source
+
+use crate::ast::{Atom, Defeasible, Rule, Statement, Term};
+use crate::constraints::NEQ;
+use crate::lexer::{tokens, Token, TokenKind};
+use crate::parser::{Parser, ParserError};
+use logos_nom_bridge::Tokens;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// Kinds of lints, each associated with a stable code
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Hash)]
+pub enum LintCode {
+    /// A variable appears only once in its rule
+    SingletonVariable,
+    /// A predicate is used as a premise but no rule concludes it
+    UnknownPredicate,
+    /// A rule has a premise that can never be derived, even though rules conclude it
+    UnsatisfiableRule,
+    /// A rule is equal to an earlier one, up to variable renaming
+    DuplicateRule,
+}
+impl LintCode {
+    /// Stable code of the lint
+    pub fn code(&self) -> &'static str {
+        match self {
+            LintCode::SingletonVariable => "L001",
+            LintCode::UnknownPredicate => "L002",
+            LintCode::UnsatisfiableRule => "L003",
+            LintCode::DuplicateRule => "L004",
+        }
+    }
+
+    /// Human readable name of the lint
+    pub fn name(&self) -> &'static str {
+        match self {
+            LintCode::SingletonVariable => "singleton-variable",
+            LintCode::UnknownPredicate => "unknown-predicate",
+            LintCode::UnsatisfiableRule => "unsatisfiable-rule",
+            LintCode::DuplicateRule => "duplicate-rule",
+        }
+    }
+}
+
+/// A lint emitted on a source
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lint {
+    pub code: LintCode,
+    /// Byte span of the source the lint points to
+    pub span: Range<usize>,
+    pub message: String,
+    /// Related span of the source, such as the original rule of a duplicate
+    pub related: Option<Range<usize>>,
+}
+
+/// Spanned information about a rule, needed to point lints in the right place
+struct RuleInfo<'a> {
+    rule: Rule<String>,
+    span: Range<usize>,
+    tokens: &'a [Token],
+    /// Span of each atom symbol, premises first then the conclusion
+    atom_spans: Vec<Range<usize>>,
+}
+
+/// Runs every lint on the given source, which must parse correctly
+pub fn lint(source: &str) -> Result<Vec<Lint>, ParserError> {
+    let parsed = Parser::parse_spanned_statements(Tokens::new(source))?;
+    let all_tokens: Vec<_> = tokens(source)
+        .filter(|t| t.kind != TokenKind::Comment)
+        .collect();
+
+    let mut rules = vec![];
+    // Rules that are not linted, but whose conclusions are derivable
+    let mut others = vec![];
+    let mut next_token = 0;
+    for (statement, span) in parsed {
+        // Tokens outside of statements, such as module headers, are skipped
+        while next_token < all_tokens.len() && all_tokens[next_token].span.end <= span.start {
+            next_token += 1;
+        }
+        let first = next_token;
+        while next_token < all_tokens.len() && all_tokens[next_token].span.end <= span.end {
+            next_token += 1;
+        }
+        let rule = match statement {
+            Statement::Rule(rule, _) => rule,
+            Statement::Defeasible(Defeasible {
+                rule,
+                negated: false,
+                ..
+            }) => {
+                others.push(rule);
+                continue;
+            }
+            // Atoms of `next` rules have the time step as an implicit last parameter
+            Statement::Next(mut rule) => {
+                for atom in rule.premises.iter_mut().chain([&mut rule.conclusion]) {
+                    atom.parameters.push(Term::Variable {
+                        symbol: String::from("T"),
+                    })
+                }
+                others.push(rule);
+                continue;
+            }
+            _ => continue,
+        };
+        let rule_tokens = &all_tokens[first..next_token];
+
+        // Attributes, such as `[weight=2]`, hold no atom
+        let mut depth = 0;
+        let mut attributes = false;
+        let mut atom_spans = vec![];
+        for token in rule_tokens {
+            match token.kind {
+                TokenKind::OpeningBracket => attributes = true,
+                TokenKind::ClosingBracket => attributes = false,
+                _ if attributes => (),
+                TokenKind::Constant if depth == 0 => atom_spans.push(token.span.clone()),
+                TokenKind::OpeningParentheses => depth += 1,
+                TokenKind::ClosingParentheses => depth -= 1,
+                _ => (),
+            }
+        }
+
+        let span = rule_tokens
+            .first()
+            .map(|t| t.span.start..span.end)
+            .unwrap_or(span);
+        rules.push(RuleInfo {
+            rule,
+            span,
+            tokens: rule_tokens,
+            atom_spans,
+        });
+    }
+
+    let mut lints = vec![];
+    singleton_variables(source, &rules, &mut lints);
+    underivable_predicates(&rules, &others, &mut lints);
+    duplicate_rules(&rules, &mut lints);
+    lints.sort_by_key(|l| (l.span.start, l.code.code()));
+    Ok(lints)
+}
+
+fn singleton_variables(source: &str, rules: &[RuleInfo], lints: &mut Vec<Lint>) {
+    for RuleInfo { tokens, .. } in rules {
+        let mut occurrences: HashMap<&str, Vec<Range<usize>>> = HashMap::new();
+        for token in tokens.iter().filter(|t| t.kind == TokenKind::Variable) {
+            occurrences
+                .entry(&source[token.span.clone()])
+                .or_default()
+                .push(token.span.clone());
+        }
+        for (variable, spans) in occurrences {
+            if let [span] = &spans[..] {
+                lints.push(Lint {
+                    code: LintCode::SingletonVariable,
+                    span: span.clone(),
+                    message: format!(
+                        "variable `{variable}` appears only once in this rule, use `_` if this is intended"
+                    ),
+                    related: None,
+                })
+            }
+        }
+    }
+}
+
+fn predicate(atom: &Atom<String>) -> (&str, usize) {
+    (&atom.symbol, atom.parameters.len())
+}
+
+fn underivable_predicates(rules: &[RuleInfo], others: &[Rule<String>], lints: &mut Vec<Lint>) {
+    // Disequality constraints are built in, see the `constraints` module
+    let builtin = (NEQ, 2);
+    let all_rules: Vec<&Rule<String>> = rules.iter().map(|r| &r.rule).chain(others).collect();
+    let concluded: HashSet<_> = all_rules
+        .iter()
+        .map(|r| predicate(&r.conclusion))
+        .chain([builtin])
+        .collect();
+
+    // Predicates are derivable if a rule with only derivable premises concludes them
+    let mut derivable = HashSet::from([builtin]);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in &all_rules {
+            let conclusion = predicate(&rule.conclusion);
+            if !derivable.contains(&conclusion)
+                && rule
+                    .premises
+                    .iter()
+                    .all(|p| derivable.contains(&predicate(p)))
+            {
+                derivable.insert(conclusion);
+                changed = true;
+            }
+        }
+    }
+
+    for RuleInfo {
+        rule,
+        span,
+        atom_spans,
+        ..
+    } in rules
+    {
+        let mut has_unknown = false;
+        for (premise, premise_span) in rule.premises.iter().zip(atom_spans) {
+            let (symbol, arity) = predicate(premise);
+            if !concluded.contains(&(symbol, arity)) {
+                has_unknown = true;
+                lints.push(Lint {
+                    code: LintCode::UnknownPredicate,
+                    span: premise_span.clone(),
+                    message: format!("predicate `{symbol}/{arity}` is never concluded by any rule"),
+                    related: None,
+                })
+            }
+        }
+
+        // Unknown predicates are already reported above, so we only flag rules made
+        // unsatisfiable through their dependencies
+        if let Some(premise) = rule
+            .premises
+            .iter()
+            .find(|p| !derivable.contains(&predicate(p)))
+            .filter(|_| !has_unknown)
+        {
+            let (symbol, arity) = predicate(premise);
+            lints.push(Lint {
+                code: LintCode::UnsatisfiableRule,
+                span: span.clone(),
+                message: format!(
+                    "rule can never fire: premise `{symbol}/{arity}` is not derivable"
+                ),
+                related: None,
+            })
+        }
+    }
+}
+
+fn duplicate_rules(rules: &[RuleInfo], lints: &mut Vec<Lint>) {
+    let mut seen: HashMap<Rule<String>, &Range<usize>> = HashMap::new();
+    for RuleInfo { rule, span, .. } in rules {
+        if let Some(original) = seen.get(&rule.normalized()) {
+            lints.push(Lint {
+                code: LintCode::DuplicateRule,
+                span: span.clone(),
+                message: String::from("rule is a duplicate of an earlier rule"),
+                related: Some((*original).clone()),
+            })
+        } else {
+            seen.insert(rule.normalized(), span);
+        }
+    }
+}
+
+/// Returns the 1-based line and column of a byte offset in the source
+pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(source: &str) -> Vec<LintCode> {
+        lint(source).unwrap().into_iter().map(|l| l.code).collect()
+    }
+
+    #[test]
+    fn singleton_variable_test() {
+        assert_eq!(
+            codes("att(pair(X, Y)) => att(X).\natt(a)."),
+            vec![LintCode::SingletonVariable]
+        );
+        assert!(codes("att(X) /\\ att(Y) => att(pair(X, Y)).\natt(a).").is_empty());
+    }
+
+    #[test]
+    fn underivable_predicate_test() {
+        let source = "knows(X) => att(X).\natt(X) /\\ secret(X) => leak(X).\nleak(X) => out(X).";
+        let lints = lint(source).unwrap();
+        assert_eq!(
+            lints.iter().map(|l| l.code).collect::<Vec<_>>(),
+            vec![
+                LintCode::UnknownPredicate,
+                LintCode::UnknownPredicate,
+                LintCode::UnsatisfiableRule
+            ]
+        );
+        assert_eq!(&source[lints[0].span.clone()], "knows");
+        assert_eq!(&source[lints[1].span.clone()], "secret");
+        assert_eq!(&source[lints[2].span.clone()], "leak(X) => out(X).");
+        // Disequality constraints are built in
+        assert!(codes("att(a).\natt(X) /\\ neq(X, b) => out(X).").is_empty());
+        // Defeasible and `next` rules conclude predicates too, the latter with a
+        // time step as last parameter
+        assert!(codes("q(a).\np(a) <~ q(a).\np(X) => r(X).").is_empty());
+        assert!(codes("q(a, 0).\nnext q(X) => p(X).\np(X, T) => r(X, T).").is_empty());
+        assert_eq!(
+            codes("q(a).\n~p(a) <~ q(a).\np(X) => r(X)."),
+            vec![LintCode::UnknownPredicate]
+        );
+        // Attributes are not atoms
+        let source = "q(a).\n[weight=2] p(X) => q(X).";
+        let lints = lint(source).unwrap();
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].code, LintCode::UnknownPredicate);
+        assert_eq!(&source[lints[0].span.clone()], "p");
+    }
+
+    #[test]
+    fn duplicate_rule_test() {
+        let source = "# rules\natt(h(X)) => att(X).\natt(h(Y)) => att(Y).\natt(a).";
+        let lints = lint(source).unwrap();
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].code, LintCode::DuplicateRule);
+        assert_eq!(&source[lints[0].span.clone()], "att(h(Y)) => att(Y).");
+        assert_eq!(
+            &source[lints[0].related.clone().unwrap()],
+            "att(h(X)) => att(X)."
+        );
+    }
+}
@@ -0,0 +1,535 @@
+//! LSP module
+//! A minimal language server for `.pif` files, speaking JSON-RPC over stdio.
+//! It provides diagnostics, go-to-definition for predicates, hover information
+//! and document symbols
+
+use crate::lexer::{tokens, Token, TokenKind};
+use crate::lint::lint;
+use crate::parser::Parser;
+use logos_nom_bridge::Tokens;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::ops::Range;
+
+/// An occurrence of a predicate symbol in a document
+struct Occurrence {
+    symbol: String,
+    arity: usize,
+    span: Range<usize>,
+    /// Whether this occurrence is the conclusion of a rule
+    conclusion: bool,
+}
+
+/// A rule of a document, as a span and the index of its conclusion occurrence
+struct RuleInfo {
+    span: Range<usize>,
+    conclusion: Option<usize>,
+    is_axiom: bool,
+}
+
+/// Syntactic information extracted from a `.pif` document
+pub struct Analysis {
+    occurrences: Vec<Occurrence>,
+    rules: Vec<RuleInfo>,
+    errors: Vec<(Range<usize>, String)>,
+}
+impl Analysis {
+    /// Analyses the given source
+    pub fn new(source: &str) -> Analysis {
+        let mut analysis = Analysis {
+            occurrences: vec![],
+            rules: vec![],
+            errors: vec![],
+        };
+
+        let tokens: Vec<_> = tokens(source)
+            .filter(|t| t.kind != TokenKind::Comment)
+            .collect();
+        let mut depth = 0usize;
+        let mut rule_start = None;
+        let mut rule_atoms = vec![];
+        let mut implies_at = None;
+        let mut is_directive = false;
+        // Arity bookkeeping of the atom currently being read
+        let mut commas = 0;
+        let mut has_parameters = false;
+
+        for Token { kind, span } in &tokens {
+            if rule_start.is_none() {
+                rule_start = Some(span.start);
+            }
+            match kind {
+                TokenKind::Constant if depth == 0 => {
+                    rule_atoms.push(analysis.occurrences.len());
+                    analysis.occurrences.push(Occurrence {
+                        symbol: source[span.clone()].to_string(),
+                        arity: 0,
+                        span: span.clone(),
+                        conclusion: false,
+                    });
+                }
+                TokenKind::OpeningParentheses => {
+                    if depth == 0 {
+                        commas = 0;
+                        has_parameters = false;
+                    } else {
+                        has_parameters = true;
+                    }
+                    depth += 1;
+                }
+                TokenKind::ClosingParentheses => {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 {
+                        if let Some(occurrence) = analysis.occurrences.last_mut() {
+                            occurrence.arity = if has_parameters { commas + 1 } else { 0 };
+                        }
+                    }
+                }
+                TokenKind::Comma if depth == 1 => commas += 1,
+                TokenKind::Implies if depth == 0 => implies_at = Some(rule_atoms.len()),
+                TokenKind::Directive if depth == 0 => is_directive = true,
+                // Directives only use predicates, so they are not listed as rules
+                TokenKind::Stop if depth == 0 && is_directive => {
+                    rule_start = None;
+                    rule_atoms.clear();
+                    is_directive = false;
+                }
+                TokenKind::Stop if depth == 0 => {
+                    let conclusion = rule_atoms.last().copied();
+                    if let Some(i) = conclusion {
+                        analysis.occurrences[i].conclusion = true;
+                    }
+                    analysis.rules.push(RuleInfo {
+                        span: rule_start.take().unwrap_or(span.start)..span.end,
+                        conclusion,
+                        is_axiom: implies_at.is_none(),
+                    });
+                    rule_atoms.clear();
+                    implies_at = None;
+                }
+                TokenKind::Unrecognized => analysis
+                    .errors
+                    .push((span.clone(), String::from("unrecognized token"))),
+                _ => (),
+            }
+            if depth > 0 && *kind != TokenKind::OpeningParentheses {
+                has_parameters = true;
+            }
+        }
+
+        if analysis.errors.is_empty() {
+            if let Err(e) = Parser::parse_rules(Tokens::new(source)) {
+                // Points the error at the first token after the failure offset
+                let span = tokens
+                    .iter()
+                    .map(|t| t.span.clone())
+                    .find(|span| span.end > e.offset)
+                    .unwrap_or(source.len()..source.len());
+                analysis
+                    .errors
+                    .push((span, e.kind.description().to_string()));
+            }
+        }
+        analysis
+    }
+
+    /// Returns the predicate occurrence at the given offset, if any
+    fn occurrence_at(&self, offset: usize) -> Option<&Occurrence> {
+        self.occurrences
+            .iter()
+            .find(|o| o.span.start <= offset && offset <= o.span.end)
+    }
+
+    /// Returns every occurrence of the predicate with the given symbol and arity
+    fn occurrences_of<'a>(
+        &'a self,
+        symbol: &'a str,
+        arity: usize,
+    ) -> impl Iterator<Item = &'a Occurrence> + 'a {
+        self.occurrences
+            .iter()
+            .filter(move |o| o.symbol == symbol && o.arity == arity)
+    }
+}
+
+/// Converts a byte offset to an LSP position (line and UTF-16 column)
+fn position(source: &str, offset: usize) -> Value {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    json!({ "line": line, "character": character })
+}
+
+/// Converts an LSP position to a byte offset
+fn offset(source: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+
+    let line_start: usize = source.split_inclusive('\n').take(line).map(str::len).sum();
+    let mut units = 0;
+    for (i, c) in source[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    source.len()
+}
+
+fn range(source: &str, span: &Range<usize>) -> Value {
+    json!({ "start": position(source, span.start), "end": position(source, span.end) })
+}
+
+/// Language server state: the open documents
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, (String, Analysis)>,
+}
+impl Server {
+    /// Handles a JSON-RPC message, returning the messages to send back
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let id = message.get("id").cloned();
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "sniffer-lsp" },
+            }),
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                return self.update(
+                    document["uri"].as_str().unwrap_or_default(),
+                    document["text"].as_str().unwrap_or_default(),
+                );
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                // We only advertise full synchronization, so the last change is the whole text
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                    .unwrap_or_default();
+                return self.update(uri, text);
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                self.documents.remove(uri);
+                return vec![];
+            }
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            "shutdown" => Value::Null,
+            _ if id.is_none() => return vec![],
+            _ => {
+                return vec![json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": -32601, "message": "method not found" },
+                })]
+            }
+        };
+
+        match id {
+            Some(id) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })],
+            None => vec![],
+        }
+    }
+
+    /// Stores the new text of a document, returning its diagnostics
+    fn update(&mut self, uri: &str, text: &str) -> Vec<Value> {
+        let analysis = Analysis::new(text);
+        let mut diagnostics: Vec<_> = analysis
+            .errors
+            .iter()
+            .map(|(span, message)| {
+                json!({
+                    "range": range(text, span),
+                    "severity": 1,
+                    "source": "sniffer",
+                    "message": message,
+                })
+            })
+            .collect();
+        if let Ok(lints) = lint(text) {
+            diagnostics.extend(lints.into_iter().map(|l| {
+                let related: Vec<_> = l
+                    .related
+                    .iter()
+                    .map(|span| {
+                        json!({
+                            "location": { "uri": uri, "range": range(text, span) },
+                            "message": "related rule",
+                        })
+                    })
+                    .collect();
+                json!({
+                    "range": range(text, &l.span),
+                    "severity": 2,
+                    "code": l.code.code(),
+                    "source": "sniffer",
+                    "message": format!("{}: {}", l.code.name(), l.message),
+                    "relatedInformation": related,
+                })
+            }));
+        }
+        self.documents
+            .insert(uri.to_string(), (text.to_string(), analysis));
+
+        vec![json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })]
+    }
+
+    /// Returns the document and occurrence pointed at by a text document position
+    fn pointed(&self, params: &Value) -> Option<(&str, &Analysis, &Occurrence)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let (text, analysis) = self.documents.get(uri)?;
+        let occurrence = analysis.occurrence_at(offset(text, &params["position"]))?;
+        Some((text, analysis, occurrence))
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let Some((text, analysis, occurrence)) = self.pointed(params) else {
+            return Value::Null;
+        };
+        let Occurrence { symbol, arity, .. } = occurrence;
+        let (usages, definitions) = analysis
+            .occurrences_of(symbol, *arity)
+            .fold((0, 0), |(u, d), o| (u + 1, d + o.conclusion as usize));
+
+        json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!(
+                    "`{symbol}/{arity}`\n\n{usages} usage(s), concluded by {definitions} rule(s)"
+                ),
+            },
+            "range": range(text, &occurrence.span),
+        })
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let Some((text, analysis, occurrence)) = self.pointed(params) else {
+            return Value::Null;
+        };
+        let uri = &params["textDocument"]["uri"];
+        analysis
+            .occurrences_of(&occurrence.symbol, occurrence.arity)
+            .filter(|o| o.conclusion)
+            .map(|o| json!({ "uri": uri, "range": range(text, &o.span) }))
+            .collect()
+    }
+
+    fn document_symbols(&self, params: &Value) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let Some((text, analysis)) = self.documents.get(uri) else {
+            return Value::Null;
+        };
+        analysis
+            .rules
+            .iter()
+            .map(|rule| {
+                let name = text[rule.span.clone()]
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let selection = rule
+                    .conclusion
+                    .map(|i| analysis.occurrences[i].span.clone())
+                    .unwrap_or(rule.span.clone());
+                json!({
+                    "name": name,
+                    // Constant for axioms, Function for rules
+                    "kind": if rule.is_axiom { 14 } else { 12 },
+                    "range": range(text, &rule.span),
+                    "selectionRange": range(text, &selection),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Reads a JSON-RPC message from the given input, returning `None` on end of input
+fn read_message(input: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let mut body = vec![0; length?];
+    input.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> std::io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+/// Runs the language server on stdio until the client exits
+pub fn run() -> std::io::Result<()> {
+    let stdin = std::io::stdin();
+    let mut input = stdin.lock();
+    let mut output = std::io::stdout();
+    let mut server = Server::default();
+
+    while let Some(message) = read_message(&mut input) {
+        if message["method"] == "exit" {
+            break;
+        }
+        for response in server.handle(&message) {
+            write_message(&mut output, &response)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "att(k).\natt(senc(X, Y)) /\\ att(Y) => att(X).\nleak(X) => out(X, k).";
+
+    #[test]
+    fn analysis_test() {
+        let analysis = Analysis::new(SOURCE);
+        assert!(analysis.errors.is_empty());
+        assert_eq!(analysis.rules.len(), 3);
+        assert!(analysis.rules[0].is_axiom && !analysis.rules[1].is_axiom);
+        let arities: Vec<_> = analysis
+            .occurrences
+            .iter()
+            .map(|o| (o.symbol.as_str(), o.arity, o.conclusion))
+            .collect();
+        assert_eq!(
+            arities,
+            vec![
+                ("att", 1, true),
+                ("att", 1, false),
+                ("att", 1, false),
+                ("att", 1, true),
+                ("leak", 1, false),
+                ("out", 2, true)
+            ]
+        );
+        assert_eq!(analysis.occurrences_of("att", 1).count(), 4);
+        assert_eq!(
+            analysis
+                .occurrence_at(SOURCE.find("leak").unwrap() + 2)
+                .map(|o| o.arity),
+            Some(1)
+        );
+
+        let analysis = Analysis::new("att(k).\natt(X) => .");
+        assert_eq!(analysis.errors.len(), 1);
+        assert_eq!(analysis.errors[0].0, 18..19);
+        assert_eq!(
+            Analysis::new("att(k) § att(a).").errors[0].1,
+            "unrecognized token"
+        );
+    }
+
+    #[test]
+    fn position_test() {
+        let source = "p(é).\nq(𝔸, b).\n";
+        for byte in [0, 2, 4, 6, 7, 9, 13, 15, source.len()] {
+            let position = position(source, byte);
+            assert_eq!(offset(source, &position), byte);
+        }
+        // UTF-16 columns count astral characters twice
+        let b = source.find('b').unwrap();
+        assert_eq!(position(source, b), json!({ "line": 1, "character": 6 }));
+        assert_eq!(
+            offset(source, &json!({ "line": 1, "character": 99 })),
+            source.len() - 1
+        );
+    }
+
+    #[test]
+    fn server_test() {
+        let mut server = Server::default();
+        let uri = "file:///test.pif";
+        let published = server.handle(&json!({
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri, "text": SOURCE } },
+        }));
+        let diagnostics = &published[0]["params"]["diagnostics"];
+        assert!(diagnostics
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|d| d["code"] == "L002"));
+
+        let request = |method: &str, line: usize, character: usize| {
+            json!({
+                "id": 1,
+                "method": method,
+                "params": {
+                    "textDocument": { "uri": uri },
+                    "position": { "line": line, "character": character },
+                },
+            })
+        };
+        let definition = &server.handle(&request("textDocument/definition", 1, 20))[0]["result"];
+        let starts: Vec<_> = definition
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|l| l["range"]["start"].clone())
+            .collect();
+        assert_eq!(
+            starts,
+            vec![
+                json!({ "line": 0, "character": 0 }),
+                json!({ "line": 1, "character": 29 })
+            ]
+        );
+        let hover = &server.handle(&request("textDocument/hover", 2, 11))[0]["result"];
+        assert_eq!(
+            hover["contents"]["value"],
+            "`out/2`\n\n1 usage(s), concluded by 1 rule(s)"
+        );
+        assert!(server.handle(&request("textDocument/hover", 1, 9))[0]["result"].is_null());
+
+        let symbols = &server.handle(&request("textDocument/documentSymbol", 0, 0))[0]["result"];
+        let symbols: Vec<_> = symbols
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| (s["name"].as_str().unwrap().to_string(), s["kind"].clone()))
+            .collect();
+        assert_eq!(symbols[0], (String::from("att(k)."), json!(14)));
+        assert_eq!(
+            symbols[2],
+            (String::from("leak(X) => out(X, k)."), json!(12))
+        );
+        assert_eq!(
+            server.handle(&request("unknown", 0, 0))[0]["error"]["code"],
+            -32601
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! Macros module
+//! Rule templates, defined in `.pif` files with `#define <name>(<parameters>) <statement>.`
+//! A statement `<name>(<arguments>).` then stands for the body of the macro, where
+//! every occurrence of a parameter is replaced by the corresponding argument,
+//! including in predicate position:
+//! ```text
+//! #define symmetric(P) P(Y, X) => P(X, Y).
+//! symmetric(connected).
+//! ```
+//! Expansion works on tokens, before the expanded statement is parsed, so macros
+//! are only expanded as whole statements. Macros are told apart by their name and
+//! number of parameters
+
+use crate::hashing::HashMap;
+use std::fmt::{Display, Formatter, Result};
+
+/// Maximum nesting of macro expansions, past which macros are considered recursive
+pub const MAX_EXPANSION_DEPTH: usize = 32;
+
+/// A macro definition
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Macro {
+    pub name: String,
+    pub parameters: Vec<String>,
+    /// Tokens of the body of the macro, as written in the source
+    pub body: Vec<String>,
+}
+impl Macro {
+    /// Expands the macro with the given arguments, written as source text
+    pub fn expand(&self, arguments: &[String]) -> String {
+        let mut expansion = String::new();
+        for token in &self.body {
+            let replacement = self
+                .parameters
+                .iter()
+                .position(|p| p == token)
+                .map_or(token, |i| &arguments[i]);
+            expansion.push_str(replacement);
+            expansion.push(' ');
+        }
+        expansion.push('.');
+        expansion
+    }
+}
+impl Display for Macro {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(
+            f,
+            "#define {}({}) {}",
+            self.name,
+            self.parameters.join(", "),
+            self.body.join(" ")
+        )
+    }
+}
+
+/// Defined macros, by name and arity. Defining a macro again replaces it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MacroTable {
+    macros: HashMap<(String, usize), Macro>,
+}
+impl MacroTable {
+    pub fn define(&mut self, definition: Macro) {
+        self.macros.insert(
+            (definition.name.clone(), definition.parameters.len()),
+            definition,
+        );
+    }
+
+    pub fn get(&self, name: &str, arity: usize) -> Option<&Macro> {
+        self.macros.get(&(name.to_string(), arity))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.macros.is_empty()
+    }
+}
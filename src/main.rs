@@ -1,26 +1,521 @@
 use itertools::Itertools;
 use logos_nom_bridge::Tokens;
 use ptree::{Color, Style};
-use sniffer::{Parser, Sniffer};
+use sniffer::lint::{line_column, lint};
+use sniffer::transform::{self, Pass};
+use sniffer::{
+    fmt, read_source, DeadRule, DerivationTree, DuplicateRule, FailedExpectation, FormatOptions,
+    GoalAnswers, LoadError, OperatorTable, Parser, Sniffer, Statement, Strategy,
+};
+use sniffer::{msc, output};
 use std::env;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
 
-fn main() {
-    let args = env::args().collect::<Vec<_>>();
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1).collect::<Vec<_>>();
+    // `--profile` may be given to any mode, printing saturation counters
+    let profiling = args.iter().any(|a| a == "--profile");
+    args.retain(|a| a != "--profile");
+    // `--warnings` prints the diagnostics gathered while loading and saturating
+    let warnings = args.iter().any(|a| a == "--warnings");
+    args.retain(|a| a != "--warnings");
 
-    // One argument (the file path) runs the REPL with basic axioms loaded in
-    // Otherwise we just run the REPL with no axioms
-    let mut sniffer = if let Some(file) = args.get(1) {
-        if let Ok(from_file) = Sniffer::new(file) {
-            from_file
+    // `repl` is the default mode, so `sniffer <file>` and `sniffer repl <file>` are equivalent
+    match args.first().map(String::as_str) {
+        Some("lint") => run_lint(&expand_globs(&args[1..])),
+        Some("test") => run_tests(&expand_globs(&args[1..]), profiling, warnings),
+        Some("prove") => {
+            // `--msc` prints the derivation as a PlantUML sequence diagram
+            let msc = args.iter().any(|a| a == "--msc");
+            args.retain(|a| a != "--msc");
+            match &args[1..] {
+                [file, goal] => prove(
+                    Path::new(file),
+                    goal,
+                    Strategy::default(),
+                    msc,
+                    profiling,
+                    warnings,
+                ),
+                [flag, strategy, file, goal] if flag == "--strategy" => {
+                    match parse_strategy(strategy) {
+                        Some(strategy) => {
+                            prove(Path::new(file), goal, strategy, msc, profiling, warnings)
+                        }
+                        None => {
+                            eprintln!(
+                                "unknown strategy {strategy}, expected bottom-up, top-down or hybrid"
+                            );
+                            ExitCode::FAILURE
+                        }
+                    }
+                }
+                _ => {
+                    eprintln!(
+                        "usage: sniffer prove [--strategy <strategy>] [--msc] <file|-> <goal>"
+                    );
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Some("transform") => run_transform(&args[1..]),
+        Some("output") => run_output(&args[1..]),
+        #[cfg(feature = "serve")]
+        Some("serve") => {
+            let address = args.get(1).map_or("127.0.0.1:8080", String::as_str);
+            match sniffer::server::serve(address) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("failed to serve on {address}: {e}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Some("repl") => {
+            repl(&expand_globs(&args[1..]), profiling, warnings);
+            ExitCode::SUCCESS
+        }
+        _ => {
+            repl(&expand_globs(&args), profiling, warnings);
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+/// Expands the file name patterns given on the command line, where `*` matches
+/// any sequence of characters and `?` any single character. Only the last
+/// component of a path may contain wildcards, and patterns matching nothing are
+/// kept as is so that the error is reported when opening them
+fn expand_globs<S: AsRef<str>>(patterns: &[S]) -> Vec<PathBuf> {
+    let mut files = vec![];
+    for pattern in patterns {
+        let path = Path::new(pattern.as_ref());
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if !name.contains(['*', '?']) {
+            files.push(path.to_path_buf());
+            continue;
+        }
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut matches: Vec<_> = std::fs::read_dir(directory)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| {
+                let file_name = entry.file_name();
+                file_name
+                    .to_str()
+                    .is_some_and(|f| wildcard_match(name.as_bytes(), f.as_bytes()))
+            })
+            .map(|entry| path.with_file_name(entry.file_name()))
+            .collect();
+        if matches.is_empty() {
+            files.push(path.to_path_buf());
+        }
+        matches.sort();
+        files.append(&mut matches);
+    }
+    files
+}
+
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            wildcard_match(rest, name) || (!name.is_empty() && wildcard_match(pattern, &name[1..]))
+        }
+        (Some((b'?', rest)), Some((_, name))) => wildcard_match(rest, name),
+        (Some((p, rest)), Some((c, name))) if p == c => wildcard_match(rest, name),
+        _ => false,
+    }
+}
+
+/// Opens a file given on the command line, `-` standing for the standard input
+fn open(path: &Path) -> std::io::Result<Box<dyn Read>> {
+    if path == Path::new("-") {
+        Ok(Box::new(std::io::stdin()))
+    } else {
+        Ok(Box::new(std::fs::File::open(path)?))
+    }
+}
+
+/// Loads the given files into a new context, reporting rules given by several files
+fn load_files(files: &[PathBuf]) -> Result<Sniffer, LoadError> {
+    let mut sniffer = Sniffer::default();
+    for DuplicateRule {
+        rule,
+        first,
+        file,
+        conflicting,
+    } in sniffer.load_all_from(files.iter().map(|file| (file.clone(), open(file))))?
+    {
+        let kind = if conflicting {
+            "conflicting duplicate rule"
         } else {
-            println!("failed to parse file");
-            Sniffer::default()
+            "duplicate rule"
+        };
+        eprintln!(
+            "{}: warning: {kind} `{rule}`, first given in {}",
+            file.display(),
+            first.display()
+        );
+    }
+    for DeadRule {
+        rule,
+        premise,
+        location,
+    } in sniffer.dead_rules()
+    {
+        let location = location.map_or(String::new(), |l| format!("{l}: "));
+        eprintln!(
+            "{location}warning: rule `{rule}` can never fire, no fact or rule concludes `{}/{}`",
+            premise.symbol,
+            premise.parameters.len()
+        );
+    }
+    Ok(sniffer)
+}
+
+/// Lints the given files, failing if any lint or error was emitted
+fn run_lint(files: &[PathBuf]) -> ExitCode {
+    let mut clean = true;
+    for path in files {
+        let file = path.display();
+        let source = match open(path).and_then(read_source) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("{file}: failed to read file: {e}");
+                clean = false;
+                continue;
+            }
+        };
+        match lint(&source) {
+            Ok(lints) => {
+                for l in lints {
+                    clean = false;
+                    let (line, column) = line_column(&source, l.span.start);
+                    println!(
+                        "{file}:{line}:{column}: warning[{}] {}: {}",
+                        l.code.code(),
+                        l.code.name(),
+                        l.message
+                    );
+                }
+            }
+            Err(e) => {
+                clean = false;
+                let (line, column) = line_column(&source, e.offset);
+                println!("{file}:{line}:{column}: error: {}", e.kind.description());
+            }
         }
+    }
+
+    if clean {
+        ExitCode::SUCCESS
     } else {
-        Sniffer::default()
+        ExitCode::FAILURE
+    }
+}
+
+/// Checks the expectations of the given files, failing if any does not hold
+fn run_tests(files: &[PathBuf], profiling: bool, warnings: bool) -> ExitCode {
+    let mut success = true;
+    for file in files {
+        match open(file)
+            .map_err(LoadError::Io)
+            .and_then(Sniffer::from_reader)
+        {
+            Ok(mut sniffer) => {
+                sniffer.set_profiling(profiling);
+                let total = sniffer.expectations().len();
+                let failures = sniffer.run_expectations();
+                let violations = sniffer.check_consistency();
+                if profiling {
+                    eprint!("{}", sniffer.profile());
+                }
+                if warnings {
+                    print_diagnostics(&sniffer);
+                }
+                let operators = &sniffer.parser_options().operators;
+                print_failed_expectations(&failures, operators);
+                for violation in &violations {
+                    println!("{violation} is violated:");
+                    print_tree(&violation.derivation, operators);
+                }
+                println!(
+                    "{}: {}/{total} expectation(s) hold",
+                    file.display(),
+                    total - failures.len()
+                );
+                if !sniffer.constraints().is_empty() {
+                    println!(
+                        "{}: {}/{} constraint(s) hold",
+                        file.display(),
+                        sniffer.constraints().len() - violations.len(),
+                        sniffer.constraints().len()
+                    );
+                }
+                success &= failures.is_empty() && violations.is_empty();
+            }
+            Err(e) => {
+                eprintln!("{}: {e}", file.display());
+                success = false;
+            }
+        }
+    }
+
+    if success {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Nesting of goals allowed to `--strategy top-down`
+const TOP_DOWN_DEPTH: usize = 32;
+
+fn parse_strategy(strategy: &str) -> Option<Strategy> {
+    match strategy {
+        "bottom-up" => Some(Strategy::BottomUp),
+        "top-down" => Some(Strategy::TopDown {
+            depth: TOP_DOWN_DEPTH,
+        }),
+        "hybrid" => Some(Strategy::Hybrid),
+        _ => None,
+    }
+}
+
+/// Saturates the given files, writing the facts of every output predicate to a
+/// file of the output directory as they are derived, one record per line
+fn run_output(args: &[String]) -> ExitCode {
+    const USAGE: &str = "usage: sniffer output [--format csv|json] [--dir <directory>] <file>...";
+    let mut json = false;
+    let mut directory = PathBuf::from(".");
+    let mut args = args.iter();
+    let mut files = vec![];
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.clone().next().map(String::as_str)) {
+            ("--format", Some(format @ ("csv" | "json"))) => json = format == "json",
+            ("--dir", Some(path)) => directory = PathBuf::from(path),
+            ("--format" | "--dir", _) => {
+                eprintln!("{USAGE}");
+                return ExitCode::FAILURE;
+            }
+            (file, _) => {
+                files.push(file.to_string());
+                continue;
+            }
+        }
+        args.next();
+    }
+    let mut sniffer = match load_files(&expand_globs(&files)) {
+        Ok(sniffer) => sniffer,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    // Facts are output as they are derived, by a run of this fingerprint
+    let fingerprint = sniffer.fingerprint();
+    let mut writers = vec![];
+    for predicate in sniffer.outputs() {
+        let path = directory.join(format!(
+            "{predicate}.{}",
+            if json { "jsonl" } else { "csv" }
+        ));
+        let writer = match std::fs::File::create(&path) {
+            Ok(file) => Arc::new(Mutex::new((BufWriter::new(file), Ok(())))),
+            Err(e) => {
+                eprintln!("{}: failed to create file: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        };
+        let sink = writer.clone();
+        let fingerprint = fingerprint.clone();
+        sniffer.on_output(&predicate, move |fact| {
+            let record = if json {
+                output::to_json(fact, &fingerprint)
+            } else {
+                output::to_csv(fact)
+            };
+            // The first write error is reported once saturated
+            let (file, result) = &mut *sink.lock().unwrap();
+            if result.is_ok() {
+                *result = writeln!(file, "{record}");
+            }
+        });
+        writers.push((path, writer));
+    }
+    sniffer.saturate_all();
+    let mut status = ExitCode::SUCCESS;
+    for (path, writer) in writers {
+        let (file, result) = &mut *writer.lock().unwrap();
+        if let Err(e) = std::mem::replace(result, Ok(())).and_then(|_| file.flush()) {
+            eprintln!("{}: failed to write file: {e}", path.display());
+            status = ExitCode::FAILURE;
+        }
+    }
+    status
+}
+
+/// Runs the passes given as flags over the rules of a file, in order, printing the
+/// other statements as written then the resulting rules
+fn run_transform(args: &[String]) -> ExitCode {
+    const USAGE: &str =
+        "usage: sniffer transform [--normalize|--unfold|--skolemize|--magic <goal>]... <file|->";
+    let Some((file, flags)) = args.split_last() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    let mut passes = vec![];
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        let pass = match flag.as_str() {
+            "--normalize" => Pass::Normalize,
+            "--unfold" => Pass::Unfold,
+            "--skolemize" => Pass::Skolemize,
+            "--magic" => {
+                let goal = flags
+                    .next()
+                    .map(|g| format!("{}.", g.trim().trim_end_matches('.')));
+                match goal.and_then(|g| Parser::parse_query(Tokens::new(&g)).ok()) {
+                    Some(goal) => Pass::MagicSets(goal),
+                    None => {
+                        eprintln!("failed to parse goal");
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            _ => {
+                eprintln!("{USAGE}");
+                return ExitCode::FAILURE;
+            }
+        };
+        passes.push(pass)
+    }
+    let path = Path::new(file);
+    let source = match open(path).and_then(read_source) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{}: failed to read file: {e}", path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    match Parser::parse_spanned_statements(Tokens::new(&source)) {
+        Ok(statements) => {
+            let mut rules = vec![];
+            let mut printed = None;
+            for (statement, span) in statements {
+                match statement {
+                    Statement::Rule(rule, _) => rules.push(rule),
+                    // Statements expanded from the same macro use share its span
+                    _ if printed.as_ref() != Some(&span) => {
+                        println!("{}", source[span.clone()].trim());
+                        printed = Some(span)
+                    }
+                    _ => (),
+                }
+            }
+            for rule in transform::run(rules, &passes) {
+                println!("{rule}.")
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}: {e}", path.display());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Prints a derivation of the goal from the rules of the given file, found with the
+/// given strategy, failing if there is none
+fn prove(
+    file: &Path,
+    goal: &str,
+    strategy: Strategy,
+    msc: bool,
+    profiling: bool,
+    warnings: bool,
+) -> ExitCode {
+    let mut sniffer = match open(file)
+        .map_err(LoadError::Io)
+        .and_then(Sniffer::from_reader)
+    {
+        Ok(sniffer) => sniffer,
+        Err(e) => {
+            eprintln!("{}: {e}", file.display());
+            return ExitCode::FAILURE;
+        }
     };
+    // The final stop of the goal may be left out
+    let goal = format!("{}.", goal.trim().trim_end_matches('.'));
+    let Ok(goal) = Parser::parse_query_with(Tokens::new(&goal), sniffer.parser_options()) else {
+        eprintln!("failed to parse goal");
+        return ExitCode::FAILURE;
+    };
+    sniffer.set_strategy(strategy);
+    sniffer.set_profiling(profiling);
+    let proof = sniffer.prove(&goal);
+    if profiling {
+        eprint!("{}", sniffer.profile());
+    }
+    if warnings {
+        print_diagnostics(&sniffer);
+    }
+    match proof {
+        Ok(tree) if msc => {
+            print!("{}", msc::to_plantuml(&tree, &msc::MscOptions::default()));
+            ExitCode::SUCCESS
+        }
+        Ok(tree) => {
+            print_tree(&tree, &sniffer.parser_options().operators);
+            ExitCode::SUCCESS
+        }
+        Err(_) => {
+            println!("no derivation of {goal}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn print_failed_expectations(failures: &[FailedExpectation], operators: &OperatorTable) {
+    for FailedExpectation {
+        expectation,
+        derivation,
+    } in failures
+    {
+        if let Some(derivation) = derivation {
+            println!("expected {} not to be derivable, but:", expectation.atom);
+            print_tree(derivation, operators);
+        } else {
+            println!("expected {} to be derivable", expectation.atom);
+        }
+    }
+}
+
+/// Runs the REPL, with axioms from the given files loaded in
+fn repl(files: &[PathBuf], profiling: bool, warnings: bool) {
+    let mut sniffer = load_files(files).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        Sniffer::default()
+    });
+    sniffer.set_profiling(profiling);
+    if warnings {
+        print_diagnostics(&sniffer);
+    }
 
     let mut rl = rustyline::DefaultEditor::new().expect("failed to open repl");
+    let history = history_path();
+    if let Some(history) = &history {
+        let _ = rl.load_history(history);
+    }
+
     let prompt_style = Style {
         foreground: Some(Color::Yellow),
         background: None,
@@ -28,22 +523,79 @@ fn main() {
         ..Default::default()
     };
     while let Ok(line) = rl.readline(&prompt_style.paint("sniffer >> ").to_string()) {
-        let _ = rl.add_history_entry(line.clone());
         let mut words = line.split_whitespace();
-        let command = words.next().unwrap();
+        let Some(command) = words.next() else {
+            continue;
+        };
+        let _ = rl.add_history_entry(line.clone());
         let query = words.join(" ");
 
         match handle_command(command, &query, &mut sniffer) {
             CommandResult::ParsingError => eprintln!("parsing error"),
-            CommandResult::UnknownCommand => eprintln!("unknown command"),
+            CommandResult::UnknownCommand => eprintln!("unknown command (try `help`)"),
             CommandResult::FileError => eprintln!("failed to open/write to file"),
             CommandResult::NotFoundQuery => eprintln!("no result for query"),
             CommandResult::Quit => break,
             _ => (),
         }
     }
+
+    if let Some(history) = &history {
+        let _ = rl.save_history(history);
+    }
+}
+
+/// Location of the REPL history file, kept in the user's home directory
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".sniffer_history"))
+}
+
+const HELP: &str = "\
+load <files>          loads `.pif` files into a new context
+assert <rules>        adds rules, axioms or expectations to the current context
+remove <rules>        removes rules along with what was derived from them
+expectations          checks every `#expect`/`#expect_not` directive
+goals                 answers every `?-` goal
+query <atom>          saturates the rule set, showing a derivation of the atom if one exists
+query-all <atom>      saturates the rule set, showing a derivation of every instance of the atom
+probability <atom>    saturates the rule set, showing the probability of the atom's derivation
+defeasible            resolves the defeasible rules, adding their accepted conclusions
+rules [predicates]    lists defined rules, or those concluding the given predicates
+symbols               lists the predicates, functions and constants of the rule set
+profile [on|off]      shows how often rules fired and predicates were unified, or toggles profiling
+warnings [clear]      shows the diagnostics gathered so far, or forgets them
+derivation [rules]    prints the derivation tree of the given rules, or all if none are given
+help                  shows this message
+quit                  exits the REPL";
+
+/// Options printing rules for the terminal: only the given predicates are shown if
+/// any, and rules are wrapped to the `COLUMNS` width if it is set. With the `color`
+/// feature, rules are colored when printed to a terminal
+fn format_options(predicates: &str) -> FormatOptions {
+    FormatOptions {
+        #[cfg(feature = "color")]
+        color: std::io::IsTerminal::is_terminal(&std::io::stdout()),
+        max_width: env::var("COLUMNS").ok().and_then(|c| c.parse().ok()),
+        predicates: predicates.split_whitespace().map(String::from).collect(),
+        ..FormatOptions::default()
+    }
+}
+
+/// Prints the diagnostics of a Sniffer to the standard error
+fn print_diagnostics(sniffer: &Sniffer) {
+    for diagnostic in sniffer.diagnostics().iter() {
+        eprintln!("{diagnostic}");
+    }
+}
+
+fn print_tree(tree: &DerivationTree, operators: &OperatorTable) {
+    print!(
+        "{}",
+        fmt::tree_to_string(tree, operators, &format_options(""))
+    );
 }
 
+#[derive(Debug, PartialEq)]
 enum CommandResult {
     OkCommand,
     ParsingError,
@@ -55,62 +607,250 @@ enum CommandResult {
 fn handle_command(command: &str, query: &str, sniffer: &mut Sniffer) -> CommandResult {
     match command {
         "load" => {
-            if let Ok(from_file) = Sniffer::new(query) {
-                *sniffer = from_file;
+            let files: Vec<_> = query.split_whitespace().collect();
+            match load_files(&expand_globs(&files)) {
+                Ok(mut from_files) => {
+                    from_files.set_profiling(sniffer.is_profiling());
+                    *sniffer = from_files;
+                    CommandResult::OkCommand
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    match e {
+                        LoadError::File(_, e) if matches!(*e, LoadError::Parsing(_)) => {
+                            CommandResult::ParsingError
+                        }
+                        _ => CommandResult::FileError,
+                    }
+                }
+            }
+        }
+
+        "assert" => match sniffer.assert(query) {
+            Ok(count) => {
+                println!("added {count} statement(s)");
                 CommandResult::OkCommand
-            } else {
-                CommandResult::FileError
             }
+            Err(_) => CommandResult::ParsingError,
+        },
+
+        "remove" => {
+            let Ok(rules) = Parser::parse_rules_with(Tokens::new(query), sniffer.parser_options())
+            else {
+                return CommandResult::ParsingError;
+            };
+            for rule in rules {
+                match sniffer.remove_rule(&rule) {
+                    Some(retracted) => {
+                        for fact in retracted {
+                            println!("retracted {fact}");
+                        }
+                    }
+                    None => eprintln!("unknown rule {rule}"),
+                }
+            }
+            CommandResult::OkCommand
         }
 
         "query" => {
-            let query = if let Ok(query) = Parser::parse_query(Tokens::new(query)) {
+            let query = if let Ok(query) =
+                Parser::parse_query_with(Tokens::new(query), sniffer.parser_options())
+            {
                 query
             } else {
                 return CommandResult::ParsingError;
             };
 
             if let Ok(derivation_tree) = sniffer.find(&query) {
-                ptree::print_tree(&derivation_tree).unwrap();
+                print_tree(&derivation_tree, &sniffer.parser_options().operators);
+                CommandResult::OkCommand
+            } else {
+                CommandResult::NotFoundQuery
+            }
+        }
+
+        "query-all" => {
+            let Ok(query) = Parser::parse_query_with(Tokens::new(query), sniffer.parser_options())
+            else {
+                return CommandResult::ParsingError;
+            };
+            match sniffer.find_all(&query) {
+                Ok(found) if !found.is_empty() => {
+                    for (_, derivation_tree) in found {
+                        print_tree(&derivation_tree, &sniffer.parser_options().operators);
+                    }
+                    CommandResult::OkCommand
+                }
+                _ => CommandResult::NotFoundQuery,
+            }
+        }
+
+        "probability" => {
+            let Ok(query) = Parser::parse_query_with(Tokens::new(query), sniffer.parser_options())
+            else {
+                return CommandResult::ParsingError;
+            };
+            if let Ok(probability) = sniffer.probability(&query) {
+                println!("{probability}");
                 CommandResult::OkCommand
             } else {
                 CommandResult::NotFoundQuery
             }
         }
 
+        "defeasible" => {
+            let outcome = sniffer.run_defeasible();
+            for (label, atoms) in [
+                ("concluded", outcome.concluded),
+                ("refuted", outcome.refuted),
+                ("undecided", outcome.undecided),
+            ] {
+                for atom in atoms {
+                    println!("{label} {atom}");
+                }
+            }
+            CommandResult::OkCommand
+        }
+
+        "symbols" => {
+            for symbol in sniffer.symbols() {
+                println!("{symbol}");
+            }
+            CommandResult::OkCommand
+        }
         "rules" => {
-            println!("{}", sniffer.rules_to_string());
+            println!("{}", sniffer.rules_to_string_with(&format_options(query)));
             CommandResult::OkCommand
         }
+        "profile" => match query {
+            "on" | "off" => {
+                sniffer.set_profiling(query == "on");
+                CommandResult::OkCommand
+            }
+            "" if sniffer.is_profiling() => {
+                print!("{}", sniffer.profile());
+                CommandResult::OkCommand
+            }
+            "" => {
+                eprintln!("profiling is off (try `profile on`)");
+                CommandResult::OkCommand
+            }
+            _ => CommandResult::ParsingError,
+        },
+        "warnings" => match query {
+            "" => {
+                print_diagnostics(sniffer);
+                CommandResult::OkCommand
+            }
+            "clear" => {
+                sniffer.clear_diagnostics();
+                CommandResult::OkCommand
+            }
+            _ => CommandResult::ParsingError,
+        },
         "derivation" => {
-            if query.is_empty() {
-                let rules: Vec<_> = sniffer.iter_rules().collect();
+            let rules = if query.is_empty() {
+                sniffer.iter_rules().collect()
+            } else if let Ok(rules) =
+                Parser::parse_rules_with(Tokens::new(query), sniffer.parser_options())
+            {
+                rules
+            } else {
+                return CommandResult::ParsingError;
+            };
 
-                for tree in rules
-                    .into_iter()
-                    .filter_map(|r| sniffer.derivation_tree(&r))
-                {
-                    ptree::print_tree(&tree).unwrap()
+            for rule in rules {
+                match sniffer.derivation_tree(&rule) {
+                    Ok(tree) => print_tree(&tree, &sniffer.parser_options().operators),
+                    Err(e) => eprintln!("{e}"),
                 }
-                CommandResult::OkCommand
-            } else {
-                let rules = if let Ok(rules) = Parser::parse_rules(Tokens::new(query)) {
-                    rules
-                } else {
-                    return CommandResult::ParsingError;
-                };
-
-                for tree in rules
-                    .into_iter()
-                    .filter_map(|r| sniffer.derivation_tree(&r))
-                {
-                    ptree::print_tree(&tree).unwrap()
+            }
+            CommandResult::OkCommand
+        }
+
+        "expectations" => {
+            let total = sniffer.expectations().len();
+            let failures = sniffer.run_expectations();
+            print_failed_expectations(&failures, &sniffer.parser_options().operators);
+            println!("{}/{total} expectation(s) hold", total - failures.len());
+            CommandResult::OkCommand
+        }
+
+        "goals" => {
+            for GoalAnswers { goal, answers } in sniffer.run_goals() {
+                println!("?- {goal}.");
+                if answers.is_empty() {
+                    println!("   no answer");
+                }
+                for answer in answers {
+                    println!("   {answer}");
                 }
-                CommandResult::OkCommand
             }
+            CommandResult::OkCommand
         }
 
+        "help" => {
+            println!("{HELP}");
+            CommandResult::OkCommand
+        }
         "quit" => CommandResult::Quit,
         _ => CommandResult::UnknownCommand,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{handle_command, CommandResult};
+    use sniffer::{NamingConvention, ParserOptions, Sniffer};
+
+    #[test]
+    fn repl_test() {
+        let mut sniffer = Sniffer::default();
+        let mut run = |line: &str| {
+            let (command, query) = line.split_once(' ').unwrap_or((line, ""));
+            handle_command(command, query, &mut sniffer)
+        };
+        assert_eq!(
+            run("assert att(pair(a, h(b))). att(pair(X, Y)) => att(X). att(pair(X, Y)) => att(Y)."),
+            CommandResult::OkCommand
+        );
+        assert_eq!(run("assert att(pair(a, "), CommandResult::ParsingError);
+
+        // Variables of queries are bound to any derivable fact
+        assert_eq!(run("query att(h(Y))."), CommandResult::OkCommand);
+        assert_eq!(run("query-all att(X)."), CommandResult::OkCommand);
+        assert_eq!(run("query att(c)."), CommandResult::NotFoundQuery);
+        assert_eq!(run("assert att(c)."), CommandResult::OkCommand);
+        assert_eq!(run("query att(c)."), CommandResult::OkCommand);
+
+        // Queries are parsed with the operators declared by the rules
+        assert_eq!(
+            run("assert op 500 yfx +. att(d + e)."),
+            CommandResult::OkCommand
+        );
+        assert_eq!(run("query att(d + e)."), CommandResult::OkCommand);
+        assert_eq!(run("derivation att(d + e)."), CommandResult::OkCommand);
+
+        assert_eq!(run("frobnicate"), CommandResult::UnknownCommand);
+        assert_eq!(run("quit"), CommandResult::Quit);
+
+        // Queries follow the naming convention of the Sniffer
+        let options = ParserOptions {
+            convention: NamingConvention::Sigil,
+            ..Default::default()
+        };
+        let mut sniffer = Sniffer::with_parser_options(options);
+        let mut run = |line: &str| {
+            let (command, query) = line.split_once(' ').unwrap_or((line, ""));
+            handle_command(command, query, &mut sniffer)
+        };
+        assert_eq!(
+            run("assert att(pair(Alice, Bob)). att(pair(?x, ?y)) => att(?x)."),
+            CommandResult::OkCommand
+        );
+        assert_eq!(run("query att(pair(?x, Bob))."), CommandResult::OkCommand);
+        assert_eq!(run("query-all att(?x)."), CommandResult::OkCommand);
+        assert_eq!(run("query att(Carol)."), CommandResult::NotFoundQuery);
+        assert_eq!(run("derivation att(Alice)."), CommandResult::OkCommand);
+    }
+}
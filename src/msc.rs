@@ -0,0 +1,291 @@
+//! MSC module
+//! Exports the derivation of a fact known to the attacker as a message sequence
+//! chart, written as a PlantUML sequence diagram, so that attack traces can be read
+//! without following resolutions.
+//!
+//! Every fact of the derivation comes from the input rule it was derived with the
+//! conclusion of: this rule is fired once the facts it is resolved with are known.
+//! Input rules are read as follows:
+//! - rules given to a role, or rules with premises holding constants, are protocol
+//!   steps: the attacker sends the instances of their premises to the role, which
+//!   answers with the instance of their conclusion. Steps of no role are done by a
+//!   `Protocol` participant
+//! - other rules with premises are computations of the attacker, which are only
+//!   shown when they yield a fact
+//! - facts given to a role are sent by it, and other facts are initial knowledge.
+//!
+//! Atoms of the attacker predicate (`att` by default) stand for their message, other
+//! atoms for themselves. Identical steps are only shown once
+use crate::ast::{Atom, Rule, Term};
+use crate::hashing::HashSet;
+use crate::substitution::Substitution;
+use crate::DerivationTree;
+use crate::Selection;
+
+/// Participant of the attacker
+const ATTACKER: &str = "Attacker";
+/// Participant of the protocol steps given to no role
+const PROTOCOL: &str = "Protocol";
+
+/// How derivations are turned into charts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MscOptions {
+    /// Predicate of the facts known to the attacker
+    pub attacker: String,
+    /// Participants of the protocol along with the input rules modeling their steps,
+    /// compared up to normalization (see `Rule::normalized`)
+    pub roles: Vec<(String, Vec<Rule<String>>)>,
+}
+impl Default for MscOptions {
+    fn default() -> Self {
+        MscOptions {
+            attacker: "att".to_string(),
+            roles: vec![],
+        }
+    }
+}
+impl MscOptions {
+    /// Adds a participant doing the steps modeled by the given rules
+    pub fn role(mut self, name: &str, rules: Vec<Rule<String>>) -> Self {
+        let rules = rules.iter().map(Rule::normalized).collect();
+        self.roles.push((name.to_string(), rules));
+        self
+    }
+
+    fn role_of(&self, rule: &Rule<String>) -> Option<&str> {
+        let rule = rule.normalized();
+        self.roles
+            .iter()
+            .find(|(_, rules)| rules.contains(&rule))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Message an atom stands for
+    fn message(&self, atom: &Atom<String>) -> String {
+        match &atom.parameters[..] {
+            [message] if atom.symbol == self.attacker => message.to_string(),
+            _ => atom.to_string(),
+        }
+    }
+}
+
+/// Builds the chart of a derivation, ending with the fact at its root
+pub fn to_plantuml(tree: &DerivationTree, options: &MscOptions) -> String {
+    let mut chart = Chart {
+        options,
+        participants: vec![ATTACKER.to_string()],
+        lines: vec![],
+        shown: HashSet::default(),
+    };
+    chart.step(tree);
+    if tree.root().premises.is_empty() {
+        let learnt = options.message(&tree.root().conclusion);
+        chart
+            .lines
+            .push(format!("note over {ATTACKER} : learns {learnt}"));
+    }
+
+    let mut puml = String::from("@startuml\n");
+    for participant in &chart.participants {
+        puml.push_str(&format!("participant {}\n", quoted(participant)));
+    }
+    for line in chart.lines {
+        puml.push_str(&line);
+        puml.push('\n');
+    }
+    puml.push_str("@enduml\n");
+    puml
+}
+
+struct Chart<'a> {
+    options: &'a MscOptions,
+    /// Participants in order of appearance, the attacker first
+    participants: Vec<String>,
+    lines: Vec<String>,
+    /// Steps already shown
+    shown: HashSet<Vec<String>>,
+}
+impl Chart<'_> {
+    /// Shows the steps leading to the root of a tree, then the input rule its
+    /// conclusion comes from
+    fn step(&mut self, tree: &DerivationTree) {
+        // The conclusion is kept from the rule resolved upon one of its premises
+        let mut rule = tree;
+        let mut inputs = vec![];
+        while let [a, b] = rule.subtrees() {
+            let (kept, input) = match a.selection() {
+                Some(Selection::Conclusion(_)) => (b, a),
+                _ => (a, b),
+            };
+            inputs.push(input);
+            rule = kept;
+        }
+        for input in inputs.into_iter().rev() {
+            self.step(input)
+        }
+        self.fire(
+            rule.root(),
+            &tree.root().conclusion,
+            tree.root().premises.is_empty(),
+        );
+    }
+
+    /// Shows an input rule yielding an instance of its conclusion
+    fn fire(&mut self, rule: &Rule<String>, conclusion: &Atom<String>, fact: bool) {
+        let options = self.options;
+        let mut bindings = Substitution::new();
+        matches(&rule.conclusion, conclusion, &mut bindings);
+        let sent = options.message(conclusion);
+        let lines = match options.role_of(rule) {
+            Some(role) if rule.premises.is_empty() => vec![arrow(role, ATTACKER, &sent)],
+            None if rule.premises.is_empty() => {
+                vec![format!("note over {ATTACKER} : knows {sent}")]
+            }
+            role if role.is_some() || rule.premises.iter().any(has_constants) => {
+                let role = role.unwrap_or(PROTOCOL);
+                let mut lines: Vec<_> = rule
+                    .premises
+                    .iter()
+                    .map(|p| arrow(ATTACKER, role, &options.message(&p.apply(&bindings))))
+                    .collect();
+                lines.push(arrow(role, ATTACKER, &sent));
+                lines
+            }
+            // Computations of the attacker are shown once they yield a fact
+            _ if fact => vec![arrow(ATTACKER, ATTACKER, &sent)],
+            _ => vec![],
+        };
+        if lines.is_empty() || !self.shown.insert(lines.clone()) {
+            return;
+        }
+        for participant in [options.role_of(rule), Some(PROTOCOL)] {
+            let Some(participant) = participant else {
+                continue;
+            };
+            let used = lines.iter().any(|l| l.contains(&quoted(participant)));
+            if used && !self.participants.iter().any(|p| p == participant) {
+                self.participants.push(participant.to_string())
+            }
+        }
+        self.lines.extend(lines)
+    }
+}
+
+fn has_constants(atom: &Atom<String>) -> bool {
+    atom.parameters.iter().any(|t| !t.constants().is_empty())
+}
+
+/// Binds the variables of `pattern` so that it matches `term`, returning whether it does
+fn matches(
+    pattern: &Atom<String>,
+    atom: &Atom<String>,
+    bindings: &mut Substitution<String>,
+) -> bool {
+    fn matches_term(
+        pattern: &Term<String>,
+        term: &Term<String>,
+        bindings: &mut Substitution<String>,
+    ) -> bool {
+        match (pattern, term) {
+            (Term::Variable { symbol }, _) => match bindings.get(symbol) {
+                Some(bound) => bound == term,
+                None => {
+                    bindings.bind(symbol.clone(), term.clone());
+                    true
+                }
+            },
+            (
+                Term::Function { symbol, parameters },
+                Term::Function {
+                    symbol: other,
+                    parameters: others,
+                },
+            ) => {
+                symbol == other
+                    && parameters.len() == others.len()
+                    && parameters
+                        .iter()
+                        .zip(others)
+                        .all(|(p, t)| matches_term(p, t, bindings))
+            }
+            _ => false,
+        }
+    }
+    pattern.symbol == atom.symbol
+        && pattern.parameters.len() == atom.parameters.len()
+        && pattern
+            .parameters
+            .iter()
+            .zip(&atom.parameters)
+            .all(|(p, t)| matches_term(p, t, bindings))
+}
+
+fn arrow(from: &str, to: &str, message: &str) -> String {
+    format!("{} -> {} : {message}", quoted(from), quoted(to))
+}
+
+/// Names PlantUML does not read as identifiers are quoted
+fn quoted(name: &str) -> String {
+    if name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "'"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_plantuml, MscOptions};
+    use crate::{Parser, Sniffer};
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn msc_test() {
+        let a = "att(aenc(pair(na, pub(ska)), pub(ski))).\n\
+                 att(aenc(pair(na, Y), pub(ska))) => att(aenc(Y, pub(ski))).";
+        let b = "att(aenc(pair(X, pub(ska)), pub(skb))) => att(aenc(pair(X, secret), pub(ska))).";
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "att(X) /\\ att(Y) => att(pair(X, Y)).\n\
+                 att(pair(X, Y)) => att(X).\natt(pair(X, Y)) => att(Y).\n\
+                 att(X) /\\ att(Y) => att(aenc(X, Y)).\n\
+                 att(aenc(X, pub(Y))) /\\ att(Y) => att(X).\n\
+                 att(pub(ska)). att(pub(skb)). att(ski).",
+            )
+            .unwrap();
+        sniffer.assert(a).unwrap();
+        sniffer.assert(b).unwrap();
+        let goal = Parser::parse_query(Tokens::new("att(secret).")).unwrap();
+        let tree = sniffer.prove(&goal).unwrap();
+
+        // Without roles, steps with constants in their premises are done by the protocol
+        let chart = to_plantuml(&tree, &MscOptions::default());
+        assert!(chart.starts_with("@startuml\nparticipant Attacker\nparticipant Protocol\n"));
+        assert!(chart.ends_with("note over Attacker : learns secret\n@enduml\n"));
+        assert!(chart.contains("note over Attacker : knows ski\n"));
+
+        let rules = |source: &str| Parser::parse_rules(Tokens::new(source)).unwrap();
+        let options = MscOptions::default()
+            .role("A", rules(a))
+            .role("B", rules(b));
+        let chart = to_plantuml(&tree, &options);
+        let lines: Vec<_> = chart.lines().collect();
+        assert_eq!(
+            &lines[..3],
+            ["@startuml", "participant Attacker", "participant A"]
+        );
+        assert!(lines.contains(&"participant B") && !lines.contains(&"participant Protocol"));
+        let position = |line: &str| lines.iter().position(|l| *l == line).unwrap();
+        // The attacker decrypts the nonce of A, forwards it to B and the answer back to A
+        let sent = position("A -> Attacker : aenc(pair(na, pub(ska)), pub(ski))");
+        let decrypted = position("Attacker -> Attacker : pair(na, pub(ska))");
+        let forwarded = position("Attacker -> B : aenc(pair(na, pub(ska)), pub(skb))");
+        let answered = position("B -> Attacker : aenc(pair(na, secret), pub(ska))");
+        let replayed = position("Attacker -> A : aenc(pair(na, secret), pub(ska))");
+        let leaked = position("A -> Attacker : aenc(secret, pub(ski))");
+        assert!(sent < decrypted && decrypted < forwarded && forwarded < answered);
+        assert!(answered < replayed && replayed < leaked);
+        assert_eq!(lines.iter().filter(|l| l.contains("knows ski")).count(), 1);
+    }
+}
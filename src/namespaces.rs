@@ -0,0 +1,126 @@
+//! Namespaces module
+//! Qualification of predicate names, so that rule bases written separately can be
+//! combined without collisions. Predicates declared in a `module crypto { ... }`
+//! block are named `crypto.<name>`, and may be referred to from anywhere with their
+//! qualified name (`crypto.enc(X)`).
+//!
+//! Inside a block, unqualified predicates belong to the module. `use <name>.` makes
+//! the last segment of a name refer to it for the rest of the enclosing block (or
+//! source), which is also how predicates shared by every module (`use att.`) are
+//! reached from inside a module. Only predicate names are qualified, function
+//! symbols are left as is
+
+use crate::ast::{Atom, Defeasible, Mode, Rule, Statement};
+use crate::hashing::HashMap;
+
+#[derive(Debug, Default)]
+struct Scope {
+    /// Qualified name of the module, none at the top level
+    module: Option<String>,
+    /// Imported names, by their last segment
+    imports: HashMap<String, String>,
+}
+
+/// The module blocks enclosing the statement being parsed
+#[derive(Debug)]
+pub(crate) struct Namespaces {
+    scopes: Vec<Scope>,
+}
+impl Default for Namespaces {
+    fn default() -> Self {
+        Namespaces {
+            scopes: vec![Scope::default()],
+        }
+    }
+}
+impl Namespaces {
+    /// Enters a module block
+    pub fn enter(&mut self, name: &str) {
+        let module = match self.module() {
+            Some(parent) => format!("{parent}.{name}"),
+            None => name.to_string(),
+        };
+        self.scopes.push(Scope {
+            module: Some(module),
+            imports: HashMap::default(),
+        })
+    }
+
+    /// Exits the innermost module block, returning `false` at the top level
+    pub fn exit(&mut self) -> bool {
+        self.scopes.len() > 1 && self.scopes.pop().is_some()
+    }
+
+    /// Whether a module block is still open
+    pub fn is_nested(&self) -> bool {
+        self.scopes.len() > 1
+    }
+
+    pub fn import(&mut self, name: String) {
+        let last = name.rsplit('.').next().unwrap_or(&name).to_string();
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.imports.insert(last, name);
+        }
+    }
+
+    fn module(&self) -> Option<&str> {
+        self.scopes.last().and_then(|s| s.module.as_deref())
+    }
+
+    /// Returns the qualified name of a predicate
+    fn resolve(&self, symbol: &str) -> Option<String> {
+        // Operator symbols and qualified names are left as is
+        if symbol.contains('.') || !symbol.starts_with(char::is_alphabetic) {
+            return None;
+        }
+        if let Some(import) = self.scopes.iter().rev().find_map(|s| s.imports.get(symbol)) {
+            return Some(import.clone());
+        }
+        self.module().map(|module| format!("{module}.{symbol}"))
+    }
+
+    /// Qualifies the predicates of a statement
+    pub fn qualify(&self, statement: &mut Statement<String>) {
+        if let Statement::Output(predicate) | Statement::Mode(Mode { predicate, .. }) = statement {
+            if let Some(symbol) = self.resolve(predicate) {
+                *predicate = symbol
+            }
+            return;
+        }
+        let atoms: Vec<&mut Atom<String>> = match statement {
+            Statement::Rule(
+                Rule {
+                    premises,
+                    conclusion,
+                },
+                _,
+            )
+            | Statement::Next(Rule {
+                premises,
+                conclusion,
+            })
+            | Statement::Defeasible(Defeasible {
+                rule:
+                    Rule {
+                        premises,
+                        conclusion,
+                    },
+                ..
+            }) => premises.iter_mut().chain([conclusion]).collect(),
+            Statement::Expect(atom) | Statement::ExpectNot(atom) | Statement::Goal(atom) => {
+                vec![atom]
+            }
+            Statement::Constraint(atoms) => atoms.iter_mut().collect(),
+            Statement::Operator(_)
+            | Statement::Macro(_)
+            | Statement::Horizon(_)
+            | Statement::Output(_)
+            | Statement::Mode(_) => vec![],
+        };
+        for atom in atoms {
+            if let Some(symbol) = self.resolve(&atom.symbol) {
+                atom.symbol = symbol
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! Operators module
+//! User-defined infix operators, declared in `.pif` files with `op <precedence> <type> <symbol>.`
+//! An application of an operator `a ~> b` stands for the term (or atom) `'~>'(a, b)`,
+//! operators only change how terms are read and printed
+
+use crate::ast::{Atom, Rule, Term};
+use crate::fmt::Rendering;
+use crate::hashing::HashMap;
+use std::fmt::{Display, Formatter, Result};
+
+/// Highest precedence of an operator, which is also the precedence at which atoms
+/// and arguments are parsed
+pub const MAX_PRECEDENCE: u16 = 1200;
+
+/// Associativity of an operator, named after the Prolog operator types: `x` stands
+/// for an argument of strictly lower precedence, `y` for an argument of lower or
+/// equal precedence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OperatorKind {
+    /// Non associative, `a ~> b ~> c` is rejected
+    Xfx,
+    /// Right associative, `a ~> b ~> c` reads as `a ~> (b ~> c)`
+    Xfy,
+    /// Left associative, `a ~> b ~> c` reads as `(a ~> b) ~> c`
+    Yfx,
+}
+impl OperatorKind {
+    pub fn from_name(name: &str) -> Option<OperatorKind> {
+        match name {
+            "xfx" => Some(OperatorKind::Xfx),
+            "xfy" => Some(OperatorKind::Xfy),
+            "yfx" => Some(OperatorKind::Yfx),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OperatorKind::Xfx => "xfx",
+            OperatorKind::Xfy => "xfy",
+            OperatorKind::Yfx => "yfx",
+        }
+    }
+}
+
+/// An infix operator declaration
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Operator {
+    pub precedence: u16,
+    pub kind: OperatorKind,
+    pub symbol: String,
+}
+impl Operator {
+    /// Maximum precedence of the left and right arguments of this operator
+    pub fn argument_precedences(&self) -> (u16, u16) {
+        let p = self.precedence;
+        match self.kind {
+            OperatorKind::Xfx => (p - 1, p - 1),
+            OperatorKind::Xfy => (p - 1, p),
+            OperatorKind::Yfx => (p, p - 1),
+        }
+    }
+}
+impl Display for Operator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(
+            f,
+            "op {} {} {}",
+            self.precedence,
+            self.kind.name(),
+            self.symbol
+        )
+    }
+}
+
+/// Declared operators, by symbol. Declaring an operator again replaces it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OperatorTable {
+    operators: HashMap<String, Operator>,
+}
+impl OperatorTable {
+    pub fn declare(&mut self, operator: Operator) {
+        self.operators.insert(operator.symbol.clone(), operator);
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&Operator> {
+        self.operators.get(symbol)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operators.is_empty()
+    }
+
+    /// Displays a rule, writing applications of declared operators infix
+    pub fn display<'a>(&'a self, rule: &Rule<String>) -> impl Display + 'a {
+        RuleDisplay {
+            operators: self,
+            rule: rule.displayed(),
+        }
+    }
+
+    /// Displays an atom like `display`, writing its symbols with the given rendering
+    pub(crate) fn display_atom<'a>(
+        &'a self,
+        atom: &'a Atom<String>,
+        rendering: &'a Rendering,
+    ) -> impl Display + 'a {
+        AtomDisplay {
+            operators: self,
+            atom,
+            rendering,
+        }
+    }
+
+    /// Returns the operator applied at the root of the given term if any, that is
+    /// if the term is a binary application of a declared operator
+    fn infix(&self, symbol: &str, parameters: &[Term<String>]) -> Option<&Operator> {
+        self.get(symbol).filter(|_| parameters.len() == 2)
+    }
+
+    fn write_term(
+        &self,
+        f: &mut Formatter<'_>,
+        term: &Term<String>,
+        max: u16,
+        rendering: &Rendering,
+        depth: usize,
+    ) -> Result {
+        if rendering.elides(depth) {
+            return write!(f, "...");
+        }
+        match term {
+            Term::Variable { symbol } => rendering.write_variable(f, symbol),
+            Term::Function { symbol, parameters } => {
+                self.write_application(f, symbol, parameters, max, rendering, depth)
+            }
+        }
+    }
+
+    fn write_application(
+        &self,
+        f: &mut Formatter<'_>,
+        symbol: &str,
+        parameters: &[Term<String>],
+        max: u16,
+        rendering: &Rendering,
+        depth: usize,
+    ) -> Result {
+        if let Some(operator) = self.infix(symbol, parameters) {
+            let parenthesized = operator.precedence > max;
+            let (left, right) = operator.argument_precedences();
+            if parenthesized {
+                write!(f, "(")?;
+            }
+            self.write_term(f, &parameters[0], left, rendering, depth + 1)?;
+            write!(f, " {symbol} ")?;
+            self.write_term(f, &parameters[1], right, rendering, depth + 1)?;
+            if parenthesized {
+                write!(f, ")")?;
+            }
+            return Ok(());
+        }
+
+        // Atoms are at depth 0, their parameters at depth 1
+        if depth == 0 {
+            rendering.write_predicate(f, symbol, !parameters.is_empty())?;
+        } else if parameters.is_empty() {
+            rendering.write_constant(f, symbol)?;
+        } else {
+            rendering.write_function(f, symbol)?;
+        }
+        if !parameters.is_empty() {
+            write!(f, "(")?;
+            for (i, parameter) in parameters.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                self.write_term(f, parameter, MAX_PRECEDENCE, rendering, depth + 1)?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+
+    fn write_atom(
+        &self,
+        f: &mut Formatter<'_>,
+        atom: &Atom<String>,
+        rendering: &Rendering,
+    ) -> Result {
+        self.write_application(
+            f,
+            &atom.symbol,
+            &atom.parameters,
+            MAX_PRECEDENCE,
+            rendering,
+            0,
+        )
+    }
+}
+
+struct RuleDisplay<'a> {
+    operators: &'a OperatorTable,
+    rule: Rule<String>,
+}
+impl Display for RuleDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        for (i, premise) in self.rule.premises.iter().enumerate() {
+            if i > 0 {
+                write!(f, " /\\ ")?;
+            }
+            self.operators
+                .write_atom(f, premise, &Rendering::default())?;
+        }
+        if !self.rule.premises.is_empty() {
+            write!(f, " => ")?;
+        }
+        self.operators
+            .write_atom(f, &self.rule.conclusion, &Rendering::default())
+    }
+}
+
+struct AtomDisplay<'a> {
+    operators: &'a OperatorTable,
+    atom: &'a Atom<String>,
+    rendering: &'a Rendering,
+}
+impl Display for AtomDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        self.operators.write_atom(f, self.atom, self.rendering)
+    }
+}
@@ -0,0 +1,175 @@
+//! Output module
+//! Predicates whose facts are handed to user code as they are added to the rule
+//! set, for relations computed for downstream consumption.
+//!
+//! Predicates are designated by `output <predicate>.` directives, or by
+//! registering a callback with `Sniffer::on_output`. Callbacks are called once
+//! for every fact of their predicate, whatever its arity: first for the facts
+//! already known when they are registered, then for each fact when saturation or
+//! an assertion adds it. Facts are not retracted when the rules they come from are
+//! removed, and derived facts come with variables when they have some
+use crate::ast::{Atom, InnerRule};
+use crate::fingerprint::RunFingerprint;
+use crate::hashing::HashMap;
+use crate::identifiers::Identifier;
+use crate::journal::json_string;
+use crate::provenance::Provenance;
+use crate::Sniffer;
+use std::sync::Arc;
+
+/// A callback receiving facts of an output predicate
+pub type OutputCallback = Arc<dyn Fn(&Atom<String>) + Send + Sync>;
+
+/// Output predicates, in order of designation, along with their callbacks
+#[derive(Clone, Default)]
+pub(crate) struct Outputs {
+    predicates: Vec<Identifier>,
+    callbacks: HashMap<Identifier, Vec<OutputCallback>>,
+}
+impl Outputs {
+    pub fn designate(&mut self, predicate: Identifier) {
+        if !self.predicates.contains(&predicate) {
+            self.predicates.push(predicate)
+        }
+    }
+}
+
+/// Formats a fact as a CSV record of its arguments, quoting those holding commas,
+/// quotes or line breaks
+pub fn to_csv(fact: &Atom<String>) -> String {
+    fact.parameters
+        .iter()
+        .map(|parameter| {
+            let field = parameter.to_string();
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Formats a fact as a JSON object holding the array of its arguments, as strings,
+/// and the fingerprint of the run deriving it, see the `fingerprint` module
+pub fn to_json(fact: &Atom<String>, fingerprint: &RunFingerprint) -> String {
+    let fields: Vec<_> = fact
+        .parameters
+        .iter()
+        .map(|parameter| json_string(&parameter.to_string()))
+        .collect();
+    format!(
+        "{{\"arguments\":[{}],\"fingerprint\":{}}}",
+        fields.join(","),
+        json_string(&fingerprint.to_string())
+    )
+}
+
+impl<P: Provenance> Sniffer<P> {
+    /// Designates a predicate as an output, calling the callback on every one of
+    /// its facts, see the `output` module
+    pub fn on_output(
+        &mut self,
+        predicate: &str,
+        callback: impl Fn(&Atom<String>) + Send + Sync + 'static,
+    ) {
+        let symbol = self.id_server.register_function(predicate);
+        self.outputs.designate(symbol);
+        let known: Vec<InnerRule> = self
+            .all_rules()
+            .filter(|r| r.premises.is_empty() && r.conclusion.symbol == symbol)
+            .collect();
+        for fact in known {
+            callback(&fact.conclusion.to_string(&self.id_server))
+        }
+        self.outputs
+            .callbacks
+            .entry(symbol)
+            .or_default()
+            .push(Arc::new(callback))
+    }
+
+    /// Output predicates, in order of designation
+    pub fn outputs(&self) -> Vec<String> {
+        self.outputs
+            .predicates
+            .iter()
+            .filter_map(|p| self.id_server.name_of(p))
+            .collect()
+    }
+
+    /// Hands a rule just added to the rule set to the callbacks of its predicate,
+    /// if it is a fact
+    pub(crate) fn output(&self, rule: &InnerRule) {
+        if !rule.premises.is_empty() {
+            return;
+        }
+        let Some(callbacks) = self.outputs.callbacks.get(&rule.conclusion.symbol) else {
+            return;
+        };
+        let fact = rule.conclusion.to_string(&self.id_server);
+        for callback in callbacks {
+            callback(&fact)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_csv, to_json};
+    use crate::{Parser, Sniffer};
+    use logos_nom_bridge::Tokens;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn output_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "output path.\nedge(a, b). edge(b, c).\n\
+                 edge(X, Y) => path(X, Y).\npath(X, Y) /\\ edge(Y, Z) => path(X, Z).",
+            )
+            .unwrap();
+        assert_eq!(sniffer.outputs(), vec!["path"]);
+
+        let facts = Arc::new(Mutex::new(vec![]));
+        let sink = facts.clone();
+        sniffer.on_output("path", move |fact| {
+            sink.lock().unwrap().push(fact.to_string())
+        });
+        sniffer.saturate_all();
+        sniffer.assert("edge(c, d).").unwrap();
+        sniffer.saturate_all();
+        let mut facts = facts.lock().unwrap().clone();
+        facts.sort();
+        assert_eq!(
+            facts,
+            vec![
+                "path(a, b)",
+                "path(a, c)",
+                "path(a, d)",
+                "path(b, c)",
+                "path(b, d)",
+                "path(c, d)"
+            ]
+        );
+
+        // Facts already known are output on registration
+        let count = Arc::new(Mutex::new(0));
+        let counter = count.clone();
+        sniffer.on_output("edge", move |_| *counter.lock().unwrap() += 1);
+        assert_eq!(*count.lock().unwrap(), 3);
+        assert_eq!(sniffer.outputs(), vec!["path", "edge"]);
+
+        let fact = Parser::parse_query(Tokens::new("p(a, f(b, c), 'say \\'hi\\'').")).unwrap();
+        assert_eq!(to_csv(&fact), "a,\"f(b, c)\",'say \\'hi\\''");
+        let fingerprint = sniffer.fingerprint();
+        assert_eq!(
+            to_json(&fact, &fingerprint),
+            format!(
+                "{{\"arguments\":[\"a\",\"f(b, c)\",\"'say \\\\'hi\\\\''\"],\"fingerprint\":\"{fingerprint}\"}}"
+            )
+        );
+    }
+}
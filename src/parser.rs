@@ -4,34 +4,616 @@
 
 use crate::ast::*;
 use crate::lexer::Lexeme;
+use crate::macros::{Macro, MacroTable, MAX_EXPANSION_DEPTH};
+use crate::namespaces::Namespaces;
+use crate::operators::{Operator, OperatorKind, OperatorTable, MAX_PRECEDENCE};
 use logos_nom_bridge::Tokens;
 use nom::branch::alt;
-use nom::multi::{many0, separated_list0};
-use nom::sequence::{delimited, pair, terminated};
+use nom::combinator::{map, map_opt, opt, value, verify};
+use nom::multi::{separated_list0, separated_list1};
+use nom::sequence::{delimited, pair, preceded, terminated, tuple};
+use std::ops::Range;
+
+/// Gives a distinct name to every occurrence of `_` in a statement, so that each
+/// stands for a fresh variable. Names start with `_`, which user variables cannot
+fn name_wildcards(statement: &mut Statement<String>) {
+    fn name_term(term: &mut Term<String>, count: &mut usize) {
+        match term {
+            Term::Variable { symbol } if symbol == "_" => {
+                *symbol = format!("_{count}");
+                *count += 1;
+            }
+            Term::Variable { .. } => (),
+            Term::Function { parameters, .. } => {
+                parameters.iter_mut().for_each(|t| name_term(t, count))
+            }
+        }
+    }
+    let atoms: Vec<&mut Atom<String>> = match statement {
+        Statement::Rule(
+            Rule {
+                premises,
+                conclusion,
+            },
+            _,
+        ) => premises.iter_mut().chain([conclusion]).collect(),
+        Statement::Expect(atom) | Statement::ExpectNot(atom) | Statement::Goal(atom) => {
+            vec![atom]
+        }
+        Statement::Constraint(atoms) => atoms.iter_mut().collect(),
+        Statement::Defeasible(Defeasible {
+            rule: Rule {
+                premises,
+                conclusion,
+            },
+            ..
+        }) => premises.iter_mut().chain([conclusion]).collect(),
+        Statement::Next(Rule {
+            premises,
+            conclusion,
+        }) => premises.iter_mut().chain([conclusion]).collect(),
+        Statement::Operator(_)
+        | Statement::Macro(_)
+        | Statement::Horizon(_)
+        | Statement::Output(_)
+        | Statement::Mode(_) => vec![],
+    };
+    let mut count = 0;
+    for atom in atoms {
+        atom.parameters
+            .iter_mut()
+            .for_each(|t| name_term(t, &mut count))
+    }
+}
+
+/// Kinds of parsing failures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParserErrorKind {
+    /// Characters of the source do not form any valid token
+    UnrecognizedToken,
+    /// A valid token appears where the grammar does not allow it
+    UnexpectedToken,
+    /// The source ends in the middle of a statement
+    UnexpectedEnd,
+    /// Macros expand into each other too deeply, most likely recursively
+    RecursiveMacro,
+}
 
 #[derive(Debug)]
-pub struct ParserError;
+pub struct ParserError {
+    /// Byte offset in the source at which parsing failed
+    pub offset: usize,
+    pub kind: ParserErrorKind,
+}
+impl ParserErrorKind {
+    pub fn description(&self) -> &'static str {
+        match self {
+            ParserErrorKind::UnrecognizedToken => "unrecognized token",
+            ParserErrorKind::UnexpectedToken => "unexpected token",
+            ParserErrorKind::UnexpectedEnd => "unexpected end of input",
+            ParserErrorKind::RecursiveMacro => "recursive macro expansion",
+        }
+    }
+}
+impl std::fmt::Display for ParserError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.kind.description(), self.offset)
+    }
+}
+impl std::error::Error for ParserError {}
 
 type Input<'source> = Tokens<'source, Lexeme>;
 
+/// A parsed statement along with the byte span it covers in the source
+pub type SpannedStatement = (Statement<String>, Range<usize>);
+
+impl ParserError {
+    /// Builds an error from the input remaining where parsing failed
+    fn at(source_len: usize, rest: &Input) -> ParserError {
+        let kind = match rest.peek() {
+            None => ParserErrorKind::UnexpectedEnd,
+            Some((Lexeme::UnrecognizedLexeme, _)) => ParserErrorKind::UnrecognizedToken,
+            Some(_) => ParserErrorKind::UnexpectedToken,
+        };
+        ParserError {
+            offset: source_len - rest.len(),
+            kind,
+        }
+    }
+
+    /// Builds an error from the remaining input of a failed nom parser
+    fn from_nom(source_len: usize, error: nom::Err<nom::error::Error<Input>>) -> ParserError {
+        match error {
+            nom::Err::Error(e) | nom::Err::Failure(e) => ParserError::at(source_len, &e.input),
+            nom::Err::Incomplete(_) => ParserError {
+                offset: source_len,
+                kind: ParserErrorKind::UnexpectedEnd,
+            },
+        }
+    }
+}
+
+/// Conventions telling variables apart from constants in the surface syntax
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NamingConvention {
+    /// Capitalized names are variables (`X`), as in Prolog
+    #[default]
+    Capitalized,
+    /// Variables are written with a `?` sigil (`?x`), any other name is a constant
+    Sigil,
+}
+
+/// Options of the parser
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ParserOptions {
+    pub convention: NamingConvention,
+    /// Operators known before parsing starts. Operators declared by the parsed
+    /// source are added to these for the rest of the source
+    pub operators: OperatorTable,
+    /// Macros known before parsing starts, extended like operators
+    pub macros: MacroTable,
+}
+
 pub struct Parser;
 impl Parser {
     pub fn parse_query(input: Input) -> Result<Atom<String>, ParserError> {
-        match terminated(Self::parse_atom, Lexeme::Stop)(input) {
+        Self::parse_query_with(input, &ParserOptions::default())
+    }
+    pub fn parse_query_with(
+        input: Input,
+        options: &ParserOptions,
+    ) -> Result<Atom<String>, ParserError> {
+        let source_len = input.len();
+        match terminated(|i| Self::parse_atom(options, i), Lexeme::Stop)(input) {
             Ok((_, atom)) => Ok(atom),
-            Err(_) => Err(ParserError),
+            Err(e) => Err(ParserError::from_nom(source_len, e)),
         }
     }
+    /// Parses rules, ignoring any other statement
     pub fn parse_rules(input: Input) -> Result<Vec<Rule<String>>, ParserError> {
-        match many0(Self::parse_rule)(input) {
-            Ok((_, rules)) => Ok(rules),
-            Err(_) => Err(ParserError),
+        Self::parse_rules_with(input, &ParserOptions::default())
+    }
+    pub fn parse_rules_with(
+        input: Input,
+        options: &ParserOptions,
+    ) -> Result<Vec<Rule<String>>, ParserError> {
+        Ok(Self::parse_statements_with(input, options)?
+            .into_iter()
+            .filter_map(|s| match s {
+                Statement::Rule(rule, _) => Some(rule),
+                _ => None,
+            })
+            .collect())
+    }
+    pub fn parse_statements(input: Input) -> Result<Vec<Statement<String>>, ParserError> {
+        Self::parse_statements_with(input, &ParserOptions::default())
+    }
+    pub fn parse_statements_with(
+        input: Input,
+        options: &ParserOptions,
+    ) -> Result<Vec<Statement<String>>, ParserError> {
+        Ok(Self::parse_spanned_statements_with(input, options)?
+            .into_iter()
+            .map(|(statement, _)| statement)
+            .collect())
+    }
+    /// Parses statements along with the byte span each one covers in the source.
+    /// Spans may include the whitespace and comments preceding a statement
+    pub fn parse_spanned_statements(input: Input) -> Result<Vec<SpannedStatement>, ParserError> {
+        Self::parse_spanned_statements_with(input, &ParserOptions::default())
+    }
+    pub fn parse_spanned_statements_with(
+        input: Input,
+        options: &ParserOptions,
+    ) -> Result<Vec<SpannedStatement>, ParserError> {
+        Self::parse_expanded_statements(input, &mut options.clone(), 0)
+    }
+
+    /// Parses statements, expanding macros at the given nesting depth. Statements
+    /// resulting from an expansion span the statement using the macro
+    fn parse_expanded_statements(
+        mut input: Input,
+        options: &mut ParserOptions,
+        depth: usize,
+    ) -> Result<Vec<SpannedStatement>, ParserError> {
+        let source_len = input.len();
+        let mut statements = vec![];
+        let mut namespaces = Namespaces::default();
+        while input.peek().is_some() {
+            let start = source_len - input.len();
+            if let Ok((rest, name)) = Self::parse_module_header(input.clone()) {
+                namespaces.enter(&name);
+                input = rest;
+                continue;
+            }
+            if matches!(input.peek(), Some((Lexeme::ClosingBrace, _))) && namespaces.exit() {
+                input = input.advance();
+                continue;
+            }
+            if let Ok((rest, name)) = Self::parse_import(input.clone()) {
+                namespaces.import(name);
+                input = rest;
+                continue;
+            }
+
+            if let Some((rest, expansion)) = Self::expand_macro(options, input.clone()) {
+                let error = |kind| ParserError {
+                    offset: start,
+                    kind,
+                };
+                if depth >= MAX_EXPANSION_DEPTH {
+                    return Err(error(ParserErrorKind::RecursiveMacro));
+                }
+                let span = start..source_len - rest.len();
+                let expanded =
+                    Self::parse_expanded_statements(Tokens::new(&expansion), options, depth + 1)
+                        .map_err(|e| error(e.kind))?;
+                for (mut statement, _) in expanded {
+                    namespaces.qualify(&mut statement);
+                    statements.push((statement, span.clone()));
+                }
+                input = rest;
+                continue;
+            }
+
+            match Self::parse_statement(options, input) {
+                Ok((rest, mut statement)) => {
+                    namespaces.qualify(&mut statement);
+                    // Operators and macros apply from their declaration onwards
+                    match &statement {
+                        Statement::Operator(operator) => {
+                            options.operators.declare(operator.clone())
+                        }
+                        Statement::Macro(definition) => options.macros.define(definition.clone()),
+                        _ => (),
+                    }
+                    statements.push((statement, start..source_len - rest.len()));
+                    input = rest;
+                }
+                Err(e) => return Err(ParserError::from_nom(source_len, e)),
+            }
+        }
+        if namespaces.is_nested() {
+            return Err(ParserError {
+                offset: source_len,
+                kind: ParserErrorKind::UnexpectedEnd,
+            });
+        }
+        Ok(statements)
+    }
+
+    /// Parses `module <name> {`, opening a module block
+    fn parse_module_header(input: Input) -> nom::IResult<Input, String> {
+        delimited(
+            verify(Self::parse_constant, |keyword: &String| keyword == "module"),
+            Self::parse_constant,
+            Lexeme::OpeningBrace,
+        )(input)
+    }
+
+    /// Parses `use <name>.`
+    fn parse_import(input: Input) -> nom::IResult<Input, String> {
+        delimited(
+            verify(Self::parse_constant, |keyword: &String| keyword == "use"),
+            Self::parse_constant,
+            Lexeme::Stop,
+        )(input)
+    }
+
+    fn parse_statement<'a>(
+        options: &ParserOptions,
+        input: Input<'a>,
+    ) -> nom::IResult<Input<'a>, Statement<String>> {
+        let atom = |i| Self::parse_atom(options, i);
+        let (rest, mut statement) = alt((
+            map(
+                delimited(Lexeme::Expect, atom, Lexeme::Stop),
+                Statement::Expect,
+            ),
+            map(
+                delimited(Lexeme::ExpectNot, atom, Lexeme::Stop),
+                Statement::ExpectNot,
+            ),
+            map(delimited(Lexeme::Goal, atom, Lexeme::Stop), Statement::Goal),
+            map(
+                delimited(Lexeme::Horizon, Self::parse_natural, Lexeme::Stop),
+                Statement::Horizon,
+            ),
+            map(Self::parse_operator_declaration, Statement::Operator),
+            map(
+                |i| Self::parse_macro_definition(options, i),
+                Statement::Macro,
+            ),
+            map(
+                delimited(
+                    verify(Self::parse_constant, |keyword: &String| keyword == "output"),
+                    Self::parse_constant,
+                    Lexeme::Stop,
+                ),
+                Statement::Output,
+            ),
+            map(Self::parse_mode, Statement::Mode),
+            map(
+                |i| Self::parse_constraint(options, i),
+                Statement::Constraint,
+            ),
+            map(
+                preceded(
+                    verify(Self::parse_constant, |keyword: &String| keyword == "next"),
+                    |i| Self::parse_rule(options, i),
+                ),
+                Statement::Next,
+            ),
+            map(
+                |i| Self::parse_defeasible(options, i),
+                Statement::Defeasible,
+            ),
+            map(
+                tuple((
+                    opt(Self::parse_attributes),
+                    opt(Self::parse_probability),
+                    |i| Self::parse_rule(options, i),
+                )),
+                |(attributes, probability, rule)| {
+                    let mut attributes = attributes.unwrap_or_default();
+                    attributes.probability = probability;
+                    Statement::Rule(rule, attributes)
+                },
+            ),
+        ))(input)?;
+        name_wildcards(&mut statement);
+        Ok((rest, statement))
+    }
+
+    /// Parses `#define <name>(<parameters>) <statement>.`, keeping the tokens of the
+    /// body as written
+    fn parse_macro_definition<'a>(
+        options: &ParserOptions,
+        input: Input<'a>,
+    ) -> nom::IResult<Input<'a>, Macro> {
+        let variable = match options.convention {
+            NamingConvention::Capitalized => Self::parse_variable,
+            NamingConvention::Sigil => Self::parse_sigil_variable,
+        };
+        let (mut rest, (name, parameters)) = preceded(
+            Lexeme::Define,
+            pair(
+                Self::parse_constant,
+                delimited(
+                    Lexeme::OpeningParentheses,
+                    separated_list1(Lexeme::Comma, map(variable, |v| v.symbol().clone())),
+                    Lexeme::ClosingParentheses,
+                ),
+            ),
+        )(input)?;
+        let mut body = vec![];
+        loop {
+            match rest.peek() {
+                Some((Lexeme::Stop, _)) if !body.is_empty() => break,
+                Some((Lexeme::Stop | Lexeme::UnrecognizedLexeme, _)) | None => {
+                    return Err(nom::Err::Error(nom::error::Error::new(
+                        rest,
+                        nom::error::ErrorKind::Verify,
+                    )))
+                }
+                Some((_, text)) => {
+                    body.push(text.to_string());
+                    rest = rest.advance();
+                }
+            }
         }
+        Ok((
+            rest.advance(),
+            Macro {
+                name,
+                parameters,
+                body,
+            },
+        ))
     }
-    fn parse_rule(input: Input) -> nom::IResult<Input, Rule<String>> {
-        if let Ok((rest, premisses)) = terminated(Self::parse_atoms, Lexeme::Implies)(input.clone())
+
+    /// Expands a statement `<name>(<arguments>).` using a defined macro, returning
+    /// the remaining input along with the source of the expansion
+    fn expand_macro<'a>(options: &ParserOptions, input: Input<'a>) -> Option<(Input<'a>, String)> {
+        if options.macros.is_empty() {
+            return None;
+        }
+        let (Lexeme::Constant(name), _) = input.peek()? else {
+            return None;
+        };
+        let mut rest = input.advance();
+        if !matches!(rest.peek(), Some((Lexeme::OpeningParentheses, _))) {
+            return None;
+        }
+        rest = rest.advance();
+
+        // Arguments are split on top level commas
+        let mut arguments = vec![String::new()];
+        let mut depth = 0;
+        loop {
+            let (token, text) = rest.peek()?;
+            rest = rest.advance();
+            match token {
+                Lexeme::ClosingParentheses if depth == 0 => break,
+                Lexeme::Comma if depth == 0 => {
+                    arguments.push(String::new());
+                    continue;
+                }
+                Lexeme::OpeningParentheses => depth += 1,
+                Lexeme::ClosingParentheses => depth -= 1,
+                Lexeme::Stop => return None,
+                _ => (),
+            }
+            let argument = arguments.last_mut()?;
+            argument.push_str(text);
+            argument.push(' ');
+        }
+        if !matches!(rest.peek(), Some((Lexeme::Stop, _))) || arguments.iter().any(String::is_empty)
         {
-            let (rest, conclusion) = terminated(Self::parse_atom, Lexeme::Stop)(rest)?;
+            return None;
+        }
+        let definition = options.macros.get(&name, arguments.len())?;
+        Some((rest.advance(), definition.expand(&arguments)))
+    }
+
+    /// Parses `op <precedence> <type> <symbol>.`
+    fn parse_operator_declaration(input: Input) -> nom::IResult<Input, Operator> {
+        let (rest, (precedence, kind, symbol)) = delimited(
+            verify(Self::parse_constant, |keyword: &String| keyword == "op"),
+            tuple((
+                verify(Self::parse_natural, |p| {
+                    (1..=MAX_PRECEDENCE as usize).contains(p)
+                }),
+                map_opt(Self::parse_constant, |name| OperatorKind::from_name(&name)),
+                alt((Self::parse_operator_symbol, Self::parse_constant)),
+            )),
+            Lexeme::Stop,
+        )(input)?;
+        Ok((
+            rest,
+            Operator {
+                precedence: precedence as u16,
+                kind,
+                symbol,
+            },
+        ))
+    }
+
+    /// Parses `mode <predicate>(<+|->, ...).`
+    fn parse_mode(input: Input) -> nom::IResult<Input, Mode> {
+        let (rest, (predicate, bound)) = delimited(
+            verify(Self::parse_constant, |keyword: &String| keyword == "mode"),
+            pair(
+                Self::parse_constant,
+                delimited(
+                    Lexeme::OpeningParentheses,
+                    separated_list1(
+                        Lexeme::Comma,
+                        map_opt(Self::parse_operator_symbol, |mode| match mode.as_str() {
+                            "+" => Some(true),
+                            "-" => Some(false),
+                            _ => None,
+                        }),
+                    ),
+                    Lexeme::ClosingParentheses,
+                ),
+            ),
+            Lexeme::Stop,
+        )(input)?;
+        Ok((rest, Mode { predicate, bound }))
+    }
+
+    /// Parses `constraint not (<atom> && ... && <atom>).`, atoms being joined by
+    /// `&&` or `/\`
+    fn parse_constraint<'a>(
+        options: &ParserOptions,
+        input: Input<'a>,
+    ) -> nom::IResult<Input<'a>, Vec<Atom<String>>> {
+        let keyword = |word| {
+            verify(Self::parse_constant, move |keyword: &String| {
+                keyword == word
+            })
+        };
+        let and = alt((
+            value((), Lexeme::And),
+            value(
+                (),
+                verify(Self::parse_operator_symbol, |symbol: &String| {
+                    symbol == "&&"
+                }),
+            ),
+        ));
+        delimited(
+            pair(keyword("constraint"), keyword("not")),
+            delimited(
+                Lexeme::OpeningParentheses,
+                separated_list1(and, |i| Self::parse_atom(options, i)),
+                Lexeme::ClosingParentheses,
+            ),
+            Lexeme::Stop,
+        )(input)
+    }
+
+    /// Parses `[name=value, flag, ...]`, failing on unknown attributes
+    fn parse_attributes(input: Input) -> nom::IResult<Input, Attributes> {
+        let attribute = pair(
+            Self::parse_constant,
+            opt(preceded(
+                verify(Self::parse_operator_symbol, |symbol: &String| symbol == "="),
+                Self::parse_natural,
+            )),
+        );
+        map_opt(
+            delimited(
+                Lexeme::OpeningBracket,
+                separated_list1(Lexeme::Comma, attribute),
+                Lexeme::ClosingBracket,
+            ),
+            |list| {
+                let mut attributes = Attributes::default();
+                for (name, value) in list {
+                    match (name.as_str(), value) {
+                        ("weight", Some(value)) => attributes.weight = value.try_into().ok()?,
+                        ("cost", Some(value)) => attributes.cost = Some(value.try_into().ok()?),
+                        ("priority", Some(value)) => attributes.priority = value.try_into().ok()?,
+                        ("inline", None) => attributes.inline = true,
+                        _ => return None,
+                    }
+                }
+                Some(attributes)
+            },
+        )(input)
+    }
+
+    /// Parses `[attributes] [~]<atom> <~ <atom> /\ ... /\ <atom>.`
+    fn parse_defeasible<'a>(
+        options: &ParserOptions,
+        input: Input<'a>,
+    ) -> nom::IResult<Input<'a>, Defeasible<String>> {
+        let operator = |symbol| verify(Self::parse_operator_symbol, move |s: &String| s == symbol);
+        let (rest, (attributes, negation, conclusion, _, premises, _)) = tuple((
+            opt(Self::parse_attributes),
+            opt(operator("~")),
+            |i| Self::parse_atom(options, i),
+            operator("<~"),
+            |i| Self::parse_atoms(options, i),
+            Lexeme::Stop,
+        ))(input)?;
+        Ok((
+            rest,
+            Defeasible {
+                rule: Rule {
+                    premises,
+                    conclusion,
+                },
+                negated: negation.is_some(),
+                priority: attributes.unwrap_or_default().priority,
+            },
+        ))
+    }
+
+    /// Parses `<probability> ::`, the probability of the rule that follows
+    fn parse_probability(input: Input) -> nom::IResult<Input, Probability> {
+        terminated(
+            map_opt(
+                alt((Self::parse_float, map(Self::parse_natural, |n| n as f64))),
+                Probability::new,
+            ),
+            verify(Self::parse_operator_symbol, |symbol: &String| {
+                symbol == "::"
+            }),
+        )(input)
+    }
+
+    fn parse_rule<'a>(
+        options: &ParserOptions,
+        input: Input<'a>,
+    ) -> nom::IResult<Input<'a>, Rule<String>> {
+        let atom = |i| Self::parse_atom(options, i);
+        if let Ok((rest, premisses)) =
+            terminated(|i| Self::parse_atoms(options, i), Lexeme::Implies)(input.clone())
+        {
+            let (rest, conclusion) = terminated(atom, Lexeme::Stop)(rest)?;
             Ok((
                 rest,
                 Rule {
@@ -40,7 +622,7 @@ impl Parser {
                 },
             ))
         } else {
-            let (rest, conclusion) = terminated(Self::parse_atom, Lexeme::Stop)(input)?;
+            let (rest, conclusion) = terminated(atom, Lexeme::Stop)(input)?;
             Ok((
                 rest,
                 Rule {
@@ -51,42 +633,142 @@ impl Parser {
         }
     }
 
-    fn parse_atoms(input: Input) -> nom::IResult<Input, Vec<Atom<String>>> {
-        separated_list0(Lexeme::And, Self::parse_atom)(input)
+    fn parse_atoms<'a>(
+        options: &ParserOptions,
+        input: Input<'a>,
+    ) -> nom::IResult<Input<'a>, Vec<Atom<String>>> {
+        separated_list0(Lexeme::And, |i| Self::parse_atom(options, i))(input)
     }
-    fn parse_atom(input: Input) -> nom::IResult<Input, Atom<String>> {
-        let (rest, (symbol, terms)) = pair(
-            Self::parse_constant,
+    fn parse_atom<'a>(
+        options: &ParserOptions,
+        input: Input<'a>,
+    ) -> nom::IResult<Input<'a>, Atom<String>> {
+        // Atoms may be written as an application of an infix operator
+        if !options.operators.is_empty() {
+            if let Ok((rest, (Term::Function { symbol, parameters }, precedence))) =
+                Self::parse_expression(options, MAX_PRECEDENCE, input.clone())
+            {
+                if precedence > 0 {
+                    return Self::parse_time_stamp(options, Atom { symbol, parameters }, rest);
+                }
+            }
+        }
+
+        let (rest, symbol) = Self::parse_name(options, input)?;
+        // Nullary atoms are written without parentheses, `p.`
+        let (rest, terms) = if matches!(rest.peek(), Some((Lexeme::OpeningParentheses, _))) {
             delimited(
                 Lexeme::OpeningParentheses,
-                Self::parse_terms,
+                |i| Self::parse_terms(options, i),
                 Lexeme::ClosingParentheses,
-            ),
-        )(input)?;
-        Ok((
-            rest,
+            )(rest)?
+        } else {
+            (rest, vec![])
+        };
+        Self::parse_time_stamp(
+            options,
             Atom {
                 symbol,
                 parameters: terms,
             },
-        ))
+            rest,
+        )
     }
 
-    fn parse_terms(input: Input) -> nom::IResult<Input, Vec<Term<String>>> {
-        separated_list0(Lexeme::Comma, Self::parse_term)(input)
+    /// Parses an optional `@<term>` following an atom, which stands for an additional
+    /// last parameter of the atom
+    fn parse_time_stamp<'a>(
+        options: &ParserOptions,
+        mut atom: Atom<String>,
+        input: Input<'a>,
+    ) -> nom::IResult<Input<'a>, Atom<String>> {
+        let (rest, time) = opt(preceded(
+            verify(Self::parse_operator_symbol, |s: &String| s == "@"),
+            |i| Self::parse_primary(options, i),
+        ))(input)?;
+        atom.parameters.extend(time);
+        Ok((rest, atom))
+    }
+
+    fn parse_terms<'a>(
+        options: &ParserOptions,
+        input: Input<'a>,
+    ) -> nom::IResult<Input<'a>, Vec<Term<String>>> {
+        separated_list0(Lexeme::Comma, |i| Self::parse_term(options, i))(input)
+    }
+    fn parse_term<'a>(
+        options: &ParserOptions,
+        input: Input<'a>,
+    ) -> nom::IResult<Input<'a>, Term<String>> {
+        map(
+            |i| Self::parse_expression(options, MAX_PRECEDENCE, i),
+            |(term, _)| term,
+        )(input)
     }
-    fn parse_term(input: Input) -> nom::IResult<Input, Term<String>> {
+    /// Parses a term of precedence at most `max` by precedence climbing, returning
+    /// it along with the precedence of its root operator (0 if there is none)
+    fn parse_expression<'a>(
+        options: &ParserOptions,
+        max: u16,
+        input: Input<'a>,
+    ) -> nom::IResult<Input<'a>, (Term<String>, u16)> {
+        let (mut rest, mut left) = Self::parse_primary(options, input)?;
+        let mut left_precedence = 0;
+        while let Ok((after, operator)) = Self::parse_infix(options, rest.clone()) {
+            let (left_max, right_max) = operator.argument_precedences();
+            if operator.precedence > max || left_precedence > left_max {
+                break;
+            }
+            let (after, (right, _)) = Self::parse_expression(options, right_max, after)?;
+            left = Term::Function {
+                symbol: operator.symbol.clone(),
+                parameters: vec![left, right],
+            };
+            left_precedence = operator.precedence;
+            rest = after;
+        }
+        Ok((rest, (left, left_precedence)))
+    }
+    /// Parses the symbol of a declared infix operator
+    fn parse_infix<'a, 'o>(
+        options: &'o ParserOptions,
+        input: Input<'a>,
+    ) -> nom::IResult<Input<'a>, &'o Operator> {
+        map_opt(
+            alt((Self::parse_operator_symbol, Self::parse_constant)),
+            |symbol| options.operators.get(&symbol),
+        )(input)
+    }
+    fn parse_primary<'a>(
+        options: &ParserOptions,
+        input: Input<'a>,
+    ) -> nom::IResult<Input<'a>, Term<String>> {
+        let variable = match options.convention {
+            NamingConvention::Capitalized => Self::parse_variable,
+            NamingConvention::Sigil => Self::parse_sigil_variable,
+        };
         alt((
-            Self::parse_variable,
-            Self::parse_application,
+            variable,
+            map(Lexeme::Wildcard, |_| Term::Variable {
+                symbol: String::from("_"),
+            }),
+            |i| Self::parse_application(options, i),
             Self::parse_integer,
+            delimited(
+                Lexeme::OpeningParentheses,
+                |i| Self::parse_term(options, i),
+                Lexeme::ClosingParentheses,
+            ),
         ))(input)
     }
-    fn parse_application(input: Input) -> nom::IResult<Input, Term<String>> {
-        let (rest, symbol) = Self::parse_constant(input)?;
+    fn parse_application<'a>(
+        options: &ParserOptions,
+        input: Input<'a>,
+    ) -> nom::IResult<Input<'a>, Term<String>> {
+        let (rest, symbol) = Self::parse_name(options, input)?;
         if let Ok((rest, terms)) = delimited(
             Lexeme::OpeningParentheses,
-            Self::parse_terms,
+            |i| Self::parse_terms(options, i),
             Lexeme::ClosingParentheses,
         )(rest.clone())
         {
@@ -107,17 +789,262 @@ impl Parser {
             ))
         }
     }
+    /// Parses the name of a function or predicate. Capitalized names are only
+    /// names when variables are written with a sigil
+    fn parse_name<'a>(
+        options: &ParserOptions,
+        input: Input<'a>,
+    ) -> nom::IResult<Input<'a>, String> {
+        match options.convention {
+            NamingConvention::Capitalized => Self::parse_constant(input),
+            NamingConvention::Sigil => alt((Self::parse_constant, Self::parse_capitalized))(input),
+        }
+    }
 
     logos_nom_bridge::data_variant_parser! {
         fn parse_variable(input) -> Result<Term<String>>;
         pattern = Lexeme::Variable(symbol) => Term::Variable { symbol };
     }
+    logos_nom_bridge::data_variant_parser! {
+        fn parse_sigil_variable(input) -> Result<Term<String>>;
+        pattern = Lexeme::SigilVariable(symbol) => Term::Variable { symbol };
+    }
     logos_nom_bridge::data_variant_parser! {
         fn parse_integer(input) -> Result<Term<String>>;
-        pattern = Lexeme::Integer(_i) => Term::Variable { symbol: String::from("todo") };
+        pattern = Lexeme::Integer(n) => Term::Function { symbol: n.to_string(), parameters: vec![] };
+    }
+    logos_nom_bridge::data_variant_parser! {
+        fn parse_natural(input) -> Result<usize>;
+        pattern = Lexeme::Integer(n) => n;
+    }
+    logos_nom_bridge::data_variant_parser! {
+        fn parse_float(input) -> Result<f64>;
+        pattern = Lexeme::Float(f) => f.parse().unwrap_or(f64::NAN);
     }
     logos_nom_bridge::data_variant_parser! {
         fn parse_constant(input) -> Result<String>;
         pattern = Lexeme::Constant(symbol) => symbol;
     }
+    logos_nom_bridge::data_variant_parser! {
+        fn parse_capitalized(input) -> Result<String>;
+        pattern = Lexeme::Variable(symbol) => symbol;
+    }
+    logos_nom_bridge::data_variant_parser! {
+        fn parse_operator_symbol(input) -> Result<String>;
+        pattern = Lexeme::Operator(symbol) => symbol;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_kind_test() {
+        let kind = |source| Parser::parse_rules(Tokens::new(source)).unwrap_err().kind;
+        assert_eq!(kind("att(a) att(b)."), ParserErrorKind::UnexpectedToken);
+        assert_eq!(kind("att(a). att(b"), ParserErrorKind::UnexpectedEnd);
+        assert_eq!(kind("att(a). att(%)."), ParserErrorKind::UnrecognizedToken);
+        assert_eq!(kind("att(__)."), ParserErrorKind::UnrecognizedToken);
+    }
+
+    #[test]
+    fn wildcard_test() {
+        let rules =
+            Parser::parse_rules(Tokens::new("att(pair(_, X)) /\\ att(_) => att(X).")).unwrap();
+        let variables = |atom: &Atom<String>| -> Vec<String> {
+            match &atom.parameters[0] {
+                Term::Variable { symbol } => vec![symbol.clone()],
+                Term::Function { parameters, .. } => {
+                    parameters.iter().map(|t| t.symbol().clone()).collect()
+                }
+            }
+        };
+        // Every occurrence is a distinct variable, printed back as `_`
+        assert_eq!(variables(&rules[0].premises[0]), vec!["_0", "X"]);
+        assert_eq!(variables(&rules[0].premises[1]), vec!["_1"]);
+        assert_eq!(rules[0].to_string(), "att(pair(_, X)) /\\ att(_) => att(X)");
+    }
+
+    #[test]
+    fn quoted_round_trip_test() {
+        let source = "'hello world'(X) /\\ knows('a\\'b\\\\c', 'Alice', été) => 'weird()chars'(X).";
+        let rules = Parser::parse_rules(Tokens::new(source)).unwrap();
+        assert_eq!(rules[0].premises[0].symbol, "hello world");
+        assert_eq!(rules[0].to_string() + ".", source);
+    }
+
+    #[test]
+    fn operator_test() {
+        let declarations = "op 700 xfx ~>.\nop 500 yfx +.\nop 600 xfy ::.\n";
+        let parse = |source: &str| {
+            Parser::parse_statements(Tokens::new(&format!("{declarations}{source}")))
+        };
+        let statements = parse("a + b + c ~> h(d :: e :: f).").unwrap();
+        let mut options = ParserOptions::default();
+        for statement in &statements {
+            if let Statement::Operator(operator) = statement {
+                options.operators.declare(operator.clone())
+            }
+        }
+        let Statement::Rule(rule, _) = &statements[3] else {
+            panic!("expected a rule")
+        };
+        let prefix = "'~>'('+'('+'(a, b), c), h('::'(d, '::'(e, f))))";
+        assert_eq!(rule.to_string(), prefix);
+        assert_eq!(
+            options.operators.display(rule).to_string(),
+            "a + b + c ~> h(d :: e :: f)"
+        );
+
+        // Parentheses are printed only where precedences require them
+        for source in [
+            "a + (b + c) ~> d",
+            "(a ~> b) ~> c",
+            "a + (b :: c) ~> d",
+            "x(A) /\\ A ~> B => B ~> A",
+        ] {
+            let rules = Parser::parse_rules_with(Tokens::new(&format!("{source}.")), &options);
+            assert_eq!(
+                options.operators.display(&rules.unwrap()[0]).to_string(),
+                source
+            );
+        }
+
+        assert!(parse("a ~> b ~> c.").is_err());
+        assert!(Parser::parse_rules(Tokens::new("a ~> b.")).is_err());
+    }
+
+    #[test]
+    fn macro_test() {
+        let source = "#define symmetric(P) P(Y, X) => P(X, Y).\nsymmetric(connected).\n\
+                      symmetric(f(a, b), c).\n#define edge(A, B) link(A, B).\nedge(f(a, b), c).";
+        let rules: Vec<_> = Parser::parse_rules(Tokens::new(source))
+            .unwrap()
+            .iter()
+            .map(|r| r.to_string())
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                "connected(Y, X) => connected(X, Y)",
+                "symmetric(f(a, b), c)",
+                "link(f(a, b), c)"
+            ]
+        );
+
+        let recursive = Parser::parse_statements(Tokens::new("#define l(P) l(P).\nl(a)."));
+        assert_eq!(recursive.unwrap_err().kind, ParserErrorKind::RecursiveMacro);
+    }
+
+    #[test]
+    fn module_test() {
+        let source = "module crypto {\n  use att.\n  enc(M, K) => att(senc(M, K)).\n  \
+                      module inner { key(k). }\n  crypto.inner.key(K) => key(K).\n}\n\
+                      use crypto.enc.\natt(X) => enc(X, k).\ncrypto.key(k).";
+        let rules: Vec<_> = Parser::parse_rules(Tokens::new(source))
+            .unwrap()
+            .iter()
+            .map(|r| r.to_string())
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                "crypto.enc(M, K) => att(senc(M, K))",
+                "crypto.inner.key(k)",
+                "crypto.inner.key(K) => crypto.key(K)",
+                "att(X) => crypto.enc(X, k)",
+                "crypto.key(k)"
+            ]
+        );
+
+        let unclosed = Parser::parse_statements(Tokens::new("module m { p(a)."));
+        assert_eq!(unclosed.unwrap_err().kind, ParserErrorKind::UnexpectedEnd);
+        assert!(Parser::parse_statements(Tokens::new("p(a).\n}")).is_err());
+    }
+
+    #[test]
+    fn qualified_names_test() {
+        let rules = |source: &str| -> Vec<String> {
+            Parser::parse_rules(Tokens::new(source))
+                .unwrap()
+                .iter()
+                .map(|r| r.to_string())
+                .collect()
+        };
+        // Dots only qualify names applied to parameters, or names of declared modules
+        assert_eq!(rules("p.q."), vec!["p", "q"]);
+        assert_eq!(rules("p.q(a).\np.q."), vec!["p.q(a)", "p", "q"]);
+        let qualified = rules("module m { p. }\nuse m.p.\natt(m.k).\nm.p.");
+        // Qualified constants are quoted, so that they read back as one symbol
+        assert_eq!(qualified, vec!["'m.p'", "att('m.k')", "'m.p'"]);
+        assert_eq!(rules("att('m.k')."), vec!["att('m.k')"]);
+        assert!(Parser::parse_rules(Tokens::new("att(m.k).")).is_err());
+    }
+
+    #[test]
+    fn convention_test() {
+        let sigil = ParserOptions {
+            convention: NamingConvention::Sigil,
+            ..Default::default()
+        };
+        let rules =
+            Parser::parse_rules_with(Tokens::new("Knows(?x, Alice) => att(?x)."), &sigil).unwrap();
+        // Capitalized constants are quoted, so that they read back under both conventions
+        assert_eq!(rules[0].to_string(), "'Knows'(?x, 'Alice') => att(?x)");
+        assert!(
+            matches!(&rules[0].premises[0].parameters[1], Term::Function { symbol, .. } if symbol == "Alice")
+        );
+
+        // Each convention rejects the other one's variables
+        assert!(Parser::parse_rules(Tokens::new("att(?x) => att(?x).")).is_err());
+        assert!(Parser::parse_rules_with(Tokens::new("att(X(a))."), &sigil).is_ok());
+        assert!(Parser::parse_rules(Tokens::new("att(X(a)).")).is_err());
+    }
+
+    // Feeds pseudo-random sources, mostly made of valid tokens, to every entry point
+    #[test]
+    fn no_panic_test() {
+        const PIECES: [&str; 24] = [
+            "att",
+            "X",
+            "(",
+            ")",
+            ",",
+            ".",
+            "/\\",
+            "=>",
+            "#expect ",
+            "#expect_not ",
+            "# c\n",
+            "_",
+            "99999999999999999999999",
+            "é",
+            "\n",
+            "%",
+            "~>",
+            "~p(X) <~",
+            "@0",
+            "next ",
+            "#define m(X) ",
+            "module m {",
+            "}",
+            "op 700 xfy ~>.\n",
+        ];
+        let mut state = 0x2545F4914F6CDD1Du64;
+        for _ in 0..2000 {
+            let mut source = String::new();
+            for _ in 0..(state % 24) {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                source.push_str(PIECES[(state % PIECES.len() as u64) as usize]);
+            }
+            let _ = Parser::parse_statements(Tokens::new(&source));
+            let _ = Parser::parse_spanned_statements(Tokens::new(&source));
+            let _ = Parser::parse_query(Tokens::new(&source));
+            let _ = crate::lint::lint(&source);
+            let _ = crate::lexer::tokens(&source).count();
+        }
+    }
 }
@@ -0,0 +1,245 @@
+//! Planner module
+//! Join ordering for rules with several premises. Saturation matches the premises
+//! of a rule one at a time, each resolution binding the variables the premise
+//! shares with the others, so matching the most selective premise first keeps the
+//! intermediate rules few.
+//!
+//! A plan is computed before each saturation from the cardinalities of the
+//! relations: the number of input facts of the relation, scaled down for every
+//! ground parameter by the number of distinct terms of its column, plus the number
+//! of rules concluding the relation. The selection function then picks the
+//! eligible premise of lowest estimate, ties going to the premise sharing the most
+//! variables with the others, then to the first one. Premises of a predicate with
+//! a mode (`mode path(+, -).`) come before the others once every parameter the mode
+//! binds is ground, and after them until then, since the facts of the predicate are
+//! indexed on those parameters. Only the order in which premises are selected
+//! changes, rules themselves are left as written.
+//!
+//! Goal clauses of negative constraints, whose conclusion no premise uses, fall
+//! back to premises that are not eligible rather than to their conclusion, see the
+//! `consistency` module
+
+use crate::ast::{InnerAtom, InnerRule, InnerTerm, Term};
+use crate::constraints::is_constraint;
+use crate::hashing::{HashMap, HashSet};
+use crate::identifiers::Identifier;
+use crate::provenance::Provenance;
+use crate::resolution::Selection;
+use crate::Sniffer;
+
+#[derive(Debug, Default)]
+struct Cardinality {
+    /// Number of input facts
+    facts: usize,
+    /// Number of distinct terms in each column of the input facts
+    distinct: Vec<usize>,
+    /// Number of rules of the rule set concluding the relation, derived facts included
+    producers: usize,
+}
+
+/// Cardinalities of the relations of a rule set, by predicate and arity
+#[derive(Debug, Default)]
+pub(crate) struct JoinPlan {
+    relations: HashMap<(Identifier, usize), Cardinality>,
+    /// Parameters of each relation that queries bind, by mode declaration
+    modes: HashMap<(Identifier, usize), Vec<bool>>,
+    /// Predicate of disequality constraints, which are never selected
+    neq: Option<Identifier>,
+    /// Predicate of goal clauses, whose premises may all be selected
+    bottom: Option<Identifier>,
+}
+impl JoinPlan {
+    /// Estimated number of facts matching a premise
+    fn estimate(&self, premise: &InnerAtom) -> f64 {
+        let Some(cardinality) = self
+            .relations
+            .get(&(premise.symbol, premise.parameters.len()))
+        else {
+            return 0.0;
+        };
+        let mut facts = cardinality.facts as f64;
+        for (parameter, distinct) in premise.parameters.iter().zip(&cardinality.distinct) {
+            if parameter.is_ground() {
+                facts /= (*distinct).max(1) as f64
+            }
+        }
+        facts + cardinality.producers as f64
+    }
+
+    /// Whether a premise leaves free a parameter its mode binds, if it has one
+    fn unbound(&self, premise: &InnerAtom) -> bool {
+        self.modes
+            .get(&(premise.symbol, premise.parameters.len()))
+            .is_some_and(|mode| {
+                mode.iter()
+                    .zip(&premise.parameters)
+                    .any(|(bound, parameter)| *bound && !parameter.is_ground())
+            })
+    }
+
+    /// Selects the eligible premise of the rule to match first, or its conclusion
+    /// if none is eligible
+    pub fn select(
+        &self,
+        rule: &InnerRule,
+        eligible: impl Fn(&InnerAtom) -> bool,
+    ) -> Selection<Identifier> {
+        let goal_clause = Some(rule.conclusion.symbol) == self.bottom;
+        let best = self
+            .best(rule, eligible)
+            .or_else(|| self.best(rule, |_| goal_clause));
+        match best {
+            Some(i) => Selection::Premise(rule.premises[i].clone(), i),
+            None => Selection::Conclusion(rule.conclusion.clone()),
+        }
+    }
+
+    /// Index of the eligible premise of the rule to match first, if any
+    fn best(&self, rule: &InnerRule, eligible: impl Fn(&InnerAtom) -> bool) -> Option<usize> {
+        let mut best: Option<(usize, bool, f64, usize)> = None;
+        for (i, premise) in rule.premises.iter().enumerate() {
+            if !eligible(premise) || is_constraint(premise, self.neq) {
+                continue;
+            }
+            let unbound = self.unbound(premise);
+            let estimate = self.estimate(premise);
+            let shared = shared_variables(rule, i);
+            let better = best.is_none_or(|(_, u, e, s)| {
+                (!unbound && u) || (unbound == u && (estimate < e || (estimate == e && shared > s)))
+            });
+            if better {
+                best = Some((i, unbound, estimate, shared))
+            }
+        }
+        best.map(|(i, ..)| i)
+    }
+}
+
+/// Number of variables of a premise that occur in the other premises
+fn shared_variables(rule: &InnerRule, premise: usize) -> usize {
+    let mut own = HashSet::default();
+    for parameter in &rule.premises[premise].parameters {
+        variables(parameter, &mut own)
+    }
+    let mut others = HashSet::default();
+    for (_, atom) in rule
+        .premises
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != premise)
+    {
+        for parameter in &atom.parameters {
+            variables(parameter, &mut others)
+        }
+    }
+    own.intersection(&others).count()
+}
+
+fn variables(term: &InnerTerm, variables: &mut HashSet<Identifier>) {
+    match term {
+        Term::Variable { symbol } => {
+            variables.insert(*symbol);
+        }
+        Term::Function { parameters, .. } => {
+            for parameter in parameters {
+                self::variables(parameter, variables)
+            }
+        }
+    }
+}
+
+impl<P: Provenance> Sniffer<P> {
+    /// Computes the join plan of the current rule set
+    pub(crate) fn join_plan(&self) -> JoinPlan {
+        let mut plan = JoinPlan {
+            modes: self.edb.modes().clone(),
+            neq: self.neq(),
+            bottom: self.bottom(),
+            ..JoinPlan::default()
+        };
+        for (relation, facts, distinct) in self.edb.statistics() {
+            let cardinality = plan.relations.entry(relation).or_default();
+            cardinality.facts = facts;
+            cardinality.distinct = distinct;
+        }
+        for rule in &self.rules {
+            let conclusion = &rule.conclusion;
+            plan.relations
+                .entry((conclusion.symbol, conclusion.parameters.len()))
+                .or_default()
+                .producers += 1;
+        }
+        plan
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Parser, Selection, Sniffer};
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn plan_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "edge(a, b). edge(a, c). edge(b, c). edge(c, d).\nstart(a).\n\
+                 edge(X, Y) /\\ edge(Y, Z) /\\ start(X) => reach(Z).\n\
+                 edge(X, Y) /\\ edge(b, Y) => both(Y).",
+            )
+            .unwrap();
+        let plan = sniffer.join_plan();
+        let selected: Vec<_> = sniffer
+            .rules
+            .iter()
+            .map(|rule| match plan.select(rule, |_| true) {
+                Selection::Premise(_, i) => i,
+                Selection::Conclusion(_) => rule.premises.len(),
+            })
+            .collect();
+        assert_eq!(selected, vec![2, 1]);
+
+        sniffer.saturate_all();
+        for fact in ["reach(c).", "reach(d).", "both(c)."] {
+            let query = Parser::parse_query(Tokens::new(fact)).unwrap();
+            assert!(sniffer.find(&query).is_ok());
+        }
+    }
+
+    #[test]
+    fn mode_test() {
+        let rules = "edge(a, b). edge(b, c). edge(b, d). edge(c, d).\n\
+                     big(c). big(d). big(e). big(f). big(g).\n\
+                     edge(b, Y) /\\ big(Y) => r(Y).";
+        let selected = |modes: &str| {
+            let mut sniffer = Sniffer::default();
+            sniffer.assert(&format!("{modes}{rules}")).unwrap();
+            let rule = sniffer
+                .rules
+                .iter()
+                .find(|r| !r.premises.is_empty())
+                .unwrap();
+            let Selection::Premise(premise, _) = sniffer.join_plan().select(rule, |_| true) else {
+                panic!("a premise is eligible")
+            };
+            let selected = sniffer.id_server.name_of(&premise.symbol).unwrap();
+
+            sniffer.saturate_all();
+            for (fact, derivable) in [("r(c).", true), ("r(d).", true), ("r(e).", false)] {
+                let query = Parser::parse_query(Tokens::new(fact)).unwrap();
+                assert_eq!(sniffer.find(&query).is_ok(), derivable);
+            }
+            selected
+        };
+        // A premise leaving free a parameter its mode binds is matched last
+        assert_eq!(selected(""), "edge");
+        assert_eq!(selected("mode edge(-, +).\n"), "big");
+        assert_eq!(selected("mode edge(+, -).\n"), "edge");
+        assert!(Sniffer::default().assert("mode edge(+, *).").is_err());
+        let mode = crate::Mode {
+            predicate: "edge".to_string(),
+            bound: vec![false, true],
+        };
+        assert_eq!(mode.to_string(), "mode edge(-, +)");
+    }
+}
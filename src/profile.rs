@@ -0,0 +1,267 @@
+//! Profile module
+//! Counters telling which rules and predicates saturation spends its time on, once
+//! enabled with `Sniffer::set_profiling`.
+//!
+//! Every time a rule is taken from the agenda, the rules its selected atom may be
+//! resolved with are counted as attempts, and the resolvents found as firings.
+//! Both are credited to the side of the resolution whose premise is selected, and
+//! rules derived along the way credit the input rule they were derived from, so
+//! that a rule blowing up the rule set stands out even though its work is done by
+//! its descendants. Predicates count the unifications their atoms take part in
+use crate::ast::{InnerAtom, InnerRule, Rule};
+use crate::hashing::HashMap;
+use crate::identifiers::Identifier;
+use crate::provenance::Provenance;
+use crate::resolution::Selection;
+use crate::{RunFingerprint, Sniffer, SourceLocation};
+
+/// Counters gathered during saturation
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Profiler {
+    /// Attempted and successful resolutions, by rule whose premise was selected
+    rules: HashMap<InnerRule, (usize, usize)>,
+    /// Attempted unifications, by predicate and arity
+    unifications: HashMap<(Identifier, usize), usize>,
+}
+impl Profiler {
+    /// Records the resolutions attempted between a rule and the rules it may be
+    /// resolved with
+    pub fn attempt(&mut self, rule: &InnerRule, selection: &Selection<Identifier>, count: usize) {
+        if count == 0 {
+            return;
+        }
+        self.rules.entry(rule.clone()).or_default().0 += count;
+        let atom = match selection {
+            Selection::Premise(atom, _) | Selection::Conclusion(atom) => atom,
+        };
+        *self
+            .unifications
+            .entry((atom.symbol, atom.parameters.len()))
+            .or_default() += count;
+    }
+
+    /// Records a resolvent of a rule
+    pub fn fire(&mut self, rule: &InnerRule) {
+        self.rules.entry(rule.clone()).or_default().1 += 1
+    }
+}
+
+/// Counters of an input rule, its derived rules included
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleProfile {
+    pub rule: Rule<String>,
+    pub location: Option<SourceLocation>,
+    /// Resolutions attempted with the premises of the rule
+    pub attempts: usize,
+    /// Resolutions that succeeded, whether the resolvent was new or not
+    pub fired: usize,
+}
+
+/// Counters of a predicate, by name and arity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PredicateProfile {
+    pub predicate: String,
+    pub arity: usize,
+    /// Facts of the predicate in the rule set, input facts included
+    pub facts: usize,
+    /// Unifications its atoms took part in
+    pub unifications: usize,
+}
+
+/// Profile of the saturations run since profiling was enabled, see
+/// `Sniffer::profile`. Entries come by decreasing amount of work
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub rules: Vec<RuleProfile>,
+    pub predicates: Vec<PredicateProfile>,
+    /// Fingerprint of the profiled run
+    pub fingerprint: Option<RunFingerprint>,
+}
+/// Printed as two tables, rules then predicates, after the fingerprint
+impl std::fmt::Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(fingerprint) = &self.fingerprint {
+            writeln!(f, "# {fingerprint}")?;
+        }
+        writeln!(f, "{:>10} {:>10}  rule", "attempts", "fired")?;
+        for rule in &self.rules {
+            write!(f, "{:>10} {:>10}  {}", rule.attempts, rule.fired, rule.rule)?;
+            match &rule.location {
+                Some(location) => writeln!(f, "  # {location}")?,
+                None => writeln!(f)?,
+            }
+        }
+        writeln!(f, "{:>10} {:>10}  predicate", "facts", "unified")?;
+        for predicate in &self.predicates {
+            writeln!(
+                f,
+                "{:>10} {:>10}  {}/{}",
+                predicate.facts, predicate.unifications, predicate.predicate, predicate.arity
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<P: Provenance> Sniffer<P> {
+    /// Enables or disables profiling, counters being reset either way. Profiling
+    /// counts the resolutions attempted by every rule, which slows saturation down
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiler = enabled.then(Profiler::default)
+    }
+
+    pub fn is_profiling(&self) -> bool {
+        self.profiler.is_some()
+    }
+
+    /// Returns the counters gathered since profiling was enabled, along with the
+    /// number of facts of every predicate. Rules are those of the input, the
+    /// counters of derived rules adding up to those of the rule they come from
+    pub fn profile(&self) -> Profile {
+        let profiler = self.profiler.clone().unwrap_or_default();
+        let mut rules: HashMap<InnerRule, (usize, usize)> = HashMap::default();
+        for (rule, (attempts, fired)) in &profiler.rules {
+            let counters = rules.entry(self.origin(rule)).or_default();
+            counters.0 += attempts;
+            counters.1 += fired;
+        }
+        let mut profile = Profile {
+            fingerprint: Some(self.fingerprint()),
+            ..Profile::default()
+        };
+        for rule in self
+            .rules
+            .iter()
+            .filter(|r| !r.premises.is_empty() && !self.derived_from.contains_key(*r))
+        {
+            let (attempts, fired) = rules.get(rule).copied().unwrap_or_default();
+            profile.rules.push(RuleProfile {
+                rule: rule.to_string(&self.id_server),
+                location: self.locations.get(rule).cloned(),
+                attempts,
+                fired,
+            })
+        }
+        profile
+            .rules
+            .sort_by_key(|r| std::cmp::Reverse((r.fired, r.attempts)));
+
+        let mut predicates = profiler.unifications;
+        let mut facts: HashMap<(Identifier, usize), usize> = HashMap::default();
+        for rule in self.all_rules().filter(|r| r.premises.is_empty()) {
+            let relation = (rule.conclusion.symbol, rule.conclusion.parameters.len());
+            *facts.entry(relation).or_default() += 1;
+            predicates.entry(relation).or_default();
+        }
+        for ((symbol, arity), unifications) in predicates {
+            profile.predicates.push(PredicateProfile {
+                predicate: self.id_server.name_of(&symbol).unwrap_or_default(),
+                arity,
+                facts: facts.get(&(symbol, arity)).copied().unwrap_or_default(),
+                unifications,
+            })
+        }
+        profile.predicates.sort_by(|a, b| {
+            (b.unifications, b.facts)
+                .cmp(&(a.unifications, a.facts))
+                .then_with(|| (&a.predicate, a.arity).cmp(&(&b.predicate, b.arity)))
+        });
+        profile
+    }
+
+    /// Records the resolutions that may be attempted with a rule taken from the
+    /// agenda, if profiling is enabled
+    pub(crate) fn profile_attempts(
+        &mut self,
+        rule: &InnerRule,
+        select: &impl Fn(&InnerRule) -> Selection<Identifier>,
+    ) {
+        let Some(profiler) = &mut self.profiler else {
+            return;
+        };
+        let relation = |atom: &InnerAtom| (atom.symbol, atom.parameters.len());
+        let selection = select(rule);
+        match &selection {
+            Selection::Premise(premise, _) => {
+                let rules = self
+                    .rules
+                    .iter()
+                    .filter(|r| {
+                        matches!(select(r), Selection::Conclusion(c) if relation(&c) == relation(premise))
+                    })
+                    .count();
+                let facts = self.edb.matching(premise).len();
+                profiler.attempt(rule, &selection, rules + facts)
+            }
+            Selection::Conclusion(conclusion) => {
+                for other in &self.rules {
+                    let selected = select(other);
+                    if matches!(&selected, Selection::Premise(p, _) if relation(p) == relation(conclusion))
+                    {
+                        profiler.attempt(other, &selected, 1)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Input rule a rule was derived from, following the parents whose premise was
+    /// selected
+    fn origin(&self, rule: &InnerRule) -> InnerRule {
+        let mut rule = rule;
+        while let Some(info) = self.derived_from.get(rule) {
+            rule = match info.selected_atoms.0 {
+                Selection::Premise(..) => &info.rules.0,
+                Selection::Conclusion(_) => &info.rules.1,
+            }
+        }
+        rule.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Sniffer;
+
+    #[test]
+    fn profile_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "edge(a, b). edge(b, c). edge(c, d).\n\
+                 edge(X, Y) => path(X, Y).\n\
+                 path(X, Y) /\\ edge(Y, Z) => path(X, Z).\n\
+                 path(X, a) => loop(X).",
+            )
+            .unwrap();
+        sniffer.set_profiling(true);
+        sniffer.saturate_all();
+        let profile = sniffer.profile();
+
+        // The recursive rule does most of the work, through the rules derived from it
+        let rules: Vec<_> = profile
+            .rules
+            .iter()
+            .map(|r| (r.rule.conclusion.symbol.as_str(), r.rule.premises.len()))
+            .collect();
+        assert_eq!(rules, vec![("path", 2), ("path", 1), ("loop", 1)]);
+        assert!(profile.rules[0].fired > profile.rules[1].fired);
+        assert!(profile.rules[1].fired > 0);
+        assert_eq!(profile.rules[2].fired, 0);
+        assert!(profile.rules.iter().all(|r| r.attempts >= r.fired));
+        assert!(profile.rules[2].attempts > 0);
+
+        let path = profile
+            .predicates
+            .iter()
+            .find(|p| p.predicate == "path")
+            .unwrap();
+        assert_eq!((path.facts, path.arity), (6, 2));
+        assert!(path.unifications > 0);
+        let header = format!("# {}", sniffer.fingerprint());
+        assert_eq!(profile.to_string().lines().next(), Some(header.as_str()));
+
+        sniffer.set_profiling(false);
+        assert!(sniffer.profile().rules.iter().all(|r| r.attempts == 0));
+    }
+}
@@ -0,0 +1,145 @@
+//! Provenance module
+//! Annotations of derived rules in a commutative semiring, in the style of
+//! provenance semirings: input rules are annotated by the provenance, a rule
+//! resolved from two others gets the product of their annotations, and the
+//! annotations of the different derivations of a same rule are summed.
+//!
+//! Annotations are accumulated over the derivations explored by saturation, in the
+//! order they are found: a derivation found after a rule was already used does not
+//! update the rules derived from it. Rules are identified up to variable names, so
+//! in practice only ground facts are merged
+
+use crate::ast::{Attributes, Rule};
+use std::collections::BTreeSet;
+
+/// A provenance semiring annotating the rules of a Sniffer
+pub trait Provenance {
+    type Annotation: Clone;
+
+    /// Whether annotations are tracked at all, provenances that only rely on the
+    /// recorded derivations turn this off to save the bookkeeping
+    const TRACKED: bool = true;
+
+    /// Annotation of an input rule, given the index it was inserted at in the rule set
+    fn input(&self, index: usize, rule: &Rule<String>, attributes: &Attributes)
+        -> Self::Annotation;
+
+    /// Annotation of a rule resolved from rules annotated with `a` and `b`
+    fn times(&self, a: &Self::Annotation, b: &Self::Annotation) -> Self::Annotation;
+
+    /// Annotation of a rule derived in two ways, annotated with `a` and `b`
+    fn plus(&self, a: &Self::Annotation, b: &Self::Annotation) -> Self::Annotation;
+}
+
+/// The default provenance, which only records the first derivation of each rule
+/// as used to build derivation trees
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Derivations;
+impl Provenance for Derivations {
+    type Annotation = ();
+    const TRACKED: bool = false;
+
+    fn input(&self, _: usize, _: &Rule<String>, _: &Attributes) {}
+    fn times(&self, _: &(), _: &()) {}
+    fn plus(&self, _: &(), _: &()) {}
+}
+
+/// Counts the derivations of rules
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Counting;
+impl Provenance for Counting {
+    type Annotation = u64;
+
+    fn input(&self, _: usize, _: &Rule<String>, _: &Attributes) -> u64 {
+        1
+    }
+
+    fn times(&self, a: &u64, b: &u64) -> u64 {
+        a.saturating_mul(*b)
+    }
+
+    fn plus(&self, a: &u64, b: &u64) -> u64 {
+        a.saturating_add(*b)
+    }
+}
+
+/// Tracks the minimal cost of deriving rules, where the cost of a derivation is
+/// the sum of the costs of the input rules it uses (given by their `cost`
+/// attribute, 1 by default)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tropical;
+impl Provenance for Tropical {
+    type Annotation = u64;
+
+    fn input(&self, _: usize, _: &Rule<String>, attributes: &Attributes) -> u64 {
+        attributes.cost.map_or(1, u64::from)
+    }
+
+    fn times(&self, a: &u64, b: &u64) -> u64 {
+        a.saturating_add(*b)
+    }
+
+    fn plus(&self, a: &u64, b: &u64) -> u64 {
+        *a.min(b)
+    }
+}
+
+/// Why-provenance: the sets of input rules (by index) from which rules can be
+/// derived. Only minimal sets are kept
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Why;
+impl Provenance for Why {
+    type Annotation = BTreeSet<BTreeSet<usize>>;
+
+    fn input(&self, index: usize, _: &Rule<String>, _: &Attributes) -> Self::Annotation {
+        BTreeSet::from([BTreeSet::from([index])])
+    }
+
+    fn times(&self, a: &Self::Annotation, b: &Self::Annotation) -> Self::Annotation {
+        let products = a
+            .iter()
+            .flat_map(|x| b.iter().map(move |y| x.union(y).copied().collect()));
+        minimal(products.collect())
+    }
+
+    fn plus(&self, a: &Self::Annotation, b: &Self::Annotation) -> Self::Annotation {
+        minimal(a.union(b).cloned().collect())
+    }
+}
+
+/// Drops the sets that contain another one
+fn minimal(sets: BTreeSet<BTreeSet<usize>>) -> BTreeSet<BTreeSet<usize>> {
+    sets.iter()
+        .filter(|s| !sets.iter().any(|t| t != *s && t.is_subset(s)))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Parser, Sniffer};
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn semirings_test() {
+        let source = "att(a).\natt(b).\n[cost=5] att(a) => att(c).\natt(b) => att(c).";
+        let fact = &Parser::parse_rules(Tokens::new("att(c).")).unwrap()[0];
+
+        let mut counting = Sniffer::with_provenance(Counting);
+        counting.assert(source).unwrap();
+        counting.saturate_all();
+        assert_eq!(counting.annotation(fact), Some(&2));
+
+        let mut tropical = Sniffer::with_provenance(Tropical);
+        tropical.assert(source).unwrap();
+        tropical.saturate_all();
+        assert_eq!(tropical.annotation(fact), Some(&2));
+
+        let mut why = Sniffer::with_provenance(Why);
+        why.assert(source).unwrap();
+        why.saturate_all();
+        let witnesses = BTreeSet::from([BTreeSet::from([0, 2]), BTreeSet::from([1, 3])]);
+        assert_eq!(why.annotation(fact), Some(&witnesses));
+    }
+}
@@ -0,0 +1,438 @@
+//! Prover module
+//! Strategies answering a goal with `Sniffer::prove`, so that code asking for a
+//! derivation does not depend on how it is found:
+//! - bottom-up: the whole rule set is saturated, then the goal is looked up
+//! - hybrid: saturation is directed by the goal, premises of its predicate being
+//!   selected first, and stops at its first instance, as done by `find`
+//! - top-down: the goal is proven backward, resolving it with the conclusions of
+//!   the rules and proving their premises in turn, the most instantiated first,
+//!   up to a given depth. Subgoals that are variants of one of their ancestors are
+//!   not expanded again, but take the answers found for the ancestor so far, and
+//!   proving is repeated until no goal gets new answers, so that recursive rules
+//!   terminate
+//!
+//! Goals whose search was exhaustive, neither cut by the depth nor relying on the
+//! answers of an ancestor, and found no proof are kept as failure lemmas, so that
+//! later branches and later calls to `prove` prune them at once. A lemma records
+//! the number of facts and rules of every relation its search went through, and
+//! only holds while they are unchanged: asserting, saturating or removing rules of
+//! these relations forgets it.
+//!
+//! Top-down proofs are rebuilt forward, each rule being resolved with the facts
+//! proving its premises in the order they were proven, so derivations read the
+//! same whatever the strategy. They only use the rule set, leaving the timeline and
+//! the defeasible rules to saturation, and nothing they prove is added to the rule
+//! set
+
+use crate::ast::{Atom, InnerAtom, InnerRule, InnerTerm, Term};
+use crate::constraints::{self, is_constraint};
+use crate::hashing::{HashMap, HashSet};
+use crate::identifiers::Identifier;
+use crate::provenance::Provenance;
+use crate::resolution::Selection;
+use crate::{DerivationError, DerivationTree, SaturationFailure, Sniffer};
+use itertools::Itertools;
+use std::collections::VecDeque;
+
+/// How `Sniffer::prove` looks for the derivation of a goal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    /// Saturates the whole rule set, then looks the goal up
+    BottomUp,
+    /// Proves the goal backward, `depth` bounding the nesting of goals, the goal
+    /// itself included
+    TopDown { depth: usize },
+    /// Saturates the rule set with the goal in mind, see `Sniffer::find`
+    #[default]
+    Hybrid,
+}
+
+/// An atom up to variable renaming, variables being numbered by first occurrence
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Shape {
+    Variable(usize),
+    Function(Identifier, Vec<Shape>),
+}
+impl Shape {
+    pub(crate) fn of(atom: &InnerAtom) -> Shape {
+        let mut variables = vec![];
+        Shape::Function(
+            atom.symbol,
+            atom.parameters
+                .iter()
+                .map(|t| Shape::of_term(t, &mut variables))
+                .collect(),
+        )
+    }
+
+    fn of_term(term: &InnerTerm, variables: &mut Vec<Identifier>) -> Shape {
+        match term {
+            Term::Variable { symbol } => {
+                let index = variables
+                    .iter()
+                    .position(|v| v == symbol)
+                    .unwrap_or_else(|| {
+                        variables.push(*symbol);
+                        variables.len() - 1
+                    });
+                Shape::Variable(index)
+            }
+            Term::Function { symbol, parameters } => Shape::Function(
+                *symbol,
+                parameters
+                    .iter()
+                    .map(|t| Shape::of_term(t, variables))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// A proven instance of a goal, along with its derivation
+#[derive(Clone)]
+struct Proof {
+    fact: InnerRule,
+    tree: DerivationTree,
+}
+
+/// Outcome of the search for the proofs of a goal
+struct Search {
+    proofs: Vec<Proof>,
+    /// Whether some goal was cut by the depth
+    bounded: bool,
+    /// Whether some goal took the answers of an ancestor, which may grow
+    looped: bool,
+    /// Relations the search went through
+    support: HashSet<Relation>,
+}
+
+/// A predicate and its arity
+pub(crate) type Relation = (Identifier, usize);
+
+/// A goal shown to have no proof, see the module documentation
+#[derive(Debug, Clone)]
+pub(crate) struct Lemma {
+    goal: InnerAtom,
+    /// Number of facts and rules of the relations the search went through
+    support: Vec<(Relation, usize)>,
+}
+
+/// Selects the given premise of rules, and the conclusion of facts
+fn premise_at(index: usize) -> impl Fn(&InnerRule) -> Selection<Identifier> {
+    move |rule| match rule.premises.get(index) {
+        Some(premise) => Selection::Premise(premise.clone(), index),
+        None => Selection::Conclusion(rule.conclusion.clone()),
+    }
+}
+
+/// Number of function symbols in a term, telling how instantiated it is
+fn instantiation(term: &InnerTerm) -> usize {
+    match term {
+        Term::Variable { .. } => 0,
+        Term::Function { parameters, .. } => {
+            1 + parameters.iter().map(instantiation).sum::<usize>()
+        }
+    }
+}
+
+impl Lemma {
+    /// Whether the relations the search went through are unchanged
+    fn holds(&self, sizes: &HashMap<Relation, usize>) -> bool {
+        self.support
+            .iter()
+            .all(|(relation, size)| sizes.get(relation).copied().unwrap_or(0) == *size)
+    }
+}
+
+impl<P: Provenance> Sniffer<P> {
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Sets the strategy used by `prove`
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.strategy = strategy
+    }
+
+    /// Returns a derivation of an instance of the goal, found using the strategy of
+    /// this context
+    pub fn prove(&mut self, goal: &Atom<String>) -> Result<DerivationTree, SaturationFailure> {
+        match self.strategy {
+            Strategy::Hybrid => self.find(goal),
+            Strategy::BottomUp => {
+                self.saturate_all();
+                let goal = goal.to_inner(&mut self.id_server, &mut HashMap::default());
+                let fact = self.find_fact(&goal).ok_or(SaturationFailure::Saturated)?;
+                self.inner_derivation_tree(&fact, &mut vec![])
+                    .map_err(SaturationFailure::Derivation)
+            }
+            Strategy::TopDown { depth } => {
+                let sizes = self.relation_sizes();
+                self.lemmas.retain(|_, lemma| lemma.holds(&sizes));
+                let goal = goal.to_inner(&mut self.id_server, &mut HashMap::default());
+                let mut answers = HashMap::default();
+                loop {
+                    let known: usize = answers.values().map(Vec::len).sum();
+                    let search = self
+                        .solve(&goal, depth, &mut vec![], &mut answers, &sizes)
+                        .map_err(SaturationFailure::Derivation)?;
+                    if let Some(proof) = search.proofs.into_iter().next() {
+                        return Ok(proof.tree);
+                    }
+                    if answers.values().map(Vec::len).sum::<usize>() == known {
+                        // No goal gets new answers, so ancestors have all of theirs
+                        if !search.bounded {
+                            self.learn(&goal, &search.support, &sizes)
+                        }
+                        return Err(SaturationFailure::Saturated);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Goals of the failure lemmas learned by `prove`, see the `prover` module
+    pub fn failure_lemmas(&self) -> Vec<Atom<String>> {
+        self.lemmas
+            .values()
+            .map(|lemma| lemma.goal.to_string(&self.id_server))
+            .sorted_by_key(ToString::to_string)
+            .collect()
+    }
+
+    /// Number of input facts and rules of every relation, derived facts included
+    pub(crate) fn relation_sizes(&self) -> HashMap<Relation, usize> {
+        let mut sizes: HashMap<Relation, usize> = HashMap::default();
+        for (relation, facts, _) in self.edb.statistics() {
+            *sizes.entry(relation).or_default() += facts
+        }
+        for rule in &self.rules {
+            let conclusion = &rule.conclusion;
+            *sizes
+                .entry((conclusion.symbol, conclusion.parameters.len()))
+                .or_default() += 1
+        }
+        sizes
+    }
+
+    fn learn(
+        &mut self,
+        goal: &InnerAtom,
+        support: &HashSet<Relation>,
+        sizes: &HashMap<Relation, usize>,
+    ) {
+        let support = support
+            .iter()
+            .map(|relation| (*relation, sizes.get(relation).copied().unwrap_or(0)))
+            .collect();
+        self.lemmas.insert(
+            Shape::of(goal),
+            Lemma {
+                goal: goal.clone(),
+                support,
+            },
+        );
+    }
+
+    /// Proves the instances of a goal backward, `ancestors` holding the goals being
+    /// proven on the way to this one and `answers` the instances proven so far for
+    /// every goal
+    fn solve(
+        &mut self,
+        goal: &InnerAtom,
+        depth: usize,
+        ancestors: &mut Vec<Shape>,
+        answers: &mut HashMap<Shape, Vec<Proof>>,
+        sizes: &HashMap<Relation, usize>,
+    ) -> Result<Search, DerivationError> {
+        let shape = Shape::of(goal);
+        let mut search = Search {
+            proofs: vec![],
+            bounded: false,
+            looped: false,
+            support: HashSet::from_iter([(goal.symbol, goal.parameters.len())]),
+        };
+        if let Some(lemma) = self.lemmas.get(&shape) {
+            search.support.extend(lemma.support.iter().map(|(r, _)| *r));
+            return Ok(search);
+        }
+        if depth == 0 {
+            search.bounded = true;
+            return Ok(search);
+        }
+        if ancestors.contains(&shape) {
+            search.proofs = answers.get(&shape).cloned().unwrap_or_default();
+            search.looped = true;
+            return Ok(search);
+        }
+        let candidates: Vec<InnerRule> = self
+            .edb
+            .matching(goal)
+            .into_iter()
+            .chain(
+                self.rules
+                    .iter()
+                    .filter(|r| r.conclusion.unify(goal).is_some())
+                    .cloned(),
+            )
+            .collect();
+
+        ancestors.push(shape);
+        let mut proven = HashSet::default();
+        let neq = self.neq();
+        for rule in candidates {
+            // Resolvents of the rule with the facts proving some of its premises
+            let mut resolvents = VecDeque::from([(
+                rule.make_fresh(&mut self.id_server),
+                self.inner_derivation_tree(&rule, &mut vec![])?,
+            )]);
+            while let Some((current, tree)) = resolvents.pop_front() {
+                let Some(bindings) = current.conclusion.unify(goal) else {
+                    continue;
+                };
+                // Premises being stored in normal form rather than as written, the
+                // most instantiated one is proven first, the first one on ties
+                // Constraints are only checked, see the `constraints` module
+                let index = (0..current.premises.len())
+                    .filter(|i| !is_constraint(&current.premises[*i], neq))
+                    .max_by_key(|i| {
+                        let premise = current.premises[*i].substitute(&bindings);
+                        let instantiated: usize =
+                            premise.parameters.iter().map(instantiation).sum();
+                        (instantiated, std::cmp::Reverse(*i))
+                    });
+                let Some(index) = index else {
+                    // Rules left with constraints only never yield facts, as when saturating
+                    if !current.premises.is_empty() {
+                        continue;
+                    }
+                    if proven.insert(Shape::of(&current.conclusion)) {
+                        search.proofs.push(Proof {
+                            fact: current,
+                            tree,
+                        })
+                    }
+                    continue;
+                };
+                let premise = current.premises[index].clone();
+                let subgoal = premise.substitute(&bindings);
+                let subsearch = self.solve(&subgoal, depth - 1, ancestors, answers, sizes)?;
+                search.bounded |= subsearch.bounded;
+                search.looped |= subsearch.looped;
+                search.support.extend(subsearch.support);
+                for answer in subsearch.proofs {
+                    let fact = answer.fact.make_fresh(&mut self.id_server);
+                    let Some(resolvent) = current
+                        .resolve(&fact, &mut self.terms, premise_at(index), |_, _| true)
+                        .and_then(|r| constraints::simplify(r, neq))
+                    else {
+                        continue;
+                    };
+                    let mut parent = tree.clone();
+                    parent.set_selection(Selection::Premise(
+                        premise.try_to_string(&self.id_server)?,
+                        index,
+                    ));
+                    let mut premise_tree = answer.tree;
+                    premise_tree.set_selection(Selection::Conclusion(
+                        fact.conclusion.try_to_string(&self.id_server)?,
+                    ));
+                    let mut derivation =
+                        DerivationTree::new(resolvent.try_to_string(&self.id_server)?);
+                    derivation.add_subtree(parent);
+                    derivation.add_subtree(premise_tree);
+                    resolvents.push_back((resolvent, derivation));
+                }
+            }
+        }
+        let shape = ancestors.pop().unwrap();
+        if search.proofs.is_empty() && !search.bounded && !search.looped {
+            self.learn(goal, &search.support, sizes)
+        }
+        let known = answers.entry(shape).or_default();
+        for proof in &search.proofs {
+            let proven = Shape::of(&proof.fact.conclusion);
+            if !known
+                .iter()
+                .any(|p| Shape::of(&p.fact.conclusion) == proven)
+            {
+                known.push(proof.clone())
+            }
+        }
+        Ok(search)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Strategy;
+    use crate::{Parser, Sniffer};
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn strategies_test() {
+        let query = |source: &str| Parser::parse_query(Tokens::new(source)).unwrap();
+        let source = "att(k). att(enc(m, k)).\natt(enc(X, Y)) /\\ att(Y) => att(X).";
+        for strategy in [
+            Strategy::BottomUp,
+            Strategy::TopDown { depth: 8 },
+            Strategy::Hybrid,
+        ] {
+            let mut sniffer = Sniffer::default();
+            sniffer.assert(source).unwrap();
+            sniffer.set_strategy(strategy);
+            let tree = sniffer.prove(&query("att(m).")).unwrap();
+            assert_eq!(tree.root().to_string(), "att(m)");
+            assert!(sniffer.prove(&query("att(n).")).is_err());
+        }
+
+        // Left recursion is proven backward, without adding to the rule set
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "edge(a, b). edge(b, c). edge(c, d).\n\
+                 edge(X, Y) => path(X, Y).\n\
+                 path(X, Y) /\\ edge(Y, Z) => path(X, Z).",
+            )
+            .unwrap();
+        sniffer.set_strategy(Strategy::TopDown { depth: 8 });
+        let rules = sniffer.iter_rules().count();
+        let tree = sniffer.prove(&query("path(a, d).")).unwrap();
+        assert_eq!(tree.root().to_string(), "path(a, d)");
+        assert_eq!(tree.subtrees().len(), 2);
+        assert!(sniffer.prove(&query("path(d, X).")).is_err());
+        assert_eq!(sniffer.iter_rules().count(), rules);
+
+        // Proving `path(a, d)` nests three goals, down to `edge(a, Y)`
+        sniffer.set_strategy(Strategy::TopDown { depth: 2 });
+        assert!(sniffer.prove(&query("path(a, d).")).is_err());
+    }
+
+    #[test]
+    fn lemmas_test() {
+        let query = |source: &str| Parser::parse_query(Tokens::new(source)).unwrap();
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "edge(a, b). edge(b, c).
+                 edge(X, Y) => path(X, Y).
+                 path(X, Y) /\\ edge(Y, Z) => path(X, Z).",
+            )
+            .unwrap();
+        sniffer.set_strategy(Strategy::TopDown { depth: 8 });
+        assert!(sniffer.prove(&query("path(c, X).")).is_err());
+        let lemmas: Vec<_> = sniffer
+            .failure_lemmas()
+            .into_iter()
+            .map(|lemma| (lemma.symbol, lemma.parameters[0].to_string()))
+            .collect();
+        let expected = |symbol: &str| (symbol.to_string(), "c".to_string());
+        assert_eq!(lemmas, vec![expected("edge"), expected("path")]);
+        assert!(sniffer.prove(&query("path(c, X).")).is_err());
+
+        // Lemmas are forgotten once the relations they went through change
+        sniffer.assert("edge(c, d).").unwrap();
+        assert!(sniffer.prove(&query("path(c, X).")).is_ok());
+        assert!(sniffer.failure_lemmas().is_empty());
+    }
+}
@@ -1,19 +1,22 @@
 use crate::ast::{Atom, InnerRule, Rule};
+use crate::hashing::IndexSet;
 use crate::identifiers::{Identifier, IdentifierServer};
+use crate::interning::TermStore;
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Selection<T> {
     Premise(Atom<T>, usize),
     Conclusion(Atom<T>),
 }
 impl TryFrom<(&Selection<Identifier>, &IdentifierServer)> for Selection<String> {
-    type Error = ();
+    /// The first identifier that is not registered
+    type Error = Identifier;
     fn try_from(
         (s, id_server): (&Selection<Identifier>, &IdentifierServer),
     ) -> Result<Self, Self::Error> {
         Ok(match s {
-            Selection::Premise(a, i) => Selection::Premise(a.to_string(id_server), *i),
-            Selection::Conclusion(a) => Selection::Conclusion(a.to_string(id_server)),
+            Selection::Premise(a, i) => Selection::Premise(a.try_to_string(id_server)?, *i),
+            Selection::Conclusion(a) => Selection::Conclusion(a.try_to_string(id_server)?),
         })
     }
 }
@@ -30,27 +33,119 @@ impl InnerRule {
     pub fn resolve(
         &self,
         other: &InnerRule,
+        store: &mut TermStore,
         select: impl Fn(&InnerRule) -> Selection<Identifier>,
         keep: impl Fn(&Atom<Identifier>, &Atom<Identifier>) -> bool,
     ) -> Option<InnerRule> {
         match (select(self), select(other)) {
-            (Selection::Premise(p, i), Selection::Conclusion(c)) => p.unify(&c).map(|bindings| {
-                let mut premises = self.premises.clone();
-                premises.remove(i);
-                premises.append(&mut other.premises.clone());
-                let mut rule = Rule {
-                    conclusion: self.conclusion.clone(),
-                    premises,
-                };
-                rule = rule.apply(&bindings);
-                rule.premises.retain(|p| keep(p, &rule.conclusion));
-                rule
-            }),
+            (Selection::Premise(p, i), Selection::Conclusion(c)) => {
+                p.unify_in(&c, store).map(|bindings| {
+                    let mut premises = self.premises.clone();
+                    premises.remove(i);
+                    premises.append(&mut other.premises.clone());
+                    let mut rule = Rule {
+                        conclusion: self.conclusion.clone(),
+                        premises,
+                    };
+                    rule = rule.substitute(&bindings);
+                    rule.premises.retain(|p| keep(p, &rule.conclusion));
+                    rule
+                })
+            }
             (Selection::Conclusion(_), Selection::Premise(_, _)) => {
-                other.resolve(self, select, keep)
+                other.resolve(self, store, select, keep)
             }
 
             _ => None,
         }
     }
 }
+
+/// Resolves a rule against the rules of the set at the given positions, returning
+/// the resolvents along with the index of the rule they were resolved with, in the
+/// order of the positions. Without the `parallel` feature, rules are resolved on
+/// the current thread whatever the number of threads asked for
+#[cfg(not(feature = "parallel"))]
+pub fn resolvents(
+    rules: &IndexSet<InnerRule>,
+    candidates: &[usize],
+    rule: &InnerRule,
+    store: &mut TermStore,
+    _threads: Option<usize>,
+    select: &(impl Fn(&InnerRule) -> Selection<Identifier> + Sync),
+    keep: &(impl Fn(&Atom<Identifier>, &Atom<Identifier>) -> bool + Sync),
+) -> Vec<(InnerRule, usize)> {
+    candidates
+        .iter()
+        .filter_map(|&i| rule.resolve(&rules[i], store, select, keep).map(|r| (r, i)))
+        .collect()
+}
+
+/// Below this number of rules, resolving in parallel costs more than it saves
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 512;
+
+/// Resolves a rule against the rules of the set at the given positions, returning
+/// the resolvents along with the index of the rule they were resolved with, in the
+/// order of the positions. Many positions are split in chunks resolved on separate
+/// threads, each with its own term store, and the results are concatenated in
+/// order so that saturation stays deterministic. Every available thread is used
+/// unless a number of threads is given
+#[cfg(feature = "parallel")]
+pub fn resolvents(
+    rules: &IndexSet<InnerRule>,
+    candidates: &[usize],
+    rule: &InnerRule,
+    store: &mut TermStore,
+    threads: Option<usize>,
+    select: &(impl Fn(&InnerRule) -> Selection<Identifier> + Sync),
+    keep: &(impl Fn(&Atom<Identifier>, &Atom<Identifier>) -> bool + Sync),
+) -> Vec<(InnerRule, usize)> {
+    let threads =
+        threads.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+    let resolve_chunk = |chunk: &[usize], store: &mut TermStore| {
+        chunk
+            .iter()
+            .filter_map(|&i| rule.resolve(&rules[i], store, select, keep).map(|r| (r, i)))
+            .collect::<Vec<_>>()
+    };
+    if threads == 1 || candidates.len() < PARALLEL_THRESHOLD {
+        return resolve_chunk(candidates, store);
+    }
+
+    let chunk = candidates.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = candidates
+            .chunks(chunk)
+            .map(|chunk| scope.spawn(move || resolve_chunk(chunk, &mut TermStore::default())))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("resolution thread panicked"))
+            .collect()
+    })
+}
+
+#[cfg(all(test, feature = "parallel"))]
+mod tests {
+    use crate::ast::{Attributes, Statement};
+    use crate::{bench, Sniffer};
+
+    #[test]
+    fn parallel_test() {
+        let saturated = |threads: usize| {
+            let mut sniffer = Sniffer::builder().threads(threads).build().unwrap();
+            sniffer.assert_statements(
+                bench::transitive_closure(40, 80, 1)
+                    .into_iter()
+                    .map(|rule| Statement::Rule(rule, Attributes::default()))
+                    .collect(),
+            );
+            sniffer.saturate_all();
+            sniffer.rules_to_string()
+        };
+        // Rules are resolved against more than `PARALLEL_THRESHOLD` candidates
+        // towards the end, and derived in the same order whatever the threads
+        assert_eq!(saturated(1), saturated(4));
+    }
+}
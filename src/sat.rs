@@ -0,0 +1,551 @@
+//! SAT module
+//! Propositional abstraction of the rule set, checked by an embedded DPLL solver
+//! before resorting to saturation.
+//!
+//! Rules are grounded over the ground terms occurring in the rule set: every
+//! instance of a rule whose variables are bound to such terms becomes a clause
+//! `!p_1 \/ ... \/ !p_n \/ c` over ground atoms, and every `#expect_not` directive
+//! adds the negations of the instances of its atom. Disequality constraints are
+//! decided on ground instances, which are dropped when one does not hold, see the
+//! `constraints` module. Ground instances are
+//! consequences of the rules they come from, so an unsatisfiable abstraction
+//! proves that the rule set derives an atom it should not, without saturating.
+//!
+//! A satisfiable abstraction is only conclusive when grounding loses nothing,
+//! that is when rules never build terms (their conclusion only holds ground terms
+//! and variables of their premises) and there is no timeline or defeasible rule.
+//! Otherwise the atoms left undecided are looked up after saturating the rule set,
+//! which may not terminate, as `saturate_all`
+use crate::ast::{Atom, InnerAtom, InnerRule, InnerTerm, Term};
+use crate::constraints;
+use crate::hashing::{HashMap, IndexSet};
+use crate::identifiers::Identifier;
+use crate::provenance::Provenance;
+use crate::Sniffer;
+
+/// Above this number of clauses, instances are no longer added and the
+/// abstraction is not conclusive when satisfiable
+pub const MAX_CLAUSES: usize = 1 << 16;
+
+/// A literal, a propositional variable or its negation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Literal {
+    pub variable: usize,
+    pub positive: bool,
+}
+
+/// A formula in conjunctive normal form, over variables `0..variables`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cnf {
+    pub variables: usize,
+    pub clauses: Vec<Vec<Literal>>,
+}
+impl Cnf {
+    /// Adds a clause, growing the number of variables to cover its literals
+    pub fn add_clause(&mut self, clause: Vec<Literal>) {
+        for literal in &clause {
+            self.variables = self.variables.max(literal.variable + 1)
+        }
+        self.clauses.push(clause)
+    }
+
+    /// Returns a model of the formula, if it is satisfiable. Variables are set to
+    /// false first, so that Horn formulas are solved by unit propagation alone.
+    /// The search is iterative: assignments are kept on a trail, undone up to the
+    /// last decision on conflicts, and clauses are only visited when one of their
+    /// two watched literals becomes false
+    pub fn solve(&self) -> Option<Vec<bool>> {
+        let mut solver = Solver {
+            assignment: vec![None; self.variables],
+            watches: vec![vec![]; 2 * self.variables],
+            clauses: vec![],
+            trail: vec![],
+            propagated: 0,
+        };
+        for clause in &self.clauses {
+            let mut clause = clause.clone();
+            clause.sort_by_key(|l| (l.variable, l.positive));
+            clause.dedup();
+            // Clauses holding a literal and its negation always hold
+            if clause.windows(2).any(|w| w[0].variable == w[1].variable) {
+                continue;
+            }
+            match clause[..] {
+                [] => return None,
+                [literal] => {
+                    if !solver.assign(literal) {
+                        return None;
+                    }
+                }
+                _ => {
+                    let index = solver.clauses.len();
+                    solver.watches[watch(clause[0])].push(index);
+                    solver.watches[watch(clause[1])].push(index);
+                    solver.clauses.push(clause)
+                }
+            }
+        }
+
+        // Decisions, as the length of the trail before them and whether the
+        // decided variable was already flipped to true
+        let mut decisions: Vec<(usize, bool)> = vec![];
+        let mut next = 0;
+        loop {
+            if !solver.propagate() {
+                // Flips the last decision that was not flipped yet
+                loop {
+                    let (length, flipped) = decisions.pop()?;
+                    let decided = solver.trail[length];
+                    for literal in solver.trail.drain(length..) {
+                        solver.assignment[literal.variable] = None;
+                        next = next.min(literal.variable)
+                    }
+                    solver.propagated = length;
+                    if !flipped {
+                        decisions.push((length, true));
+                        solver.assign(Literal {
+                            positive: true,
+                            ..decided
+                        });
+                        break;
+                    }
+                }
+                continue;
+            }
+            while next < self.variables && solver.assignment[next].is_some() {
+                next += 1
+            }
+            if next == self.variables {
+                return Some(
+                    solver
+                        .assignment
+                        .into_iter()
+                        .map(|v| v == Some(true))
+                        .collect(),
+                );
+            }
+            decisions.push((solver.trail.len(), false));
+            solver.assign(Literal {
+                variable: next,
+                positive: false,
+            });
+        }
+    }
+}
+
+/// Index of the watch list of a literal
+fn watch(literal: Literal) -> usize {
+    2 * literal.variable + literal.positive as usize
+}
+
+/// State of the search of `Cnf::solve`
+struct Solver {
+    assignment: Vec<Option<bool>>,
+    /// Clauses watching each literal, by `watch` index
+    watches: Vec<Vec<usize>>,
+    /// Clauses of at least two literals, the first two being watched
+    clauses: Vec<Vec<Literal>>,
+    /// Literals made true, in order
+    trail: Vec<Literal>,
+    /// Number of literals of the trail already propagated
+    propagated: usize,
+}
+impl Solver {
+    fn value(&self, literal: Literal) -> Option<bool> {
+        self.assignment[literal.variable].map(|value| value == literal.positive)
+    }
+
+    /// Makes a literal true, returning `false` if it already is false
+    fn assign(&mut self, literal: Literal) -> bool {
+        match self.value(literal) {
+            Some(value) => value,
+            None => {
+                self.assignment[literal.variable] = Some(literal.positive);
+                self.trail.push(literal);
+                true
+            }
+        }
+    }
+
+    /// Propagates the literals of the trail, returning `false` on a conflict
+    fn propagate(&mut self) -> bool {
+        while self.propagated < self.trail.len() {
+            let falsified = Literal {
+                positive: !self.trail[self.propagated].positive,
+                ..self.trail[self.propagated]
+            };
+            self.propagated += 1;
+            let watching = std::mem::take(&mut self.watches[watch(falsified)]);
+            let mut kept = vec![];
+            let mut conflict = false;
+            for (i, &index) in watching.iter().enumerate() {
+                if conflict {
+                    kept.extend_from_slice(&watching[i..]);
+                    break;
+                }
+                let clause = &mut self.clauses[index];
+                if clause[0] == falsified {
+                    clause.swap(0, 1)
+                }
+                let first = clause[0];
+                // Looks for another literal to watch, which is not false
+                let assignment = &self.assignment;
+                let replacement = clause[2..]
+                    .iter()
+                    .position(|l| assignment[l.variable].is_none_or(|value| value == l.positive));
+                if self.value(first) == Some(true) {
+                    kept.push(index);
+                } else if let Some(k) = replacement {
+                    let clause = &mut self.clauses[index];
+                    clause.swap(1, k + 2);
+                    let watched = clause[1];
+                    self.watches[watch(watched)].push(index);
+                } else {
+                    kept.push(index);
+                    conflict = !self.assign(first);
+                }
+            }
+            self.watches[watch(falsified)].extend(kept);
+            if conflict {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Result of `Sniffer::sat_check`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SatCheck {
+    /// Atoms of `#expect_not` directives the rule set derives, in order
+    pub violated: Vec<Atom<String>>,
+    /// Whether the solver decided every directive, without saturating
+    pub conclusive: bool,
+}
+
+/// Ground atoms of the abstraction, numbered as propositional variables
+#[derive(Default)]
+struct Grounding {
+    atoms: IndexSet<InnerAtom>,
+    cnf: Cnf,
+    /// Whether the abstraction is exact, see the module documentation
+    exact: bool,
+}
+impl Grounding {
+    fn literal(&mut self, atom: InnerAtom, positive: bool) -> Literal {
+        let (variable, _) = self.atoms.insert_full(atom);
+        Literal { variable, positive }
+    }
+
+    fn add_clause(&mut self, clause: Vec<Literal>) -> bool {
+        if self.cnf.clauses.len() >= MAX_CLAUSES {
+            self.exact = false;
+            return false;
+        }
+        self.cnf.add_clause(clause);
+        true
+    }
+}
+
+impl<P: Provenance> Sniffer<P> {
+    /// Checks the `#expect_not` directives of the rule set against its
+    /// propositional abstraction, see the `sat` module, only saturating the rule set
+    /// for the directives the abstraction leaves undecided
+    pub fn sat_check(&mut self) -> SatCheck {
+        let refuted: Vec<Atom<String>> = self
+            .expectations
+            .iter()
+            .filter(|e| !e.derivable)
+            .map(|e| e.atom.clone())
+            .collect();
+        let goals: Vec<InnerAtom> = refuted
+            .iter()
+            .map(|atom| atom.to_inner(&mut self.id_server, &mut HashMap::default()))
+            .collect();
+        let grounding = self.ground(&goals);
+        let universe = grounding.universe();
+
+        let mut violated = vec![];
+        let mut undecided = vec![];
+        for (index, (atom, goal)) in refuted.into_iter().zip(&goals).enumerate() {
+            let mut cnf = grounding.cnf.clone();
+            for instance in instances(goal, &universe) {
+                if let Some(variable) = grounding.atoms.get_index_of(&instance) {
+                    cnf.add_clause(vec![Literal {
+                        variable,
+                        positive: false,
+                    }])
+                }
+            }
+            if cnf.solve().is_none() {
+                violated.push((index, atom))
+            } else if !grounding.exact {
+                undecided.push((index, atom, goal.clone()))
+            }
+        }
+        let conclusive = undecided.is_empty();
+        if !conclusive {
+            self.saturate_all();
+            for (index, atom, goal) in undecided {
+                if self.find_fact(&goal).is_some() {
+                    violated.push((index, atom))
+                }
+            }
+            violated.sort_by_key(|(index, _)| *index);
+        }
+        SatCheck {
+            violated: violated.into_iter().map(|(_, atom)| atom).collect(),
+            conclusive,
+        }
+    }
+
+    /// Grounds the rule set over its ground terms and those of the given atoms
+    fn ground(&self, atoms: &[InnerAtom]) -> Grounding {
+        let rules: Vec<InnerRule> = self.all_rules().collect();
+        let mut universe = IndexSet::default();
+        for rule in &rules {
+            for atom in rule.premises.iter().chain([&rule.conclusion]) {
+                ground_terms(atom, &mut universe)
+            }
+        }
+        for atom in atoms {
+            ground_terms(atom, &mut universe)
+        }
+        let universe: Vec<InnerTerm> = universe.into_iter().collect();
+
+        let mut grounding = Grounding {
+            exact: self.defeasible.is_empty() && self.timeline.rules.is_empty(),
+            ..Default::default()
+        };
+        let neq = self.neq();
+        for rule in &rules {
+            grounding.exact &= builds_no_term(rule);
+            let variables: Vec<InnerTerm> = rule_variables(rule);
+            // Instances are truncated past the limit, see `substitutions`
+            if universe.len().saturating_pow(variables.len() as u32) > MAX_CLAUSES {
+                grounding.exact = false
+            }
+            for bindings in substitutions(&variables, &universe) {
+                let Some(instance) = constraints::simplify(rule.substitute(&bindings), neq) else {
+                    continue;
+                };
+                let mut clause: Vec<Literal> = instance
+                    .premises
+                    .into_iter()
+                    .map(|p| grounding.literal(p, false))
+                    .collect();
+                clause.push(grounding.literal(instance.conclusion, true));
+                if !grounding.add_clause(clause) {
+                    return grounding;
+                }
+            }
+        }
+        grounding
+    }
+}
+impl Grounding {
+    /// Ground terms the atoms of the abstraction are built from
+    fn universe(&self) -> Vec<InnerTerm> {
+        let mut universe = IndexSet::default();
+        for atom in &self.atoms {
+            ground_terms(atom, &mut universe)
+        }
+        universe.into_iter().collect()
+    }
+}
+
+/// Ground instances of an atom over the given terms
+fn instances(atom: &InnerAtom, universe: &[InnerTerm]) -> Vec<InnerAtom> {
+    let mut variables = IndexSet::default();
+    for parameter in &atom.parameters {
+        term_variables(parameter, &mut variables)
+    }
+    let variables: Vec<InnerTerm> = variables.into_iter().collect();
+    substitutions(&variables, universe)
+        .map(|bindings| atom.substitute(&bindings))
+        .collect()
+}
+
+/// Bindings of the given variables to the given terms, at most `MAX_CLAUSES`
+fn substitutions<'a>(
+    variables: &'a [InnerTerm],
+    universe: &'a [InnerTerm],
+) -> impl Iterator<Item = HashMap<InnerTerm, InnerTerm>> + 'a {
+    let count = if variables.is_empty() {
+        1
+    } else if universe.is_empty() {
+        0
+    } else {
+        universe.len().saturating_pow(variables.len() as u32)
+    };
+    (0..count.min(MAX_CLAUSES)).map(move |mut index| {
+        variables
+            .iter()
+            .map(|variable| {
+                let term = universe[index % universe.len()].clone();
+                index /= universe.len();
+                (variable.clone(), term)
+            })
+            .collect()
+    })
+}
+
+/// Whether the conclusion of a rule only holds ground terms and variables of its
+/// premises, so that its instances over existing terms are all it can derive
+fn builds_no_term(rule: &InnerRule) -> bool {
+    let mut premises = IndexSet::default();
+    for premise in &rule.premises {
+        for parameter in &premise.parameters {
+            term_variables(parameter, &mut premises)
+        }
+    }
+    rule.conclusion
+        .parameters
+        .iter()
+        .all(|parameter| match parameter {
+            Term::Variable { .. } => premises.contains(parameter),
+            Term::Function { .. } => is_ground(parameter),
+        })
+}
+
+fn rule_variables(rule: &InnerRule) -> Vec<InnerTerm> {
+    let mut variables = IndexSet::default();
+    for atom in rule.premises.iter().chain([&rule.conclusion]) {
+        for parameter in &atom.parameters {
+            term_variables(parameter, &mut variables)
+        }
+    }
+    variables.into_iter().collect()
+}
+
+fn term_variables(term: &InnerTerm, variables: &mut IndexSet<InnerTerm>) {
+    match term {
+        Term::Variable { .. } => {
+            variables.insert(term.clone());
+        }
+        Term::Function { parameters, .. } => {
+            for parameter in parameters {
+                term_variables(parameter, variables)
+            }
+        }
+    }
+}
+
+/// Adds the ground subterms of an atom's parameters
+fn ground_terms(atom: &Atom<Identifier>, terms: &mut IndexSet<InnerTerm>) {
+    fn visit(term: &InnerTerm, terms: &mut IndexSet<InnerTerm>) {
+        if is_ground(term) {
+            terms.insert(term.clone());
+        }
+        if let Term::Function { parameters, .. } = term {
+            for parameter in parameters {
+                visit(parameter, terms)
+            }
+        }
+    }
+    for parameter in &atom.parameters {
+        visit(parameter, terms)
+    }
+}
+
+fn is_ground(term: &InnerTerm) -> bool {
+    match term {
+        Term::Variable { .. } => false,
+        Term::Function { parameters, .. } => parameters.iter().all(is_ground),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cnf, Literal};
+    use crate::Sniffer;
+
+    #[test]
+    fn sat_test() {
+        let literal = |variable, positive| Literal { variable, positive };
+        let mut cnf = Cnf::default();
+        cnf.add_clause(vec![literal(0, true), literal(1, true)]);
+        cnf.add_clause(vec![literal(0, false), literal(1, true)]);
+        assert_eq!(cnf.solve(), Some(vec![false, true]));
+        cnf.add_clause(vec![literal(1, false)]);
+        assert_eq!(cnf.solve(), None);
+
+        // Three pigeons do not fit in two holes, which takes backtracking to refute
+        let pigeon = |p: usize, h: usize| 2 * p + h;
+        let mut cnf = Cnf::default();
+        for p in 0..3 {
+            cnf.add_clause(vec![
+                literal(pigeon(p, 0), true),
+                literal(pigeon(p, 1), true),
+            ]);
+            for q in p + 1..3 {
+                for h in 0..2 {
+                    cnf.add_clause(vec![
+                        literal(pigeon(p, h), false),
+                        literal(pigeon(q, h), false),
+                    ])
+                }
+            }
+        }
+        assert_eq!(cnf.solve(), None);
+        cnf.clauses.pop();
+        let model = cnf.solve().unwrap();
+        assert!(cnf
+            .clauses
+            .iter()
+            .all(|c| c.iter().any(|l| model[l.variable] == l.positive)));
+
+        // Long implication chains are propagated without recursing
+        let mut cnf = Cnf::default();
+        cnf.add_clause(vec![literal(0, true)]);
+        for i in 0..100_000 {
+            cnf.add_clause(vec![literal(i, false), literal(i + 1, true)])
+        }
+        assert!(cnf.solve().unwrap().iter().all(|v| *v));
+        cnf.add_clause(vec![literal(100_000, false)]);
+        assert_eq!(cnf.solve(), None);
+
+        // Grounding decides function-free rule sets on its own
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "edge(a, b). edge(b, c).\nedge(X, Y) => path(X, Y).\n\
+                 path(X, Y) /\\ edge(Y, Z) => path(X, Z).\n\
+                 #expect_not path(a, c).\n#expect_not path(c, X).",
+            )
+            .unwrap();
+        let rules = sniffer.iter_rules().count();
+        let check = sniffer.sat_check();
+        let violated: Vec<_> = check.violated.iter().map(ToString::to_string).collect();
+        assert_eq!(violated, vec!["path(a, c)"]);
+        assert!(check.conclusive);
+        assert_eq!(sniffer.iter_rules().count(), rules);
+
+        // Rules building terms leave satisfiable abstractions to saturation
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "att(k). att(enc(m, k)).\natt(enc(X, Y)) /\\ att(Y) => att(X).\n\
+                 att(X) => seen(h(X)).\n#expect_not att(m).\n#expect_not att(n).",
+            )
+            .unwrap();
+        let check = sniffer.sat_check();
+        let violated: Vec<_> = check.violated.iter().map(ToString::to_string).collect();
+        assert_eq!(violated, vec!["att(m)"]);
+        assert!(!check.conclusive);
+
+        // Disequality constraints are decided on ground instances
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "agent(a, host). agent(b, host).\n\
+                 agent(X, host) /\\ agent(Y, host) /\\ neq(X, Y) => pair(X, Y).\n\
+                 #expect_not pair(a, b).\n#expect_not pair(a, a).",
+            )
+            .unwrap();
+        let check = sniffer.sat_check();
+        let violated: Vec<_> = check.violated.iter().map(ToString::to_string).collect();
+        assert_eq!(violated, vec!["pair(a, b)"]);
+        assert!(check.conclusive);
+    }
+}
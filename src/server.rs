@@ -0,0 +1,247 @@
+//! Server module
+//! A small HTTP server answering JSON requests on a shared rule set, so the engine
+//! can back a knowledge-base service for clients that do not link Rust.
+//!
+//! Endpoints (request bodies are `.pif` sources or atoms, responses are JSON):
+//! - `PUT /rules` replaces the rule set with the uploaded statements
+//! - `POST /assert` adds statements to the rule set
+//! - `POST /query` runs a query such as `att(m).`, returning its derivation if any
+//! - `GET /facts` saturates the rule set and returns every fact
+//! - `POST /derivation` returns the derivation tree of a rule of the rule set
+//!
+//! Errors are reported with a 4xx status and an `{"error": ...}` body
+use crate::{Parser, ParserOptions, SaturationFailure, Sniffer};
+use logos_nom_bridge::Tokens;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+/// Largest request body the server accepts
+const MAX_BODY: usize = 16 * 1024 * 1024;
+
+/// State of the server, independent of the transport
+#[derive(Default)]
+pub struct Server {
+    sniffer: Sniffer,
+    /// Options the rule sets and queries are parsed with
+    options: ParserOptions,
+}
+impl Server {
+    /// Creates a server parsing rule sets and queries with the given options, such
+    /// as a naming convention
+    pub fn with_parser_options(options: ParserOptions) -> Server {
+        Server {
+            sniffer: Sniffer::with_parser_options(options.clone()),
+            options,
+        }
+    }
+
+    /// Handles a request, returning the status code and body of the response.
+    /// Successful responses of queries carry the fingerprint of the run
+    pub fn handle(&mut self, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let (status, mut response) = self.respond(method, path, body);
+        if status == 200 && matches!(path, "/query" | "/facts" | "/derivation") {
+            response["fingerprint"] = json!(self.sniffer.fingerprint().to_string());
+        }
+        (status, response)
+    }
+
+    fn respond(&mut self, method: &str, path: &str, body: &str) -> (u16, Value) {
+        match (method, path) {
+            ("PUT", "/rules") => {
+                let mut sniffer = Sniffer::with_parser_options(self.options.clone());
+                match sniffer.assert(body) {
+                    Ok(count) => {
+                        self.sniffer = sniffer;
+                        (200, json!({ "statements": count }))
+                    }
+                    Err(e) => (400, json!({ "error": e.to_string() })),
+                }
+            }
+            ("POST", "/assert") => match self.sniffer.assert(body) {
+                Ok(count) => (200, json!({ "statements": count })),
+                Err(e) => (400, json!({ "error": e.to_string() })),
+            },
+            ("POST", "/query") => match Parser::parse_query_with(
+                Tokens::new(body.trim()),
+                self.sniffer.parser_options(),
+            ) {
+                Ok(atom) => match self.sniffer.find(&atom) {
+                    Ok(tree) => (200, json!({ "derivation": tree.to_json() })),
+                    Err(SaturationFailure::Derivation(e)) => {
+                        (500, json!({ "error": e.to_string() }))
+                    }
+                    Err(_) => (200, json!({ "derivation": null })),
+                },
+                Err(e) => (400, json!({ "error": e.to_string() })),
+            },
+            ("GET", "/facts") => {
+                self.sniffer.saturate_all();
+                let facts: Vec<_> = self.sniffer.iter_facts().map(|f| f.to_string()).collect();
+                (200, json!({ "facts": facts }))
+            }
+            ("POST", "/derivation") => {
+                match Parser::parse_rules_with(Tokens::new(body), self.sniffer.parser_options()) {
+                    Ok(rules) if rules.len() == 1 => {
+                        match self.sniffer.derivation_tree(&rules[0]) {
+                            Ok(tree) => (200, json!({ "derivation": tree.to_json() })),
+                            Err(e) => (404, json!({ "error": e.to_string() })),
+                        }
+                    }
+                    Ok(_) => (400, json!({ "error": "expected exactly one rule" })),
+                    Err(e) => (400, json!({ "error": e.to_string() })),
+                }
+            }
+            (_, "/rules" | "/assert" | "/query" | "/facts" | "/derivation") => {
+                (405, json!({ "error": "method not allowed" }))
+            }
+            _ => (404, json!({ "error": "not found" })),
+        }
+    }
+}
+
+/// Reads a request, returning its method, path and body
+fn read_request(reader: &mut impl BufRead) -> std::io::Result<(String, String, String)> {
+    let invalid = |message| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let mut length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("invalid content length"))?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(invalid("request body too large"));
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|_| invalid("request body is not UTF-8"))?;
+    Ok((method, path, body))
+}
+
+fn write_response(writer: &mut impl Write, status: u16, body: &Value) -> std::io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+    let body = body.to_string();
+    write!(
+        writer,
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    writer.flush()
+}
+
+fn handle_connection(server: &mut Server, stream: TcpStream) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    match read_request(&mut reader) {
+        Ok((method, path, body)) => {
+            let (status, response) = server.handle(&method, &path, &body);
+            write_response(&mut writer, status, &response)
+        }
+        Err(e) => write_response(&mut writer, 400, &json!({ "error": e.to_string() })),
+    }
+}
+
+/// Serves requests on the given address, one connection at a time
+pub fn serve(address: impl ToSocketAddrs) -> std::io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    let mut server = Server::default();
+    for stream in listener.incoming() {
+        if let Err(e) = stream.and_then(|stream| handle_connection(&mut server, stream)) {
+            eprintln!("connection failed: {e}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoints_test() {
+        let mut server = Server::default();
+        let source = "att(senc(X, Y)) /\\ att(Y) => att(X).\natt(senc(m, k)).";
+        assert_eq!(server.handle("PUT", "/rules", source).0, 200);
+        assert_eq!(server.handle("PUT", "/rules", "att(").0, 400);
+
+        let (_, response) = server.handle("POST", "/query", "att(m).");
+        assert!(response["derivation"].is_null());
+        assert_eq!(server.handle("POST", "/assert", "att(k).").0, 200);
+        let (_, response) = server.handle("POST", "/query", "att(m).");
+        assert_eq!(response["derivation"]["rule"], "att(m)");
+        assert_eq!(
+            response["fingerprint"],
+            server.sniffer.fingerprint().to_string()
+        );
+
+        let (_, response) = server.handle("GET", "/facts", "");
+        assert_eq!(response["facts"].as_array().unwrap().len(), 3);
+        let (status, response) = server.handle("POST", "/derivation", "att(m).");
+        assert_eq!(status, 200);
+        assert_eq!(
+            response["derivation"]["subtrees"].as_array().unwrap().len(),
+            2
+        );
+
+        // Queries are parsed with the operators declared by the rules
+        let source = "op 500 yfx +.\natt(a + b).\natt(X + Y) => att(X).";
+        assert_eq!(server.handle("PUT", "/rules", source).0, 200);
+        let (_, response) = server.handle("POST", "/query", "att(a).");
+        assert!(!response["derivation"].is_null());
+        assert_eq!(server.handle("POST", "/derivation", "att(a + b).").0, 200);
+
+        assert_eq!(server.handle("GET", "/query", "").0, 405);
+        assert_eq!(server.handle("GET", "/", "").0, 404);
+    }
+
+    #[test]
+    fn convention_test() {
+        let options = ParserOptions {
+            convention: crate::NamingConvention::Sigil,
+            ..Default::default()
+        };
+        let mut server = Server::with_parser_options(options);
+        let source = "att(pair(Alice, Bob)).\natt(pair(?x, ?y)) => att(?x).";
+        assert_eq!(server.handle("PUT", "/rules", source).0, 200);
+        // Queries follow the convention of the rule set
+        let (_, response) = server.handle("POST", "/query", "att(pair(?x, Bob)).");
+        assert!(!response["derivation"].is_null());
+        let (_, response) = server.handle("POST", "/query", "att(Alice).");
+        assert_eq!(response["derivation"]["rule"], "att('Alice')");
+        let (_, response) = server.handle("POST", "/query", "att(Carol).");
+        assert!(response["derivation"].is_null());
+        assert_eq!(server.handle("POST", "/derivation", "att(Alice).").0, 200);
+    }
+
+    #[test]
+    fn request_test() {
+        let request = "POST /query HTTP/1.1\r\nHost: localhost\r\nContent-Length: 7\r\n\r\natt(m).";
+        let (method, path, body) = read_request(&mut request.as_bytes()).unwrap();
+        assert_eq!((&*method, &*path, &*body), ("POST", "/query", "att(m)."));
+    }
+}
@@ -0,0 +1,289 @@
+//! Strategies module
+//! Seeded generation of well-formed rule sets and ground fact sets for property
+//! testing, along with a shrinking-friendly representation of said sets.
+//!
+//! Values are generated as specs (small integers standing for names), which shrink
+//! structurally (dropping rules, premises or subterms) and convert to the AST with
+//! `to_rules`. `check` runs a property over many seeds, returning the smallest
+//! counterexample it could shrink a failure to
+
+use crate::arbitrary::Bounds;
+use crate::ast::{Atom, Rule, Term};
+use std::collections::BTreeSet;
+
+/// Small deterministic pseudo-random generator (splitmix64)
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns an integer in `0..=max`
+    pub fn below_or_eq(&mut self, max: usize) -> usize {
+        (self.next_u64() % (max as u64 + 1)) as usize
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TermSpec {
+    Variable(usize),
+    Constant(usize),
+    Function(usize, Vec<TermSpec>),
+}
+impl TermSpec {
+    fn to_term(&self) -> Term<String> {
+        match self {
+            TermSpec::Variable(v) => Term::Variable {
+                symbol: format!("X{v}"),
+            },
+            TermSpec::Constant(c) => Term::Function {
+                symbol: format!("c{c}"),
+                parameters: vec![],
+            },
+            TermSpec::Function(f, parameters) => Term::Function {
+                symbol: format!("f{f}"),
+                parameters: parameters.iter().map(TermSpec::to_term).collect(),
+            },
+        }
+    }
+
+    fn variables(&self, variables: &mut BTreeSet<usize>) {
+        match self {
+            TermSpec::Variable(v) => {
+                variables.insert(*v);
+            }
+            TermSpec::Constant(_) => (),
+            TermSpec::Function(_, parameters) => {
+                parameters.iter().for_each(|t| t.variables(variables))
+            }
+        }
+    }
+
+    /// Smaller terms: constants, and the direct subterms of functions
+    fn shrink(&self) -> Vec<TermSpec> {
+        match self {
+            TermSpec::Variable(_) | TermSpec::Constant(0) => vec![],
+            TermSpec::Constant(_) => vec![TermSpec::Constant(0)],
+            TermSpec::Function(_, parameters) => std::iter::once(TermSpec::Constant(0))
+                .chain(parameters.iter().cloned())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AtomSpec {
+    pub predicate: usize,
+    pub arguments: Vec<TermSpec>,
+}
+impl AtomSpec {
+    fn to_atom(&self) -> Atom<String> {
+        Atom {
+            symbol: format!("p{}", self.predicate),
+            parameters: self.arguments.iter().map(TermSpec::to_term).collect(),
+        }
+    }
+
+    fn variables(&self, variables: &mut BTreeSet<usize>) {
+        self.arguments.iter().for_each(|t| t.variables(variables))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RuleSpec {
+    pub premises: Vec<AtomSpec>,
+    pub conclusion: AtomSpec,
+}
+impl RuleSpec {
+    /// Checks that every variable of the conclusion appears in a premise, which
+    /// ensures that only ground facts are derived from ground facts
+    pub fn is_range_restricted(&self) -> bool {
+        let mut premises = BTreeSet::new();
+        self.premises
+            .iter()
+            .for_each(|p| p.variables(&mut premises));
+        let mut conclusion = BTreeSet::new();
+        self.conclusion.variables(&mut conclusion);
+        conclusion.is_subset(&premises)
+    }
+}
+
+/// Shrinking-friendly representation of a rule set
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct RuleSetSpec {
+    pub rules: Vec<RuleSpec>,
+}
+impl RuleSetSpec {
+    pub fn to_rules(&self) -> Vec<Rule<String>> {
+        self.rules
+            .iter()
+            .map(|r| Rule {
+                premises: r.premises.iter().map(AtomSpec::to_atom).collect(),
+                conclusion: r.conclusion.to_atom(),
+            })
+            .collect()
+    }
+
+    /// Returns strictly smaller well-formed rule sets, simplest candidates first
+    pub fn shrink(&self) -> Vec<RuleSetSpec> {
+        let mut candidates = vec![];
+        for i in 0..self.rules.len() {
+            let mut smaller = self.clone();
+            smaller.rules.remove(i);
+            candidates.push(smaller);
+        }
+        for (i, rule) in self.rules.iter().enumerate() {
+            for j in 0..rule.premises.len() {
+                let mut smaller = self.clone();
+                smaller.rules[i].premises.remove(j);
+                candidates.push(smaller);
+            }
+            let atoms = rule.premises.iter().chain([&rule.conclusion]).enumerate();
+            for (j, atom) in atoms {
+                for (k, argument) in atom.arguments.iter().enumerate() {
+                    for term in argument.shrink() {
+                        let mut smaller = self.clone();
+                        let rule = &mut smaller.rules[i];
+                        let atom = if j < rule.premises.len() {
+                            &mut rule.premises[j]
+                        } else {
+                            &mut rule.conclusion
+                        };
+                        atom.arguments[k] = term;
+                        candidates.push(smaller);
+                    }
+                }
+            }
+        }
+        candidates.retain(|c| c.rules.iter().all(RuleSpec::is_range_restricted));
+        candidates
+    }
+}
+
+fn term(rng: &mut Rng, bounds: &Bounds, depth: usize, variables: bool) -> TermSpec {
+    let last = bounds.symbols.saturating_sub(1);
+    let choice = if depth >= bounds.max_depth {
+        rng.below_or_eq(1)
+    } else {
+        rng.below_or_eq(2)
+    };
+    match choice {
+        0 if variables => TermSpec::Variable(rng.below_or_eq(last)),
+        0 | 1 => TermSpec::Constant(rng.below_or_eq(last)),
+        _ => {
+            let arity = 1 + rng.below_or_eq(bounds.max_arity.saturating_sub(1));
+            TermSpec::Function(
+                rng.below_or_eq(last),
+                (0..arity)
+                    .map(|_| term(rng, bounds, depth + 1, variables))
+                    .collect(),
+            )
+        }
+    }
+}
+
+fn atom(rng: &mut Rng, bounds: &Bounds, variables: bool) -> AtomSpec {
+    // Predicates have a fixed arity so that rules can actually fire
+    let predicate = rng.below_or_eq(bounds.symbols.saturating_sub(1));
+    let arity = 1 + predicate % bounds.max_arity.max(1);
+    AtomSpec {
+        predicate,
+        arguments: (0..arity)
+            .map(|_| term(rng, bounds, 1, variables))
+            .collect(),
+    }
+}
+
+/// Generates a set of ground facts
+pub fn ground_facts(seed: u64, bounds: &Bounds) -> RuleSetSpec {
+    let mut rng = Rng::new(seed);
+    let count = 1 + rng.below_or_eq(bounds.max_premises * 2);
+    RuleSetSpec {
+        rules: (0..count)
+            .map(|_| RuleSpec {
+                premises: vec![],
+                conclusion: atom(&mut rng, bounds, false),
+            })
+            .collect(),
+    }
+}
+
+/// Generates a well-formed rule set: facts are ground and rules are range restricted
+pub fn rule_set(seed: u64, bounds: &Bounds) -> RuleSetSpec {
+    let mut rng = Rng::new(seed);
+    let mut spec = ground_facts(rng.next_u64(), bounds);
+
+    let count = spec.rules.len() + 1 + rng.below_or_eq(bounds.max_premises * 2);
+    while spec.rules.len() < count {
+        let premises = 1 + rng.below_or_eq(bounds.max_premises.saturating_sub(1));
+        let rule = RuleSpec {
+            premises: (0..premises)
+                .map(|_| atom(&mut rng, bounds, true))
+                .collect(),
+            conclusion: atom(&mut rng, bounds, true),
+        };
+        if rule.is_range_restricted() {
+            spec.rules.push(rule)
+        }
+    }
+    spec
+}
+
+/// Runs a property over `cases` rule sets generated from successive seeds.
+/// On failure, the counterexample is shrunk as much as possible and returned
+pub fn check(
+    cases: u64,
+    generate: impl Fn(u64) -> RuleSetSpec,
+    property: impl Fn(&RuleSetSpec) -> bool,
+) -> Result<(), RuleSetSpec> {
+    for seed in 0..cases {
+        let mut counterexample = generate(seed);
+        if property(&counterexample) {
+            continue;
+        }
+        while let Some(smaller) = counterexample.shrink().into_iter().find(|c| !property(c)) {
+            counterexample = smaller;
+        }
+        return Err(counterexample);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn well_formed_test() {
+        let bounds = Bounds::default();
+        for seed in 0..64 {
+            let spec = rule_set(seed, &bounds);
+            assert!(spec.rules.iter().all(RuleSpec::is_range_restricted));
+            assert!(spec
+                .shrink()
+                .iter()
+                .all(|s| s.rules.len() <= spec.rules.len()));
+        }
+    }
+
+    #[test]
+    fn shrinking_test() {
+        // Fails as soon as there are two rules, so the minimal counterexample has two rules
+        let counterexample = check(
+            16,
+            |seed| rule_set(seed, &Bounds::default()),
+            |spec| spec.rules.len() < 2,
+        )
+        .unwrap_err();
+        assert_eq!(counterexample.rules.len(), 2);
+    }
+}
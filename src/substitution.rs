@@ -0,0 +1,154 @@
+//! Substitution module
+//! Valuations of variables, applied to the public AST to instantiate rule schemas.
+//! Variables of a rule are local to it, so applying a substitution to a rule first
+//! renames apart the variables of the rule that are left unbound but occur in the
+//! substituted terms, which would otherwise be captured:
+//! ```text
+//! {X -> f(Y)} applied to p(X, Y) => q(X)  gives  p(f(Y), Y0) => q(f(Y))
+//! ```
+
+use crate::ast::{Atom, Folder, Rule, Term, Visitor};
+use crate::hashing::{HashSet, IndexMap};
+use std::hash::Hash;
+
+/// A mapping of variables, by name, to terms
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitution<T: Hash + Eq> {
+    bindings: IndexMap<T, Term<T>>,
+}
+impl<T: Hash + Eq> Default for Substitution<T> {
+    fn default() -> Self {
+        Substitution {
+            bindings: IndexMap::default(),
+        }
+    }
+}
+impl<T: Clone + Hash + Eq> Substitution<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Binds a variable, returning its previous binding
+    pub fn bind(&mut self, variable: T, term: Term<T>) -> Option<Term<T>> {
+        self.bindings.insert(variable, term)
+    }
+
+    pub fn get(&self, variable: &T) -> Option<&Term<T>> {
+        self.bindings.get(variable)
+    }
+
+    /// Bindings, in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = (&T, &Term<T>)> {
+        self.bindings.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bindings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bindings.is_empty()
+    }
+}
+impl<T: Clone + Hash + Eq> FromIterator<(T, Term<T>)> for Substitution<T> {
+    fn from_iter<I: IntoIterator<Item = (T, Term<T>)>>(iter: I) -> Self {
+        Substitution {
+            bindings: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Replaces the variables bound by a substitution
+struct Apply<'a, T: Hash + Eq>(&'a Substitution<T>);
+impl<T: Clone + Hash + Eq> Folder<T> for Apply<'_, T> {
+    fn fold_variable(&mut self, symbol: T) -> Term<T> {
+        match self.0.get(&symbol) {
+            Some(term) => term.clone(),
+            None => Term::Variable { symbol },
+        }
+    }
+}
+
+/// Collects variable names
+struct Variables<'a, T>(&'a mut HashSet<T>);
+impl<T: Clone + Hash + Eq> Visitor<T> for Variables<'_, T> {
+    fn visit_variable(&mut self, symbol: &T) {
+        self.0.insert(symbol.clone());
+    }
+}
+
+impl<T: Clone + Hash + Eq> Term<T> {
+    /// Applies a substitution to this term
+    pub fn apply(&self, substitution: &Substitution<T>) -> Term<T> {
+        Apply(substitution).fold_term(self.clone())
+    }
+}
+impl<T: Clone + Hash + Eq> Atom<T> {
+    /// Applies a substitution to this atom
+    pub fn apply(&self, substitution: &Substitution<T>) -> Atom<T> {
+        Apply(substitution).fold_atom(self.clone())
+    }
+}
+impl Rule<String> {
+    /// Applies a substitution to this rule, renaming apart the unbound variables
+    /// of the rule that occur in the substituted terms
+    pub fn apply(&self, substitution: &Substitution<String>) -> Rule<String> {
+        let mut variables = HashSet::default();
+        Variables(&mut variables).visit_rule(self);
+        let mut captured = HashSet::default();
+        for (_, term) in substitution.iter() {
+            Variables(&mut captured).visit_term(term);
+        }
+        let mut renamed: Vec<_> = variables
+            .iter()
+            .filter(|v| captured.contains(*v) && substitution.get(v).is_none())
+            .cloned()
+            .collect();
+        renamed.sort();
+
+        let mut used: HashSet<_> = variables.union(&captured).cloned().collect();
+        let mut bindings: Substitution<String> = substitution.clone();
+        for variable in renamed {
+            let fresh = (0..)
+                .map(|i| format!("{variable}{i}"))
+                .find(|name| !used.contains(name))
+                .unwrap();
+            used.insert(fresh.clone());
+            bindings.bind(variable, Term::Variable { symbol: fresh });
+        }
+        Apply(&bindings).fold_rule(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Substitution;
+    use crate::{Parser, Term};
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn apply_test() {
+        let rule = |source: &str| Parser::parse_rules(Tokens::new(source)).unwrap().remove(0);
+        let f = |symbol: &str| Term::Function {
+            symbol: "f".to_string(),
+            parameters: vec![Term::Variable {
+                symbol: symbol.to_string(),
+            }],
+        };
+        let schema = rule("p(X, Y) => q(X).");
+        let substitution = Substitution::from_iter([("X".to_string(), f("Y"))]);
+        assert_eq!(schema.apply(&substitution), rule("p(f(Y), Y0) => q(f(Y))."));
+        assert_eq!(
+            schema.conclusion.apply(&substitution).to_string(),
+            "q(f(Y))"
+        );
+
+        // Variables bound themselves are not renamed
+        let substitution =
+            Substitution::from_iter([("X".to_string(), f("Y")), ("Y".to_string(), f("X"))]);
+        assert_eq!(
+            schema.apply(&substitution),
+            rule("p(f(Y), f(X)) => q(f(Y)).")
+        );
+    }
+}
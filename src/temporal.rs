@@ -0,0 +1,105 @@
+//! Temporal module
+//! Sugar for rule bases evolving over discrete time steps. An atom `holds(F)@T`
+//! stands for `holds(F, T)`, time being an implicit last parameter, and steps
+//! being written as naturals.
+//!
+//! A rule written `next <premises> => <conclusion>.` gives every one of its atoms
+//! an implicit time: premises hold at some step `t` and the conclusion at `t + 1`.
+//! Such rules are evaluated stratum by stratum: the rule set is saturated at step
+//! `t` before the rules leading to step `t + 1` are added, up to the horizon set
+//! by `#horizon <natural>.` (`DEFAULT_HORIZON` steps otherwise)
+
+use crate::ast::{Atom, Attributes, InnerRule, Rule, Term};
+use crate::provenance::Provenance;
+use crate::Sniffer;
+
+/// Number of time steps evaluated when no `#horizon` is declared
+pub const DEFAULT_HORIZON: usize = 16;
+
+/// Time steps of a Sniffer, along with the rules moving from one to the next
+#[derive(Debug, Clone)]
+pub(crate) struct Timeline {
+    pub rules: Vec<Rule<String>>,
+    pub horizon: usize,
+    /// Number of steps whose rules have been added to the rule set
+    pub steps: usize,
+}
+impl Default for Timeline {
+    fn default() -> Self {
+        Timeline {
+            rules: vec![],
+            horizon: DEFAULT_HORIZON,
+            steps: 0,
+        }
+    }
+}
+
+/// Appends the time step to the parameters of an atom
+fn at(atom: &Atom<String>, step: usize) -> Atom<String> {
+    let mut atom = atom.clone();
+    atom.parameters.push(Term::Function {
+        symbol: step.to_string(),
+        parameters: vec![],
+    });
+    atom
+}
+
+impl<P: Provenance> Sniffer<P> {
+    /// Number of time steps evaluated so far
+    pub fn time(&self) -> usize {
+        self.timeline.steps
+    }
+
+    /// Adds the rules leading from the current time step to the next one, unless
+    /// the horizon is reached, returning them
+    pub(crate) fn advance_time(&mut self) -> Vec<InnerRule> {
+        let step = self.timeline.steps;
+        if self.timeline.rules.is_empty() || step >= self.timeline.horizon {
+            return vec![];
+        }
+        self.timeline.steps += 1;
+        let instances: Vec<_> = self
+            .timeline
+            .rules
+            .iter()
+            .map(|rule| Rule {
+                premises: rule.premises.iter().map(|p| at(p, step)).collect(),
+                conclusion: at(&rule.conclusion, step + 1),
+            })
+            .collect();
+        instances
+            .into_iter()
+            .map(|rule| self.insert_input(rule, &Attributes::default()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Parser, Sniffer};
+    use logos_nom_bridge::Tokens;
+
+    #[test]
+    fn next_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert(
+                "#horizon 3.\nholds(on)@0.\nnext holds(on) => holds(off).\n\
+                 next holds(off) => holds(on).\nholds(on)@T => lit(T).",
+            )
+            .unwrap();
+        let query = |q| Parser::parse_query(Tokens::new(q)).unwrap();
+        assert!(sniffer.find(&query("holds(off)@3.")).is_ok());
+        assert!(sniffer.find(&query("holds(on)@3.")).is_err());
+        assert!(sniffer.find(&query("holds(_)@4.")).is_err());
+        assert_eq!(sniffer.time(), 3);
+
+        sniffer.saturate_all();
+        let lit: Vec<_> = sniffer
+            .iter_facts()
+            .filter(|f| f.symbol == "lit")
+            .map(|f| f.to_string())
+            .collect();
+        assert_eq!(lit, vec!["lit(0)", "lit(2)"]);
+    }
+}
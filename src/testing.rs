@@ -0,0 +1,86 @@
+//! Testing module
+//! Helpers for golden-file tests: saturating a rule set and dumping the derived
+//! facts in a canonical, deterministic textual form
+
+use crate::ast::Rule;
+use crate::{LoadError, Sniffer};
+use std::path::Path;
+
+/// Saturates the rule set and returns its facts, one per line.
+/// Facts are sorted and deduplicated, with variables renamed in order of
+/// appearance, so that identical rule sets always produce the same dump
+pub fn facts_dump(sniffer: &mut Sniffer) -> String {
+    sniffer.saturate_all();
+    let mut facts: Vec<_> = sniffer
+        .iter_facts()
+        .map(|conclusion| {
+            let fact = Rule {
+                premises: vec![],
+                conclusion,
+            };
+            format!("{}.", fact.canonical())
+        })
+        .collect();
+    facts.sort();
+    facts.dedup();
+    facts.into_iter().map(|f| f + "\n").collect()
+}
+
+/// Loads and saturates a `.pif` file, returning the dump of its facts
+pub fn saturate_file<P: AsRef<Path>>(file: P) -> Result<String, LoadError> {
+    Ok(facts_dump(&mut Sniffer::new(file)?))
+}
+
+/// Compares a dump against the content of a golden file, panicking with both
+/// versions if they differ.
+/// When the `UPDATE_GOLDEN` environment variable is set, the golden file is
+/// (re)written with the dump instead
+pub fn assert_golden<P: AsRef<Path>>(dump: &str, golden: P) {
+    let golden = golden.as_ref();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(golden, dump).expect("failed to write golden file");
+        return;
+    }
+
+    let expected = std::fs::read_to_string(golden).unwrap_or_else(|e| {
+        panic!(
+            "failed to read golden file {} ({e}), set UPDATE_GOLDEN to create it",
+            golden.display()
+        )
+    });
+    if expected != dump {
+        panic!(
+            "dump differs from golden file {}\n--- expected\n{expected}--- actual\n{dump}",
+            golden.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn facts_dump_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert("att(senc(X, Y)) /\\ att(Y) => att(X).\natt(senc(m, k)).\natt(k).\natt(h(Z)).")
+            .unwrap();
+        assert_eq!(
+            facts_dump(&mut sniffer),
+            "att(h(_)).\natt(k).\natt(m).\natt(senc(m, k)).\n"
+        );
+    }
+
+    #[test]
+    fn deterministic_output_test() {
+        let source = std::fs::read_to_string("examples/example.pif").unwrap();
+        let run = || {
+            let mut sniffer = Sniffer::default();
+            sniffer.assert(&source).unwrap();
+            sniffer.saturate_all();
+            sniffer.rules_to_string()
+        };
+        assert_eq!(run(), run());
+    }
+}
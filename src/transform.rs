@@ -0,0 +1,920 @@
+//! Transform module
+//! Rewriting passes over parsed statements and rules, run before they are asserted
+//! into a Sniffer. Passes do not depend on any Sniffer, so that rule bases can be
+//! preprocessed offline and shipped as `.pif` files, see `run` and the `transform`
+//! command. Passes keep the facts derivable for the predicates that remain, but may
+//! remove predicates altogether, so they are opt-in
+
+use crate::ast::{Atom, Attributes, Defeasible, Rule, Statement, Term};
+use crate::hashing::{HashMap, HashSet, IndexSet};
+use crate::substitution::Substitution;
+
+/// A predicate, by name and arity
+type Predicate = (String, usize);
+
+fn predicate(atom: &Atom<String>) -> Predicate {
+    (atom.symbol.clone(), atom.parameters.len())
+}
+
+/// Predicate of an atom of a `next` rule, which takes the time step as an implicit
+/// last parameter
+fn timed(atom: &Atom<String>) -> Predicate {
+    (atom.symbol.clone(), atom.parameters.len() + 1)
+}
+
+/// Inlines rules into the rules using their conclusion, so that the intermediate
+/// atoms they conclude are never derived: given `p(X) /\ q(X) => r(X).` and
+/// `s(X) => q(X).`, the first rule becomes `p(X) /\ s(X) => r(X).` and the second
+/// one is removed.
+///
+/// A predicate is inlined when it appears in a single premise of the rule set, or
+/// when one of the rules concluding it has the `[inline]` attribute, in which case
+/// every premise using it is expanded with every rule concluding it. Predicates
+/// that are recursive, given other attributes, or used by other statements
+/// (expectations, goals, outputs, defeasible and temporal rules) are kept, as are
+/// the predicates no rule uses, which are the results of the rule set
+pub fn unfold(mut statements: Vec<Statement<String>>) -> Vec<Statement<String>> {
+    while let Some(inlined) = inlinable(&statements) {
+        let mut definitions = vec![];
+        let mut kept = vec![];
+        for statement in statements {
+            match statement {
+                Statement::Rule(rule, _) if predicate(&rule.conclusion) == inlined => {
+                    definitions.push(rule)
+                }
+                statement => kept.push(statement),
+            }
+        }
+        statements = kept
+            .into_iter()
+            .flat_map(|statement| match statement {
+                Statement::Rule(rule, attributes) => expand(rule, &inlined, &definitions)
+                    .into_iter()
+                    .map(|rule| Statement::Rule(rule, attributes.clone()))
+                    .collect(),
+                statement => vec![statement],
+            })
+            .collect();
+    }
+    statements
+}
+
+/// A pass of `run`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pass {
+    /// See `normalize`
+    Normalize,
+    /// See `unfold`, rules having no attributes
+    Unfold,
+    /// See `skolemize`
+    Skolemize,
+    /// See `magic_sets`
+    MagicSets(Atom<String>),
+}
+
+/// Runs the passes over the rules, in order
+pub fn run(mut rules: Vec<Rule<String>>, passes: &[Pass]) -> Vec<Rule<String>> {
+    for pass in passes {
+        rules = match pass {
+            Pass::Normalize => normalize(rules),
+            Pass::Unfold => {
+                let statements = rules
+                    .into_iter()
+                    .map(|rule| Statement::Rule(rule, Attributes::default()))
+                    .collect();
+                unfold(statements)
+                    .into_iter()
+                    .filter_map(|statement| match statement {
+                        Statement::Rule(rule, _) => Some(rule),
+                        _ => None,
+                    })
+                    .collect()
+            }
+            Pass::Skolemize => skolemize(rules),
+            Pass::MagicSets(query) => magic_sets(rules, query),
+        }
+    }
+    rules
+}
+
+/// Puts every rule in normal form, see `Rule::normalized`, keeping the first of
+/// the rules having the same one
+pub fn normalize(rules: Vec<Rule<String>>) -> Vec<Rule<String>> {
+    let normalized: IndexSet<Rule<String>> = rules.iter().map(Rule::normalized).collect();
+    normalized.into_iter().collect()
+}
+
+/// Replaces the variables of a conclusion that no premise binds by Skolem terms
+/// of the variables of the premises: `p(X) => q(X, Y).` becomes
+/// `p(X) => q(X, sk0(X)).`, reading `Y` as some value depending on `X` rather than
+/// as any value. Skolem functions are named `sk<n>`, skipping the names already in
+/// use, and differ for every variable of every rule
+pub fn skolemize(rules: Vec<Rule<String>>) -> Vec<Rule<String>> {
+    let mut used = HashSet::default();
+    for rule in &rules {
+        for atom in rule.premises.iter().chain([&rule.conclusion]) {
+            used.insert(atom.symbol.clone());
+            atom.parameters.iter().for_each(|t| symbols(t, &mut used))
+        }
+    }
+    let mut fresh = (0..)
+        .map(|i| format!("sk{i}"))
+        .filter(|f| !used.contains(f));
+
+    let mut skolemized = vec![];
+    for rule in rules {
+        let mut bound = vec![];
+        for premise in &rule.premises {
+            ordered_variables(premise, &mut bound)
+        }
+        let mut free = vec![];
+        ordered_variables(&rule.conclusion, &mut free);
+        let parameters: Vec<_> = bound
+            .iter()
+            .map(|symbol| Term::Variable {
+                symbol: symbol.clone(),
+            })
+            .collect();
+        let mut skolem = Substitution::new();
+        for variable in free.into_iter().filter(|v| !bound.contains(v)) {
+            let term = Term::Function {
+                symbol: fresh.next().unwrap(),
+                parameters: parameters.clone(),
+            };
+            skolem.bind(variable, term);
+        }
+        skolemized.push(Rule {
+            conclusion: rule.conclusion.apply(&skolem),
+            premises: rule.premises,
+        })
+    }
+    skolemized
+}
+
+/// Rewrites the rules so that saturating them only derives what the query needs:
+/// rules of the predicates the query depends on are restricted to the arguments
+/// it binds, passed left to right through their premises. A predicate `p` whose
+/// arguments are bound as `bf` is renamed `p_bf`, and only derives the facts whose
+/// first argument is in `magic_p_bf`, which holds the bound arguments of the goals
+/// met while answering the query. A last rule concludes the query predicate from
+/// its adorned version, so answers of the query are kept, but other facts of the
+/// rewritten predicates are not derived. Other rules are kept as is, and
+/// predicates named like rewritten ones may clash with them
+pub fn magic_sets(rules: Vec<Rule<String>>, query: &Atom<String>) -> Vec<Rule<String>> {
+    let intensional: HashSet<Predicate> = rules
+        .iter()
+        .filter(|r| !r.premises.is_empty())
+        .map(|r| predicate(&r.conclusion))
+        .collect();
+    if !intensional.contains(&predicate(query)) {
+        return rules;
+    }
+    let adorned = |atom: &Atom<String>, adornment: &str| Atom {
+        symbol: format!("{}_{adornment}", atom.symbol),
+        parameters: atom.parameters.clone(),
+    };
+    // Bound arguments of an atom, under the name of its magic predicate
+    let magic = |atom: &Atom<String>, adornment: &str| {
+        adornment.contains('b').then(|| Atom {
+            symbol: format!("magic_{}_{adornment}", atom.symbol),
+            parameters: atom
+                .parameters
+                .iter()
+                .zip(adornment.chars())
+                .filter(|(_, a)| *a == 'b')
+                .map(|(t, _)| t.clone())
+                .collect(),
+        })
+    };
+
+    let mut rewritten = vec![];
+    let query_adornment = adornment(query, &HashSet::default());
+    if let Some(seed) = magic(query, &query_adornment) {
+        rewritten.push(Rule {
+            premises: vec![],
+            conclusion: seed,
+        })
+    }
+    let mut stack = vec![(predicate(query), query_adornment.clone())];
+    let mut seen: HashSet<(Predicate, String)> = stack.iter().cloned().collect();
+    while let Some((head, head_adornment)) = stack.pop() {
+        for rule in rules.iter().filter(|r| predicate(&r.conclusion) == head) {
+            let mut bound = HashSet::default();
+            for (parameter, a) in rule
+                .conclusion
+                .parameters
+                .iter()
+                .zip(head_adornment.chars())
+            {
+                if a == 'b' {
+                    variables(parameter, &mut bound)
+                }
+            }
+            let mut premises: Vec<_> = magic(&rule.conclusion, &head_adornment)
+                .into_iter()
+                .collect();
+            for premise in &rule.premises {
+                if intensional.contains(&predicate(premise)) {
+                    let premise_adornment = adornment(premise, &bound);
+                    let conclusion = magic(premise, &premise_adornment);
+                    if let Some(conclusion) = conclusion.filter(|c| !premises.contains(c)) {
+                        rewritten.push(Rule {
+                            premises: premises.clone(),
+                            conclusion,
+                        })
+                    }
+                    premises.push(adorned(premise, &premise_adornment));
+                    if seen.insert((predicate(premise), premise_adornment.clone())) {
+                        stack.push((predicate(premise), premise_adornment))
+                    }
+                } else {
+                    premises.push(premise.clone())
+                }
+                premise
+                    .parameters
+                    .iter()
+                    .for_each(|t| variables(t, &mut bound));
+            }
+            rewritten.push(Rule {
+                premises,
+                conclusion: adorned(&rule.conclusion, &head_adornment),
+            })
+        }
+    }
+    let answer = Atom {
+        symbol: query.symbol.clone(),
+        parameters: (0..query.parameters.len())
+            .map(|i| Term::Variable {
+                symbol: format!("X{i}"),
+            })
+            .collect(),
+    };
+    rewritten.push(Rule {
+        premises: vec![adorned(&answer, &query_adornment)],
+        conclusion: answer,
+    });
+    let rewritten_predicates: HashSet<Predicate> = seen.into_iter().map(|(p, _)| p).collect();
+    let kept = rules
+        .into_iter()
+        .filter(|r| !rewritten_predicates.contains(&predicate(&r.conclusion)));
+    kept.chain(rewritten).collect()
+}
+
+/// Tells which arguments of an atom are bound (`b`) by the given variables, or
+/// free (`f`)
+fn adornment(atom: &Atom<String>, bound: &HashSet<String>) -> String {
+    atom.parameters
+        .iter()
+        .map(|parameter| {
+            let mut own = HashSet::default();
+            variables(parameter, &mut own);
+            if own.is_subset(bound) {
+                'b'
+            } else {
+                'f'
+            }
+        })
+        .collect()
+}
+
+/// Names of the functions of a term
+fn symbols(term: &Term<String>, symbols: &mut HashSet<String>) {
+    if let Term::Function { symbol, parameters } = term {
+        symbols.insert(symbol.clone());
+        for parameter in parameters {
+            self::symbols(parameter, symbols)
+        }
+    }
+}
+
+/// Variables of an atom, in order of appearance
+fn ordered_variables(atom: &Atom<String>, variables: &mut Vec<String>) {
+    fn walk(term: &Term<String>, variables: &mut Vec<String>) {
+        match term {
+            Term::Variable { symbol } if !variables.contains(symbol) => {
+                variables.push(symbol.clone())
+            }
+            Term::Variable { .. } => (),
+            Term::Function { parameters, .. } => parameters.iter().for_each(|p| walk(p, variables)),
+        }
+    }
+    atom.parameters.iter().for_each(|p| walk(p, variables))
+}
+
+/// What `prune` removed from a rule base
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pruned {
+    /// Removed rules, in order of appearance
+    pub rules: Vec<Rule<String>>,
+    /// Predicates, by name and arity, that only appeared in removed rules
+    pub predicates: Vec<(String, usize)>,
+}
+
+/// Removes the rules that cannot contribute to any of the given queries, nor to
+/// the goals, expectations and outputs of the statements, returning what was
+/// removed.
+/// A rule contributes if it concludes a predicate of a query, or of a premise of a
+/// contributing rule. Defeasible and temporal rules are kept whenever their
+/// conclusion is relevant, and make their premises relevant as well
+pub fn prune(
+    statements: Vec<Statement<String>>,
+    queries: &[Atom<String>],
+) -> (Vec<Statement<String>>, Pruned) {
+    // Conclusion and premises of every rule-like statement
+    let dependencies = |statement: &Statement<String>| -> Option<(Predicate, Vec<Predicate>)> {
+        match statement {
+            Statement::Rule(rule, _) | Statement::Defeasible(Defeasible { rule, .. }) => Some((
+                predicate(&rule.conclusion),
+                rule.premises.iter().map(predicate).collect(),
+            )),
+            Statement::Next(rule) => Some((
+                timed(&rule.conclusion),
+                rule.premises.iter().map(timed).collect(),
+            )),
+            _ => None,
+        }
+    };
+
+    let mut relevant: HashSet<Predicate> = queries.iter().map(predicate).collect();
+    let mut outputs: HashSet<&String> = HashSet::default();
+    for statement in &statements {
+        match statement {
+            Statement::Expect(atom) | Statement::ExpectNot(atom) | Statement::Goal(atom) => {
+                relevant.insert(predicate(atom));
+            }
+            Statement::Constraint(atoms) => relevant.extend(atoms.iter().map(predicate)),
+            Statement::Output(symbol) => {
+                outputs.insert(symbol);
+            }
+            _ => (),
+        }
+    }
+    let mut producers: HashMap<Predicate, Vec<Vec<Predicate>>> = HashMap::default();
+    for (conclusion, premises) in statements.iter().filter_map(dependencies) {
+        // Output predicates are relevant whatever their arity
+        if outputs.contains(&conclusion.0) {
+            relevant.insert(conclusion.clone());
+        }
+        producers.entry(conclusion).or_default().push(premises)
+    }
+    let mut stack: Vec<Predicate> = relevant.iter().cloned().collect();
+    while let Some(predicate) = stack.pop() {
+        for premises in producers.get(&predicate).into_iter().flatten() {
+            for premise in premises {
+                if relevant.insert(premise.clone()) {
+                    stack.push(premise.clone())
+                }
+            }
+        }
+    }
+
+    let mut kept = vec![];
+    let mut removed = vec![];
+    for statement in statements {
+        match dependencies(&statement) {
+            Some((conclusion, _)) if !relevant.contains(&conclusion) => removed.push(statement),
+            _ => kept.push(statement),
+        }
+    }
+
+    let mut pruned = Pruned::default();
+    let mentioned: HashSet<Predicate> = kept
+        .iter()
+        .filter_map(dependencies)
+        .flat_map(|(conclusion, premises)| premises.into_iter().chain([conclusion]))
+        .collect();
+    for statement in removed {
+        let (conclusion, premises) = dependencies(&statement).unwrap_or_default();
+        for predicate in [conclusion].into_iter().chain(premises) {
+            if !mentioned.contains(&predicate) && !pruned.predicates.contains(&predicate) {
+                pruned.predicates.push(predicate)
+            }
+        }
+        match statement {
+            Statement::Rule(rule, _)
+            | Statement::Defeasible(Defeasible { rule, .. })
+            | Statement::Next(rule) => pruned.rules.push(rule),
+            _ => (),
+        }
+    }
+    (kept, pruned)
+}
+
+/// The outcome of `equivalent`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Equivalence {
+    /// Both rule sets derive the same facts
+    Equivalent,
+    /// Both rule sets derive the same facts whose terms are within the bound, but
+    /// deeper facts were left out
+    EquivalentWithinBound,
+    /// A fact is derived by one of the rule sets only
+    Different {
+        fact: Atom<String>,
+        /// Whether the fact is derived by the first rule set
+        in_first: bool,
+    },
+    /// A fact is derived by one of the rule sets only, but the other one left out
+    /// facts deeper than the bound, from which it may still be derived
+    DifferentWithinBound {
+        fact: Atom<String>,
+        /// Whether the fact is derived by the first rule set
+        in_first: bool,
+    },
+}
+
+/// Checks whether two rule sets derive the same facts, up to variable renaming.
+/// Facts are derived bottom-up; those holding terms nested deeper than
+/// `domain_bound` are left out, so the check is exact whenever no fact is left
+/// out, which is always the case for Datalog rule sets (without function symbols)
+/// given a bound of at least 1. A fact derived by one rule set only proves them
+/// different when the other one left no fact out
+pub fn equivalent(a: &[Rule<String>], b: &[Rule<String>], domain_bound: usize) -> Equivalence {
+    let (first, first_bounded) = consequences(a, domain_bound);
+    let (second, second_bounded) = consequences(b, domain_bound);
+    // Differences also tell whether the rule set missing the fact left facts out
+    let difference = first
+        .iter()
+        .find(|f| !second.contains(*f))
+        .map(|fact| (fact, true, second_bounded))
+        .or_else(|| {
+            second
+                .iter()
+                .find(|f| !first.contains(*f))
+                .map(|fact| (fact, false, first_bounded))
+        });
+    match difference {
+        Some((fact, in_first, false)) => Equivalence::Different {
+            fact: fact.conclusion.clone(),
+            in_first,
+        },
+        Some((fact, in_first, true)) => Equivalence::DifferentWithinBound {
+            fact: fact.conclusion.clone(),
+            in_first,
+        },
+        None if first_bounded || second_bounded => Equivalence::EquivalentWithinBound,
+        None => Equivalence::Equivalent,
+    }
+}
+
+/// Facts derivable from the rules, in canonical form, along with whether facts
+/// deeper than the bound were left out
+fn consequences(rules: &[Rule<String>], bound: usize) -> (IndexSet<Rule<String>>, bool) {
+    let mut facts: IndexSet<Rule<String>> = IndexSet::default();
+    let mut bounded = false;
+    loop {
+        let mut new = vec![];
+        for rule in rules {
+            let known: Vec<_> = facts.iter().map(|f| rename_apart(f, rule)).collect();
+            for bindings in joins(&rule.premises, &known, HashMap::default()) {
+                let conclusion = Atom {
+                    symbol: rule.conclusion.symbol.clone(),
+                    parameters: rule
+                        .conclusion
+                        .parameters
+                        .iter()
+                        .map(|t| resolve(&bindings, t))
+                        .collect(),
+                };
+                if conclusion.parameters.iter().any(|t| depth(t) > bound) {
+                    bounded = true;
+                    continue;
+                }
+                let fact = Rule {
+                    premises: vec![],
+                    conclusion,
+                }
+                .canonical();
+                if !facts.contains(&fact) {
+                    new.push(fact)
+                }
+            }
+        }
+        if new.is_empty() {
+            return (facts, bounded);
+        }
+        facts.extend(new)
+    }
+}
+
+/// Enumerates the bindings matching the premises against the given facts
+fn joins(
+    premises: &[Atom<String>],
+    facts: &[Rule<String>],
+    bindings: HashMap<String, Term<String>>,
+) -> Vec<HashMap<String, Term<String>>> {
+    let Some((premise, rest)) = premises.split_first() else {
+        return vec![bindings];
+    };
+    let mut matches = vec![];
+    for fact in facts {
+        let fact = &fact.conclusion;
+        if predicate(fact) != predicate(premise) {
+            continue;
+        }
+        let mut extended = bindings.clone();
+        if premise
+            .parameters
+            .iter()
+            .zip(&fact.parameters)
+            .all(|(p, f)| unify_terms(&mut extended, p, f))
+        {
+            matches.extend(joins(rest, facts, extended))
+        }
+    }
+    matches
+}
+
+/// Nesting depth of a term, constants having a depth of 1
+fn depth(term: &Term<String>) -> usize {
+    match term {
+        Term::Variable { .. } => 0,
+        Term::Function { parameters, .. } => 1 + parameters.iter().map(depth).max().unwrap_or(0),
+    }
+}
+
+/// Returns the first predicate that can be inlined, in order of appearance
+fn inlinable(statements: &[Statement<String>]) -> Option<Predicate> {
+    let mut uses: HashMap<Predicate, usize> = HashMap::default();
+    let mut blocked: HashSet<Predicate> = HashSet::default();
+    let mut marked: HashSet<Predicate> = HashSet::default();
+    let mut outputs: HashSet<&String> = HashSet::default();
+    let mut candidates: Vec<Predicate> = vec![];
+    for statement in statements {
+        match statement {
+            Statement::Rule(rule, attributes) => {
+                let conclusion = predicate(&rule.conclusion);
+                for premise in &rule.premises {
+                    *uses.entry(predicate(premise)).or_default() += 1;
+                    if predicate(premise) == conclusion {
+                        blocked.insert(conclusion.clone());
+                    }
+                }
+                let inline = Attributes {
+                    inline: attributes.inline,
+                    ..Default::default()
+                };
+                if *attributes != inline {
+                    blocked.insert(conclusion.clone());
+                }
+                if attributes.inline {
+                    marked.insert(conclusion.clone());
+                }
+                if !candidates.contains(&conclusion) {
+                    candidates.push(conclusion)
+                }
+            }
+            Statement::Expect(atom) | Statement::ExpectNot(atom) | Statement::Goal(atom) => {
+                blocked.insert(predicate(atom));
+            }
+            Statement::Constraint(atoms) => blocked.extend(atoms.iter().map(predicate)),
+            Statement::Defeasible(defeasible) => {
+                let rule = &defeasible.rule;
+                blocked.extend(
+                    rule.premises
+                        .iter()
+                        .chain([&rule.conclusion])
+                        .map(predicate),
+                );
+            }
+            Statement::Next(rule) => {
+                blocked.extend(rule.premises.iter().chain([&rule.conclusion]).map(timed));
+            }
+            Statement::Output(symbol) => {
+                outputs.insert(symbol);
+            }
+            Statement::Operator(_)
+            | Statement::Macro(_)
+            | Statement::Horizon(_)
+            | Statement::Mode(_) => (),
+        }
+    }
+    candidates.into_iter().find(|p| {
+        let uses = uses.get(p).copied().unwrap_or(0);
+        !blocked.contains(p)
+            && !outputs.contains(&p.0)
+            && (uses == 1 || (uses > 1 && marked.contains(p)))
+    })
+}
+
+/// Replaces every premise of the rule using the predicate by the premises of each
+/// of its definitions, giving one rule per combination of definitions
+fn expand(
+    rule: Rule<String>,
+    inlined: &Predicate,
+    definitions: &[Rule<String>],
+) -> Vec<Rule<String>> {
+    let Some(i) = rule.premises.iter().position(|p| predicate(p) == *inlined) else {
+        return vec![rule];
+    };
+    let mut expanded = vec![];
+    for definition in definitions {
+        let definition = rename_apart(definition, &rule);
+        // Variables of the definition are bound first, so the rule keeps its names
+        let Some(bindings) = unify(&definition.conclusion, &rule.premises[i]) else {
+            continue;
+        };
+        let mut premises = rule.premises.clone();
+        premises.splice(i..=i, definition.premises);
+        let unfolded = Rule {
+            premises: premises.iter().map(|p| p.apply(&bindings)).collect(),
+            conclusion: rule.conclusion.apply(&bindings),
+        };
+        expanded.extend(expand(unfolded, inlined, definitions))
+    }
+    expanded
+}
+
+fn variables(term: &Term<String>, variables: &mut HashSet<String>) {
+    match term {
+        Term::Variable { symbol } => {
+            variables.insert(symbol.clone());
+        }
+        Term::Function { parameters, .. } => {
+            for parameter in parameters {
+                self::variables(parameter, variables)
+            }
+        }
+    }
+}
+
+fn rule_variables(rule: &Rule<String>) -> HashSet<String> {
+    let mut names = HashSet::default();
+    for atom in rule.premises.iter().chain([&rule.conclusion]) {
+        for parameter in &atom.parameters {
+            variables(parameter, &mut names)
+        }
+    }
+    names
+}
+
+/// Renames the variables of a definition that also occur in the rule it is
+/// inlined into
+fn rename_apart(definition: &Rule<String>, rule: &Rule<String>) -> Rule<String> {
+    let taken = rule_variables(rule);
+    let own = rule_variables(definition);
+    let mut used: HashSet<_> = taken.union(&own).cloned().collect();
+    let mut clashing: Vec<_> = own.into_iter().filter(|v| taken.contains(v)).collect();
+    clashing.sort();
+    let mut renaming = Substitution::new();
+    for variable in clashing {
+        let fresh = (0..)
+            .map(|i| format!("{variable}{i}"))
+            .find(|name| !used.contains(name))
+            .unwrap();
+        used.insert(fresh.clone());
+        renaming.bind(variable, Term::Variable { symbol: fresh });
+    }
+    Rule {
+        premises: definition
+            .premises
+            .iter()
+            .map(|p| p.apply(&renaming))
+            .collect(),
+        conclusion: definition.conclusion.apply(&renaming),
+    }
+}
+
+/// Most general unifier of two atoms, as an idempotent substitution
+fn unify(a: &Atom<String>, b: &Atom<String>) -> Option<Substitution<String>> {
+    if predicate(a) != predicate(b) {
+        return None;
+    }
+    let mut bindings = HashMap::default();
+    for (s, t) in a.parameters.iter().zip(&b.parameters) {
+        if !unify_terms(&mut bindings, s, t) {
+            return None;
+        }
+    }
+    let resolved: Vec<_> = bindings
+        .keys()
+        .map(|v| {
+            (
+                v.clone(),
+                resolve(&bindings, &Term::Variable { symbol: v.clone() }),
+            )
+        })
+        .collect();
+    Some(Substitution::from_iter(resolved))
+}
+
+fn unify_terms(
+    bindings: &mut HashMap<String, Term<String>>,
+    s: &Term<String>,
+    t: &Term<String>,
+) -> bool {
+    let (s, t) = (walk(bindings, s), walk(bindings, t));
+    match (&s, &t) {
+        (Term::Variable { symbol: x }, Term::Variable { symbol: y }) if x == y => true,
+        (Term::Variable { symbol }, term) | (term, Term::Variable { symbol }) => {
+            if occurs(bindings, symbol, term) {
+                return false;
+            }
+            bindings.insert(symbol.clone(), term.clone());
+            true
+        }
+        (
+            Term::Function {
+                symbol: f,
+                parameters: ps,
+            },
+            Term::Function {
+                symbol: g,
+                parameters: qs,
+            },
+        ) => {
+            f == g
+                && ps.len() == qs.len()
+                && ps.iter().zip(qs).all(|(p, q)| unify_terms(bindings, p, q))
+        }
+    }
+}
+
+/// Follows the bindings of a variable until an unbound variable or a function
+fn walk(bindings: &HashMap<String, Term<String>>, term: &Term<String>) -> Term<String> {
+    let mut term = term.clone();
+    while let Term::Variable { symbol } = &term {
+        match bindings.get(symbol) {
+            Some(bound) => term = bound.clone(),
+            None => break,
+        }
+    }
+    term
+}
+
+fn occurs(bindings: &HashMap<String, Term<String>>, variable: &str, term: &Term<String>) -> bool {
+    match walk(bindings, term) {
+        Term::Variable { symbol } => symbol == variable,
+        Term::Function { parameters, .. } => {
+            parameters.iter().any(|p| occurs(bindings, variable, p))
+        }
+    }
+}
+
+fn resolve(bindings: &HashMap<String, Term<String>>, term: &Term<String>) -> Term<String> {
+    match walk(bindings, term) {
+        Term::Function { symbol, parameters } => Term::Function {
+            symbol,
+            parameters: parameters.iter().map(|p| resolve(bindings, p)).collect(),
+        },
+        variable => variable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{equivalent, normalize, prune, run, skolemize, unfold, Equivalence, Pass, Pruned};
+    use crate::{Parser, Rule, Sniffer, Statement};
+    use logos_nom_bridge::Tokens;
+
+    fn rules(statements: &[Statement<String>]) -> Vec<String> {
+        statements
+            .iter()
+            .filter_map(|s| match s {
+                Statement::Rule(rule, _) => Some(rule.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unfold_test() {
+        let source = "att(pair(a, k)).\natt(pair(X, Y)) => split(X, Y).\n\
+                      split(X, Y) /\\ att(Y) => att(X).\n\
+                      [inline] key(k). [inline] key(l).\n\
+                      key(K) /\\ att(senc(M, K)) => att(M).\nkey(K) => att(h(K)).\n\
+                      #expect att(h(l)).";
+        let statements = Parser::parse_statements(Tokens::new(source)).unwrap();
+        let unfolded = unfold(statements.clone());
+        assert_eq!(
+            rules(&unfolded),
+            vec![
+                "att(pair(a, k))",
+                "att(pair(X, Y)) /\\ att(Y) => att(X)",
+                "att(senc(M, k)) => att(M)",
+                "att(senc(M, l)) => att(M)",
+                "att(h(k))",
+                "att(h(l))",
+            ]
+        );
+
+        // Expectations keep a predicate, recursive ones are never inlined
+        let source = "q(a).\nq(X) => r(X).\nr(f(X)) => r(X).\n#expect q(a).";
+        let statements = Parser::parse_statements(Tokens::new(source)).unwrap();
+        assert_eq!(rules(&unfold(statements.clone())), rules(&statements));
+
+        let mut sniffer = Sniffer::default();
+        sniffer.assert_statements(unfolded);
+        assert!(sniffer.run_expectations().is_empty());
+    }
+
+    #[test]
+    fn prune_test() {
+        let source = "att(senc(m, k)).\natt(k).\natt(senc(M, K)) /\\ att(K) => att(M).\n\
+                      log(X) => audit(X).\nuser(X) => log(X).\nuser(alice).\n\
+                      ?- att(m).";
+        let statements = Parser::parse_statements(Tokens::new(source)).unwrap();
+        let (kept, pruned) = prune(statements, &[]);
+        assert_eq!(
+            rules(&kept),
+            vec![
+                "att(senc(m, k))",
+                "att(k)",
+                "att(senc(M, K)) /\\ att(K) => att(M)"
+            ]
+        );
+        assert_eq!(
+            pruned.predicates,
+            vec![
+                ("audit".to_string(), 1),
+                ("log".to_string(), 1),
+                ("user".to_string(), 1)
+            ]
+        );
+        assert_eq!(pruned.rules.len(), 3);
+
+        let statements = Parser::parse_statements(Tokens::new(source)).unwrap();
+        let audit = Parser::parse_query(Tokens::new("audit(X).")).unwrap();
+        let (kept, pruned) = prune(statements.clone(), &[audit]);
+        assert_eq!(kept, statements);
+        assert_eq!(pruned, Pruned::default());
+    }
+
+    #[test]
+    fn passes_test() {
+        let rules = |source: &str| Parser::parse_rules(Tokens::new(source)).unwrap();
+        let printed = |rules: &[Rule<String>]| -> Vec<String> {
+            rules.iter().map(|r| r.to_string()).collect()
+        };
+        let normalized = normalize(rules(
+            "q(X) /\\ p(X) /\\ q(X) => r(X).\np(Y) /\\ q(Y) => r(Y).",
+        ));
+        assert_eq!(printed(&normalized), vec!["p(X0) /\\ q(X0) => r(X0)"]);
+
+        let skolemized = skolemize(rules("sk0(X) => key(X, Y, Y).\nnonce(N)."));
+        assert_eq!(
+            printed(&skolemized),
+            vec!["sk0(X) => key(X, sk1(X), sk1(X))", "nonce(sk2)"]
+        );
+
+        // Only the paths starting from `a` are derived
+        let base = "edge(a, b). edge(b, c). edge(x, y).\nedge(X, Y) => path(X, Y).\n\
+                    path(X, Y) /\\ edge(Y, Z) => path(X, Z).";
+        let query = Parser::parse_query(Tokens::new("path(a, X).")).unwrap();
+        let magic = run(rules(base), &[Pass::MagicSets(query), Pass::Normalize]);
+        let source: String = magic.iter().map(|r| format!("{r}.\n")).collect();
+        let mut sniffer = Sniffer::default();
+        sniffer.assert(&source).unwrap();
+        sniffer.saturate_all();
+        let mut paths: Vec<_> = sniffer
+            .iter_rules()
+            .filter(|r| r.premises.is_empty() && r.conclusion.symbol == "path")
+            .map(|r| r.to_string())
+            .collect();
+        paths.sort();
+        assert_eq!(paths, vec!["path(a, b)", "path(a, c)"]);
+        assert!(source.contains("magic_path_bf(a)"), "{source}");
+    }
+
+    #[test]
+    fn equivalent_test() {
+        let rules = |source: &str| Parser::parse_rules(Tokens::new(source)).unwrap();
+        let base = rules(
+            "edge(a, b). edge(b, c).\nedge(X, Y) => path(X, Y).\n\
+                          path(X, Y) /\\ edge(Y, Z) => path(X, Z).",
+        );
+        let refactored = rules(
+            "edge(a, b). edge(b, c).\nedge(X, Y) => path(X, Y).\n\
+                                edge(X, Y) /\\ path(Y, Z) => path(X, Z).",
+        );
+        let broken = rules("edge(a, b). edge(b, c).\nedge(X, Y) => path(X, Y).");
+        assert_eq!(equivalent(&base, &refactored, 1), Equivalence::Equivalent);
+        assert_eq!(
+            equivalent(&base, &broken, 1),
+            Equivalence::Different {
+                fact: Parser::parse_query(Tokens::new("path(a, c).")).unwrap(),
+                in_first: true,
+            }
+        );
+
+        let nat = rules("nat(z).\nnat(X) => nat(s(X)).");
+        let twice = rules("nat(z). nat(s(z)).\nnat(X) => nat(s(s(X))).");
+        assert_eq!(
+            equivalent(&nat, &twice, 4),
+            Equivalence::EquivalentWithinBound
+        );
+
+        // The fact missing from the first rule set is only derived from a fact
+        // deeper than the bound
+        let nested = rules("q(f(f(a))).\nq(f(X)) => r(X).");
+        let unfolded = rules("q(f(f(a))). r(f(a)).");
+        assert_eq!(
+            equivalent(&nested, &unfolded, 2),
+            Equivalence::DifferentWithinBound {
+                fact: Parser::parse_query(Tokens::new("r(f(a)).")).unwrap(),
+                in_first: false,
+            }
+        );
+        assert_eq!(equivalent(&nested, &unfolded, 3), Equivalence::Equivalent);
+    }
+}
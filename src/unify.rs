@@ -1,27 +1,24 @@
-use crate::ast::{InnerAtom, InnerTerm, Term};
-use crate::identifiers::Identifier;
+use crate::ast::{Atom, InnerAtom, InnerRule, InnerTerm, Term};
+use crate::hashing::HashMap;
+use crate::identifiers::{Identifier, IdentifierServer};
+use crate::interning::{Node, TermId, TermStore};
 use crate::union_find::UnionFind;
-use rustc_hash::FxHashMap;
-use std::collections::HashMap;
 
 struct VarInfo {
-    pub marker: usize,
-    pub bound: Option<InnerTerm>,
+    pub bound: Option<TermId>,
 }
 
 #[derive(Default)]
 pub struct UnificationGraph {
     marker: usize,
-    nodes: FxHashMap<Identifier, VarInfo>,
-    equivalence_classes: UnionFind<InnerTerm>,
+    nodes: HashMap<Identifier, VarInfo>,
+    visited: HashMap<TermId, usize>,
+    equivalence_classes: UnionFind<TermId>,
 }
 impl UnificationGraph {
     /// Returns `false` if the symbol is already bound
-    pub fn bind(&mut self, symbol: Identifier, term: InnerTerm) -> bool {
-        let node = self.nodes.entry(symbol).or_insert(VarInfo {
-            marker: self.marker,
-            bound: None,
-        });
+    pub fn bind(&mut self, symbol: Identifier, term: TermId) -> bool {
+        let node = self.nodes.entry(symbol).or_insert(VarInfo { bound: None });
 
         if node.bound.is_none() {
             node.bound = Some(term);
@@ -31,7 +28,7 @@ impl UnificationGraph {
         }
     }
 
-    pub fn union(&mut self, x: InnerTerm, y: InnerTerm) {
+    pub fn union(&mut self, x: TermId, y: TermId) {
         self.equivalence_classes.union(x, y);
     }
 
@@ -40,135 +37,157 @@ impl UnificationGraph {
     }
 
     /// Returns `true` if the term was not already visited
-    pub fn visit(&mut self, symbol: Identifier) -> bool {
-        let node = self.nodes.entry(symbol).or_insert(VarInfo {
-            marker: self.marker + 1,
-            bound: None,
-        });
-
-        if node.marker != self.marker {
-            node.marker = self.marker;
+    pub fn visit(&mut self, term: TermId) -> bool {
+        let marker = self.visited.entry(term).or_insert(self.marker + 1);
+
+        if *marker != self.marker {
+            *marker = self.marker;
             true
         } else {
             false
         }
     }
 
-    pub fn deref_mut(&mut self, term: InnerTerm) -> InnerTerm {
+    pub fn deref_mut(&mut self, store: &TermStore, term: TermId) -> TermId {
         let root = self.equivalence_classes.find_equivalence_mut(term);
-        if let Some(VarInfo { bound: Some(t), .. }) = self.nodes.get(root.symbol()) {
-            self.equivalence_classes.find_equivalence_mut(t.clone())
-        } else {
-            root
-        }
-    }
-
-    pub fn deref(&self, term: InnerTerm) -> Option<InnerTerm> {
-        let root = self.equivalence_classes.find_equivalence(term)?;
-        if let Some(VarInfo { bound: Some(t), .. }) = self.nodes.get(root.symbol()) {
-            Some(
-                self.equivalence_classes
-                    .find_equivalence(t.clone())
-                    .unwrap_or(t.clone()),
-            )
-        } else {
-            Some(root)
+        if let Node::Variable(symbol) = store.node(root) {
+            if let Some(VarInfo { bound: Some(t) }) = self.nodes.get(&symbol) {
+                return self.equivalence_classes.find_equivalence_mut(*t);
+            }
         }
+        root
     }
 
-    pub fn bindings(mut self) -> FxHashMap<InnerTerm, InnerTerm> {
+    /// Bindings of the variables, as used by `apply`
+    pub fn bindings(mut self, store: &TermStore) -> HashMap<InnerTerm, InnerTerm> {
         let mut bindings = HashMap::default();
         for t in self.equivalence_classes.clone().iter() {
-            bindings.insert(t.clone(), self.deref_mut(t.clone()));
+            if let Node::Variable(_) = store.node(*t) {
+                bindings.insert(store.term(*t), store.term(self.deref_mut(store, *t)));
+            }
         }
         bindings
     }
+
+    /// Checks if term `t` contains term `u`
+    pub fn contains(&mut self, store: &TermStore, t: TermId, u: TermId) -> bool {
+        self.incr_marker();
+
+        // Sadly Rust does not guarantee tail call optimizations (c.f https://dev.to/seanchen1991/the-story-of-tail-call-optimizations-in-rust-35hf)
+        // Therefore, we must optimize this by hand.
+        // Interned terms are shared, so each subterm only needs to be visited once
+        let mut to_visit = vec![t];
+        while let Some(t) = to_visit.pop() {
+            if t == u {
+                return true;
+            }
+
+            if let Node::Function(_, parameters) = store.node(t) {
+                if self.visit(t) {
+                    to_visit.extend(parameters.iter())
+                }
+            }
+        }
+
+        false
+    }
 }
 
 impl InnerAtom {
-    pub fn unify(&self, other: &InnerAtom) -> Option<FxHashMap<InnerTerm, InnerTerm>> {
-        Term::from(self.clone()).unify(&Term::from(other.clone()))
+    /// Tries to unify this atom with another
+    pub fn unify(&self, other: &InnerAtom) -> Option<HashMap<InnerTerm, InnerTerm>> {
+        self.unify_in(other, &mut TermStore::default())
+    }
+
+    /// Tries to unify this atom with another, interning terms in the given store
+    pub fn unify_in(
+        &self,
+        other: &InnerAtom,
+        store: &mut TermStore,
+    ) -> Option<HashMap<InnerTerm, InnerTerm>> {
+        Term::from(self.clone()).unify_in(&Term::from(other.clone()), store)
     }
 }
 
-impl InnerTerm {
-    /// Tries to unify this term with another
-    pub fn unify(&self, other: &InnerTerm) -> Option<FxHashMap<InnerTerm, InnerTerm>> {
-        let mut context = UnificationGraph::default();
-        let mut to_visit = vec![(self.clone(), other.clone())];
+impl Atom<String> {
+    /// Checks if this atom unifies with another, their variables being renamed apart
+    pub fn unifies_with(&self, other: &Atom<String>) -> bool {
+        let mut id_server = IdentifierServer::default();
+        let atom = self.to_inner(&mut id_server, &mut HashMap::default());
+        let other = other.to_inner(&mut id_server, &mut HashMap::default());
+        atom.unify(&other).is_some()
+    }
+}
 
-        while let Some((t, u)) = to_visit.pop() {
-            // Finds leaves of terms `self` and `other`
-            let (t, u) = (context.deref_mut(t), context.deref_mut(u));
+impl InnerRule {
+    /// Checks if this rule is a fact whose conclusion unifies with the given atom
+    pub fn is_fact_matching(&self, atom: &InnerAtom) -> bool {
+        self.premises.is_empty() && self.conclusion.unify(atom).is_some()
+    }
+}
 
-            // If the leaves are equal, we can simply continue
-            if t == u {
-                continue;
-            }
+impl InnerTerm {
+    /// Tries to unify this term with another
+    pub fn unify(&self, other: &InnerTerm) -> Option<HashMap<InnerTerm, InnerTerm>> {
+        self.unify_in(other, &mut TermStore::default())
+    }
 
-            // Otherwise, our actions depend on the types of `leaf1` and `leaf2`
-            match (&t, &u) {
-                (Term::Variable { .. }, Term::Variable { .. }) => {
-                    context.union(t, u);
-                }
-                (x @ Term::Variable { symbol: x_id }, f @ Term::Function { .. })
-                | (f @ Term::Function { .. }, x @ Term::Variable { symbol: x_id }) => {
-                    if f.contains(x, &mut context) {
-                        return None;
-                    } else {
-                        context.bind(*x_id, f.clone());
-                    }
-                }
-                (
-                    Term::Function {
-                        symbol: f,
-                        parameters: f_params,
-                    },
-                    Term::Function {
-                        symbol: g,
-                        parameters: g_params,
-                    },
-                ) => {
-                    if f == g && f_params.len() == g_params.len() {
-                        context.union(t.clone(), u.clone());
-                        for unify in f_params.clone().into_iter().zip(g_params.clone()) {
-                            to_visit.push(unify)
-                        }
-                    } else {
-                        return None;
-                    }
-                }
-            }
-        }
+    /// Tries to unify this term with another, interning terms in the given store
+    pub fn unify_in(
+        &self,
+        other: &InnerTerm,
+        store: &mut TermStore,
+    ) -> Option<HashMap<InnerTerm, InnerTerm>> {
+        let (t, u) = (store.intern(self), store.intern(other));
+        unify_ids(store, t, u)
+    }
+}
 
-        Some(context.bindings())
+/// Unifies two interned terms
+fn unify_ids(store: &TermStore, t: TermId, u: TermId) -> Option<HashMap<InnerTerm, InnerTerm>> {
+    // Interned terms are shared, so ground terms unify exactly when they are the same
+    if store.is_ground(t) && store.is_ground(u) {
+        return (t == u).then(HashMap::default);
     }
+    let mut context = UnificationGraph::default();
+    let mut to_visit = vec![(t, u)];
 
-    /// Checks if this term contains variable `t`
-    pub fn contains(&self, u: &InnerTerm, context: &mut UnificationGraph) -> bool {
-        context.incr_marker();
+    while let Some((t, u)) = to_visit.pop() {
+        // Finds leaves of terms `self` and `other`
+        let (t, u) = (context.deref_mut(store, t), context.deref_mut(store, u));
 
-        // Sadly Rust does not guarantee tail call optimizations (c.f https://dev.to/seanchen1991/the-story-of-tail-call-optimizations-in-rust-35hf)
-        // Therefore, we must optimize this by hand
-        let mut to_visit = vec![self];
-        while let Some(t) = to_visit.pop() {
-            if t == u {
-                return true;
-            }
+        // If the leaves are equal, we can simply continue
+        if t == u {
+            continue;
+        }
 
-            if let Term::Function { symbol, parameters } = t {
-                if context.visit(*symbol) {
-                    for param in parameters {
-                        to_visit.push(param)
-                    }
+        // Otherwise, our actions depend on the types of `leaf1` and `leaf2`
+        match (store.node(t), store.node(u)) {
+            (Node::Variable(_), Node::Variable(_)) => context.union(t, u),
+            (Node::Variable(x_id), Node::Function(..)) => {
+                if context.contains(store, u, t) {
+                    return None;
+                }
+                context.bind(x_id, u);
+            }
+            (Node::Function(..), Node::Variable(x_id)) => {
+                if context.contains(store, t, u) {
+                    return None;
+                }
+                context.bind(x_id, t);
+            }
+            (Node::Function(f, f_params), Node::Function(g, g_params)) => {
+                if f == g && f_params.len() == g_params.len() {
+                    context.union(t, u);
+                    to_visit.extend(f_params.iter().copied().zip(g_params.iter().copied()))
                 } else {
-                    return false;
+                    return None;
                 }
             }
         }
-
-        false
     }
+
+    Some(context.bindings(store))
 }
 
 #[cfg(test)]
@@ -198,44 +217,49 @@ mod tests {
             ],
         };
 
+        let mut store = TermStore::default();
         let mut context = UnificationGraph::default();
+        let mut contains = |t: &InnerTerm, u: &InnerTerm| {
+            let (t, u) = (store.intern(t), store.intern(u));
+            context.contains(&store, t, u)
+        };
 
-        assert!(test_var_term.contains(
+        assert!(contains(
+            &test_var_term,
             &Term::Variable {
                 symbol: Identifier::Variable(0)
-            },
-            &mut context
+            }
         ));
-        assert!(!test_var_term.contains(
+        assert!(!contains(
+            &test_var_term,
             &Term::Variable {
                 symbol: Identifier::Variable(12)
-            },
-            &mut context
+            }
         ));
 
-        assert!(test_fun_term.contains(
+        assert!(contains(
+            &test_fun_term,
             &Term::Variable {
                 symbol: Identifier::Variable(1)
-            },
-            &mut context
+            }
         ));
-        assert!(test_fun_term.contains(
+        assert!(contains(
+            &test_fun_term,
             &Term::Variable {
                 symbol: Identifier::Variable(0)
-            },
-            &mut context
+            }
         ));
-        assert!(test_fun_term.contains(
+        assert!(contains(
+            &test_fun_term,
             &Term::Variable {
                 symbol: Identifier::Variable(3)
-            },
-            &mut context
+            }
         ));
-        assert!(!test_fun_term.contains(
+        assert!(!contains(
+            &test_fun_term,
             &Term::Variable {
                 symbol: Identifier::Variable(12)
-            },
-            &mut context
+            }
         ));
     }
 
@@ -1,4 +1,4 @@
-use rustc_hash::FxHashMap;
+use crate::hashing::HashMap;
 use std::hash::Hash;
 
 #[derive(Clone)]
@@ -17,7 +17,7 @@ impl<T: Hash + Eq + PartialEq + Clone> Default for NodeInfo<T> {
 
 #[derive(Clone)]
 pub struct UnionFind<T: Hash + Eq + PartialEq + Clone> {
-    nodes: FxHashMap<T, NodeInfo<T>>,
+    nodes: HashMap<T, NodeInfo<T>>,
 }
 impl<T: Hash + Eq + PartialEq + Clone> Default for UnionFind<T> {
     fn default() -> Self {
@@ -28,7 +28,7 @@ impl<T: Hash + Eq + PartialEq + Clone> Default for UnionFind<T> {
 }
 impl<T: Hash + Eq + PartialEq + Clone> UnionFind<T> {
     pub fn insert(&mut self, value: T) {
-        self.nodes.entry(value).or_insert(NodeInfo::default());
+        self.nodes.entry(value).or_default();
     }
 
     pub fn union(&mut self, x: T, y: T) {
@@ -76,24 +76,6 @@ impl<T: Hash + Eq + PartialEq + Clone> UnionFind<T> {
         value
     }
 
-    pub fn find_equivalence(&self, mut value: T) -> Option<T> {
-        if !self.nodes.contains_key(&value) {
-            return None;
-        }
-
-        while let Some(NodeInfo {
-            parent: Some(parent),
-            ..
-        }) = self.nodes.get(&value)
-        {
-            if parent == &value {
-                break;
-            }
-            value = parent.clone();
-        }
-        Some(value)
-    }
-
     pub fn iter(&self) -> impl Iterator<Item = &T> {
         self.nodes.keys()
     }
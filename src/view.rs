@@ -0,0 +1,137 @@
+//! View module
+//! Read handles on the facts of a Sniffer. A `SnifferView` is cheap to clone and
+//! can be sent to other threads, which answer queries on the latest snapshot
+//! published by the Sniffer while it keeps saturating.
+//!
+//! Snapshots are published after assertions, regularly during saturation, and
+//! once saturation ends. Every snapshot only holds facts that are derivable, so
+//! answers are always sound, if possibly incomplete until saturation ends
+use crate::ast::{Atom, Rule};
+use crate::hashing::HashSet;
+use std::sync::{Arc, RwLock};
+
+/// Facts of a Sniffer at some point in time
+#[derive(Default, Debug)]
+pub struct Snapshot {
+    generation: u64,
+    facts: Vec<Atom<String>>,
+    index: HashSet<Atom<String>>,
+}
+impl Snapshot {
+    /// Number of snapshots published before this one
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Facts of the snapshot, with variables renamed canonically
+    pub fn facts(&self) -> &[Atom<String>] {
+        &self.facts
+    }
+
+    /// Checks if the atom is a fact of the snapshot, up to variable renaming
+    pub fn contains(&self, atom: &Atom<String>) -> bool {
+        self.index.contains(&canonical(atom))
+    }
+
+    /// Returns the facts unifying with the given atom
+    pub fn query(&self, atom: &Atom<String>) -> Vec<Atom<String>> {
+        self.facts
+            .iter()
+            .filter(|fact| atom.unifies_with(fact))
+            .cloned()
+            .collect()
+    }
+}
+
+fn canonical(atom: &Atom<String>) -> Atom<String> {
+    Rule {
+        premises: vec![],
+        conclusion: atom.clone(),
+    }
+    .canonical()
+    .conclusion
+}
+
+/// Cloneable read handle on the latest snapshot of a Sniffer
+#[derive(Clone, Default, Debug)]
+pub struct SnifferView {
+    latest: Arc<RwLock<Arc<Snapshot>>>,
+}
+impl SnifferView {
+    /// Returns the latest published snapshot, which stays valid while newer ones are published
+    pub fn snapshot(&self) -> Arc<Snapshot> {
+        self.latest
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Checks if the atom is a fact of the latest snapshot, up to variable renaming
+    pub fn contains(&self, atom: &Atom<String>) -> bool {
+        self.snapshot().contains(atom)
+    }
+
+    /// Returns the facts of the latest snapshot unifying with the given atom
+    pub fn query(&self, atom: &Atom<String>) -> Vec<Atom<String>> {
+        self.snapshot().query(atom)
+    }
+
+    /// Replaces the latest snapshot with the given facts
+    pub(crate) fn publish(&self, facts: impl Iterator<Item = Atom<String>>) {
+        let mut facts: Vec<_> = facts.map(|f| canonical(&f)).collect();
+        facts.sort_by_cached_key(|f| f.to_string());
+        facts.dedup();
+        let index = facts.iter().cloned().collect();
+
+        let mut latest = self.latest.write().unwrap_or_else(|e| e.into_inner());
+        *latest = Arc::new(Snapshot {
+            generation: latest.generation + 1,
+            facts,
+            index,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Atom, Sniffer, Term};
+
+    fn att(symbol: &str) -> Atom<String> {
+        let term = if symbol.starts_with(char::is_uppercase) {
+            Term::Variable {
+                symbol: symbol.to_string(),
+            }
+        } else {
+            Term::Function {
+                symbol: symbol.to_string(),
+                parameters: vec![],
+            }
+        };
+        Atom {
+            symbol: String::from("att"),
+            parameters: vec![term],
+        }
+    }
+
+    #[test]
+    fn concurrent_view_test() {
+        let mut sniffer = Sniffer::default();
+        sniffer
+            .assert("att(senc(X, Y)) /\\ att(Y) => att(X).\natt(senc(m, k)).\natt(k).")
+            .unwrap();
+        let view = sniffer.view();
+        assert!(view.contains(&att("k")));
+        assert!(!view.contains(&att("m")));
+
+        let before = view.snapshot();
+        std::thread::spawn(move || sniffer.saturate_all())
+            .join()
+            .unwrap();
+
+        // Old snapshots are left untouched
+        assert!(!before.contains(&att("m")));
+        assert!(view.contains(&att("m")));
+        assert_eq!(view.query(&att("X")).len(), 3);
+        assert!(view.snapshot().generation() > before.generation());
+    }
+}
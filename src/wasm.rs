@@ -0,0 +1,141 @@
+//! WebAssembly module
+//! Entry points for running the engine in a browser, such as a playground for
+//! `.pif` files. `Playground` only exchanges strings, with results encoded as
+//! JSON, so that it maps directly onto JavaScript.
+//!
+//! On `wasm32` targets, the playground is also exported through a plain C ABI
+//! working on the module memory, which needs no glue beyond copying strings:
+//! - `playground_alloc(len)` and `playground_free(ptr, len)` manage buffers of the module memory
+//! - `playground_load(ptr, len)` loads a source, returning a handle (or null on errors)
+//! - `playground_query(handle, ptr, len)` runs a query, returning a JSON string as
+//!   `(ptr << 32) | len`, to be freed with `playground_free`
+//! - `playground_drop(handle)` releases a playground
+use crate::{Parser, SaturationFailure, Sniffer};
+use logos_nom_bridge::Tokens;
+use serde_json::json;
+
+/// A Sniffer loaded from a source string
+pub struct Playground {
+    sniffer: Sniffer,
+}
+impl Playground {
+    /// Loads rules (and expectations) from a source string
+    pub fn load(source: &str) -> Result<Playground, String> {
+        let mut sniffer = Sniffer::default();
+        sniffer.assert(source).map_err(|e| e.to_string())?;
+        Ok(Playground { sniffer })
+    }
+
+    /// Runs a query, with or without its final stop. The result is a JSON object
+    /// with either an `error` or a `derivation` field, the latter being `null`
+    /// if the atom is not derivable, and a `fingerprint` field for the run
+    pub fn query(&mut self, query: &str) -> String {
+        let query = query.trim();
+        let query = if query.ends_with('.') {
+            query.to_string()
+        } else {
+            format!("{query}.")
+        };
+        let mut result =
+            match Parser::parse_query_with(Tokens::new(&query), self.sniffer.parser_options()) {
+                Err(e) => json!({ "error": e.to_string() }),
+                Ok(atom) => match self.sniffer.find(&atom) {
+                    Ok(tree) => json!({ "derivation": tree.to_json() }),
+                    Err(SaturationFailure::Derivation(e)) => json!({ "error": e.to_string() }),
+                    Err(_) => json!({ "derivation": null }),
+                },
+            };
+        if result.get("error").is_none() {
+            result["fingerprint"] = json!(self.sniffer.fingerprint().to_string());
+        }
+        result.to_string()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod exports {
+    use super::Playground;
+
+    /// Reads a string written by the host in the module memory
+    unsafe fn read(ptr: *const u8, len: usize) -> String {
+        String::from_utf8_lossy(std::slice::from_raw_parts(ptr, len)).into_owned()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn playground_alloc(len: usize) -> *mut u8 {
+        let mut buffer = Vec::<u8>::with_capacity(len);
+        let ptr = buffer.as_mut_ptr();
+        std::mem::forget(buffer);
+        ptr
+    }
+
+    /// # Safety
+    /// `ptr` must come from `playground_alloc(len)` or `playground_query`, and not be freed twice
+    #[no_mangle]
+    pub unsafe extern "C" fn playground_free(ptr: *mut u8, len: usize) {
+        drop(Vec::from_raw_parts(ptr, 0, len))
+    }
+
+    /// # Safety
+    /// `ptr` must point to `len` readable bytes
+    #[no_mangle]
+    pub unsafe extern "C" fn playground_load(ptr: *const u8, len: usize) -> *mut Playground {
+        match Playground::load(&read(ptr, len)) {
+            Ok(playground) => Box::into_raw(Box::new(playground)),
+            Err(_) => std::ptr::null_mut(),
+        }
+    }
+
+    /// # Safety
+    /// `playground` must come from `playground_load`, and `ptr` must point to `len` readable bytes
+    #[no_mangle]
+    pub unsafe extern "C" fn playground_query(
+        playground: *mut Playground,
+        ptr: *const u8,
+        len: usize,
+    ) -> u64 {
+        let result = (*playground).query(&read(ptr, len)).into_bytes();
+        let result = std::mem::ManuallyDrop::new(result.into_boxed_slice());
+        ((result.as_ptr() as u64) << 32) | result.len() as u64
+    }
+
+    /// # Safety
+    /// `playground` must come from `playground_load`, and not be dropped twice
+    #[no_mangle]
+    pub unsafe extern "C" fn playground_drop(playground: *mut Playground) {
+        drop(Box::from_raw(playground))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn query_test() {
+        let mut playground =
+            Playground::load("att(senc(X, Y)) /\\ att(Y) => att(X).\natt(senc(m, k)).\natt(k).")
+                .unwrap();
+
+        let result: Value = serde_json::from_str(&playground.query("att(m)")).unwrap();
+        let derivation = &result["derivation"];
+        assert_eq!(derivation["rule"], "att(m)");
+        assert_eq!(derivation["subtrees"].as_array().unwrap().len(), 2);
+
+        let result: Value = serde_json::from_str(&playground.query("att(n).")).unwrap();
+        assert!(result["derivation"].is_null());
+        assert!(result["fingerprint"]
+            .as_str()
+            .unwrap()
+            .starts_with("sniffer/"));
+        let result: Value = serde_json::from_str(&playground.query("att(")).unwrap();
+        assert!(result["error"].is_string());
+        assert!(Playground::load("att(").is_err());
+
+        // Queries are parsed with the operators declared by the rules
+        let mut playground = Playground::load("op 500 yfx +.\natt(a + b).").unwrap();
+        let result: Value = serde_json::from_str(&playground.query("att(a + b)")).unwrap();
+        assert!(!result["derivation"].is_null());
+    }
+}
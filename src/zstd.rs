@@ -0,0 +1,775 @@
+//! Zstd module
+//! Transparent decompression of zstd compressed sources (such as `.pif.zst` files),
+//! recognized by their magic bytes like gzip ones, see the `compression` module.
+//! Decompression is done in crate, following RFC 8878: frames may be concatenated
+//! and skippable frames are skipped, but frames needing a dictionary are refused.
+//!
+//! Compressed blocks hold literals, compressed with a Huffman code, and sequences
+//! copying earlier bytes, whose codes are compressed with finite state entropy
+//! (FSE) tables. Both are read from backward bit streams, the last bits written
+//! being read first
+
+use std::io::{Error, ErrorKind, Result};
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Decompresses a source if it is zstd compressed, returning it as is otherwise
+pub(crate) fn decompress(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    if bytes.starts_with(&ZSTD_MAGIC) {
+        unzstd(&bytes)
+    } else {
+        Ok(bytes)
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid zstd data: {message}"),
+    )
+}
+
+/// Bytes of a source, read in order
+struct Bytes<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+impl<'a> Bytes<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + count)
+            .ok_or_else(|| invalid("truncated data"))?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    /// Reads a little endian integer of `count` bytes
+    fn integer(&mut self, count: usize) -> Result<u64> {
+        let bytes = self.take(count)?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |value, byte| value << 8 | *byte as u64))
+    }
+}
+
+fn unzstd(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut input = Bytes {
+        data: bytes,
+        position: 0,
+    };
+    let mut output = vec![];
+    while input.position < bytes.len() {
+        let magic = input.integer(4)? as u32;
+        if magic & 0xffff_fff0 == 0x184d_2a50 {
+            let size = input.integer(4)? as usize;
+            input.take(size)?;
+        } else if magic.to_le_bytes() == ZSTD_MAGIC {
+            frame(&mut input, &mut output)?;
+        } else {
+            return Err(invalid("bad frame magic number"));
+        }
+    }
+    Ok(output)
+}
+
+/// Decodes a frame, whose magic number was already read
+fn frame(input: &mut Bytes, output: &mut Vec<u8>) -> Result<()> {
+    let descriptor = input.integer(1)? as u8;
+    let single_segment = descriptor & 0x20 != 0;
+    let checksum = descriptor & 0x04 != 0;
+    if descriptor & 0x08 != 0 {
+        return Err(invalid("reserved frame header bit set"));
+    }
+    if !single_segment {
+        input.take(1)?;
+    }
+    let dictionary = match descriptor & 3 {
+        0 => 0,
+        flag => input.integer(1 << (flag - 1))?,
+    };
+    if dictionary != 0 {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "zstd frames compressed with a dictionary are not supported",
+        ));
+    }
+    let content_size = match (descriptor >> 6, single_segment) {
+        (0, false) => None,
+        (0, true) => Some(input.integer(1)?),
+        (1, _) => Some(input.integer(2)? + 256),
+        (flag, _) => Some(input.integer(1 << flag)?),
+    };
+
+    let start = output.len();
+    let mut state = FrameState::default();
+    loop {
+        let header = input.integer(3)?;
+        let size = (header >> 3) as usize;
+        match (header >> 1) & 3 {
+            0 => output.extend_from_slice(input.take(size)?),
+            1 => {
+                let byte = input.take(1)?[0];
+                output.extend(std::iter::repeat_n(byte, size))
+            }
+            2 => block(input.take(size)?, output, start, &mut state)?,
+            _ => return Err(invalid("reserved block type")),
+        }
+        if header & 1 != 0 {
+            break;
+        }
+    }
+
+    if content_size.is_some_and(|size| size != (output.len() - start) as u64) {
+        return Err(invalid("content size mismatch"));
+    }
+    if checksum && input.integer(4)? != xxh64(&output[start..]) & 0xffff_ffff {
+        return Err(invalid("checksum mismatch"));
+    }
+    Ok(())
+}
+
+/// Tables and offsets kept from one block of a frame to the next
+struct FrameState {
+    huffman: Option<Huffman>,
+    literal_lengths: Option<Fse>,
+    offsets: Option<Fse>,
+    match_lengths: Option<Fse>,
+    repeated_offsets: [usize; 3],
+}
+impl Default for FrameState {
+    fn default() -> Self {
+        FrameState {
+            huffman: None,
+            literal_lengths: None,
+            offsets: None,
+            match_lengths: None,
+            repeated_offsets: [1, 4, 8],
+        }
+    }
+}
+
+/// Decodes a compressed block, `start` being where the output of the frame starts
+fn block(data: &[u8], output: &mut Vec<u8>, start: usize, state: &mut FrameState) -> Result<()> {
+    let mut input = Bytes { data, position: 0 };
+    let literals = literals(&mut input, state)?;
+    let sequences = sequences(&mut input, state)?;
+
+    let mut next = 0;
+    for (literal_length, offset, match_length) in sequences {
+        let copied = literals
+            .get(next..next + literal_length)
+            .ok_or_else(|| invalid("not enough literals"))?;
+        output.extend_from_slice(copied);
+        next += literal_length;
+        if offset == 0 || offset > output.len() - start {
+            return Err(invalid("offset too far back"));
+        }
+        // Copies may overlap with the bytes they produce
+        let from = output.len() - offset;
+        for i in 0..match_length {
+            output.push(output[from + i]);
+        }
+    }
+    output.extend_from_slice(&literals[next..]);
+    Ok(())
+}
+
+fn literals(input: &mut Bytes, state: &mut FrameState) -> Result<Vec<u8>> {
+    let header = input.integer(1)?;
+    let kind = header & 3;
+    let format = (header >> 2) & 3;
+    if kind < 2 {
+        let size = match format {
+            0 | 2 => header >> 3,
+            1 => header >> 4 | input.integer(1)? << 4,
+            _ => header >> 4 | input.integer(2)? << 4,
+        } as usize;
+        return Ok(match kind {
+            0 => input.take(size)?.to_vec(),
+            _ => vec![input.take(1)?[0]; size],
+        });
+    }
+
+    let (streams, header_bytes, size_bits) = match format {
+        0 => (1, 3, 10),
+        1 => (4, 3, 10),
+        2 => (4, 4, 14),
+        _ => (4, 5, 18),
+    };
+    let header = header | input.integer(header_bytes - 1)? << 8;
+    let mask = (1 << size_bits) - 1;
+    let regenerated = (header >> 4 & mask) as usize;
+    let compressed = (header >> (4 + size_bits) & mask) as usize;
+    let mut data = Bytes {
+        data: input.take(compressed)?,
+        position: 0,
+    };
+    if kind == 2 {
+        state.huffman = Some(Huffman::read(&mut data)?);
+    }
+    let huffman = state
+        .huffman
+        .as_ref()
+        .ok_or_else(|| invalid("missing Huffman table"))?;
+
+    let rest = &data.data[data.position..];
+    let mut literals = Vec::with_capacity(regenerated);
+    if streams == 1 {
+        huffman.decode(rest, regenerated, &mut literals)?;
+    } else {
+        let jump = rest
+            .get(..6)
+            .ok_or_else(|| invalid("truncated jump table"))?;
+        let mut sizes = [0; 4];
+        for (i, size) in sizes.iter_mut().take(3).enumerate() {
+            *size = u16::from_le_bytes([jump[2 * i], jump[2 * i + 1]]) as usize;
+        }
+        sizes[3] = (rest.len() - 6)
+            .checked_sub(sizes[..3].iter().sum())
+            .ok_or_else(|| invalid("bad jump table"))?;
+        let segment = regenerated.div_ceil(4);
+        let mut position = 6;
+        for (i, size) in sizes.into_iter().enumerate() {
+            let count = if i < 3 {
+                segment
+            } else {
+                regenerated
+                    .checked_sub(3 * segment)
+                    .ok_or_else(|| invalid("bad literals size"))?
+            };
+            huffman.decode(&rest[position..position + size], count, &mut literals)?;
+            position += size;
+        }
+    }
+    Ok(literals)
+}
+
+/// Decodes the sequences of a block, as literal lengths, offsets and match lengths
+fn sequences(input: &mut Bytes, state: &mut FrameState) -> Result<Vec<(usize, usize, usize)>> {
+    let count = match input.integer(1)? as usize {
+        0 => return Ok(vec![]),
+        byte @ 1..=127 => byte,
+        byte @ 128..=254 => (byte - 128) << 8 | input.integer(1)? as usize,
+        _ => input.integer(2)? as usize + 0x7f00,
+    };
+    let modes = input.integer(1)? as u8;
+    if modes & 3 != 0 {
+        return Err(invalid("reserved sequence modes bits set"));
+    }
+    let tables = [
+        (&mut state.literal_lengths, modes >> 6, &LITERAL_LENGTHS),
+        (&mut state.offsets, modes >> 4 & 3, &OFFSETS),
+        (&mut state.match_lengths, modes >> 2 & 3, &MATCH_LENGTHS),
+    ];
+    for (table, mode, code) in tables {
+        match mode {
+            0 => *table = Some(Fse::new(code.default, code.default_log)?),
+            1 => *table = Some(Fse::rle(input.take(1)?[0])),
+            2 => *table = Some(Fse::read(input, code.max_log)?),
+            _ if table.is_none() => return Err(invalid("missing sequence table")),
+            _ => (),
+        }
+    }
+    let (Some(literal_lengths), Some(offsets), Some(match_lengths)) =
+        (&state.literal_lengths, &state.offsets, &state.match_lengths)
+    else {
+        unreachable!("every table was set above")
+    };
+
+    let mut bits = BackwardBits::new(&input.data[input.position..])?;
+    let mut literal_state = bits.read(literal_lengths.log)? as usize;
+    let mut offset_state = bits.read(offsets.log)? as usize;
+    let mut match_state = bits.read(match_lengths.log)? as usize;
+    let repeated = &mut state.repeated_offsets;
+    let mut sequences = Vec::with_capacity(count);
+    for i in 0..count {
+        let offset_code = offsets.symbols[offset_state] as u32;
+        if offset_code > 31 {
+            return Err(invalid("bad offset code"));
+        }
+        let offset_value = (1 << offset_code) + bits.read(offset_code)? as usize;
+        let match_length = MATCH_LENGTHS.value(match_lengths.symbols[match_state], &mut bits)?;
+        let literal_length =
+            LITERAL_LENGTHS.value(literal_lengths.symbols[literal_state], &mut bits)?;
+
+        // The three last offsets are kept, and may be repeated with small codes
+        let offset = if offset_value > 3 {
+            let offset = offset_value - 3;
+            *repeated = [offset, repeated[0], repeated[1]];
+            offset
+        } else {
+            let index = offset_value - 1 + (literal_length == 0) as usize;
+            match index {
+                0 => repeated[0],
+                _ => {
+                    let offset = match index {
+                        3 => repeated[0].saturating_sub(1),
+                        _ => repeated[index],
+                    };
+                    if index > 1 {
+                        repeated[2] = repeated[1];
+                    }
+                    repeated[1] = repeated[0];
+                    repeated[0] = offset;
+                    offset
+                }
+            }
+        };
+        sequences.push((literal_length, offset, match_length));
+
+        if i + 1 < count {
+            literal_state = literal_lengths.update(literal_state, &mut bits)?;
+            match_state = match_lengths.update(match_state, &mut bits)?;
+            offset_state = offsets.update(offset_state, &mut bits)?;
+        }
+    }
+    if bits.position != 0 {
+        return Err(invalid("sequences left unread"));
+    }
+    Ok(sequences)
+}
+
+/// Bits read from the end of a stream, whose last byte is padded with zeros above
+/// a leading one. Reading past the start of the stream gives zeros
+struct BackwardBits<'a> {
+    data: &'a [u8],
+    /// Number of bits left to read, negative once past the start
+    position: isize,
+}
+impl<'a> BackwardBits<'a> {
+    fn new(data: &'a [u8]) -> Result<BackwardBits<'a>> {
+        let last = *data
+            .last()
+            .filter(|b| **b != 0)
+            .ok_or_else(|| invalid("bad stream padding"))?;
+        Ok(BackwardBits {
+            data,
+            position: (data.len() * 8 - last.leading_zeros() as usize - 1) as isize,
+        })
+    }
+
+    fn read(&mut self, count: u32) -> Result<u64> {
+        if count == 0 {
+            return Ok(0);
+        }
+        if self.position < -64 {
+            return Err(invalid("stream overread"));
+        }
+        self.position -= count as isize;
+        let mut value = 0;
+        for i in (0..count as isize).rev() {
+            let bit = self.position + i;
+            let set = bit >= 0 && self.data[bit as usize / 8] >> (bit % 8) & 1 != 0;
+            value = value << 1 | set as u64;
+        }
+        Ok(value)
+    }
+}
+
+/// Bits read from the start of a stream, least significant first
+struct ForwardBits<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+impl ForwardBits<'_> {
+    fn read(&mut self, count: u32) -> Result<u32> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self
+                .data
+                .get(self.position / 8)
+                .ok_or_else(|| invalid("truncated table"))?;
+            value |= ((*byte as u32 >> (self.position % 8)) & 1) << i;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+}
+
+/// A finite state entropy decoding table: each state gives a symbol, then the
+/// next state from the given number of bits
+struct Fse {
+    log: u32,
+    symbols: Vec<u8>,
+    bits: Vec<u8>,
+    bases: Vec<u16>,
+}
+impl Fse {
+    /// Builds the table of the given normalized probabilities, `-1` standing for
+    /// probabilities below one state
+    fn new(probabilities: &[i16], log: u32) -> Result<Fse> {
+        let size = 1 << log;
+        let mut symbols = vec![0u8; size];
+        let mut next = vec![0u16; probabilities.len()];
+        let mut high = size;
+        for (symbol, probability) in probabilities.iter().enumerate() {
+            if *probability == -1 {
+                high -= 1;
+                symbols[high] = symbol as u8;
+                next[symbol] = 1;
+            }
+        }
+        let step = (size >> 1) + (size >> 3) + 3;
+        let mut position = 0;
+        for (symbol, probability) in probabilities.iter().enumerate() {
+            if *probability <= 0 {
+                continue;
+            }
+            next[symbol] = *probability as u16;
+            for _ in 0..*probability {
+                symbols[position] = symbol as u8;
+                position = (position + step) & (size - 1);
+                while position >= high {
+                    position = (position + step) & (size - 1);
+                }
+            }
+        }
+        if position != 0 {
+            return Err(invalid("bad FSE distribution"));
+        }
+        let mut bits = vec![0u8; size];
+        let mut bases = vec![0u16; size];
+        for state in 0..size {
+            let next_state = next[symbols[state] as usize];
+            next[symbols[state] as usize] += 1;
+            bits[state] = (log - (15 - next_state.leading_zeros())) as u8;
+            bases[state] = ((next_state as usize) << bits[state]).wrapping_sub(size) as u16;
+        }
+        Ok(Fse {
+            log,
+            symbols,
+            bits,
+            bases,
+        })
+    }
+
+    /// A table always giving the same symbol
+    fn rle(symbol: u8) -> Fse {
+        Fse {
+            log: 0,
+            symbols: vec![symbol],
+            bits: vec![0],
+            bases: vec![0],
+        }
+    }
+
+    /// Reads the description of a table whose log is at most `max_log`
+    fn read(input: &mut Bytes, max_log: u32) -> Result<Fse> {
+        let mut bits = ForwardBits {
+            data: &input.data[input.position..],
+            position: 0,
+        };
+        let log = bits.read(4)? + 5;
+        if log > max_log {
+            return Err(invalid("FSE table too large"));
+        }
+        let mut remaining = 1i32 << log;
+        let mut probabilities = vec![];
+        while remaining > 0 {
+            // Values are written on as few bits as the remaining probability allows
+            let count = 32 - (remaining + 1).leading_zeros();
+            let lower = (1 << (count - 1)) - 1;
+            let threshold = (1 << count) - 1 - (remaining + 1) as u32;
+            let mut value = bits.read(count)?;
+            if value & lower < threshold {
+                bits.position -= 1;
+                value &= lower;
+            } else if value > lower {
+                value -= threshold;
+            }
+            let probability = value as i16 - 1;
+            remaining -= probability.unsigned_abs() as i32;
+            probabilities.push(probability);
+            if probability == 0 {
+                loop {
+                    let repeat = bits.read(2)?;
+                    probabilities.extend(std::iter::repeat_n(0, repeat as usize));
+                    if repeat != 3 {
+                        break;
+                    }
+                }
+            }
+            if probabilities.len() > 256 {
+                return Err(invalid("too many FSE symbols"));
+            }
+        }
+        if remaining != 0 {
+            return Err(invalid("bad FSE distribution"));
+        }
+        input.position += bits.position.div_ceil(8);
+        Fse::new(&probabilities, log)
+    }
+
+    fn update(&self, state: usize, bits: &mut BackwardBits) -> Result<usize> {
+        Ok(self.bases[state] as usize + bits.read(self.bits[state] as u32)? as usize)
+    }
+}
+
+/// Baselines and extra bits of the codes of literal lengths, offsets and match
+/// lengths, along with their default distributions
+struct Code {
+    baselines: &'static [u32],
+    extra: &'static [u8],
+    default: &'static [i16],
+    default_log: u32,
+    max_log: u32,
+}
+impl Code {
+    fn value(&self, code: u8, bits: &mut BackwardBits) -> Result<usize> {
+        let (Some(baseline), Some(extra)) = (
+            self.baselines.get(code as usize),
+            self.extra.get(code as usize),
+        ) else {
+            return Err(invalid("bad length code"));
+        };
+        Ok(*baseline as usize + bits.read(*extra as u32)? as usize)
+    }
+}
+
+const LITERAL_LENGTHS: Code = Code {
+    baselines: &[
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 20, 22, 24, 28, 32, 40, 48,
+        64, 128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768, 65536,
+    ],
+    extra: &[
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 3, 3, 4, 6, 7, 8, 9, 10,
+        11, 12, 13, 14, 15, 16,
+    ],
+    default: &[
+        4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1,
+        1, 1, -1, -1, -1, -1,
+    ],
+    default_log: 6,
+    max_log: 9,
+};
+const MATCH_LENGTHS: Code = Code {
+    baselines: &[
+        3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26,
+        27, 28, 29, 30, 31, 32, 33, 34, 35, 37, 39, 41, 43, 47, 51, 59, 67, 83, 99, 131, 259, 515,
+        1027, 2051, 4099, 8195, 16387, 32771, 65539,
+    ],
+    extra: &[
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 1, 1, 1, 1, 2, 2, 3, 3, 4, 4, 5, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+    ],
+    default: &[
+        1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1, -1, -1,
+    ],
+    default_log: 6,
+    max_log: 9,
+};
+/// Offsets are only given a default distribution, their values being decoded apart
+const OFFSETS: Code = Code {
+    baselines: &[],
+    extra: &[],
+    default: &[
+        1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
+    ],
+    default_log: 5,
+    max_log: 8,
+};
+
+/// A Huffman decoding table indexed by the next `log` bits of a stream
+struct Huffman {
+    log: u32,
+    symbols: Vec<u8>,
+    bits: Vec<u8>,
+}
+impl Huffman {
+    /// Reads the description of a table, as the weights of its symbols
+    fn read(input: &mut Bytes) -> Result<Huffman> {
+        let header = input.integer(1)? as usize;
+        let mut weights = if header < 128 {
+            // Weights compressed with an FSE table, decoded with two interleaved states
+            let data = input.take(header)?;
+            let mut table = Bytes { data, position: 0 };
+            let fse = Fse::read(&mut table, 6)?;
+            let mut bits = BackwardBits::new(&data[table.position..])?;
+            let mut states = [bits.read(fse.log)? as usize, bits.read(fse.log)? as usize];
+            let mut weights = vec![];
+            for i in (0..2).cycle() {
+                weights.push(fse.symbols[states[i]]);
+                states[i] = fse.update(states[i], &mut bits)?;
+                if bits.position < 0 {
+                    weights.push(fse.symbols[states[1 - i]]);
+                    break;
+                }
+                if weights.len() > 255 {
+                    return Err(invalid("too many Huffman weights"));
+                }
+            }
+            weights
+        } else {
+            let count = header - 127;
+            input
+                .take(count.div_ceil(2))?
+                .iter()
+                .flat_map(|byte| [byte >> 4, byte & 15])
+                .take(count)
+                .collect()
+        };
+
+        // The weight of the last symbol is implied by the others
+        let total: u32 = weights
+            .iter()
+            .filter(|w| **w > 0)
+            .map(|w| 1 << (w - 1))
+            .sum();
+        if total == 0 || weights.iter().any(|w| *w > 11) {
+            return Err(invalid("bad Huffman weights"));
+        }
+        let log = 32 - total.leading_zeros();
+        let left = (1 << log) - total;
+        if log > 11 || !left.is_power_of_two() {
+            return Err(invalid("bad Huffman weights"));
+        }
+        weights.push(left.trailing_zeros() as u8 + 1);
+
+        let lengths: Vec<u32> = weights
+            .iter()
+            .map(|w| if *w > 0 { log + 1 - *w as u32 } else { 0 })
+            .collect();
+        let mut counts = vec![0usize; log as usize + 1];
+        for length in lengths.iter().filter(|l| **l > 0) {
+            counts[*length as usize] += 1;
+        }
+        // Longer codes come first, each length taking a contiguous range of states
+        let mut starts = vec![0usize; log as usize + 1];
+        let mut end = 0;
+        for length in (1..=log as usize).rev() {
+            starts[length] = end;
+            end += counts[length] << (log as usize - length);
+        }
+        let size = 1 << log;
+        if end != size {
+            return Err(invalid("bad Huffman weights"));
+        }
+        let mut symbols = vec![0u8; size];
+        let mut bits = vec![0u8; size];
+        for (symbol, length) in lengths.iter().enumerate().filter(|(_, l)| **l > 0) {
+            let start = starts[*length as usize];
+            let len = 1 << (log - length);
+            symbols[start..start + len].fill(symbol as u8);
+            bits[start..start + len].fill(*length as u8);
+            starts[*length as usize] += len;
+        }
+        Ok(Huffman { log, symbols, bits })
+    }
+
+    /// Decodes `count` symbols of a stream
+    fn decode(&self, stream: &[u8], count: usize, output: &mut Vec<u8>) -> Result<()> {
+        let mut bits = BackwardBits::new(stream)?;
+        let mask = (1 << self.log) - 1;
+        let mut state = bits.read(self.log)? as usize;
+        for _ in 0..count {
+            output.push(self.symbols[state]);
+            let length = self.bits[state] as u32;
+            state = ((state << length) | bits.read(length)? as usize) & mask;
+        }
+        if bits.position != -(self.log as isize) {
+            return Err(invalid("bad literals stream"));
+        }
+        Ok(())
+    }
+}
+
+/// XXH64 hash of some data, with a zero seed
+fn xxh64(data: &[u8]) -> u64 {
+    const P1: u64 = 0x9E3779B185EBCA87;
+    const P2: u64 = 0xC2B2AE3D27D4EB4F;
+    const P3: u64 = 0x165667B19E3779F9;
+    const P4: u64 = 0x85EBCA77C2B2AE63;
+    const P5: u64 = 0x27D4EB2F165667C5;
+    let round = |acc: u64, input: u64| {
+        acc.wrapping_add(input.wrapping_mul(P2))
+            .rotate_left(31)
+            .wrapping_mul(P1)
+    };
+    let word = |bytes: &[u8]| u64::from_le_bytes(bytes[..8].try_into().unwrap());
+
+    let mut chunks = data.chunks_exact(32);
+    let mut hash = if data.len() >= 32 {
+        let mut lanes = [P1.wrapping_add(P2), P2, 0, 0u64.wrapping_sub(P1)];
+        for chunk in &mut chunks {
+            for (i, lane) in lanes.iter_mut().enumerate() {
+                *lane = round(*lane, word(&chunk[8 * i..]));
+            }
+        }
+        let mut hash = [1, 7, 12, 18]
+            .iter()
+            .zip(lanes)
+            .fold(0u64, |hash, (r, lane)| {
+                hash.wrapping_add(lane.rotate_left(*r))
+            });
+        for lane in lanes {
+            hash = (hash ^ round(0, lane)).wrapping_mul(P1).wrapping_add(P4);
+        }
+        hash
+    } else {
+        P5
+    };
+    hash = hash.wrapping_add(data.len() as u64);
+
+    let mut rest = chunks.remainder();
+    while rest.len() >= 8 {
+        hash ^= round(0, word(rest));
+        hash = hash.rotate_left(27).wrapping_mul(P1).wrapping_add(P4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        let half = u32::from_le_bytes(rest[..4].try_into().unwrap()) as u64;
+        hash ^= half.wrapping_mul(P1);
+        hash = hash.rotate_left(23).wrapping_mul(P2).wrapping_add(P3);
+        rest = &rest[4..];
+    }
+    for byte in rest {
+        hash ^= (*byte as u64).wrapping_mul(P5);
+        hash = hash.rotate_left(11).wrapping_mul(P1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(P2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(P3);
+    hash ^ (hash >> 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unzstd_test() {
+        // A frame with a compressed block, a skippable frame, then a frame with a raw block
+        let compressed = [
+            0x28, 0xb5, 0x2f, 0xfd, 0x64, 0x57, 0x00, 0xed, 0x02, 0x00, 0x32, 0x44, 0x0e, 0x11,
+            0xa0, 0x3d, 0x0c, 0x9c, 0xad, 0xfd, 0x6a, 0x3b, 0xfe, 0x96, 0x54, 0x51, 0xcf, 0xfb,
+            0xd9, 0x00, 0x03, 0x60, 0x2c, 0x0b, 0x73, 0x3a, 0x5d, 0x4a, 0x77, 0x9b, 0x0a, 0x56,
+            0xca, 0xbf, 0x7b, 0x37, 0x33, 0x73, 0x4a, 0x08, 0x9d, 0x63, 0x4c, 0x29, 0xff, 0xee,
+            0xdd, 0xcc, 0x60, 0xec, 0x92, 0xa7, 0x82, 0xb8, 0x20, 0xa1, 0x8b, 0x61, 0x4e, 0x17,
+            0x1f, 0xa8, 0x10, 0xf0, 0xfb, 0x77, 0xc0, 0xe3, 0xea, 0xb0, 0x4c, 0x62, 0x24, 0x12,
+            0x3d, 0xb7, 0x95, 0xbb, 0x61, 0x09, 0x71, 0x33, 0x4c, 0x85, 0x3d, 0x3b, 0x72, 0x74,
+            0xf4, 0x9e, 0x6a, 0x90, 0xa4, 0xb8, 0xeb, 0xc0, 0x8b, 0x50, 0x2a, 0x4d, 0x18, 0x03,
+            0x00, 0x00, 0x00, 0x61, 0x62, 0x63, 0x28, 0xb5, 0x2f, 0xfd, 0x00, 0x58, 0xd1, 0x00,
+            0x00, 0x61, 0x74, 0x74, 0x28, 0x61, 0x29, 0x2e, 0x0a, 0x61, 0x74, 0x74, 0x28, 0x61,
+            0x29, 0x20, 0x3d, 0x3e, 0x20, 0x61, 0x74, 0x74, 0x28, 0x62, 0x29, 0x2e, 0x0a,
+        ];
+        let mut expected: String = (0..16)
+            .map(|i| format!("att(pair(a{i}, b{i})).\n"))
+            .collect();
+        expected.push_str("att(pair(X, Y)) => att(X).\natt(a).\natt(a) => att(b).\n");
+        assert_eq!(
+            decompress(compressed.to_vec()).unwrap(),
+            expected.as_bytes()
+        );
+
+        let mut corrupted = compressed.to_vec();
+        corrupted[40] ^= 0x10;
+        assert!(decompress(corrupted).is_err());
+        assert!(decompress(compressed[..100].to_vec()).is_err());
+        assert_eq!(decompress(b"att(a).".to_vec()).unwrap(), b"att(a).");
+    }
+}